@@ -0,0 +1,227 @@
+use pipebuf::{PBufRd, PBufWr};
+use std::io::{ErrorKind, Read, Result, Write};
+use std::ops::{BitOr, BitOrAssign};
+
+/// Activity status returned by the `process` calls of the links
+///
+/// This is a set of flags describing what happened during the call.
+/// Use [`Activity::is_any`] to test whether anything happened at all,
+/// which is what the `bool` activity status returned in earlier
+/// versions indicated.  Flags may be combined with `|`.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Activity(u8);
+
+impl Activity {
+    /// No activity
+    pub const NONE: Activity = Activity(0);
+
+    /// Data was read into the incoming pipe
+    pub const READ: Activity = Activity(1);
+
+    /// Data was taken from the outgoing pipe and written out
+    pub const WROTE: Activity = Activity(2);
+
+    /// The incoming pipe was closed or aborted
+    pub const EOF_IN: Activity = Activity(4);
+
+    /// EOF was consumed from the outgoing pipe and passed on
+    pub const EOF_OUT: Activity = Activity(8);
+
+    /// An abort was passed on, in either direction, or the far end was
+    /// found to be gone.  When passed on, this accompanies `EOF_IN` or
+    /// `EOF_OUT`.
+    pub const ABORTED: Activity = Activity(16);
+
+    /// The link was aborted by its idle timeout or close deadline.
+    /// This accompanies `ABORTED`, and `EOF_IN` if the incoming pipe
+    /// was still open.
+    pub const TIMED_OUT: Activity = Activity(32);
+
+    /// The link's read or write quota ran out.  For the read quota
+    /// this accompanies `EOF_IN`, and `ABORTED` if set to abort.  For
+    /// the write quota it accompanies `ABORTED` and `EOF_IN` if set to
+    /// abort, and otherwise appears alone.
+    pub const QUOTA: Activity = Activity(64);
+
+    /// Test whether anything happened at all
+    #[inline]
+    pub fn is_any(self) -> bool {
+        self.0 != 0
+    }
+
+    /// Test whether all the flags in `other` are set
+    #[inline]
+    pub fn contains(self, other: Activity) -> bool {
+        (self.0 & other.0) == other.0
+    }
+
+    /// Test whether any of the flags in `other` are set
+    #[inline]
+    pub fn intersects(self, other: Activity) -> bool {
+        (self.0 & other.0) != 0
+    }
+}
+
+impl BitOr for Activity {
+    type Output = Activity;
+    #[inline]
+    fn bitor(self, rhs: Activity) -> Activity {
+        Activity(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Activity {
+    #[inline]
+    fn bitor_assign(&mut self, rhs: Activity) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl From<Activity> for bool {
+    #[inline]
+    fn from(act: Activity) -> bool {
+        act.is_any()
+    }
+}
+
+// Close the incoming pipe, recording activity if it wasn't already
+// closed
+#[inline]
+pub(crate) fn close_in(pwr: &mut PBufWr, act: &mut Activity) {
+    if !pwr.is_eof() {
+        pwr.close();
+        *act |= Activity::EOF_IN;
+    }
+}
+
+// Abort the incoming pipe, recording activity if it wasn't already
+// closed
+#[inline]
+pub(crate) fn abort_in(pwr: &mut PBufWr, act: &mut Activity) {
+    if !pwr.is_eof() {
+        pwr.abort();
+        *act |= Activity::EOF_IN | Activity::ABORTED;
+    }
+}
+
+// Consume EOF from the outgoing pipe, recording activity
+#[inline]
+pub(crate) fn consume_eof_out(prd: &mut PBufRd, act: &mut Activity) {
+    if prd.consume_eof() {
+        *act |= Activity::EOF_OUT;
+        if prd.is_aborted() {
+            *act |= Activity::ABORTED;
+        }
+    }
+}
+
+// Limit a read of up to `len` bytes to the free space left in the
+// pipe, if it has a fixed capacity
+#[inline]
+pub(crate) fn read_room(pwr: &PBufWr, len: usize) -> usize {
+    pwr.free_space().map_or(len, |free| free.min(len))
+}
+
+// Equivalent of `PBufWr::input_from`, but recording activity, and
+// reading no more than there is space for in a fixed-capacity pipe.
+// Data may have been read even if an error is returned.
+#[inline]
+pub(crate) fn input_from(
+    pwr: &mut PBufWr,
+    source: &mut impl Read,
+    len: usize,
+    act: &mut Activity,
+) -> Result<()> {
+    input_from_with(pwr, source, len, act, |_| ())
+}
+
+// As `input_from`, but also passing each chunk of data read to
+// `on_data`
+pub(crate) fn input_from_with(
+    pwr: &mut PBufWr,
+    source: &mut impl Read,
+    len: usize,
+    act: &mut Activity,
+    mut on_data: impl FnMut(&[u8]),
+) -> Result<()> {
+    if pwr.is_eof() {
+        return Ok(());
+    }
+
+    let mut total = 0;
+    while total < len {
+        let room = read_room(pwr, len - total);
+        if room == 0 {
+            break;
+        }
+        let rv: Result<usize> = pwr.write_with(room, |buf| {
+            let count = source.read(buf)?;
+            on_data(&buf[..count]);
+            Ok(count)
+        });
+        match rv {
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+            Ok(0) => {
+                close_in(pwr, act);
+                return Ok(());
+            }
+            Ok(count) => {
+                *act |= Activity::READ;
+                total += count;
+            }
+        }
+    }
+    Ok(())
+}
+
+// As `output_to`, but writing at most `max` bytes.  A "push" is
+// only consumed if the pipe was drained.
+pub(crate) fn output_to_max(
+    prd: &mut PBufRd,
+    sink: &mut impl Write,
+    max: usize,
+    act: &mut Activity,
+) -> Result<()> {
+    if prd.len() <= max {
+        return output_to(prd, sink, act);
+    }
+    let mut total = 0;
+    while total < max {
+        let len = prd.len().min(max - total);
+        match sink.write(&prd.data()[..len]) {
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(count) => {
+                prd.consume(count);
+                *act |= Activity::WROTE;
+                total += count;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Equivalent of `PBufRd::output_to`, but recording activity.  Data
+// may have been written even if an error is returned.
+pub(crate) fn output_to(prd: &mut PBufRd, sink: &mut impl Write, act: &mut Activity) -> Result<()> {
+    let len = prd.len();
+    let rv = prd.output_to(sink, false);
+    if prd.len() < len {
+        *act |= Activity::WROTE;
+    }
+    check_write_zero(prd, rv)
+}
+
+// `PBufRd::output_to` only stops without an error when the pipe is
+// empty or the sink returns `Ok(0)`.  The latter means that the sink
+// can't accept data, and would make no progress however often it was
+// retried, so convert it into a `WriteZero` error.
+#[inline]
+pub(crate) fn check_write_zero(prd: &PBufRd, rv: Result<()>) -> Result<()> {
+    match rv {
+        Ok(()) if !prd.is_empty() => Err(ErrorKind::WriteZero.into()),
+        rv => rv,
+    }
+}
@@ -0,0 +1,139 @@
+// Deferred changes to the kernel socket buffer sizes, shared by the
+// TCP and Unix stream links
+
+use crate::trace::Tracer;
+use crate::{Direction, Error, Op, Result};
+use std::io::{self, ErrorKind};
+#[cfg(target_family = "unix")]
+use std::os::unix::io::AsRawFd;
+
+// A socket whose kernel buffer sizes can be changed and queried
+pub(crate) trait SocketBuffers {
+    fn set_recv_buffer_size(&self, size: usize) -> io::Result<()>;
+    fn set_send_buffer_size(&self, size: usize) -> io::Result<()>;
+    fn recv_buffer_size(&self) -> io::Result<usize>;
+    fn send_buffer_size(&self) -> io::Result<usize>;
+}
+
+macro_rules! impl_socket_buffers {
+    ($($stream:ty),*) => {
+        $(
+            #[cfg(target_family = "unix")]
+            impl SocketBuffers for $stream {
+                fn set_recv_buffer_size(&self, size: usize) -> io::Result<()> {
+                    crate::sockopt::set_buffer_size(self.as_raw_fd(), libc::SO_RCVBUF, size)
+                }
+                fn set_send_buffer_size(&self, size: usize) -> io::Result<()> {
+                    crate::sockopt::set_buffer_size(self.as_raw_fd(), libc::SO_SNDBUF, size)
+                }
+                fn recv_buffer_size(&self) -> io::Result<usize> {
+                    crate::sockopt::buffer_size(self.as_raw_fd(), libc::SO_RCVBUF)
+                }
+                fn send_buffer_size(&self) -> io::Result<usize> {
+                    crate::sockopt::buffer_size(self.as_raw_fd(), libc::SO_SNDBUF)
+                }
+            }
+
+            #[cfg(not(target_family = "unix"))]
+            impl SocketBuffers for $stream {
+                fn set_recv_buffer_size(&self, _size: usize) -> io::Result<()> {
+                    Err(crate::sockopt::unsupported("Socket buffer size"))
+                }
+                fn set_send_buffer_size(&self, _size: usize) -> io::Result<()> {
+                    Err(crate::sockopt::unsupported("Socket buffer size"))
+                }
+                fn recv_buffer_size(&self) -> io::Result<usize> {
+                    Err(crate::sockopt::unsupported("Socket buffer size"))
+                }
+                fn send_buffer_size(&self) -> io::Result<usize> {
+                    Err(crate::sockopt::unsupported("Socket buffer size"))
+                }
+            }
+        )*
+    };
+}
+
+impl_socket_buffers!(mio::net::TcpStream);
+#[cfg(target_family = "unix")]
+impl_socket_buffers!(mio::net::UnixStream);
+#[cfg(feature = "std-net")]
+impl_socket_buffers!(std::net::TcpStream);
+
+// Through a shared reference, as used by the split TCP links
+impl SocketBuffers for &mio::net::TcpStream {
+    fn set_recv_buffer_size(&self, size: usize) -> io::Result<()> {
+        (*self).set_recv_buffer_size(size)
+    }
+    fn set_send_buffer_size(&self, size: usize) -> io::Result<()> {
+        (*self).set_send_buffer_size(size)
+    }
+    fn recv_buffer_size(&self) -> io::Result<usize> {
+        (*self).recv_buffer_size()
+    }
+    fn send_buffer_size(&self) -> io::Result<usize> {
+        (*self).send_buffer_size()
+    }
+}
+
+// Buffer sizes requested, applied on the next `process` call
+#[derive(Debug, Default)]
+pub(crate) struct BufferSizes {
+    // SO_RCVBUF requested, or `None` to leave the OS default
+    pub(crate) recv: Option<usize>,
+
+    // Pending change to SO_RCVBUF
+    pending_recv: bool,
+
+    // SO_SNDBUF requested, or `None` to leave the OS default
+    pub(crate) send: Option<usize>,
+
+    // Pending change to SO_SNDBUF
+    pending_send: bool,
+}
+
+impl BufferSizes {
+    #[inline]
+    pub(crate) fn set_recv(&mut self, size: usize) {
+        if self.recv != Some(size) {
+            self.recv = Some(size);
+            self.pending_recv = true;
+        }
+    }
+
+    #[inline]
+    pub(crate) fn set_send(&mut self, size: usize) {
+        if self.send != Some(size) {
+            self.send = Some(size);
+            self.pending_send = true;
+        }
+    }
+
+    // Test whether any changes are waiting to be applied
+    #[inline]
+    pub(crate) fn has_pending(&self) -> bool {
+        self.pending_recv || self.pending_send
+    }
+
+    // Apply any pending changes.  Each change is only attempted once.
+    pub(crate) fn apply(&mut self, stream: &impl SocketBuffers, tracer: &Tracer) -> Result<()> {
+        if self.pending_recv {
+            self.pending_recv = false;
+            if let Some(size) = self.recv {
+                retry!(stream.set_recv_buffer_size(size))
+                    .map_err(|e| Error::new(Direction::Incoming, Op::SetOption, e))
+                    .inspect_err(|e| tracer.error(e))?;
+                tracer.option("recv_buffer_size", size);
+            }
+        }
+        if self.pending_send {
+            self.pending_send = false;
+            if let Some(size) = self.send {
+                retry!(stream.set_send_buffer_size(size))
+                    .map_err(|e| Error::new(Direction::Outgoing, Op::SetOption, e))
+                    .inspect_err(|e| tracer.error(e))?;
+                tracer.option("send_buffer_size", size);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+use crate::{Activity, Result};
+use crate::{PipeReceiverLink, PipeSenderLink};
+use mio::unix::pipe::{Receiver, Sender};
+use mio::{Interest, Registry, Token};
+use pipebuf::{PBufRd, PBufWr};
+use std::io;
+use std::process::Child;
+
+/// Exchange data with a child process via its standard streams
+///
+/// This takes ownership of whichever of the child's stdin, stdout
+/// and stderr were set up as pipes (using `Stdio::piped()`), and
+/// converts them into non-blocking `mio` pipe endpoints.  Data from
+/// the `stdin` pipe-buffer is written to the child's stdin, and
+/// output from the child's stdout and stderr is written to the
+/// `stdout` and `stderr` pipe-buffers.
+///
+/// Each stream is handled independently, and closed and dropped as
+/// soon as it is finished.  See [`PipeSenderLink`] and
+/// [`PipeReceiverLink`] for the details.  If the child exits before
+/// all of the stdin data has been written, then the remaining data is
+/// discarded, and this is reported by [`ChildLink::is_stdin_broken`].
+/// Streams that were not set up as pipes are treated as already
+/// closed, and any data for stdin is discarded.
+///
+/// Once [`ChildLink::is_done`] returns `true`, all the streams have
+/// closed, and the caller should `wait()` on the child to collect its
+/// exit status.
+#[derive(Debug)]
+pub struct ChildLink {
+    stdin: Option<Sender>,
+    stdout: Option<Receiver>,
+    stderr: Option<Receiver>,
+    stdin_link: PipeSenderLink,
+    stdout_link: PipeReceiverLink,
+    stderr_link: PipeReceiverLink,
+}
+
+impl ChildLink {
+    /// Take the piped standard streams from the given child and set
+    /// them to non-blocking mode
+    pub fn new(child: &mut Child) -> io::Result<Self> {
+        let stdin = child.stdin.take().map(Sender::from);
+        let stdout = child.stdout.take().map(Receiver::from);
+        let stderr = child.stderr.take().map(Receiver::from);
+        if let Some(ref s) = stdin {
+            s.set_nonblocking(true)?;
+        }
+        for r in [&stdout, &stderr].into_iter().flatten() {
+            r.set_nonblocking(true)?;
+        }
+        Ok(Self {
+            stdin,
+            stdout,
+            stderr,
+            stdin_link: PipeSenderLink::new(),
+            stdout_link: PipeReceiverLink::new(),
+            stderr_link: PipeReceiverLink::new(),
+        })
+    }
+
+    /// Change the maximum number of bytes to read from each of
+    /// stdout and stderr in each `process` call
+    #[inline]
+    pub fn set_max_read_unit(&mut self, max_read_unit: usize) {
+        self.stdout_link.set_max_read_unit(max_read_unit);
+        self.stderr_link.set_max_read_unit(max_read_unit);
+    }
+
+    /// Register those streams which are still open with the given
+    /// `Registry`, using a different token for each stream
+    pub fn register(
+        &mut self,
+        registry: &Registry,
+        stdin: Token,
+        stdout: Token,
+        stderr: Token,
+    ) -> io::Result<()> {
+        if let Some(ref mut s) = self.stdin {
+            registry.register(s, stdin, Interest::WRITABLE)?;
+        }
+        if let Some(ref mut r) = self.stdout {
+            registry.register(r, stdout, Interest::READABLE)?;
+        }
+        if let Some(ref mut r) = self.stderr {
+            registry.register(r, stderr, Interest::READABLE)?;
+        }
+        Ok(())
+    }
+
+    /// Deregister those streams which are still open from the given
+    /// `Registry`.  Streams which have already been closed were
+    /// removed from the `Registry` automatically when they closed.
+    pub fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        if let Some(ref mut s) = self.stdin {
+            registry.deregister(s)?;
+        }
+        if let Some(ref mut r) = self.stdout {
+            registry.deregister(r)?;
+        }
+        if let Some(ref mut r) = self.stderr {
+            registry.deregister(r)?;
+        }
+        Ok(())
+    }
+
+    /// Transfer as much data as possible to and from the child
+    /// process's streams.  Returns the activity flags (see
+    /// [`Activity`]), which are empty if no progress could be made,
+    /// or `Err(_)` if there was a fatal error on one of the streams.
+    ///
+    /// Assumes that it is always called with the same pipe-buffers.
+    /// Things will behave unpredictably otherwise.
+    pub fn process(&mut self, stdin: PBufRd, stdout: PBufWr, stderr: PBufWr) -> Result<Activity> {
+        let mut activity = self.stdin_link.process_out(&mut self.stdin, stdin)?;
+        activity |= Self::process_receiver(&mut self.stdout_link, &mut self.stdout, stdout)?;
+        activity |= Self::process_receiver(&mut self.stderr_link, &mut self.stderr, stderr)?;
+        Ok(activity)
+    }
+
+    fn process_receiver(
+        link: &mut PipeReceiverLink,
+        rx: &mut Option<Receiver>,
+        mut pwr: PBufWr,
+    ) -> Result<Activity> {
+        let Some(receiver) = rx else {
+            return Ok(Activity::NONE);
+        };
+        let activity = link.process_in(receiver, pwr.reborrow())?;
+        if pwr.is_eof() {
+            *rx = None;
+        }
+        Ok(activity)
+    }
+
+    /// Test whether the child went away before all the stdin data
+    /// could be written
+    #[inline]
+    pub fn is_stdin_broken(&self) -> bool {
+        self.stdin_link.is_broken()
+    }
+
+    /// Test whether all the streams have closed.  In that case the
+    /// caller should `wait()` on the child.
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        self.stdin.is_none() && self.stdout.is_none() && self.stderr.is_none()
+    }
+}
@@ -0,0 +1,274 @@
+use crate::TcpLink;
+#[cfg(target_family = "unix")]
+use crate::UnixStreamLink;
+use crate::{Activity, Result};
+use mio::event::Event;
+use mio::net::TcpStream;
+#[cfg(target_family = "unix")]
+use mio::net::UnixStream;
+use mio::{Interest, Registry, Token};
+use pipebuf::{PBufRdWr, PipeBufPair};
+use std::io;
+use std::net::SocketAddr;
+#[cfg(target_family = "unix")]
+use std::path::Path;
+
+/// A TCP connection bundling a `mio` [`TcpStream`], a [`TcpLink`]
+/// and the pipe-buffers between the link and the application
+///
+/// The link uses the "lower" end of the [`PipeBufPair`], and the
+/// application-side protocol code uses the "upper" end, obtained with
+/// [`TcpConn::pbuf`].
+///
+/// Reads and writes are unpaused automatically on the first readable
+/// or writable event passed to [`TcpConn::process_event`].  Call
+/// `process_event` for each event on the connection's token, and call
+/// [`TcpConn::process`] after the application has written data or
+/// changed the state of the pipes.
+pub struct TcpConn {
+    stream: TcpStream,
+    link: TcpLink,
+    pipes: PipeBufPair,
+}
+
+impl TcpConn {
+    /// Wrap an existing stream, for example one that has been
+    /// accepted.  Reads and writes start paused until the first
+    /// event.
+    #[inline]
+    pub fn new(stream: TcpStream) -> Self {
+        Self::with_link(stream, TcpLink::new())
+    }
+
+    /// Wrap an existing stream, using the given link, for example
+    /// one created with custom settings
+    #[inline]
+    pub fn with_link(stream: TcpStream, link: TcpLink) -> Self {
+        Self {
+            stream,
+            link,
+            pipes: PipeBufPair::new(),
+        }
+    }
+
+    /// Start a non-blocking connect to the given address.  Register
+    /// the connection for writable events to be told when the connect
+    /// has completed.  See also [`crate::TcpConnector`].
+    #[inline]
+    pub fn connect(addr: SocketAddr) -> io::Result<Self> {
+        Ok(Self::new(TcpStream::connect(addr)?))
+    }
+
+    /// Register the stream with the given token and interest
+    #[inline]
+    pub fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interest: Interest,
+    ) -> io::Result<()> {
+        registry.register(&mut self.stream, token, interest)
+    }
+
+    /// Change the token or interest of the registered stream
+    #[inline]
+    pub fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interest: Interest,
+    ) -> io::Result<()> {
+        registry.reregister(&mut self.stream, token, interest)
+    }
+
+    /// Deregister the stream
+    #[inline]
+    pub fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        registry.deregister(&mut self.stream)
+    }
+
+    /// Get the application-side end of the pipe-buffers
+    #[inline]
+    pub fn pbuf(&mut self) -> PBufRdWr<'_> {
+        self.pipes.upper()
+    }
+
+    /// Get access to the pipe-buffers, for example to reset them
+    #[inline]
+    pub fn pipes(&mut self) -> &mut PipeBufPair {
+        &mut self.pipes
+    }
+
+    /// Get access to the stream, for example to query addresses
+    #[inline]
+    pub fn stream(&self) -> &TcpStream {
+        &self.stream
+    }
+
+    /// Get access to the link, to change settings
+    #[inline]
+    pub fn link(&mut self) -> &mut TcpLink {
+        &mut self.link
+    }
+
+    /// Unpause reads or writes on the first readable or writable
+    /// event, and then read and write as much data as possible.  See
+    /// [`TcpLink::process_event`].  Returns the activity status as for
+    /// [`TcpConn::process`].
+    pub fn process_event(&mut self, event: &Event) -> Result<Activity> {
+        self.link
+            .process_event(&mut self.stream, self.pipes.lower(), event)
+    }
+
+    /// Read and write as much data as possible to and from the
+    /// stream.  Returns the activity flags (see [`Activity`]), which
+    /// are empty if no progress could be made, or `Err(_)` if there
+    /// was a fatal error on the stream.
+    #[inline]
+    pub fn process(&mut self) -> Result<Activity> {
+        self.link.process(&mut self.stream, self.pipes.lower())
+    }
+
+    /// Test whether the connection is finished, i.e. EOF has been
+    /// passed through and consumed in both directions
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        self.pipes.down.is_done() && self.pipes.up.is_done()
+    }
+}
+
+/// A Unix stream connection bundling a `mio` [`UnixStream`], a
+/// [`UnixStreamLink`] and the pipe-buffers between the link and the
+/// application
+///
+/// This works like [`TcpConn`].  In addition, the registry is kept
+/// while the stream is registered, so that the stream can be
+/// deregistered automatically when the `UnixConn` is dropped.
+#[cfg(target_family = "unix")]
+pub struct UnixConn {
+    stream: UnixStream,
+    link: UnixStreamLink,
+    pipes: PipeBufPair,
+
+    // Registry, whilst registered
+    registry: Option<Registry>,
+}
+
+#[cfg(target_family = "unix")]
+impl UnixConn {
+    /// Wrap an existing stream, for example one that has been
+    /// accepted.  Reads and writes start paused until the first
+    /// event.
+    #[inline]
+    pub fn new(stream: UnixStream) -> Self {
+        Self::with_link(stream, UnixStreamLink::new())
+    }
+
+    /// Wrap an existing stream, using the given link, for example
+    /// one created with custom settings
+    #[inline]
+    pub fn with_link(stream: UnixStream, link: UnixStreamLink) -> Self {
+        Self {
+            stream,
+            link,
+            pipes: PipeBufPair::new(),
+            registry: None,
+        }
+    }
+
+    /// Start a non-blocking connect to the socket at the given path.
+    /// See also [`crate::UnixConnector`].
+    #[inline]
+    pub fn connect(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(UnixStream::connect(path)?))
+    }
+
+    /// Register the stream with the given token and interest
+    pub fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interest: Interest,
+    ) -> io::Result<()> {
+        let clone = registry.try_clone()?;
+        registry.register(&mut self.stream, token, interest)?;
+        self.registry = Some(clone);
+        Ok(())
+    }
+
+    /// Change the token or interest of the registered stream
+    #[inline]
+    pub fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interest: Interest,
+    ) -> io::Result<()> {
+        registry.reregister(&mut self.stream, token, interest)
+    }
+
+    /// Deregister the stream
+    #[inline]
+    pub fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        self.registry = None;
+        registry.deregister(&mut self.stream)
+    }
+
+    /// Get the application-side end of the pipe-buffers
+    #[inline]
+    pub fn pbuf(&mut self) -> PBufRdWr<'_> {
+        self.pipes.upper()
+    }
+
+    /// Get access to the pipe-buffers, for example to reset them
+    #[inline]
+    pub fn pipes(&mut self) -> &mut PipeBufPair {
+        &mut self.pipes
+    }
+
+    /// Get access to the stream, for example to query addresses
+    #[inline]
+    pub fn stream(&self) -> &UnixStream {
+        &self.stream
+    }
+
+    /// Get access to the link, to change settings
+    #[inline]
+    pub fn link(&mut self) -> &mut UnixStreamLink {
+        &mut self.link
+    }
+
+    /// Unpause reads or writes on the first readable or writable
+    /// event, and then read and write as much data as possible.  See
+    /// [`UnixStreamLink::process_event`].  Returns the activity
+    /// status as for [`UnixConn::process`].
+    pub fn process_event(&mut self, event: &Event) -> Result<Activity> {
+        self.link
+            .process_event(&mut self.stream, self.pipes.lower(), event)
+    }
+
+    /// Read and write as much data as possible to and from the
+    /// stream.  Returns the activity flags (see [`Activity`]), which
+    /// are empty if no progress could be made, or `Err(_)` if there
+    /// was a fatal error on the stream.
+    #[inline]
+    pub fn process(&mut self) -> Result<Activity> {
+        self.link.process(&mut self.stream, self.pipes.lower())
+    }
+
+    /// Test whether the connection is finished, i.e. EOF has been
+    /// passed through and consumed in both directions
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        self.pipes.down.is_done() && self.pipes.up.is_done()
+    }
+}
+
+#[cfg(target_family = "unix")]
+impl Drop for UnixConn {
+    fn drop(&mut self) {
+        if let Some(registry) = self.registry.take() {
+            let _ = registry.deregister(&mut self.stream);
+        }
+    }
+}
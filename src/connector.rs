@@ -0,0 +1,447 @@
+use crate::TcpLink;
+#[cfg(target_family = "unix")]
+use crate::UnixStreamLink;
+use mio::event::Event;
+use mio::net::TcpStream;
+#[cfg(target_family = "unix")]
+use mio::net::UnixStream;
+use mio::{Interest, Registry, Token};
+use std::io::{Error, ErrorKind, Result};
+use std::net::SocketAddr;
+#[cfg(target_family = "unix")]
+use std::path::{Path, PathBuf};
+
+/// Status of a non-blocking connect, as returned by
+/// [`TcpConnector::process`] and `UnixConnector::process`
+pub enum ConnectStatus<S, L> {
+    /// The connection is still in progress
+    Pending,
+
+    /// The connection has completed.  The stream is still registered
+    /// with the token passed to `register`, and the link has reads and
+    /// writes unpaused, ready for use.
+    Connected(S, L),
+
+    /// The connection failed.  The stream has been dropped.
+    Failed(Error),
+}
+
+/// Carry out a non-blocking connect on a `mio` [`TcpStream`]
+///
+/// Call [`TcpConnector::register`] to start the connect and register
+/// the stream, and then pass each event for the stream's token to
+/// [`TcpConnector::process`].  Once the connection has completed,
+/// this hands back the `TcpStream` along with a [`TcpLink`] with
+/// reads and writes already unpaused.  The checks recommended by
+/// `mio` are made on each event: `take_error` to pick up a failed
+/// connect, and then `peer_addr` to distinguish a completed connect
+/// from a spurious wake-up, which is reported as still pending.
+///
+/// No timeout is applied here.  If the caller wishes to give up on
+/// the connect, it should call [`TcpConnector::deregister`] or just
+/// drop the connector.
+pub struct TcpConnector {
+    addr: SocketAddr,
+    token: Option<Token>,
+    stream: Option<TcpStream>,
+    mss: Option<u32>,
+}
+
+impl TcpConnector {
+    /// Create a connector for the given address.  The connect is not
+    /// started until `register` is called.
+    #[inline]
+    pub fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            token: None,
+            stream: None,
+            mss: None,
+        }
+    }
+
+    /// Clamp the TCP maximum segment size with `TCP_MAXSEG` before the
+    /// connect is started, so that it is taken into account for the
+    /// SYN on all platforms, or pass `None` to leave the OS default.
+    /// See [`TcpLink::set_mss`] for changing it after the connect.
+    ///
+    /// This is only supported on Unix.  Elsewhere `register` fails
+    /// with `Unsupported`.
+    #[inline]
+    pub fn set_mss(&mut self, mss: Option<u32>) {
+        self.mss = mss;
+    }
+
+    /// Get the address being connected to
+    #[inline]
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Start the connect and register the stream for both read and
+    /// write readiness with the given token.  If the connect fails
+    /// immediately, the error is returned here.
+    pub fn register(&mut self, registry: &Registry, token: Token) -> Result<()> {
+        let mut stream = match self.mss {
+            Some(mss) => connect_with_mss(self.addr, mss)?,
+            None => TcpStream::connect(self.addr)?,
+        };
+        registry.register(&mut stream, token, Interest::READABLE | Interest::WRITABLE)?;
+        self.stream = Some(stream);
+        self.token = Some(token);
+        Ok(())
+    }
+
+    /// Deregister and drop the stream, abandoning the connect
+    pub fn deregister(&mut self, registry: &Registry) -> Result<()> {
+        self.token = None;
+        match self.stream.take() {
+            Some(mut stream) => registry.deregister(&mut stream),
+            None => Ok(()),
+        }
+    }
+
+    /// Check the progress of the connect on an event.  Events for
+    /// other tokens are ignored.  Once `Connected` or `Failed` has
+    /// been returned, the connector has finished, and any further
+    /// call returns a `NotConnected` failure until `register` is
+    /// called again.
+    pub fn process(&mut self, event: &Event) -> ConnectStatus<TcpStream, TcpLink> {
+        if self.stream.is_some() && Some(event.token()) != self.token {
+            return ConnectStatus::Pending;
+        }
+        match check_connect(self.stream.as_ref(), |s| s.take_error(), |s| s.peer_addr()) {
+            Ok(false) => ConnectStatus::Pending,
+            Ok(true) => {
+                let mut link = TcpLink::new();
+                link.set_pause_reads(false);
+                link.set_pause_writes(false);
+                ConnectStatus::Connected(self.stream.take().unwrap(), link)
+            }
+            Err(e) => {
+                self.stream = None;
+                ConnectStatus::Failed(e)
+            }
+        }
+    }
+}
+
+// Start a connect with `TCP_MAXSEG` set on the socket beforehand
+#[cfg(target_family = "unix")]
+fn connect_with_mss(addr: SocketAddr, mss: u32) -> Result<TcpStream> {
+    crate::sockopt::connect_with_mss(addr, mss).map(TcpStream::from_std)
+}
+
+#[cfg(not(target_family = "unix"))]
+fn connect_with_mss(_addr: SocketAddr, _mss: u32) -> Result<TcpStream> {
+    Err(crate::sockopt::unsupported("TCP_MAXSEG"))
+}
+
+/// Carry out a non-blocking connect on a `mio` [`UnixStream`]
+///
+/// This works like [`TcpConnector`], and hands back the `UnixStream`
+/// along with a [`UnixStreamLink`] with reads and writes already
+/// unpaused.  On Linux a Unix-domain connect usually completes
+/// immediately, but the completion is still reported via
+/// [`UnixConnector::process`] on the first event, so the caller
+/// doesn't need to handle two cases.
+///
+/// Errors from the connect itself are returned from
+/// [`UnixConnector::register`] unchanged, so that they can be told
+/// apart: `NotFound` if there is no socket at the path,
+/// `ConnectionRefused` if there is no listener on it, and
+/// `WouldBlock` if the listener's backlog is full.  In the last case
+/// the connect can be retried later by calling `register` again.
+#[cfg(target_family = "unix")]
+pub struct UnixConnector {
+    path: PathBuf,
+    token: Option<Token>,
+    stream: Option<UnixStream>,
+}
+
+#[cfg(target_family = "unix")]
+impl UnixConnector {
+    /// Create a connector for the given socket path.  The connect is
+    /// not started until `register` is called.
+    #[inline]
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            token: None,
+            stream: None,
+        }
+    }
+
+    /// Get the socket path being connected to
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Start the connect and register the stream for both read and
+    /// write readiness with the given token.  If the connect fails
+    /// immediately, the error is returned here.
+    pub fn register(&mut self, registry: &Registry, token: Token) -> Result<()> {
+        let mut stream = UnixStream::connect(&self.path)?;
+        registry.register(&mut stream, token, Interest::READABLE | Interest::WRITABLE)?;
+        self.stream = Some(stream);
+        self.token = Some(token);
+        Ok(())
+    }
+
+    /// Deregister and drop the stream, abandoning the connect
+    pub fn deregister(&mut self, registry: &Registry) -> Result<()> {
+        self.token = None;
+        match self.stream.take() {
+            Some(mut stream) => registry.deregister(&mut stream),
+            None => Ok(()),
+        }
+    }
+
+    /// Check the progress of the connect on an event.  Events for
+    /// other tokens are ignored.  Once `Connected` or `Failed` has
+    /// been returned, the connector has finished, and any further
+    /// call returns a `NotConnected` failure until `register` is
+    /// called again.
+    pub fn process(&mut self, event: &Event) -> ConnectStatus<UnixStream, UnixStreamLink> {
+        if self.stream.is_some() && Some(event.token()) != self.token {
+            return ConnectStatus::Pending;
+        }
+        match check_connect(self.stream.as_ref(), |s| s.take_error(), |s| s.peer_addr()) {
+            Ok(false) => ConnectStatus::Pending,
+            Ok(true) => {
+                let mut link = UnixStreamLink::new();
+                link.set_pause_reads(false);
+                link.set_pause_writes(false);
+                ConnectStatus::Connected(self.stream.take().unwrap(), link)
+            }
+            Err(e) => {
+                self.stream = None;
+                ConnectStatus::Failed(e)
+            }
+        }
+    }
+}
+
+// Check whether a connect has completed, following the procedure
+// recommended by `mio`.  Returns `Ok(false)` if still in progress.
+pub(crate) fn check_connect<S, A>(
+    stream: Option<&S>,
+    take_error: impl FnOnce(&S) -> Result<Option<Error>>,
+    peer_addr: impl FnOnce(&S) -> Result<A>,
+) -> Result<bool> {
+    let Some(stream) = stream else {
+        return Err(Error::new(
+            ErrorKind::NotConnected,
+            "No connect in progress",
+        ));
+    };
+    if let Some(e) = take_error(stream)? {
+        return Err(e);
+    }
+    match peer_addr(stream) {
+        Ok(_) => Ok(true),
+        Err(ref e) if e.kind() == ErrorKind::NotConnected => Ok(false),
+        Err(ref e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mio::{Events, Poll};
+    use std::time::{Duration, Instant};
+
+    const TOKEN: Token = Token(7);
+
+    // Feed events to the connector until it finishes or the timeout
+    // passes, returning `None` on timeout
+    fn run<S, L>(
+        poll: &mut Poll,
+        timeout: Duration,
+        mut process: impl FnMut(&Event) -> ConnectStatus<S, L>,
+    ) -> Option<ConnectStatus<S, L>> {
+        let mut events = Events::with_capacity(8);
+        let end = Instant::now() + timeout;
+        while let Some(left) = end.checked_duration_since(Instant::now()) {
+            poll.poll(&mut events, Some(left)).unwrap();
+            for event in events.iter() {
+                match process(event) {
+                    ConnectStatus::Pending => (),
+                    status => return Some(status),
+                }
+            }
+        }
+        None
+    }
+
+    fn failed<S, L>(status: Option<ConnectStatus<S, L>>) -> ErrorKind {
+        match status {
+            Some(ConnectStatus::Failed(e)) => e.kind(),
+            Some(ConnectStatus::Connected(..)) => panic!("Unexpected connect"),
+            _ => panic!("Connect still pending"),
+        }
+    }
+
+    #[test]
+    fn tcp_connected() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut poll = Poll::new().unwrap();
+        let mut conn = TcpConnector::new(listener.local_addr().unwrap());
+        conn.register(poll.registry(), TOKEN).unwrap();
+
+        let status = run(&mut poll, Duration::from_secs(5), |ev| conn.process(ev));
+        let Some(ConnectStatus::Connected(stream, link)) = status else {
+            panic!("Connect failed");
+        };
+        assert_eq!(stream.peer_addr().unwrap(), listener.local_addr().unwrap());
+        assert!(!link.pause_reads() && !link.pause_writes());
+
+        // Finished, so further events are refused
+        let status = run(&mut poll, Duration::from_millis(50), |ev| conn.process(ev));
+        assert!(status.is_none() || failed(status) == ErrorKind::NotConnected);
+    }
+
+    #[test]
+    fn tcp_refused() {
+        let addr = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap()
+        };
+        let mut poll = Poll::new().unwrap();
+        let mut conn = TcpConnector::new(addr);
+
+        // Some platforms report the refusal at once, others on the
+        // first event
+        match conn.register(poll.registry(), TOKEN) {
+            Err(e) => assert_eq!(e.kind(), ErrorKind::ConnectionRefused),
+            Ok(()) => {
+                let status = run(&mut poll, Duration::from_secs(5), |ev| conn.process(ev));
+                assert_eq!(failed(status), ErrorKind::ConnectionRefused);
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn tcp_caller_timeout() {
+        // A listener that never accepts, with its backlog already full,
+        // drops the SYN so the connect stays in progress
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        crate::testutil::shrink_backlog(&listener);
+        let addr = listener.local_addr().unwrap();
+        let mut poll = Poll::new().unwrap();
+        let mut filler = TcpConnector::new(addr);
+        filler.register(poll.registry(), Token(1)).unwrap();
+        let status = run(&mut poll, Duration::from_secs(5), |ev| filler.process(ev));
+        assert!(matches!(status, Some(ConnectStatus::Connected(..))));
+
+        let mut conn = TcpConnector::new(addr);
+        conn.register(poll.registry(), TOKEN).unwrap();
+        let status = run(&mut poll, Duration::from_millis(300), |ev| conn.process(ev));
+        assert!(status.is_none());
+
+        // The caller gives up
+        conn.deregister(poll.registry()).unwrap();
+        let status = run(&mut poll, Duration::from_millis(50), |ev| conn.process(ev));
+        assert!(status.is_none());
+    }
+
+    #[test]
+    fn check_connect_quirks() {
+        let unused = |_: &()| -> Result<()> { unreachable!() };
+        let no_error = |_: &()| Ok(None);
+
+        // No connect in progress
+        let rv = check_connect(None, no_error, unused);
+        assert_eq!(rv.unwrap_err().kind(), ErrorKind::NotConnected);
+
+        // A pending socket error wins over anything else
+        let refused = |_: &()| Ok(Some(Error::from(ErrorKind::ConnectionRefused)));
+        let rv = check_connect(Some(&()), refused, unused);
+        assert_eq!(rv.unwrap_err().kind(), ErrorKind::ConnectionRefused);
+
+        // Spurious wake-ups while still in progress (EINPROGRESS)
+        for kind in [ErrorKind::NotConnected, ErrorKind::WouldBlock] {
+            let rv = check_connect(Some(&()), no_error, |_| Err::<(), _>(Error::from(kind)));
+            assert!(!rv.unwrap());
+        }
+
+        // Other errors from `peer_addr` are failures
+        let rv = check_connect(Some(&()), no_error, |_| {
+            Err::<(), _>(Error::from(ErrorKind::ConnectionReset))
+        });
+        assert_eq!(rv.unwrap_err().kind(), ErrorKind::ConnectionReset);
+
+        assert!(check_connect(Some(&()), no_error, |_| Ok(())).unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_connected_on_first_event() {
+        let path = crate::testutil::socket_path("connector");
+        let _listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let mut poll = Poll::new().unwrap();
+        let mut conn = UnixConnector::new(&path);
+        conn.register(poll.registry(), TOKEN).unwrap();
+
+        let status = run(&mut poll, Duration::from_secs(5), |ev| conn.process(ev));
+        let Some(ConnectStatus::Connected(_stream, link)) = status else {
+            panic!("Connect failed");
+        };
+        assert!(!link.pause_reads() && !link.pause_writes());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_missing_path() {
+        let path = crate::testutil::socket_path("missing");
+        let poll = Poll::new().unwrap();
+        let mut conn = UnixConnector::new(&path);
+        let err = conn.register(poll.registry(), TOKEN).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_no_listener() {
+        let path = crate::testutil::socket_path("stale");
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let poll = Poll::new().unwrap();
+        let mut conn = UnixConnector::new(&path);
+        let err = conn.register(poll.registry(), TOKEN).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn unix_full_backlog() {
+        let path = crate::testutil::socket_path("backlog");
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        crate::testutil::shrink_backlog(&listener);
+        let mut poll = Poll::new().unwrap();
+
+        // Fill the backlog, keeping the connections open
+        let mut held = Vec::new();
+        let err = loop {
+            let mut conn = UnixConnector::new(&path);
+            match conn.register(poll.registry(), Token(held.len() + 100)) {
+                Ok(()) => held.push(conn),
+                Err(e) => break e,
+            }
+            assert!(held.len() < 100, "backlog never filled");
+        };
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+        // Once there is room again, a retry succeeds
+        let _accepted = listener.accept().unwrap();
+        let mut conn = UnixConnector::new(&path);
+        conn.register(poll.registry(), TOKEN).unwrap();
+        let status = run(&mut poll, Duration::from_secs(5), |ev| conn.process(ev));
+        assert!(matches!(status, Some(ConnectStatus::Connected(..))));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
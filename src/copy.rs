@@ -0,0 +1,100 @@
+use crate::activity::{abort_in, check_write_zero, input_from};
+use crate::{Activity, Error, Result};
+use pipebuf::{PBufRd, PBufWr};
+use std::io::{ErrorKind, Read, Write};
+
+/// Status returned by [`copy_in`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InStatus {
+    /// The source has no more data available right now
+    /// (`WouldBlock`).  Some data may have been read before this.
+    WouldBlock,
+
+    /// The limit on the number of bytes to read was reached.  More
+    /// data may be available.
+    Limit,
+
+    /// The source indicated EOF, or the pipe was already closed.  The
+    /// pipe has been closed.
+    Closed,
+
+    /// The source reported that the connection was reset or aborted.
+    /// The pipe has been aborted.
+    Aborted,
+}
+
+/// Status returned by [`copy_out`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutStatus {
+    /// The sink can't accept more data right now (`WouldBlock`).
+    /// Some data may have been written before this.
+    WouldBlock,
+
+    /// All the data in the pipe has been written, and there is no
+    /// EOF to pass on
+    Flushed,
+
+    /// All the data has been written, and a "close" EOF has been
+    /// consumed from the pipe.  The caller should pass this on, for
+    /// example with a shutdown.
+    Closed,
+
+    /// An "abort" EOF has been consumed from the pipe.  The caller
+    /// should pass this on, for example by tearing down the
+    /// connection.
+    Aborted,
+}
+
+/// Read from a non-blocking source into a pipe, up to `max` bytes
+///
+/// This handles the details that the links in this crate rely on:
+/// `Interrupted` errors are retried, `WouldBlock` is not an error, a
+/// zero-length read is passed on as a "close" of the pipe, and
+/// `ConnectionReset` or `ConnectionAborted` is passed on as an
+/// "abort" of the pipe.  Any other error is returned, with
+/// [`crate::Op::Read`].  This makes it possible to build links for
+/// other `Read` types, such as `mio` sources not covered by this
+/// crate, or wrappers like TLS streams.
+pub fn copy_in<R: Read>(source: &mut R, mut pwr: PBufWr, max: usize) -> Result<InStatus> {
+    if pwr.is_eof() {
+        return Ok(InStatus::Closed);
+    }
+    let mut act = Activity::NONE;
+    match input_from(&mut pwr, source, max, &mut act) {
+        Ok(()) if pwr.is_eof() => Ok(InStatus::Closed),
+        Ok(()) => Ok(InStatus::Limit),
+        Err(e) => match e.kind() {
+            ErrorKind::WouldBlock => Ok(InStatus::WouldBlock),
+            ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted => {
+                abort_in(&mut pwr, &mut act);
+                Ok(InStatus::Aborted)
+            }
+            _ => Err(Error::read(e)),
+        },
+    }
+}
+
+/// Write as much data as possible from a pipe to a non-blocking sink
+///
+/// `Interrupted` errors are retried, and `WouldBlock` is not an
+/// error.  Once all the data has been written, any pending EOF is
+/// consumed from the pipe and reported as [`OutStatus::Closed`] or
+/// [`OutStatus::Aborted`], so that the caller can pass it on in
+/// whatever way suits the sink.  This is reported only once.  Any
+/// other error is returned, with [`crate::Op::Write`].  A sink that
+/// accepts no data by returning `Ok(0)` gives a `WriteZero` error.
+pub fn copy_out<W: Write>(sink: &mut W, mut prd: PBufRd) -> Result<OutStatus> {
+    let rv = prd.output_to(sink, false);
+    match check_write_zero(&prd, rv) {
+        Err(ref e) if e.kind() == ErrorKind::WouldBlock => Ok(OutStatus::WouldBlock),
+        Err(e) => Err(Error::write(e)),
+        Ok(()) if prd.is_empty() && prd.consume_eof() => {
+            if prd.is_aborted() {
+                Ok(OutStatus::Aborted)
+            } else {
+                Ok(OutStatus::Closed)
+            }
+        }
+        Ok(()) => Ok(OutStatus::Flushed),
+    }
+}
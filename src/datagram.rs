@@ -0,0 +1,230 @@
+#[cfg(target_family = "unix")]
+use crate::UnixDatagramLink;
+use crate::{Activity, Result};
+use crate::{MulticastLink, UdpLink, UdpPeerLink};
+use mio::net::UdpSocket;
+#[cfg(target_family = "unix")]
+use mio::net::UnixDatagram;
+use pipebuf::PBufRdWr;
+
+/// Common interface to the datagram links
+///
+/// This allows generic code to drive links for different datagram
+/// socket types uniformly, either as a generic parameter or as a
+/// `Box<dyn DatagramLink<Socket = ...>>`.  All these links use the
+/// same record framing, as described in the [`crate::record`]
+/// module.  The methods behave as documented on each link type.
+pub trait DatagramLink {
+    /// The socket type handled by the link
+    type Socket;
+
+    /// Change the maximum number of bytes of records to read in each
+    /// `process` call
+    fn set_max_read_unit(&mut self, max_read_unit: usize);
+
+    /// Change the maximum size of a datagram that will be accepted.
+    /// Larger datagrams are discarded.
+    fn set_max_datagram_size(&mut self, max_datagram_size: usize);
+
+    /// Pause or unpause writes.  This takes effect on the next
+    /// `process` call.
+    fn set_pause_writes(&mut self, pause: bool);
+
+    /// Pause or unpause reads.  This takes effect on the next
+    /// `process` call.
+    fn set_pause_reads(&mut self, pause: bool);
+
+    /// Send and receive as many datagrams as possible.  Returns the
+    /// activity status.
+    fn process(&mut self, sock: &mut Self::Socket, pbuf: PBufRdWr) -> Result<Activity>;
+
+    /// Send as many complete records as possible from the outgoing
+    /// pipe, one datagram per record.  Returns the activity status.
+    fn process_out(&mut self, sock: &mut Self::Socket, pbuf: PBufRdWr) -> Result<Activity>;
+
+    /// Receive as many datagrams as possible, writing each as a
+    /// record to the incoming pipe.  Returns the activity status.
+    fn process_in(&mut self, sock: &mut Self::Socket, pbuf: PBufRdWr) -> Result<Activity>;
+}
+
+macro_rules! impl_datagram_link {
+    ($link:ty, $sock:ty) => {
+        impl DatagramLink for $link {
+            type Socket = $sock;
+
+            #[inline]
+            fn set_max_read_unit(&mut self, max_read_unit: usize) {
+                <$link>::set_max_read_unit(self, max_read_unit)
+            }
+            #[inline]
+            fn set_max_datagram_size(&mut self, max_datagram_size: usize) {
+                <$link>::set_max_datagram_size(self, max_datagram_size)
+            }
+            #[inline]
+            fn set_pause_writes(&mut self, pause: bool) {
+                <$link>::set_pause_writes(self, pause)
+            }
+            #[inline]
+            fn set_pause_reads(&mut self, pause: bool) {
+                <$link>::set_pause_reads(self, pause)
+            }
+            #[inline]
+            fn process(&mut self, sock: &mut $sock, pbuf: PBufRdWr) -> Result<Activity> {
+                <$link>::process(self, sock, pbuf)
+            }
+            #[inline]
+            fn process_out(&mut self, sock: &mut $sock, pbuf: PBufRdWr) -> Result<Activity> {
+                <$link>::process_out(self, sock, pbuf)
+            }
+            #[inline]
+            fn process_in(&mut self, sock: &mut $sock, pbuf: PBufRdWr) -> Result<Activity> {
+                <$link>::process_in(self, sock, pbuf)
+            }
+        }
+    };
+}
+
+impl_datagram_link!(UdpLink, UdpSocket);
+impl_datagram_link!(UdpPeerLink, UdpSocket);
+impl_datagram_link!(MulticastLink, UdpSocket);
+#[cfg(target_family = "unix")]
+impl_datagram_link!(UnixDatagramLink, UnixDatagram);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::{self, HEADER_LEN};
+    use crate::testutil::wait_for;
+    use pipebuf::PipeBufPair;
+    use std::net::SocketAddr;
+
+    const PAYLOADS: [&[u8]; 4] = [b"one", b"", &[0x55; 9000], b"last"];
+
+    // Pass a series of records from one link to another through the
+    // trait, checking that the framing survives unchanged whatever
+    // the socket type
+    fn round_trip<L: DatagramLink + ?Sized>(
+        a: &mut L,
+        sa: &mut L::Socket,
+        b: &mut L,
+        sb: &mut L::Socket,
+    ) {
+        let mut pa = PipeBufPair::new();
+        let mut pb = PipeBufPair::new();
+        for payload in PAYLOADS {
+            record::push_record(&mut pa.upper().wr, payload);
+        }
+        let act = a.process(sa, pa.lower()).unwrap();
+        assert!(act.contains(Activity::WROTE));
+        assert!(pa.lower().rd.is_empty());
+
+        for payload in PAYLOADS {
+            wait_for(|| {
+                b.process(sb, pb.lower()).unwrap();
+                record::next_record(pb.upper().rd.data()).is_some()
+            });
+            let mut app = pb.upper();
+            let total = record::next_record(app.rd.data()).unwrap();
+            assert_eq!(&app.rd.data()[HEADER_LEN..total], payload);
+            app.rd.consume(total);
+        }
+    }
+
+    // Check the settings are passed through the trait
+    fn settings<L: DatagramLink + ?Sized>(
+        a: &mut L,
+        sa: &mut L::Socket,
+        b: &mut L,
+        sb: &mut L::Socket,
+    ) {
+        let mut pa = PipeBufPair::new();
+        let mut pb = PipeBufPair::new();
+        a.set_pause_writes(true);
+        record::push_record(&mut pa.upper().wr, b"too long");
+        assert_eq!(a.process_out(sa, pa.lower()).unwrap(), Activity::NONE);
+        a.set_pause_writes(false);
+        a.process_out(sa, pa.lower()).unwrap();
+        record::push_record(&mut pa.upper().wr, b"short");
+        a.process_out(sa, pa.lower()).unwrap();
+
+        b.set_pause_reads(true);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        assert_eq!(b.process_in(sb, pb.lower()).unwrap(), Activity::NONE);
+        b.set_pause_reads(false);
+        b.set_max_datagram_size(5);
+        b.set_max_read_unit(1);
+        wait_for(|| {
+            b.process_in(sb, pb.lower()).unwrap();
+            !pb.upper().rd.is_empty()
+        });
+        let mut app = pb.upper();
+        let total = record::next_record(app.rd.data()).unwrap();
+        assert_eq!(&app.rd.data()[HEADER_LEN..total], b"short");
+        app.rd.consume(total);
+        assert!(app.rd.is_empty());
+    }
+
+    fn udp_pair() -> (UdpSocket, UdpSocket) {
+        let any: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let a = UdpSocket::bind(any).unwrap();
+        let b = UdpSocket::bind(any).unwrap();
+        a.connect(b.local_addr().unwrap()).unwrap();
+        b.connect(a.local_addr().unwrap()).unwrap();
+        (a, b)
+    }
+
+    #[test]
+    fn udp_framing() {
+        let (mut sa, mut sb) = udp_pair();
+        round_trip(&mut UdpLink::new(), &mut sa, &mut UdpLink::new(), &mut sb);
+        settings(&mut UdpLink::new(), &mut sa, &mut UdpLink::new(), &mut sb);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn unix_framing() {
+        let (mut sa, mut sb) = UnixDatagram::pair().unwrap();
+        let (mut a, mut b) = (UnixDatagramLink::new(), UnixDatagramLink::new());
+        round_trip(&mut a, &mut sa, &mut b, &mut sb);
+        let (mut a, mut b) = (UnixDatagramLink::new(), UnixDatagramLink::new());
+        settings(&mut a, &mut sa, &mut b, &mut sb);
+    }
+
+    #[test]
+    fn boxed_framing() {
+        let (mut sa, mut sb) = udp_pair();
+        let mut a: Box<dyn DatagramLink<Socket = UdpSocket>> = Box::new(UdpLink::new());
+        let mut b: Box<dyn DatagramLink<Socket = UdpSocket>> = Box::new(UdpLink::new());
+        round_trip(&mut *a, &mut sa, &mut *b, &mut sb);
+    }
+
+    #[test]
+    fn peer_framing() {
+        // With `UdpPeerLink` the payload carries the address first,
+        // and the rest of the record is unchanged
+        let (mut sa, mut sb) = udp_pair();
+        let from = sa.local_addr().unwrap();
+        let mut a: Box<dyn DatagramLink<Socket = UdpSocket>> = Box::new(UdpLink::new());
+        let mut b: Box<dyn DatagramLink<Socket = UdpSocket>> = Box::new(UdpPeerLink::new());
+        let mut pa = PipeBufPair::new();
+        let mut pb = PipeBufPair::new();
+        for payload in PAYLOADS {
+            record::push_record(&mut pa.upper().wr, payload);
+        }
+        a.process(&mut sa, pa.lower()).unwrap();
+
+        for payload in PAYLOADS {
+            wait_for(|| {
+                b.process(&mut sb, pb.lower()).unwrap();
+                record::next_record(pb.upper().rd.data()).is_some()
+            });
+            let mut app = pb.upper();
+            let total = record::next_record(app.rd.data()).unwrap();
+            let rec = &app.rd.data()[HEADER_LEN..total];
+            let (addr, addr_len) = record::get_addr(rec).unwrap();
+            assert_eq!(addr, from);
+            assert_eq!(&rec[addr_len..], payload);
+            app.rd.consume(total);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::fmt;
+use std::io::{self, ErrorKind};
+
+/// Result type returned by the `process` calls of the links
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Direction of the data flow in which an error occurred
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    /// Data coming in from the stream, socket or device into the
+    /// incoming pipe
+    Incoming,
+
+    /// Data going out from the outgoing pipe to the stream, socket or
+    /// device
+    Outgoing,
+
+    /// Both directions at once, for operations on the stream or
+    /// socket as a whole
+    Both,
+}
+
+/// Operation that failed
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Op {
+    /// Reading or receiving data
+    Read,
+
+    /// Writing or sending data, or flushing it out
+    Write,
+
+    /// Shutting down the stream to pass on EOF
+    Shutdown,
+
+    /// Changing an option on the stream or socket
+    SetOption,
+
+    /// Registering, reregistering or deregistering with a `mio`
+    /// `Registry`
+    Register,
+
+    /// Completing a non-blocking connect
+    Connect,
+}
+
+/// Error returned by the `process` calls of the links
+///
+/// This wraps the underlying `io::Error` along with the direction
+/// and operation that failed, so that logs can say which part of the
+/// processing went wrong.  Use [`Error::kind`] to match on the
+/// `ErrorKind` as before.  Converts into an `io::Error` with `?` where
+/// required, keeping the context in the message.
+#[derive(Debug)]
+pub struct Error {
+    error: io::Error,
+    direction: Direction,
+    op: Op,
+}
+
+impl Error {
+    /// Wrap an `io::Error` with the given context
+    #[inline]
+    pub fn new(direction: Direction, op: Op, error: io::Error) -> Self {
+        Self {
+            error,
+            direction,
+            op,
+        }
+    }
+
+    // Error on reading incoming data
+    #[inline]
+    pub(crate) fn read(error: io::Error) -> Self {
+        Self::new(Direction::Incoming, Op::Read, error)
+    }
+
+    // Error on writing outgoing data
+    #[inline]
+    pub(crate) fn write(error: io::Error) -> Self {
+        Self::new(Direction::Outgoing, Op::Write, error)
+    }
+
+    // Error on shutting down the outgoing side
+    #[inline]
+    pub(crate) fn shutdown(error: io::Error) -> Self {
+        Self::new(Direction::Outgoing, Op::Shutdown, error)
+    }
+
+    // Error on registration with a `Registry`
+    #[inline]
+    pub(crate) fn register(error: io::Error) -> Self {
+        Self::new(Direction::Both, Op::Register, error)
+    }
+
+    // Error on completing a connect
+    #[inline]
+    pub(crate) fn connect(error: io::Error) -> Self {
+        Self::new(Direction::Both, Op::Connect, error)
+    }
+
+    /// Get the `ErrorKind` of the underlying `io::Error`
+    #[inline]
+    pub fn kind(&self) -> ErrorKind {
+        self.error.kind()
+    }
+
+    /// Get the direction of the data flow that failed
+    #[inline]
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Get the operation that failed
+    #[inline]
+    pub fn op(&self) -> Op {
+        self.op
+    }
+
+    /// Get a reference to the underlying `io::Error`
+    #[inline]
+    pub fn io_error(&self) -> &io::Error {
+        &self.error
+    }
+
+    /// Discard the context and return the underlying `io::Error`
+    #[inline]
+    pub fn into_io_error(self) -> io::Error {
+        self.error
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Direction::Incoming => "incoming",
+            Direction::Outgoing => "outgoing",
+            Direction::Both => "both directions",
+        })
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Op::Read => "read",
+            Op::Write => "write",
+            Op::Shutdown => "shutdown",
+            Op::SetOption => "set option",
+            Op::Register => "registration",
+            Op::Connect => "connect",
+        })
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} failed ({}): {}", self.op, self.direction, self.error)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        io::Error::new(err.error.kind(), err)
+    }
+}
@@ -0,0 +1,209 @@
+use crate::activity::{abort_in, consume_eof_out, input_from, output_to};
+use crate::{Activity, Error, Result};
+use pipebuf::PBufRdWr;
+use std::fs::File;
+use std::io::{self, ErrorKind};
+use std::mem::ManuallyDrop;
+use std::os::unix::io::{FromRawFd, RawFd};
+
+/// Exchange stream data via an arbitrary Unix file descriptor
+///
+/// This is for devices such as serial ports and character devices
+/// which `mio` can only poll via [`mio::unix::SourceFd`].  The file
+/// descriptor is not owned by the link, and must remain open for as
+/// long as the link is in use.  On creation the file descriptor is
+/// switched to non-blocking mode if it is not already.
+///
+/// Errors from `read` and `write` are mapped as follows:
+///
+/// - EAGAIN/EWOULDBLOCK: No progress possible right now
+///
+/// - EINTR: Retried
+///
+/// - A zero-length read: Passed on as a "close" of the incoming pipe
+///
+/// - ECONNRESET, ECONNABORTED, EPIPE or EIO (the usual error after a
+///   terminal or serial device hangs up): Passed on as an "abort" of
+///   the incoming pipe.  On writing, any pending outgoing data is
+///   discarded.
+///
+/// - Anything else: Returned as a fatal error
+///
+/// Since the file descriptor is not owned, it is not closed on
+/// outgoing EOF.  The EOF is just consumed once all data has been
+/// written.  An outgoing "abort" is passed on as an "abort" of the
+/// incoming pipe.
+///
+/// Reading and writing start unpaused.
+#[derive(Debug)]
+pub struct FdLink {
+    // The file descriptor
+    fd: RawFd,
+
+    // Maximum amount of data to read in one go (in bytes)
+    max_read_unit: usize,
+
+    // Set to pause writes
+    pause_writes: bool,
+
+    // Set to pause reads
+    pause_reads: bool,
+}
+
+impl FdLink {
+    /// Create the component for the given file descriptor, switching
+    /// it to non-blocking mode if necessary, with default settings:
+    ///
+    /// - **max_read_unit** of 2048
+    ///
+    /// - Both reads and writes unpaused
+    pub fn new(fd: RawFd) -> io::Result<Self> {
+        set_nonblocking(fd)?;
+        Ok(Self {
+            fd,
+            max_read_unit: 2048,
+            pause_writes: false,
+            pause_reads: false,
+        })
+    }
+
+    /// Get the file descriptor, e.g. for registering via
+    /// [`mio::unix::SourceFd`]
+    #[inline]
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    /// Change the maximum number of bytes to read in each `process`
+    /// call
+    #[inline]
+    pub fn set_max_read_unit(&mut self, max_read_unit: usize) {
+        self.max_read_unit = max_read_unit;
+    }
+
+    /// Pause or unpause writes.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_writes(&mut self, pause: bool) {
+        self.pause_writes = pause;
+    }
+
+    /// Pause or unpause reads.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_reads(&mut self, pause: bool) {
+        self.pause_reads = pause;
+    }
+
+    /// Read and write as much data as possible to and from the file
+    /// descriptor.  Returns the activity flags (see [`Activity`]),
+    /// which are empty if no progress could be made, or `Err(_)` if
+    /// there was a fatal error.
+    ///
+    /// Assumes that it is always called with the same pipe-buffer.
+    /// Things will behave unpredictably otherwise.
+    pub fn process(&mut self, mut pbuf: PBufRdWr) -> Result<Activity> {
+        let rd_activity = self.process_out(pbuf.reborrow())?;
+        let wr_activity = self.process_in(pbuf.reborrow())?;
+        Ok(rd_activity | wr_activity)
+    }
+
+    /// Write as much data as possible out to the file descriptor.
+    /// Returns the activity flags (see [`Activity`]), which are empty
+    /// if no progress could be made, or `Err(_)` if there was a fatal
+    /// error.
+    pub fn process_out(&mut self, mut pbuf: PBufRdWr) -> Result<Activity> {
+        if self.pause_writes {
+            return Ok(Activity::NONE);
+        }
+
+        let mut prd = pbuf.rd;
+        let mut act = Activity::NONE;
+        match output_to(&mut prd, &mut *raw_file(self.fd), &mut act) {
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => (),
+            Err(ref e) if is_abort_error(e) => {
+                let len = prd.len();
+                prd.consume(len);
+                consume_eof_out(&mut prd, &mut act);
+                abort_in(&mut pbuf.wr, &mut act);
+            }
+            Err(e) => return Err(Error::write(e)),
+            Ok(_) => {
+                if prd.is_empty() && prd.has_pending_eof() {
+                    consume_eof_out(&mut prd, &mut act);
+                    if prd.is_aborted() {
+                        abort_in(&mut pbuf.wr, &mut act);
+                    }
+                }
+            }
+        }
+        Ok(act)
+    }
+
+    /// Read as much data as possible from the file descriptor, up to
+    /// **max_read_unit** bytes.  Returns the activity flags (see
+    /// [`Activity`]), which are empty if no progress could be made,
+    /// or `Err(_)` if there was a fatal error.
+    pub fn process_in(&mut self, pbuf: PBufRdWr) -> Result<Activity> {
+        let mut pwr = pbuf.wr;
+        if self.pause_reads || pwr.is_eof() {
+            return Ok(Activity::NONE);
+        }
+
+        let mut act = Activity::NONE;
+        if let Err(e) = input_from(
+            &mut pwr,
+            &mut *raw_file(self.fd),
+            self.max_read_unit,
+            &mut act,
+        ) {
+            match e.kind() {
+                ErrorKind::WouldBlock => (),
+                _ if is_abort_error(&e) => abort_in(&mut pwr, &mut act),
+                _ => return Err(Error::read(e)),
+            }
+        }
+        Ok(act)
+    }
+}
+
+fn is_abort_error(e: &io::Error) -> bool {
+    matches!(
+        e.raw_os_error(),
+        Some(libc::ECONNRESET | libc::ECONNABORTED | libc::EPIPE | libc::EIO)
+    )
+}
+
+// Borrow a file descriptor as a `File` without taking ownership
+pub(crate) fn raw_file(fd: RawFd) -> ManuallyDrop<File> {
+    // Safety: The `File` is never dropped, so the file descriptor is
+    // not closed
+    ManuallyDrop::new(unsafe { File::from_raw_fd(fd) })
+}
+
+// Set O_NONBLOCK, returning the original flags
+pub(crate) fn set_nonblocking(fd: RawFd) -> io::Result<libc::c_int> {
+    // Safety: F_GETFL and F_SETFL have no memory-safety implications
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if flags & libc::O_NONBLOCK == 0
+            && libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(flags)
+    }
+}
+
+// Restore the file status flags saved by `set_nonblocking`
+#[cfg(feature = "stdio")]
+pub(crate) fn restore_flags(fd: RawFd, flags: libc::c_int) {
+    // Safety: F_SETFL has no memory-safety implications.  Errors are
+    // ignored as there is nothing useful to do about them.
+    unsafe {
+        libc::fcntl(fd, libc::F_SETFL, flags);
+    }
+}
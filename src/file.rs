@@ -0,0 +1,184 @@
+use crate::activity::{close_in, consume_eof_out, read_room};
+use crate::{Activity, Error, Result};
+use pipebuf::{PBufRd, PBufWr};
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+
+/// Stream a regular file into or out of a pipe-buffer in bounded
+/// chunks
+///
+/// Reads and writes on regular files don't return `WouldBlock`, and
+/// for moderate amounts of data don't block for long, so this allows
+/// file data to be handled on the poll loop thread without a separate
+/// thread.  Each `process_in` call reads at most **max_read_unit**
+/// bytes, so that other work can be interleaved.  Since there are no
+/// readiness events for files, the caller should call `process_in`
+/// again whenever there is space in the pipe, for example after the
+/// pipe has been drained by the consumer.
+///
+/// In read mode (`process_in`), the file is read from **offset**
+/// onwards, and the pipe is closed at end-of-file or at the end of
+/// the byte range given to [`FileLink::set_range`].  In write mode
+/// (`process_out`), data from the pipe is written at **offset**
+/// onwards, and on EOF from the pipe the file is optionally synced to
+/// disk with `sync_all`.  A single `FileLink` should be used in only
+/// one direction.
+#[derive(Debug)]
+pub struct FileLink {
+    // Maximum amount of data to read in one go (in bytes)
+    max_read_unit: usize,
+
+    // Current position in the file
+    offset: u64,
+
+    // End of the byte range to read, if any
+    end: Option<u64>,
+
+    // Seek to `offset` needed before the next read or write
+    pending_seek: bool,
+
+    // Call `sync_all` on EOF in write mode
+    sync_on_eof: bool,
+}
+
+impl FileLink {
+    /// Create the component with default settings:
+    ///
+    /// - **max_read_unit** of 65536
+    ///
+    /// - **offset** of 0, with no end to the range
+    ///
+    /// - **sync_on_eof** set to `false`
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            max_read_unit: 65536,
+            offset: 0,
+            end: None,
+            pending_seek: true,
+            sync_on_eof: false,
+        }
+    }
+
+    /// Change the maximum number of bytes to read in each
+    /// `process_in` call
+    #[inline]
+    pub fn set_max_read_unit(&mut self, max_read_unit: usize) {
+        self.max_read_unit = max_read_unit;
+    }
+
+    /// Set the byte range of the file to read or write, from `start`
+    /// up to but not including `end`, or to the end of the file if
+    /// `end` is `None`.  This takes effect on the next `process_in`
+    /// or `process_out` call.  The end of the range is only applied
+    /// when reading.
+    #[inline]
+    pub fn set_range(&mut self, start: u64, end: Option<u64>) {
+        self.offset = start;
+        self.end = end;
+        self.pending_seek = true;
+    }
+
+    /// Enable or disable syncing the file to disk with `sync_all`
+    /// when EOF is reached on the outgoing pipe in write mode
+    #[inline]
+    pub fn set_sync_on_eof(&mut self, sync: bool) {
+        self.sync_on_eof = sync;
+    }
+
+    /// Get the current offset in the file, i.e. the position of the
+    /// next byte to be read or written
+    #[inline]
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Read up to **max_read_unit** bytes from the file into the
+    /// pipe, closing the pipe at end-of-file or at the end of the
+    /// range.  Returns the activity flags (see [`Activity`]), which
+    /// are empty if no progress could be made, or `Err(_)` if there
+    /// was an error on the file.
+    ///
+    /// Assumes that it is always called with the same `File` and
+    /// pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process_in(&mut self, file: &mut File, mut pwr: PBufWr) -> Result<Activity> {
+        if pwr.is_eof() {
+            return Ok(Activity::NONE);
+        }
+        self.seek(file).map_err(Error::read)?;
+
+        let mut act = Activity::NONE;
+        let mut total = 0;
+        while total < self.max_read_unit {
+            let mut len = self.max_read_unit - total;
+            if let Some(end) = self.end {
+                len = len.min(end.saturating_sub(self.offset) as usize);
+                if len == 0 {
+                    close_in(&mut pwr, &mut act);
+                    break;
+                }
+            }
+            len = read_room(&pwr, len);
+            if len == 0 {
+                break;
+            }
+            let space = pwr.space(len);
+            match retry!(file.read(space)) {
+                Err(e) => return Err(Error::read(e)),
+                Ok(0) => {
+                    close_in(&mut pwr, &mut act);
+                    break;
+                }
+                Ok(count) => {
+                    pwr.commit(count);
+                    act |= Activity::READ;
+                    self.offset += count as u64;
+                    total += count;
+                }
+            }
+        }
+        Ok(act)
+    }
+
+    /// Write all the data in the pipe to the file.  On EOF from the
+    /// pipe, syncs the file if **sync_on_eof** is enabled and
+    /// consumes the EOF.  Returns the activity flags (see
+    /// [`Activity`]), which are empty if no progress could be made,
+    /// or `Err(_)` if there was an error on the file.
+    ///
+    /// Assumes that it is always called with the same `File` and
+    /// pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process_out(&mut self, file: &mut File, mut prd: PBufRd) -> Result<Activity> {
+        let mut act = Activity::NONE;
+        if !prd.is_empty() {
+            self.seek(file).map_err(Error::write)?;
+            let data = prd.data();
+            file.write_all(data).map_err(Error::write)?;
+            let len = data.len();
+            prd.consume(len);
+            self.offset += len as u64;
+            act |= Activity::WROTE;
+        }
+        if prd.has_pending_eof() {
+            if self.sync_on_eof && !prd.is_aborted() {
+                retry!(file.sync_all()).map_err(Error::write)?;
+            }
+            consume_eof_out(&mut prd, &mut act);
+        }
+        Ok(act)
+    }
+
+    fn seek(&mut self, file: &mut File) -> io::Result<()> {
+        if self.pending_seek {
+            retry!(file.seek(SeekFrom::Start(self.offset)))?;
+            self.pending_seek = false;
+        }
+        Ok(())
+    }
+}
+
+impl Default for FileLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
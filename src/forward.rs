@@ -0,0 +1,104 @@
+use crate::{Activity, Result, StreamLink};
+use pipebuf::PipeBufPair;
+
+/// Relay data in both directions between two stream links
+///
+/// This splices two links together through a single [`PipeBufPair`]:
+/// link A uses the "lower" end and link B the "upper" end, so data
+/// read by one link is written out by the other.  Since the pipes
+/// carry EOF and abort just as they carry data, these are passed
+/// through in both directions.  Each direction is independent, so
+/// the half-closed case works as expected: when the peer of A
+/// closes, B shuts down its outgoing half, and data from B to A
+/// continues to flow until B's peer also closes.
+///
+/// The links start paused as usual, so unpause them via
+/// [`Forwarder::link_a`] and [`Forwarder::link_b`] on the first
+/// "ready" indications from their streams.
+pub struct Forwarder<A: StreamLink, B: StreamLink> {
+    link_a: A,
+    link_b: B,
+    pipes: PipeBufPair,
+
+    // Bytes written out by link B, i.e. relayed from A to B
+    a_to_b: u64,
+
+    // Bytes written out by link A, i.e. relayed from B to A
+    b_to_a: u64,
+}
+
+impl<A: StreamLink, B: StreamLink> Forwarder<A, B> {
+    /// Create the relay from the two links, with empty pipes
+    #[inline]
+    pub fn new(link_a: A, link_b: B) -> Self {
+        Self {
+            link_a,
+            link_b,
+            pipes: PipeBufPair::new(),
+            a_to_b: 0,
+            b_to_a: 0,
+        }
+    }
+
+    /// Get access to link A, e.g. to unpause it
+    #[inline]
+    pub fn link_a(&mut self) -> &mut A {
+        &mut self.link_a
+    }
+
+    /// Get access to link B, e.g. to unpause it
+    #[inline]
+    pub fn link_b(&mut self) -> &mut B {
+        &mut self.link_b
+    }
+
+    /// Get the number of bytes relayed from A to B so far, i.e.
+    /// written out to stream B
+    #[inline]
+    pub fn bytes_a_to_b(&self) -> u64 {
+        self.a_to_b
+    }
+
+    /// Get the number of bytes relayed from B to A so far, i.e.
+    /// written out to stream A
+    #[inline]
+    pub fn bytes_b_to_a(&self) -> u64 {
+        self.b_to_a
+    }
+
+    /// Test whether the relay is finished, i.e. EOF has been passed
+    /// through in both directions
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        self.pipes.down.is_done() && self.pipes.up.is_done()
+    }
+
+    /// Read from both streams, and then write out as much data as
+    /// possible to both streams.  Returns the combined activity flags
+    /// of both links (see [`Activity`]), which are empty if no
+    /// progress could be made, or `Err(_)` if there was a fatal error
+    /// on either stream.
+    ///
+    /// Assumes that it is always called with the same two streams.
+    /// Things will behave unpredictably otherwise.
+    pub fn process(
+        &mut self,
+        stream_a: &mut A::Stream,
+        stream_b: &mut B::Stream,
+    ) -> Result<Activity> {
+        let mut act = self.link_a.process_in(stream_a, self.pipes.lower())?;
+        act |= self.link_b.process_in(stream_b, self.pipes.upper())?;
+
+        let mut pbuf = self.pipes.upper();
+        let len = pbuf.rd.len();
+        act |= self.link_b.process_out(stream_b, pbuf.reborrow())?;
+        self.a_to_b += (len - pbuf.rd.len()) as u64;
+
+        let mut pbuf = self.pipes.lower();
+        let len = pbuf.rd.len();
+        act |= self.link_a.process_out(stream_a, pbuf.reborrow())?;
+        self.b_to_a += (len - pbuf.rd.len()) as u64;
+
+        Ok(act)
+    }
+}
@@ -0,0 +1,1570 @@
+// Logic shared by the stream links.  Each public stream link is a
+// thin wrapper around `GenericLink`, adding any options specific to
+// its stream type.
+
+use crate::activity::{abort_in, close_in, consume_eof_out, input_from_with, output_to_max};
+use crate::observer::{observe_read, observe_write};
+use crate::ratelimit::TokenBucket;
+use crate::stats::StatsStream;
+use crate::tee::{read_in_tee, tee_eof, Tee};
+use crate::trace::Tracer;
+use crate::{
+    AbortMode, Activity, Direction, Error, LinkObserver, LinkState, LinkStats, Op, ProcessOrder,
+    RateLimit, ReadQuotaMode, Result, WriteQuotaMode,
+};
+use mio::event::Event;
+use mio::Interest;
+use pipebuf::{PBufRd, PBufRdWr, PBufWr};
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::Shutdown;
+use std::time::{Duration, Instant};
+
+// A stream that supports half-close
+pub(crate) trait Shutdownable: Read + Write {
+    fn shutdown(&mut self, how: Shutdown) -> io::Result<()>;
+
+    // Reset the connection, if the stream type supports it.  Where
+    // the platform allows, the RST is sent at once, otherwise it is
+    // sent when the stream is closed.
+    fn reset(&mut self) -> io::Result<()> {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+}
+
+// Interpret the result of a shutdown.  `NotConnected` and
+// `BrokenPipe` mean that the stream is already effectively shut
+// down, typically because the peer closed or reset the connection
+// while the shutdown was pending, so they count as success.  Some
+// platforms give EINVAL (`InvalidInput`) for a shutdown of a stream
+// that has already been torn down, which counts the same way.
+#[inline]
+pub(crate) fn shutdown_result(rv: io::Result<()>) -> io::Result<()> {
+    match rv {
+        Err(ref e)
+            if matches!(
+                e.kind(),
+                ErrorKind::NotConnected | ErrorKind::BrokenPipe | ErrorKind::InvalidInput
+            ) =>
+        {
+            Ok(())
+        }
+        rv => rv,
+    }
+}
+
+// Wrapper around a stream which notes whether a call returned
+// `WouldBlock`, and keeps a copy of the last other error, including
+// errors that are then handled as an abort or ignored
+pub(crate) struct LatchStream<'a, S> {
+    stream: &'a mut S,
+    pub(crate) blocked: bool,
+    pub(crate) error: Option<io::Error>,
+}
+
+impl<'a, S> LatchStream<'a, S> {
+    #[inline]
+    pub(crate) fn new(stream: &'a mut S) -> Self {
+        Self {
+            stream,
+            blocked: false,
+            error: None,
+        }
+    }
+
+    #[inline]
+    fn note<T>(&mut self, rv: io::Result<T>) -> io::Result<T> {
+        if let Err(ref e) = rv {
+            match e.kind() {
+                ErrorKind::WouldBlock => self.blocked = true,
+                ErrorKind::Interrupted => (),
+                _ => self.error = Some(copy_error(e)),
+            }
+        }
+        rv
+    }
+}
+
+// Make a copy of an `io::Error`, keeping the OS error code if there
+// is one, or else just the kind
+fn copy_error(e: &io::Error) -> io::Error {
+    match e.raw_os_error() {
+        Some(code) => io::Error::from_raw_os_error(code),
+        None => e.kind().into(),
+    }
+}
+
+impl<S: Read> Read for LatchStream<'_, S> {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let rv = self.stream.read(buf);
+        self.note(rv)
+    }
+}
+
+impl<S: Write> Write for LatchStream<'_, S> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let rv = self.stream.write(buf);
+        self.note(rv)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl<S: Shutdownable> Shutdownable for LatchStream<'_, S> {
+    #[inline]
+    fn shutdown(&mut self, how: Shutdown) -> io::Result<()> {
+        let rv = self.stream.shutdown(how);
+        if let Err(ref e) = rv {
+            if e.kind() != ErrorKind::WouldBlock {
+                self.error = Some(copy_error(e));
+            }
+        }
+        rv
+    }
+
+    #[inline]
+    fn reset(&mut self) -> io::Result<()> {
+        self.stream.reset()
+    }
+}
+
+macro_rules! impl_shutdownable {
+    ($($stream:ty),*) => {
+        $(
+            impl Shutdownable for $stream {
+                #[inline]
+                fn shutdown(&mut self, how: Shutdown) -> io::Result<()> {
+                    <$stream>::shutdown(self, how)
+                }
+            }
+        )*
+    };
+}
+
+impl_shutdownable!(crate::LoopbackStream);
+#[cfg(target_family = "unix")]
+impl_shutdownable!(mio::net::UnixStream);
+#[cfg(any(test, feature = "test-util"))]
+impl_shutdownable!(crate::MockStream);
+
+impl Shutdownable for mio::net::TcpStream {
+    #[inline]
+    fn shutdown(&mut self, how: Shutdown) -> io::Result<()> {
+        mio::net::TcpStream::shutdown(self, how)
+    }
+
+    #[cfg(target_family = "unix")]
+    fn reset(&mut self) -> io::Result<()> {
+        use std::os::unix::io::AsRawFd;
+        crate::sockopt::reset(self.as_raw_fd())
+    }
+}
+
+impl Shutdownable for &mio::net::TcpStream {
+    #[inline]
+    fn shutdown(&mut self, how: Shutdown) -> io::Result<()> {
+        mio::net::TcpStream::shutdown(self, how)
+    }
+
+    #[cfg(target_family = "unix")]
+    fn reset(&mut self) -> io::Result<()> {
+        use std::os::unix::io::AsRawFd;
+        crate::sockopt::reset(self.as_raw_fd())
+    }
+}
+
+#[cfg(feature = "std-net")]
+impl Shutdownable for std::net::TcpStream {
+    #[inline]
+    fn shutdown(&mut self, how: Shutdown) -> io::Result<()> {
+        std::net::TcpStream::shutdown(self, how)
+    }
+
+    #[cfg(target_family = "unix")]
+    fn reset(&mut self) -> io::Result<()> {
+        use std::os::unix::io::AsRawFd;
+        crate::sockopt::reset(self.as_raw_fd())
+    }
+}
+
+// Settings and processing common to all the stream links
+pub(crate) struct GenericLink {
+    // Maximum amount of data to read in one go (in bytes)
+    pub(crate) max_read_unit: usize,
+
+    // Bounds within which **max_read_unit** adapts to the traffic, if
+    // enabled
+    pub(crate) read_unit_range: Option<(usize, usize)>,
+
+    // Maximum amount of data to read per `process_in` call (in
+    // bytes), or `None` for one **max_read_unit**
+    pub(crate) max_read_total: Option<usize>,
+
+    // Minimum space in the incoming pipe needed to attempt a read
+    pub(crate) read_low_water: usize,
+
+    // Maximum amount of data to write per `process_out` call (in
+    // bytes)
+    pub(crate) max_write_unit: usize,
+
+    // Backlog in the incoming pipe above which reads are held off,
+    // if enabled
+    pub(crate) read_backpressure: Option<usize>,
+
+    // Set while reads are held off by backpressure
+    backpressured: bool,
+
+    // Set to pause writes (waiting for first "ready" indication)
+    pub(crate) pause_writes: bool,
+
+    // Set to pause reads (waiting for first "ready" indication)
+    pub(crate) pause_reads: bool,
+
+    // Set to skip reads or writes after `WouldBlock` until there is
+    // fresh readiness
+    pub(crate) latch: bool,
+
+    // Set when the last read gave `WouldBlock`
+    read_blocked: bool,
+
+    // Set when the last write gave `WouldBlock`
+    write_blocked: bool,
+
+    // Events for the `tracing` feature
+    pub(crate) tracer: Tracer,
+
+    // Traffic callbacks, if enabled
+    pub(crate) observer: Option<Box<dyn LinkObserver>>,
+
+    // Copy of incoming data, if enabled
+    pub(crate) tee: Option<Tee>,
+
+    // Syscall statistics, if enabled
+    pub(crate) stats: Option<LinkStats>,
+
+    // Limit on the read bandwidth, if enabled
+    read_rate: Option<TokenBucket>,
+
+    // Limit on the write bandwidth, if enabled
+    write_rate: Option<TokenBucket>,
+
+    // Bytes that may still be read, if limited
+    pub(crate) read_quota: Option<u64>,
+
+    // How to end the incoming pipe when the read quota runs out
+    pub(crate) read_quota_mode: ReadQuotaMode,
+
+    // Set to shut down the read half when the read quota runs out
+    pub(crate) read_quota_shutdown: bool,
+
+    // Bytes that may still be written, if limited
+    write_quota: Option<u64>,
+
+    // What to do when the write quota runs out
+    pub(crate) write_quota_mode: WriteQuotaMode,
+
+    // Set once the write quota running out has been reported
+    write_quota_hit: bool,
+
+    // Set to hold off reads into the pipe, e.g. while the caller
+    // peeks at the stream
+    pub(crate) hold_reads: bool,
+
+    // Set once EOF from the stream has been passed to the incoming
+    // pipe
+    eof_in: bool,
+
+    // Set once EOF from the outgoing pipe has been passed on to the
+    // stream
+    eof_out: bool,
+
+    // Set while the outgoing pipe's EOF is waiting for a shutdown
+    // that gave `WouldBlock`
+    shutdown_pending: bool,
+
+    // Set once an abort has been passed on in either direction
+    aborted: bool,
+
+    // Set when the incoming pipe needs aborting but is not held by
+    // this link, as for the write half of a split `TcpLink`
+    pub(crate) abort_incoming: bool,
+
+    // Set to shut down the stream once the outgoing pipe is empty,
+    // without waiting for its EOF
+    pub(crate) close_when_flushed: bool,
+
+    // Set once the stream has been shut down by `close_when_flushed`
+    // or `abort_now`, or once a write has found the connection dead
+    // or passed on an outgoing abort, after which data from the
+    // outgoing pipe is discarded instead of written
+    closed_early: bool,
+
+    // Total bytes read from the stream, since creation or the last
+    // `take_counters`
+    pub(crate) bytes_in: u64,
+
+    // Total bytes written to the stream, likewise
+    pub(crate) bytes_out: u64,
+
+    // Source of timestamps, replaceable for tests
+    pub(crate) clock: fn() -> Instant,
+
+    // Time of the last read or write that transferred data, if any
+    last_activity: Option<Instant>,
+
+    // Last error seen on reads, including ones handled as an abort
+    pub(crate) last_read_error: Option<io::Error>,
+
+    // Last error seen on writes and shutdowns, likewise
+    pub(crate) last_write_error: Option<io::Error>,
+
+    // Inactivity after which `process` aborts the link, if enabled
+    idle_timeout: Option<Duration>,
+
+    // Start of the current idle period
+    idle_since: Instant,
+
+    // Set if the idle timer was held at the end of the last `process`
+    // call
+    idle_held: bool,
+
+    // Set once the link has been aborted by the idle timeout or the
+    // close deadline
+    timed_out: bool,
+
+    // Set once a `process` call has returned a fatal error
+    failed: bool,
+
+    // Time allowed for the peer to close after our EOF, if enabled
+    close_deadline: Option<Duration>,
+
+    // Time at which EOF was passed on to the stream, if it has been
+    close_since: Option<Instant>,
+
+    // How to pass on an outgoing abort
+    pub(crate) abort_mode: AbortMode,
+
+    // Order in which `process` handles the two directions
+    pub(crate) order: ProcessOrder,
+}
+
+impl GenericLink {
+    // Create with both reads and writes paused
+    #[inline]
+    pub(crate) fn new(max_read_unit: usize) -> Self {
+        let clock: fn() -> Instant = Instant::now;
+        Self {
+            max_read_unit,
+            read_unit_range: None,
+            max_read_total: None,
+            read_low_water: 0,
+            max_write_unit: usize::MAX,
+            read_backpressure: None,
+            backpressured: false,
+            pause_writes: true,
+            pause_reads: true,
+            latch: false,
+            read_blocked: false,
+            write_blocked: false,
+            tracer: Tracer::default(),
+            observer: None,
+            tee: None,
+            stats: None,
+            read_rate: None,
+            write_rate: None,
+            read_quota: None,
+            read_quota_mode: ReadQuotaMode::Close,
+            read_quota_shutdown: false,
+            write_quota: None,
+            write_quota_mode: WriteQuotaMode::Stall,
+            write_quota_hit: false,
+            hold_reads: false,
+            eof_in: false,
+            eof_out: false,
+            shutdown_pending: false,
+            aborted: false,
+            abort_incoming: false,
+            close_when_flushed: false,
+            closed_early: false,
+            bytes_in: 0,
+            bytes_out: 0,
+            clock,
+            last_activity: None,
+            last_read_error: None,
+            last_write_error: None,
+            idle_timeout: None,
+            idle_since: clock(),
+            idle_held: false,
+            timed_out: false,
+            failed: false,
+            close_deadline: None,
+            close_since: None,
+            abort_mode: AbortMode::ShutdownBoth,
+            order: ProcessOrder::WriteThenRead,
+        }
+    }
+
+    // Set a fixed **max_read_unit**, ending any adaptation
+    #[inline]
+    pub(crate) fn set_max_read_unit(&mut self, max_read_unit: usize) {
+        self.max_read_unit = max_read_unit;
+        self.read_unit_range = None;
+    }
+
+    // Let **max_read_unit** adapt to the traffic between `min` and
+    // `max`, starting from `min`
+    pub(crate) fn set_read_unit_range(&mut self, min: usize, max: usize) {
+        let min = min.max(1);
+        let max = max.max(min);
+        self.read_unit_range = Some((min, max));
+        self.max_read_unit = min;
+    }
+
+    // Adapt **max_read_unit** after `bytes` were read with a limit of
+    // `limit` bytes for the call, if enabled: double it if a whole
+    // unit was read, since more data is likely waiting, or halve it
+    // if less than a quarter of what could have been read arrived
+    pub(crate) fn adapt_read_unit(&mut self, bytes: usize, limit: usize) {
+        let Some((min, max)) = self.read_unit_range else {
+            return;
+        };
+        let unit = self.max_read_unit;
+        if bytes >= unit {
+            self.max_read_unit = unit.saturating_mul(2).min(max);
+        } else if bytes < unit.min(limit) / 4 {
+            self.max_read_unit = (unit / 2).max(min);
+        }
+    }
+
+    // Get the maximum amount of data to read per `process_in` call
+    #[inline]
+    pub(crate) fn read_total(&self) -> usize {
+        self.max_read_total.unwrap_or(self.max_read_unit)
+    }
+
+    // Get the limits on the reads made by `process_in`
+    #[inline]
+    pub(crate) fn read_limits(&self) -> ReadLimits {
+        ReadLimits {
+            unit: self.max_read_unit,
+            total: self.read_total(),
+            low_water: self.read_low_water,
+        }
+    }
+
+    // Test whether reads should be held off because of the backlog in
+    // the incoming pipe.  Once held, reads resume only when the
+    // backlog drops to half the threshold.
+    #[inline]
+    pub(crate) fn backpressure_held(&self, pwr: &PBufWr) -> bool {
+        match self.read_backpressure {
+            None => false,
+            Some(high) if self.backpressured => pwr.exceeds_limit(high / 2),
+            Some(high) => pwr.exceeds_limit(high),
+        }
+    }
+
+    // Update the backpressure state for a read.  Returns `true` if the
+    // read should be skipped.
+    #[inline]
+    pub(crate) fn check_backpressure(&mut self, pwr: &PBufWr) -> bool {
+        self.backpressured = self.backpressure_held(pwr);
+        self.backpressured
+    }
+
+    // Get the current position in the shutdown sequence
+    pub(crate) fn state(&self) -> LinkState {
+        if self.failed {
+            LinkState::Failed
+        } else if self.aborted {
+            LinkState::Aborted
+        } else if self.shutdown_pending {
+            LinkState::ShutdownPending
+        } else {
+            match (self.eof_in, self.eof_out) {
+                (false, false) => LinkState::Open,
+                (false, true) => LinkState::OutClosed,
+                (true, false) => LinkState::InClosed,
+                (true, true) => LinkState::Closed,
+            }
+        }
+    }
+
+    // Keep the last error seen by a read, if there was one
+    #[inline]
+    pub(crate) fn note_read_error(&mut self, error: Option<io::Error>) {
+        if error.is_some() {
+            self.last_read_error = error;
+        }
+    }
+
+    // Keep the last error seen by a write or shutdown, likewise
+    #[inline]
+    pub(crate) fn note_write_error(&mut self, error: Option<io::Error>) {
+        if error.is_some() {
+            self.last_write_error = error;
+        }
+    }
+
+    // Test whether the link is finished: failed, or with EOF or an
+    // abort in the incoming pipe, and EOF or an abort passed on from
+    // the outgoing pipe
+    pub(crate) fn is_done(&self, pbuf: &PBufRdWr) -> bool {
+        self.failed || (pbuf.wr.is_eof() && (self.eof_out || self.aborted))
+    }
+
+    // Add the common settings and state to a `Debug` output
+    pub(crate) fn debug_fields(&self, d: &mut fmt::DebugStruct<'_, '_>) {
+        d.field("max_read_unit", &self.max_read_unit)
+            .field("read_unit_range", &self.read_unit_range)
+            .field("max_read_total", &self.read_total())
+            .field("read_low_water", &self.read_low_water)
+            .field("max_write_unit", &self.max_write_unit)
+            .field("read_backpressure", &self.read_backpressure)
+            .field("backpressured", &self.backpressured)
+            .field("pause_reads", &self.pause_reads)
+            .field("pause_writes", &self.pause_writes)
+            .field("latch", &self.latch)
+            .field("state", &self.state())
+            .field("bytes_in", &self.bytes_in)
+            .field("bytes_out", &self.bytes_out)
+            .field("last_activity", &self.last_activity)
+            .field("idle_timeout", &self.idle_timeout)
+            .field("timed_out", &self.timed_out)
+            .field("failed", &self.failed)
+            .field("close_deadline", &self.close_deadline)
+            .field("order", &self.order)
+            .field("tee", &self.tee)
+            .field("stats", &self.stats)
+            .field("read_rate", &self.read_rate)
+            .field("write_rate", &self.write_rate)
+            .field("read_quota", &self.read_quota)
+            .field("read_quota_mode", &self.read_quota_mode)
+            .field("read_quota_shutdown", &self.read_quota_shutdown)
+            .field("write_quota", &self.write_quota)
+            .field("write_quota_mode", &self.write_quota_mode)
+            .field("hold_reads", &self.hold_reads);
+        #[cfg(feature = "tracing")]
+        d.field("label", &self.tracer.label);
+        d.field("observer", &self.observer.is_some());
+    }
+
+    // Set or remove the read rate limit, starting with a full bucket
+    #[inline]
+    pub(crate) fn set_read_rate_limit(&mut self, limit: Option<RateLimit>) {
+        self.read_rate = limit.map(|limit| TokenBucket::new(limit, (self.clock)()));
+    }
+
+    // Set or remove the write rate limit, likewise
+    #[inline]
+    pub(crate) fn set_write_rate_limit(&mut self, limit: Option<RateLimit>) {
+        self.write_rate = limit.map(|limit| TokenBucket::new(limit, (self.clock)()));
+    }
+
+    // Set or remove the write quota, rearming the report of it
+    // running out
+    #[inline]
+    pub(crate) fn set_write_quota(&mut self, quota: Option<u64>) {
+        self.write_quota = quota;
+        self.write_quota_hit = false;
+    }
+
+    // Get the bytes that may still be written, if limited
+    #[inline]
+    pub(crate) fn write_quota(&self) -> Option<u64> {
+        self.write_quota
+    }
+
+    // Enable or disable syscall statistics, starting from zero
+    #[inline]
+    pub(crate) fn enable_stats(&mut self, enable: bool) {
+        if enable != self.stats.is_some() {
+            self.stats = enable.then(LinkStats::default);
+        }
+    }
+
+    // Test whether the write quota has run out with data still to
+    // write
+    #[inline]
+    fn write_quota_stalled(&self, prd: &PBufRd) -> bool {
+        self.write_quota == Some(0) && !prd.is_empty()
+    }
+
+    // Get and reset the byte counters
+    #[inline]
+    pub(crate) fn take_counters(&mut self) -> (u64, u64) {
+        let counters = (self.bytes_in, self.bytes_out);
+        self.bytes_in = 0;
+        self.bytes_out = 0;
+        counters
+    }
+
+    // Get the time of the last read or write that transferred data
+    #[inline]
+    pub(crate) fn last_activity(&self) -> Option<Instant> {
+        self.last_activity
+    }
+
+    // Get the time since the last read or write that transferred data
+    #[inline]
+    pub(crate) fn idle_for(&self) -> Option<Duration> {
+        self.last_activity
+            .map(|t| (self.clock)().saturating_duration_since(t))
+    }
+
+    // Record that data was transferred just now, which also restarts
+    // the idle timer
+    #[inline]
+    fn touch(&mut self, bytes: usize) {
+        if bytes > 0 {
+            let now = (self.clock)();
+            self.last_activity = Some(now);
+            self.idle_since = now;
+        }
+    }
+
+    // Replace the clock, restarting the idle timer from the new one
+    #[cfg(any(test, feature = "test-util"))]
+    pub(crate) fn set_clock(&mut self, clock: fn() -> Instant) {
+        self.clock = clock;
+        self.idle_since = clock();
+    }
+
+    // Enable or disable the idle timeout, starting the timer afresh
+    pub(crate) fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.idle_timeout = timeout;
+        self.idle_since = (self.clock)();
+        self.idle_held = false;
+    }
+
+    // Test whether the idle timer should be held, because the lack of
+    // traffic is due to the local side: both directions are paused,
+    // or reads are held off until the incoming pipe is consumed
+    fn idle_hold(&self, pbuf: &PBufRdWr) -> bool {
+        (self.pause_reads && self.pause_writes) || (!pbuf.wr.is_eof() && self.reads_held(&pbuf.wr))
+    }
+
+    // Test whether the idle timer has nothing left to watch
+    #[inline]
+    fn idle_finished(&self) -> bool {
+        self.aborted || self.state() == LinkState::Closed
+    }
+
+    // Enable or disable the close deadline
+    #[inline]
+    pub(crate) fn set_close_deadline(&mut self, deadline: Option<Duration>) {
+        self.close_deadline = deadline;
+    }
+
+    // Get the time at which the idle timeout or the close deadline
+    // will expire, or transfers held off by a rate limit can carry
+    // on, whichever is first, if enabled and running
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        if self.failed {
+            return None;
+        }
+        [
+            self.idle_expiry(),
+            self.close_expiry(),
+            self.rate_deadline(&self.read_rate, self.pause_reads, self.max_read_unit),
+            self.rate_deadline(&self.write_rate, self.pause_writes, self.max_write_unit),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    // Get the time at which transfers held off by a rate limit can
+    // carry on, in units of up to `unit` bytes
+    fn rate_deadline(
+        &self,
+        bucket: &Option<TokenBucket>,
+        paused: bool,
+        unit: usize,
+    ) -> Option<Instant> {
+        if paused || self.halted() {
+            return None;
+        }
+        bucket.as_ref()?.next_deadline(unit)
+    }
+
+    fn idle_expiry(&self) -> Option<Instant> {
+        let timeout = self.idle_timeout?;
+        if self.idle_held || self.idle_finished() {
+            return None;
+        }
+        self.idle_since.checked_add(timeout)
+    }
+
+    // The close deadline runs from when EOF was passed on until the
+    // peer's EOF arrives
+    fn close_expiry(&self) -> Option<Instant> {
+        let since = self.close_since?;
+        if self.eof_in || self.aborted {
+            return None;
+        }
+        since.checked_add(self.close_deadline?)
+    }
+
+    // Check the idle timer and the close deadline at the start of a
+    // `process` call.  Returns `Some(_)` if the call should do nothing
+    // more, either because one has just expired, in which case the
+    // link is aborted in both directions, or because one expired
+    // earlier.
+    pub(crate) fn check_idle(
+        &mut self,
+        stream: &mut impl Shutdownable,
+        mut pbuf: PBufRdWr,
+    ) -> Option<Activity> {
+        if self.halted() {
+            return Some(Activity::NONE);
+        }
+        if let Some(expiry) = self.close_expiry() {
+            if expiry <= (self.clock)() {
+                let mut act = Activity::TIMED_OUT | Activity::ABORTED;
+                self.abandon(stream, Some(&mut pbuf.wr), &mut act);
+                self.timed_out = true;
+                self.tracer.close_expired();
+                self.report_read(0, act);
+                return Some(act);
+            }
+        }
+        let timeout = self.idle_timeout?;
+        if self.idle_finished() {
+            return None;
+        }
+        let now = (self.clock)();
+        if self.idle_held || self.idle_hold(&pbuf) {
+            // Time spent held doesn't count as idle
+            self.idle_since = now;
+            return None;
+        }
+        if self.idle_since.checked_add(timeout)? > now {
+            return None;
+        }
+
+        let mut act = Activity::TIMED_OUT | Activity::ABORTED;
+        self.abandon(stream, Some(&mut pbuf.wr), &mut act);
+        self.timed_out = true;
+        self.tracer.timed_out();
+        self.report_read(0, act);
+        Some(act)
+    }
+
+    // Abort the link in both directions: abort the incoming pipe,
+    // and shut down or reset the stream according to **abort_mode**
+    pub(crate) fn abandon(
+        &mut self,
+        stream: &mut impl Shutdownable,
+        pwr: Option<&mut PBufWr>,
+        act: &mut Activity,
+    ) {
+        self.abort_in(pwr, act);
+        // The stream is being abandoned, so errors from the shutdown
+        // don't matter
+        if self.abort_mode == AbortMode::ShutdownBoth || stream.reset().is_err() {
+            let _ = retry!(stream.shutdown(Shutdown::Both));
+        }
+    }
+
+    // Move the settings and state for writes into a new link, for
+    // the write half of a split link, leaving this one as the read
+    // half.  The observer, tee and the idle and close timers stay
+    // here, and each half carries on with its own copy of the stats.
+    pub(crate) fn split_writes(&mut self) -> GenericLink {
+        let mut wr = GenericLink::new(self.max_read_unit);
+        wr.max_write_unit = self.max_write_unit;
+        wr.pause_writes = self.pause_writes;
+        wr.latch = self.latch;
+        wr.write_blocked = self.write_blocked;
+        wr.tracer = self.tracer.clone();
+        wr.stats = self.stats.clone();
+        wr.write_rate = self.write_rate.take();
+        wr.write_quota = self.write_quota.take();
+        wr.write_quota_mode = self.write_quota_mode;
+        wr.write_quota_hit = self.write_quota_hit;
+        wr.eof_out = self.eof_out;
+        wr.shutdown_pending = self.shutdown_pending;
+        wr.aborted = self.aborted;
+        wr.close_when_flushed = self.close_when_flushed;
+        wr.closed_early = self.closed_early;
+        wr.bytes_out = std::mem::take(&mut self.bytes_out);
+        wr.clock = self.clock;
+        wr.last_write_error = self.last_write_error.take();
+        wr.failed = self.failed;
+        wr.abort_mode = self.abort_mode;
+        wr
+    }
+
+    // Abort the incoming pipe, or if this link doesn't hold it, flag
+    // that its owner needs to
+    fn abort_in(&mut self, pwr: Option<&mut PBufWr>, act: &mut Activity) {
+        match pwr {
+            Some(pwr) => abort_in(pwr, act),
+            None => self.abort_incoming = true,
+        }
+        tee_eof(&mut self.tee, *act);
+    }
+
+    // Abort the link at once, as for an outgoing abort, and from then
+    // on discard data from the outgoing pipe
+    pub(crate) fn abort_now(
+        &mut self,
+        stream: &mut impl Shutdownable,
+        mut pbuf: PBufRdWr,
+    ) -> Activity {
+        if self.halted() || self.closed_early || self.aborted {
+            return Activity::NONE;
+        }
+        let mut act = Activity::EOF_OUT | Activity::ABORTED;
+        self.abandon(stream, Some(&mut pbuf.wr), &mut act);
+        self.closed_early = true;
+        self.shutdown_pending = false;
+        discard_out(&mut pbuf.rd);
+        self.report_write(0, 0, act);
+        act
+    }
+
+    // Shut down the stream for `close_when_flushed` once the
+    // outgoing pipe is empty
+    fn close_flushed(
+        &mut self,
+        stream: &mut impl Shutdownable,
+        prd: &PBufRd,
+        act: &mut Activity,
+    ) -> Result<()> {
+        if !self.close_when_flushed || self.closed_early || !prd.is_empty() || prd.is_eof() {
+            return Ok(());
+        }
+        match shutdown_result(retry!(stream.shutdown(Shutdown::Write))) {
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => self.shutdown_pending = true,
+            Err(e) => return Err(Error::shutdown(e)),
+            Ok(_) => {
+                self.closed_early = true;
+                self.shutdown_pending = false;
+                *act |= Activity::EOF_OUT;
+            }
+        }
+        Ok(())
+    }
+
+    // Update the idle timer at the end of a `process` call
+    pub(crate) fn update_idle(&mut self, pbuf: &PBufRdWr) {
+        if self.idle_timeout.is_some() {
+            self.idle_held = self.idle_hold(pbuf);
+            if self.idle_held {
+                self.idle_since = (self.clock)();
+            }
+        }
+    }
+
+    // Test whether the link has stopped for good, because of a
+    // timeout or a fatal error, so that `process` calls must not
+    // touch the stream again
+    #[inline]
+    pub(crate) fn halted(&self) -> bool {
+        self.timed_out || self.failed
+    }
+
+    // Record a fatal error from a `process` call, if there was one.
+    // Failing to set an option or to register doesn't affect the
+    // stream itself, so those errors are only passed back.
+    #[inline]
+    pub(crate) fn fail<T>(&mut self, rv: Result<T>) -> Result<T> {
+        if let Err(ref e) = rv {
+            self.failed |= !matches!(e.op(), Op::SetOption | Op::Register);
+        }
+        rv
+    }
+
+    // Pass on the outcome of a read to the tracer and observer
+    pub(crate) fn report_read(&mut self, bytes: usize, act: Activity) {
+        self.touch(bytes);
+        self.eof_in |= act.contains(Activity::EOF_IN);
+        self.aborted |= act.contains(Activity::ABORTED);
+        self.tracer.read(bytes, self.read_total(), act);
+        if let Some(ref mut observer) = self.observer {
+            observe_read(observer.as_mut(), bytes, act);
+        }
+    }
+
+    // Pass on the outcome of a write to the tracer and observer, with
+    // `left` bytes remaining in the outgoing pipe
+    pub(crate) fn report_write(&mut self, bytes: usize, left: usize, act: Activity) {
+        self.touch(bytes);
+        self.eof_in |= act.contains(Activity::EOF_IN);
+        self.eof_out |= act.contains(Activity::EOF_OUT);
+        self.aborted |= act.contains(Activity::ABORTED);
+        if act.contains(Activity::EOF_OUT) && self.close_since.is_none() {
+            self.close_since = Some((self.clock)());
+        }
+        self.tracer.wrote(bytes, left, act);
+        if let Some(ref mut observer) = self.observer {
+            observe_write(observer.as_mut(), bytes, act);
+        }
+    }
+
+    // Unpause reads and writes according to the readiness reported
+    // by the event.  Returns `false` if the event only reports that
+    // the stream is writable, in which case a read would just give
+    // `WouldBlock` and can be skipped.
+    pub(crate) fn apply_event(&mut self, event: &Event) -> bool {
+        let read_ready = event.is_readable() || event.is_read_closed() || event.is_error();
+        let write_ready = event.is_writable() || event.is_write_closed() || event.is_error();
+        if read_ready {
+            self.note_readable();
+        }
+        if write_ready {
+            self.note_writable();
+        }
+        read_ready || !write_ready
+    }
+
+    // Record fresh read readiness, unpausing reads
+    #[inline]
+    pub(crate) fn note_readable(&mut self) {
+        self.pause_reads = false;
+        self.read_blocked = false;
+    }
+
+    // Record fresh write readiness, unpausing writes
+    #[inline]
+    pub(crate) fn note_writable(&mut self) {
+        self.pause_writes = false;
+        self.write_blocked = false;
+    }
+
+    // Pause or unpause reads.  Unpausing counts as fresh readiness.
+    #[inline]
+    pub(crate) fn set_pause_reads(&mut self, pause: bool) {
+        self.pause_reads = pause;
+        self.read_blocked &= pause;
+    }
+
+    // Pause or unpause writes.  Unpausing counts as fresh readiness.
+    #[inline]
+    pub(crate) fn set_pause_writes(&mut self, pause: bool) {
+        self.pause_writes = pause;
+        self.write_blocked &= pause;
+    }
+
+    // Test whether reads should be skipped, because they are paused
+    // or latched after `WouldBlock`
+    #[inline]
+    pub(crate) fn reads_stopped(&self) -> bool {
+        self.pause_reads || (self.latch && self.read_blocked)
+    }
+
+    // Test whether writes should be skipped, likewise
+    #[inline]
+    pub(crate) fn writes_stopped(&self) -> bool {
+        self.pause_writes || (self.latch && self.write_blocked)
+    }
+
+    // Test whether reads are held off until more of the incoming pipe
+    // is consumed
+    fn reads_held(&self, pwr: &PBufWr) -> bool {
+        pwr.exceeds_limit(self.max_read_unit)
+            || self.backpressure_held(pwr)
+            || self.read_limits().room(pwr).is_none()
+    }
+
+    // Work out the minimal interest needed for the current state of
+    // the link and pipes, or `None` if no events are needed
+    pub(crate) fn needed_interest(&self, pbuf: &PBufRdWr) -> Option<Interest> {
+        if self.halted() {
+            return None;
+        }
+        let read = !self.pause_reads
+            && !pbuf.wr.is_eof()
+            && !self.reads_held(&pbuf.wr)
+            && !is_throttled(&self.read_rate);
+        let write = !self.closed_early
+            && (!pbuf.rd.is_empty() || pbuf.rd.has_pending_eof() || self.close_when_flushed)
+            && !is_throttled(&self.write_rate)
+            && !self.write_quota_stalled(&pbuf.rd);
+        match (read, write) {
+            (true, true) => Some(Interest::READABLE | Interest::WRITABLE),
+            (true, false) => Some(Interest::READABLE),
+            (false, true) => Some(Interest::WRITABLE),
+            (false, false) => None,
+        }
+    }
+
+    pub(crate) fn process(
+        &mut self,
+        stream: &mut impl Shutdownable,
+        pbuf: PBufRdWr,
+    ) -> Result<Activity> {
+        self.process_with(stream, pbuf, &mut NoHooks)
+    }
+
+    // As `process`, with stream-specific handling around the writes
+    pub(crate) fn process_with<S: Shutdownable>(
+        &mut self,
+        stream: &mut S,
+        mut pbuf: PBufRdWr,
+        hooks: &mut impl WriteHooks<S>,
+    ) -> Result<Activity> {
+        if let Some(act) = self.check_idle(stream, pbuf.reborrow()) {
+            return Ok(act);
+        }
+        let act = self.process_both(stream, pbuf.reborrow(), true, hooks)?;
+        self.update_idle(&pbuf);
+        Ok(act)
+    }
+
+    // Write and then, if `read` is set, read, in the order selected
+    // by **order**
+    pub(crate) fn process_both<S: Shutdownable>(
+        &mut self,
+        stream: &mut S,
+        mut pbuf: PBufRdWr,
+        read: bool,
+        hooks: &mut impl WriteHooks<S>,
+    ) -> Result<Activity> {
+        let all = usize::MAX;
+        if !read {
+            return self.process_out_max(stream, pbuf, all, hooks);
+        }
+        match self.order {
+            ProcessOrder::WriteThenRead => {
+                let act = self.process_out_max(stream, pbuf.reborrow(), all, hooks)?;
+                Ok(act | self.process_in_max(stream, pbuf.wr, all)?)
+            }
+            ProcessOrder::ReadThenWrite => {
+                let act = self.process_in_max(stream, pbuf.wr.reborrow(), all)?;
+                Ok(act | self.process_out_max(stream, pbuf, all, hooks)?)
+            }
+            ProcessOrder::Interleaved { chunk } => {
+                let (mut write_left, mut read_left) = (self.max_write_unit, self.read_total());
+                let mut act = Activity::NONE;
+                loop {
+                    let (bytes_in, bytes_out) = (self.bytes_in, self.bytes_out);
+                    act |= self.process_out_max(
+                        stream,
+                        pbuf.reborrow(),
+                        chunk.min(write_left),
+                        hooks,
+                    )?;
+                    act |= self.process_in_max(stream, pbuf.wr.reborrow(), chunk.min(read_left))?;
+                    if interleave_step(
+                        self.bytes_out - bytes_out,
+                        self.bytes_in - bytes_in,
+                        &mut write_left,
+                        &mut read_left,
+                    ) {
+                        return Ok(act);
+                    }
+                }
+            }
+        }
+    }
+
+    pub(crate) fn process_out(
+        &mut self,
+        stream: &mut impl Shutdownable,
+        pbuf: PBufRdWr,
+    ) -> Result<Activity> {
+        self.process_out_max(stream, pbuf, usize::MAX, &mut NoHooks)
+    }
+
+    // As `process_out`, but writing no more than `max` bytes as well
+    // as the other limits, with stream-specific handling around the
+    // write
+    pub(crate) fn process_out_max<S: Shutdownable>(
+        &mut self,
+        stream: &mut S,
+        pbuf: PBufRdWr,
+        max: usize,
+        hooks: &mut impl WriteHooks<S>,
+    ) -> Result<Activity> {
+        let PBufRdWr { rd, mut wr } = pbuf;
+        self.write_from(stream, rd, Some(&mut wr), max, hooks)
+    }
+
+    // As `process_out_max`, for a link that doesn't hold the incoming
+    // pipe.  An abort that needs passing on to the incoming pipe sets
+    // **abort_incoming** instead.
+    pub(crate) fn process_out_half<S: Shutdownable>(
+        &mut self,
+        stream: &mut S,
+        prd: PBufRd,
+        hooks: &mut impl WriteHooks<S>,
+    ) -> Result<Activity> {
+        self.write_from(stream, prd, None, usize::MAX, hooks)
+    }
+
+    fn write_from<S: Shutdownable>(
+        &mut self,
+        stream: &mut S,
+        mut prd: PBufRd,
+        mut pwr: Option<&mut PBufWr>,
+        max: usize,
+        hooks: &mut impl WriteHooks<S>,
+    ) -> Result<Activity> {
+        if self.writes_stopped() || self.halted() {
+            return Ok(Activity::NONE);
+        }
+        if self.closed_early {
+            discard_out(&mut prd);
+            return Ok(Activity::NONE);
+        }
+        if self.write_quota_stalled(&prd) {
+            return Ok(self.write_quota_reached(stream, &prd, pwr));
+        }
+        hooks.before_write(stream, &mut prd, &self.tracer)?;
+
+        let mut max = max.min(self.max_write_unit);
+        if let Some(ref mut bucket) = self.write_rate {
+            max = max.min(bucket.refill((self.clock)()));
+        }
+        if let Some(quota) = self.write_quota {
+            max = max.min(usize::try_from(quota).unwrap_or(usize::MAX));
+        }
+        let mut written = 0;
+        let mut latch = LatchStream::new(stream);
+        let rv = match self.stats {
+            Some(ref mut stats) => write_out(
+                &mut StatsStream::new(&mut latch, stats),
+                prd.reborrow(),
+                max,
+                self.abort_mode,
+                &mut written,
+            ),
+            None => write_out(
+                &mut latch,
+                prd.reborrow(),
+                max,
+                self.abort_mode,
+                &mut written,
+            ),
+        };
+        self.write_blocked = latch.blocked;
+        self.note_write_error(latch.error.take());
+        // Count data written even if the write then failed
+        self.bytes_out += written as u64;
+        if let Some(ref mut bucket) = self.write_rate {
+            bucket.take(written, !prd.is_empty());
+        }
+        if let Some(ref mut quota) = self.write_quota {
+            *quota = quota.saturating_sub(written as u64);
+        }
+        let (mut act, abort_incoming) = self.fail(rv.inspect_err(|e| self.tracer.error(e)))?;
+        if abort_incoming {
+            self.abort_in(pwr.as_deref_mut(), &mut act);
+            self.closed_early = true;
+        }
+        self.shutdown_pending = prd.is_empty() && prd.has_pending_eof();
+        let rv = self.close_flushed(&mut latch, &prd, &mut act);
+        self.note_write_error(latch.error);
+        self.fail(rv.inspect_err(|e| self.tracer.error(e)))?;
+        self.report_write(written, prd.len(), act);
+
+        hooks.after_write(stream, &prd, &self.tracer)?;
+        if self.write_quota_stalled(&prd) {
+            act |= self.write_quota_reached(stream, &prd, pwr);
+        }
+        Ok(act)
+    }
+
+    // Act on the write quota running out with data still to write,
+    // reporting it only once
+    fn write_quota_reached(
+        &mut self,
+        stream: &mut impl Shutdownable,
+        prd: &PBufRd,
+        pwr: Option<&mut PBufWr>,
+    ) -> Activity {
+        if self.write_quota_hit {
+            return Activity::NONE;
+        }
+        self.write_quota_hit = true;
+        let mut act = Activity::QUOTA;
+        self.tracer.quota(Direction::Outgoing);
+        if self.write_quota_mode == WriteQuotaMode::Abort {
+            act |= Activity::ABORTED;
+            self.abandon(stream, pwr, &mut act);
+        }
+        self.report_write(0, prd.len(), act);
+        act
+    }
+
+    pub(crate) fn process_in(
+        &mut self,
+        stream: &mut impl Shutdownable,
+        pbuf: PBufRdWr,
+    ) -> Result<Activity> {
+        self.process_in_max(stream, pbuf.wr, usize::MAX)
+    }
+
+    // As `process_in`, but reading no more than `max` bytes as well
+    // as the other limits
+    pub(crate) fn process_in_max(
+        &mut self,
+        stream: &mut impl Shutdownable,
+        mut pwr: PBufWr,
+        max: usize,
+    ) -> Result<Activity> {
+        if self.reads_stopped()
+            || self.halted()
+            || self.hold_reads
+            || pwr.is_eof()
+            || self.check_backpressure(&pwr)
+        {
+            return Ok(Activity::NONE);
+        }
+        let mut stream = LatchStream::new(stream);
+        if self.read_quota == Some(0) {
+            let act = self.read_quota_reached(&mut stream, &mut pwr);
+            self.note_read_error(stream.error);
+            self.report_read(0, act);
+            return Ok(act);
+        }
+        let mut limits = self.read_limits();
+        limits.total = limits.total.min(max);
+        if let Some(ref mut bucket) = self.read_rate {
+            let allowed = bucket.refill((self.clock)());
+            if allowed == 0 {
+                return Ok(Activity::NONE);
+            }
+            limits.total = limits.total.min(allowed);
+        }
+        if let Some(quota) = self.read_quota {
+            limits.total = limits
+                .total
+                .min(usize::try_from(quota).unwrap_or(usize::MAX));
+        }
+        let mut bytes = 0;
+        let on_data = |data: &[u8]| bytes += data.len();
+        let rv = match self.stats {
+            Some(ref mut stats) => read_in_tee(
+                &mut StatsStream::new(&mut stream, stats),
+                pwr.reborrow(),
+                limits,
+                &mut self.tee,
+                on_data,
+            ),
+            None => read_in_tee(&mut stream, pwr.reborrow(), limits, &mut self.tee, on_data),
+        };
+        self.read_blocked = stream.blocked;
+        self.note_read_error(stream.error.take());
+        self.adapt_read_unit(bytes, limits.total);
+        // Count data read even if the read then failed
+        self.bytes_in += bytes as u64;
+        if let Some(ref mut bucket) = self.read_rate {
+            bucket.take(bytes, true);
+        }
+        if let Some(ref mut quota) = self.read_quota {
+            *quota = quota.saturating_sub(bytes as u64);
+        }
+        let mut act = self.fail(rv.inspect_err(|e| self.tracer.error(e)))?;
+        if self.read_quota == Some(0) && !pwr.is_eof() {
+            act |= self.read_quota_reached(&mut stream, &mut pwr);
+        }
+        self.note_read_error(stream.error);
+        self.report_read(bytes, act);
+        Ok(act)
+    }
+
+    // End the incoming pipe on the read quota running out, and
+    // optionally shut down the read half.  The pipe has already been
+    // ended, so a failed shutdown is only traced, and the caller keeps
+    // its error as the last read error.
+    fn read_quota_reached(&mut self, stream: &mut impl Shutdownable, pwr: &mut PBufWr) -> Activity {
+        let mut act = Activity::QUOTA;
+        match self.read_quota_mode {
+            ReadQuotaMode::Close => close_in(pwr, &mut act),
+            ReadQuotaMode::Abort => abort_in(pwr, &mut act),
+        }
+        tee_eof(&mut self.tee, act);
+        self.tracer.quota(Direction::Incoming);
+        if self.read_quota_shutdown {
+            if let Err(e) = shutdown_result(retry!(stream.shutdown(Shutdown::Read))) {
+                let e = Error::new(Direction::Incoming, Op::Shutdown, e);
+                self.tracer.error(&e);
+            }
+        }
+        act
+    }
+}
+
+// Stream-specific handling around the writes of a `process` call,
+// for example TCP corking
+pub(crate) trait WriteHooks<S> {
+    // Prepare for writing from the outgoing pipe
+    fn before_write(&mut self, stream: &mut S, prd: &mut PBufRd, tracer: &Tracer) -> Result<()>;
+
+    // Act on what is left in the outgoing pipe after writing
+    fn after_write(&mut self, stream: &mut S, prd: &PBufRd, tracer: &Tracer) -> Result<()>;
+}
+
+// No stream-specific handling around writes
+pub(crate) struct NoHooks;
+
+impl<S> WriteHooks<S> for NoHooks {
+    #[inline]
+    fn before_write(&mut self, _: &mut S, _: &mut PBufRd, _: &Tracer) -> Result<()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn after_write(&mut self, _: &mut S, _: &PBufRd, _: &Tracer) -> Result<()> {
+        Ok(())
+    }
+}
+
+// Test whether transfers are waiting for a rate limit's tokens
+#[inline]
+fn is_throttled(bucket: &Option<TokenBucket>) -> bool {
+    bucket.as_ref().is_some_and(TokenBucket::is_throttled)
+}
+
+// Throw away the data and any EOF in the outgoing pipe, once there
+// is nowhere left to write it
+fn discard_out(prd: &mut PBufRd) {
+    prd.consume(prd.len());
+    prd.consume_eof();
+}
+
+// Account for one round of `ProcessOrder::Interleaved`, given the
+// bytes written and read in the round, along with the amount left
+// that may be written and read in the `process` call.  Returns `true`
+// once neither direction made progress, or both are used up.
+pub(crate) fn interleave_step(
+    written: u64,
+    read: u64,
+    write_left: &mut usize,
+    read_left: &mut usize,
+) -> bool {
+    *write_left = write_left.saturating_sub(written as usize);
+    *read_left = read_left.saturating_sub(read as usize);
+    (written == 0 && read == 0) || (*write_left == 0 && *read_left == 0)
+}
+
+// Write as much data as possible, up to `max_write_unit` bytes,
+// handling EOF with a shutdown once the pipe is empty, or for an
+// abort as selected by `abort_mode`.  The reset modes fall
+// back to `ShutdownBoth` if the stream doesn't support a reset.  If
+// the peer has gone, or the connection timed out, e.g. on
+// `TCP_USER_TIMEOUT`, the remaining data is discarded and the abort
+// is passed on as for a read.  Returns the activity status and
+// whether the incoming pipe needs to be aborted.  The number of
+// bytes actually written is stored in `written`, even on error.
+pub(crate) fn write_out(
+    stream: &mut impl Shutdownable,
+    mut prd: PBufRd,
+    max_write_unit: usize,
+    abort_mode: AbortMode,
+    written: &mut usize,
+) -> Result<(Activity, bool)> {
+    // Stream flush() does nothing for the OS streams as they do
+    // write() syscalls directly (which don't buffer).  So there is no
+    // need to give the option to force flushes.
+    let mut act = Activity::NONE;
+    let mut abort_incoming = false;
+
+    // A shutdown would send FIN before the RST, so the reset modes
+    // skip it
+    if abort_mode == AbortMode::Reset
+        && prd.has_pending_eof()
+        && prd.is_aborted()
+        && stream.reset().is_ok()
+    {
+        prd.consume(prd.len());
+        consume_eof_out(&mut prd, &mut act);
+        return Ok((act, true));
+    }
+
+    let len = prd.len();
+    let rv = output_to_max(&mut prd, stream, max_write_unit, &mut act);
+    *written = len - prd.len();
+    match rv {
+        Err(ref e) if e.kind() == ErrorKind::WouldBlock => (),
+        Err(ref e)
+            if matches!(
+                e.kind(),
+                ErrorKind::BrokenPipe
+                    | ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::TimedOut
+            ) =>
+        {
+            // Nothing more can be written, so finish the outgoing side
+            prd.consume(prd.len());
+            consume_eof_out(&mut prd, &mut act);
+            act |= Activity::ABORTED;
+            return Ok((act, true));
+        }
+        Err(e) => return Err(Error::write(e)),
+        Ok(_) => {
+            if prd.is_empty() && prd.has_pending_eof() {
+                if prd.is_aborted()
+                    && abort_mode == AbortMode::FlushThenReset
+                    && stream.reset().is_ok()
+                {
+                    consume_eof_out(&mut prd, &mut act);
+                    return Ok((act, true));
+                }
+                let shutdown = if prd.is_aborted() {
+                    abort_incoming = true;
+                    Shutdown::Both
+                } else {
+                    Shutdown::Write
+                };
+                match shutdown_result(retry!(stream.shutdown(shutdown))) {
+                    Err(ref e) if e.kind() == ErrorKind::WouldBlock => (),
+                    Err(e) => return Err(Error::shutdown(e)),
+                    Ok(_) => consume_eof_out(&mut prd, &mut act),
+                }
+            }
+        }
+    }
+    Ok((act, abort_incoming))
+}
+
+// Limits on the reads made by one `process_in` call
+#[derive(Clone, Copy)]
+pub(crate) struct ReadLimits {
+    // Maximum size of each read
+    pub(crate) unit: usize,
+
+    // Maximum total size of the reads
+    pub(crate) total: usize,
+
+    // Minimum space in the pipe needed to attempt a read
+    pub(crate) low_water: usize,
+}
+
+impl ReadLimits {
+    // Get the space available for the next read: up to `unit` bytes,
+    // or less for a fixed-capacity pipe that is filling up.  Returns
+    // `None` if there is too little space to be worth a read.
+    #[inline]
+    pub(crate) fn room(&self, pwr: &PBufWr) -> Option<usize> {
+        let room = match pwr.free_space() {
+            Some(free) => free.min(self.unit),
+            None => self.unit,
+        };
+        (room > 0 && room >= self.low_water).then_some(room)
+    }
+}
+
+// Read in units of up to `limits.unit` bytes until the stream blocks,
+// EOF is reached, `limits.total` bytes have been read, or the pipe
+// has too little space left, passing on close and abort, and passing
+// each chunk of data read to `on_data`
+pub(crate) fn read_in_with(
+    stream: &mut impl Read,
+    mut pwr: PBufWr,
+    limits: ReadLimits,
+    mut on_data: impl FnMut(&[u8]),
+) -> Result<Activity> {
+    let mut act = Activity::NONE;
+    let mut total = 0;
+    while total < limits.total {
+        let Some(room) = limits.room(&pwr) else {
+            break;
+        };
+        let unit = room.min(limits.total - total);
+        let mut count = 0;
+        let rv = input_from_with(&mut pwr, stream, unit, &mut act, |data| {
+            count += data.len();
+            on_data(data);
+        });
+        total += count;
+        if let Err(e) = rv {
+            match e.kind() {
+                ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | ErrorKind::TimedOut => {
+                    abort_in(&mut pwr, &mut act)
+                }
+                ErrorKind::WouldBlock => (),
+                _ => return Err(Error::read(e)),
+            }
+            break;
+        }
+        // A short read without an error means EOF
+        if count < unit || count == 0 {
+            break;
+        }
+    }
+    Ok(act)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adaptive(min: usize, max: usize) -> GenericLink {
+        let mut link = GenericLink::new(2048);
+        link.set_read_unit_range(min, max);
+        link
+    }
+
+    #[test]
+    fn read_unit_grows_to_max() {
+        let mut link = adaptive(1000, 5000);
+        assert_eq!(link.max_read_unit, 1000);
+        let mut seen = Vec::new();
+        for _ in 0..5 {
+            link.adapt_read_unit(link.max_read_unit, usize::MAX);
+            seen.push(link.max_read_unit);
+        }
+        assert_eq!(seen, [2000, 4000, 5000, 5000, 5000]);
+    }
+
+    #[test]
+    fn read_unit_decays_to_min() {
+        let mut link = adaptive(1000, 64000);
+        link.max_read_unit = 64000;
+        let mut seen = Vec::new();
+        for _ in 0..8 {
+            link.adapt_read_unit(0, usize::MAX);
+            seen.push(link.max_read_unit);
+        }
+        assert_eq!(seen, [32000, 16000, 8000, 4000, 2000, 1000, 1000, 1000]);
+    }
+
+    #[test]
+    fn read_unit_holds_in_between() {
+        let mut link = adaptive(1000, 64000);
+        link.max_read_unit = 8000;
+
+        // A quarter or more of a unit, but less than a whole one
+        link.adapt_read_unit(2000, usize::MAX);
+        assert_eq!(link.max_read_unit, 8000);
+        link.adapt_read_unit(7999, usize::MAX);
+        assert_eq!(link.max_read_unit, 8000);
+
+        // A short read is not counted against the unit if the call's
+        // limit was lower, e.g. a nearly full pipe
+        link.adapt_read_unit(600, 2000);
+        assert_eq!(link.max_read_unit, 8000);
+        link.adapt_read_unit(400, 2000);
+        assert_eq!(link.max_read_unit, 4000);
+    }
+
+    #[test]
+    fn read_unit_range_is_normalized() {
+        let link = adaptive(0, 0);
+        assert_eq!(link.read_unit_range, Some((1, 1)));
+        let link = adaptive(5000, 1000);
+        assert_eq!(link.read_unit_range, Some((5000, 5000)));
+        assert_eq!(link.max_read_unit, 5000);
+    }
+
+    #[test]
+    fn fixed_read_unit_does_not_adapt() {
+        let mut link = adaptive(1000, 5000);
+        link.set_max_read_unit(3000);
+        link.adapt_read_unit(3000, usize::MAX);
+        assert_eq!(link.max_read_unit, 3000);
+        link.adapt_read_unit(0, usize::MAX);
+        assert_eq!(link.max_read_unit, 3000);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::{Activity, Result, TcpLink};
+use mio::net::TcpStream;
+use pipebuf::PBufRdWr;
+
+/// What a [`LinkGuard`] does in debug builds when it is dropped
+/// before the link is done
+///
+/// This only has an effect when `debug_assertions` are enabled, for
+/// catching connection-handling bugs in tests.  In release builds
+/// the guard just tears down the connection.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LeakCheck {
+    /// Tear down the connection quietly
+    #[default]
+    Ignore,
+    /// Tear down the connection, and print a message to stderr
+    Log,
+    /// Tear down the connection, and then panic, unless the thread
+    /// is already panicking
+    Panic,
+}
+
+/// Guard that tears down a TCP connection if it is dropped before
+/// the link is done
+///
+/// This holds a `mio` [`TcpStream`], its [`TcpLink`] and the
+/// link-side end of the pipe-buffers.  If it is dropped when
+/// [`TcpLink::is_done`] is not yet true, for example because of a
+/// bug that drops a connection without running the shutdown
+/// sequence, then the incoming pipe is aborted so that the
+/// application sees an abort rather than waiting forever, and the
+/// stream is shut down in both directions so that the peer isn't
+/// left waiting either.  The shutdown follows
+/// [`TcpLink::set_abort_mode`], i.e. it may be a reset.
+///
+/// On the normal path, once the link is done or when the parts are
+/// needed separately, take the parts back with
+/// [`LinkGuard::into_parts`], which does nothing else.
+pub struct LinkGuard<'a> {
+    parts: Option<(TcpStream, TcpLink, PBufRdWr<'a>)>,
+    leak_check: LeakCheck,
+}
+
+impl<'a> LinkGuard<'a> {
+    /// Guard the given stream, link and link-side pipe-buffers
+    #[inline]
+    pub fn new(stream: TcpStream, link: TcpLink, pbuf: PBufRdWr<'a>) -> Self {
+        Self {
+            parts: Some((stream, link, pbuf)),
+            leak_check: LeakCheck::Ignore,
+        }
+    }
+
+    /// Change what happens in debug builds if the guard is dropped
+    /// before the link is done.  See [`LeakCheck`].
+    #[inline]
+    pub fn set_leak_check(&mut self, check: LeakCheck) {
+        self.leak_check = check;
+    }
+
+    /// Read and write as much data as possible.  See
+    /// [`TcpLink::process`].
+    #[inline]
+    pub fn process(&mut self) -> Result<Activity> {
+        let (stream, link, pbuf) = self.parts_mut();
+        link.process(stream, pbuf.reborrow())
+    }
+
+    /// Test whether the link is done, in which case dropping the
+    /// guard has no effect.  See [`TcpLink::is_done`].
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        let (_, link, pbuf) = self.parts();
+        link.is_done(pbuf)
+    }
+
+    /// Get access to the stream, for example to register it
+    #[inline]
+    pub fn stream(&mut self) -> &mut TcpStream {
+        self.parts_mut().0
+    }
+
+    /// Get access to the link, to change settings or call `process`
+    /// variants
+    #[inline]
+    pub fn link(&mut self) -> &mut TcpLink {
+        self.parts_mut().1
+    }
+
+    /// Get access to all three parts at once, for passing to
+    /// [`TcpLink`] calls
+    #[inline]
+    pub fn parts_mut(&mut self) -> (&mut TcpStream, &mut TcpLink, &mut PBufRdWr<'a>) {
+        // Only `into_parts` and `drop` take the parts
+        let (stream, link, pbuf) = self.parts.as_mut().unwrap();
+        (stream, link, pbuf)
+    }
+
+    /// Take back the stream, link and pipe-buffers, without any
+    /// teardown
+    #[inline]
+    pub fn into_parts(mut self) -> (TcpStream, TcpLink, PBufRdWr<'a>) {
+        self.parts.take().unwrap()
+    }
+
+    #[inline]
+    fn parts(&self) -> &(TcpStream, TcpLink, PBufRdWr<'a>) {
+        self.parts.as_ref().unwrap()
+    }
+}
+
+impl Drop for LinkGuard<'_> {
+    fn drop(&mut self) {
+        let Some((mut stream, mut link, mut pbuf)) = self.parts.take() else {
+            return;
+        };
+        if link.is_done(&pbuf) {
+            return;
+        }
+        link.teardown(&mut stream, &mut pbuf.wr);
+
+        #[cfg(debug_assertions)]
+        match self.leak_check {
+            LeakCheck::Ignore => (),
+            LeakCheck::Log => {
+                eprintln!(
+                    "pipebuf_mio: LinkGuard dropped before link was done; connection torn down"
+                );
+            }
+            LeakCheck::Panic => {
+                if !std::thread::panicking() {
+                    panic!("LinkGuard dropped before link was done");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use crate::{Error, Result};
+use mio::event::Source;
+use mio::{Interest, Registry, Token};
+use std::io::ErrorKind;
+
+/// Registration state of a source, which avoids redundant
+/// `reregister` calls
+///
+/// This caches the `Interest` last submitted to the `Registry` for a
+/// source.  Pass it the interest the link currently needs on every
+/// loop iteration, e.g. from [`crate::TcpLink::needed_interest`], and
+/// it only makes a system call when that changes.  When nothing is
+/// needed the source is deregistered, and it is registered again when
+/// something is needed again.  Once the link is done, call
+/// [`InterestTracker::finish`] to deregister the source for good.
+///
+/// A stream whose peer has gone away may already have been dropped
+/// from the registry by the OS.  So a failed deregistration is not
+/// reported, and a `reregister` that finds the source unregistered
+/// falls back to `register`.
+pub struct InterestTracker {
+    token: Token,
+
+    // Interest currently registered, or `None` if not registered
+    current: Option<Interest>,
+
+    // Set once `finish` has been called
+    finished: bool,
+}
+
+impl InterestTracker {
+    /// Create a tracker for a source that is not yet registered, and
+    /// which is to be registered with the given token
+    #[inline]
+    pub fn new(token: Token) -> Self {
+        Self {
+            token,
+            current: None,
+            finished: false,
+        }
+    }
+
+    /// Get the token used to register the source
+    #[inline]
+    pub fn token(&self) -> Token {
+        self.token
+    }
+
+    /// Get the interest currently registered, or `None` if the source
+    /// is not registered
+    #[inline]
+    pub fn current(&self) -> Option<Interest> {
+        self.current
+    }
+
+    /// Bring the registration of the source into line with `needed`,
+    /// the interest that the link currently needs.  Returns `true` if
+    /// a call to the registry was made.  After
+    /// [`InterestTracker::finish`] this does nothing.
+    ///
+    /// On a failure to register, the registration state is treated as
+    /// unknown, so the next call will try again, and the error is
+    /// returned with [`crate::Op::Register`].  A `register` that finds
+    /// the source already registered falls back to `reregister`.
+    pub fn update(
+        &mut self,
+        registry: &Registry,
+        source: &mut impl Source,
+        needed: Option<Interest>,
+    ) -> Result<bool> {
+        if self.finished || needed == self.current {
+            return Ok(false);
+        }
+        let rv = match (self.current, needed) {
+            (_, None) => {
+                let _ = registry.deregister(source);
+                Ok(())
+            }
+            (None, Some(interest)) => match registry.register(source, self.token, interest) {
+                Err(ref e) if e.kind() == ErrorKind::AlreadyExists => {
+                    registry.reregister(source, self.token, interest)
+                }
+                rv => rv,
+            },
+            (Some(_), Some(interest)) => match registry.reregister(source, self.token, interest) {
+                Err(ref e) if e.kind() == ErrorKind::NotFound => {
+                    registry.register(source, self.token, interest)
+                }
+                rv => rv,
+            },
+        };
+        self.current = None;
+        rv.map_err(Error::register)?;
+        self.current = needed;
+        Ok(true)
+    }
+
+    /// Deregister the source for good, because the link is done.  Any
+    /// error from the registry is ignored, since by this point the
+    /// source may already have been dropped from it.
+    pub fn finish(&mut self, registry: &Registry, source: &mut impl Source) {
+        if self.current.take().is_some() {
+            let _ = registry.deregister(source);
+        }
+        self.finished = true;
+    }
+
+    /// Test whether [`InterestTracker::finish`] has been called
+    #[inline]
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
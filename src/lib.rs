@@ -1,7 +1,14 @@
 //! [`PipeBuf`] connectors for [`mio`] types
 //!
 //! This permits I/O between pipe-buffers and streaming-based
-//! interfaces.
+//! interfaces.  Datagram-based interfaces are also supported, with
+//! each datagram carried through the pipe-buffer as a
+//! length-prefixed record.
+//!
+//! Links for Unix-only types (Unix sockets, pipes, file descriptors,
+//! child processes and so on) are only compiled on Unix, so the
+//! crate builds on Windows with the TCP and UDP links and the
+//! Windows-only `NamedPipeLink` available.
 //!
 //! [`PipeBuf`]: https://crates.io/crates/pipebuf
 //! [`mio`]: https://crates.io/crates/mio
@@ -17,10 +24,166 @@ macro_rules! retry {
     }};
 }
 
+mod activity;
+pub use activity::Activity;
+
+mod conn;
+pub use conn::TcpConn;
+#[cfg(target_family = "unix")]
+pub use conn::UnixConn;
+
+mod bufsize;
+
+mod connector;
+#[cfg(target_family = "unix")]
+pub use connector::UnixConnector;
+pub use connector::{ConnectStatus, TcpConnector};
+
+mod copy;
+pub use copy::{copy_in, copy_out, InStatus, OutStatus};
+
+mod datagram;
+pub use datagram::DatagramLink;
+
+mod error;
+pub use error::{Direction, Error, Op, Result};
+
+mod file;
+pub use file::FileLink;
+
+mod forward;
+pub use forward::Forwarder;
+
+mod generic;
+
+mod guard;
+pub use guard::{LeakCheck, LinkGuard};
+
+mod interest;
+pub use interest::InterestTracker;
+
+mod listener;
+pub use listener::TcpListenerLink;
+#[cfg(target_family = "unix")]
+pub use listener::UnixListenerLink;
+
+mod loopback;
+pub use loopback::{LoopbackLink, LoopbackStream};
+
+mod manager;
+pub use manager::{Connection, ConnectionManager};
+
+mod multicast;
+pub use multicast::MulticastLink;
+
+mod observer;
+pub use observer::LinkObserver;
+
+mod ratelimit;
+pub use ratelimit::RateLimit;
+
+mod reconnect;
+pub use reconnect::{DisconnectMode, ReconnectingTcpLink};
+
+pub mod record;
+
+#[cfg(feature = "runner")]
+mod runner;
+#[cfg(feature = "runner")]
+pub use runner::Runner;
+
+mod sockopt;
+
+mod state;
+pub use state::LinkState;
+
+mod stats;
+pub use stats::LinkStats;
+
+mod streamlink;
+pub use streamlink::StreamLink;
+
 mod tcpstream;
-pub use tcpstream::TcpLink;
+#[cfg(feature = "std-net")]
+pub use tcpstream::StdTcpLink;
+pub use tcpstream::{
+    AbortMode, KeepaliveConfig, ProcessOrder, ReadQuotaMode, TcpLink, TcpLinkConfig, TcpReadLink,
+    TcpWriteLink, WriteQuotaMode,
+};
+
+mod tcpinfo;
+pub use tcpinfo::TcpInfo;
+
+mod tee;
+pub use tee::{Tee, TeePolicy};
+
+#[cfg(test)]
+mod testutil;
+
+mod trace;
+
+mod udp;
+pub use udp::{UdpLink, UdpPeerLink};
+
+mod wake;
+pub use wake::WakeOnWrite;
+
+#[cfg(any(test, feature = "test-util"))]
+mod mock;
+#[cfg(any(test, feature = "test-util"))]
+pub use mock::{MockOp, MockStream, MockStreamLink};
+
+#[cfg(feature = "socket2")]
+mod socketlink;
+#[cfg(feature = "socket2")]
+pub use socketlink::{SocketLink, SocketMode};
+
+#[cfg(target_family = "unix")]
+mod child;
+#[cfg(target_family = "unix")]
+pub use child::ChildLink;
+
+#[cfg(target_family = "unix")]
+mod fd;
+#[cfg(target_family = "unix")]
+pub use fd::FdLink;
+
+#[cfg(target_family = "unix")]
+mod pipe;
+#[cfg(target_family = "unix")]
+pub use pipe::{PipeReceiverLink, PipeSenderLink};
+
+#[cfg(target_os = "linux")]
+mod splice;
+#[cfg(target_os = "linux")]
+pub use splice::Splicer;
+
+#[cfg(all(target_family = "unix", feature = "stdio"))]
+mod stdio;
+#[cfg(all(target_family = "unix", feature = "stdio"))]
+pub use stdio::StdioLink;
+
+#[cfg(target_family = "unix")]
+mod tuntap;
+#[cfg(target_family = "unix")]
+pub use tuntap::TunTapLink;
+
+#[cfg(target_family = "unix")]
+mod unixdatagram;
+#[cfg(target_family = "unix")]
+pub use unixdatagram::UnixDatagramLink;
 
 #[cfg(target_family = "unix")]
 mod unixstream;
 #[cfg(target_family = "unix")]
-pub use unixstream::UnixStreamLink;
+pub use unixstream::{UnixStreamLink, UnixStreamReadLink, UnixStreamWriteLink};
+
+#[cfg(all(target_os = "linux", feature = "vsock"))]
+mod vsock;
+#[cfg(all(target_os = "linux", feature = "vsock"))]
+pub use vsock::VsockLink;
+
+#[cfg(target_family = "windows")]
+mod namedpipe;
+#[cfg(target_family = "windows")]
+pub use namedpipe::NamedPipeLink;
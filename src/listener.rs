@@ -0,0 +1,233 @@
+#[cfg(target_family = "unix")]
+use mio::net::{SocketAddr as UnixSocketAddr, UnixListener, UnixStream};
+use mio::net::{TcpListener, TcpStream};
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Accept incoming connections from a `mio` [`TcpListener`]
+///
+/// Connections are accepted in a loop until `WouldBlock` is
+/// returned, or until **max_accepts_per_process** connections have
+/// been accepted, so that one busy listener cannot starve the rest of
+/// the event loop.  `Interrupted` is retried, and a
+/// `ConnectionAborted` error (where the peer gave up before the
+/// connection was accepted) is skipped.
+///
+/// When the process or system runs out of file descriptors or
+/// buffers (EMFILE, ENFILE, ENOBUFS or ENOMEM), this is not treated as
+/// a fatal error.  Instead accepting is suspended for the
+/// **backoff** period, giving time for other connections to close.
+/// Use [`TcpListenerLink::backoff_until`] to find out when the
+/// listener should next be processed.  Note that with level-triggered
+/// polling the listener will keep reporting ready during this time.
+pub struct TcpListenerLink {
+    acc: Acceptor,
+}
+
+impl TcpListenerLink {
+    /// Create the component with default settings:
+    ///
+    /// - **max_accepts_per_process** of 64
+    ///
+    /// - **backoff** of 100ms
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            acc: Acceptor::new(),
+        }
+    }
+
+    /// Change the maximum number of connections accepted in each
+    /// `process` call
+    #[inline]
+    pub fn set_max_accepts_per_process(&mut self, max: usize) {
+        self.acc.max_accepts = max;
+    }
+
+    /// Change the time to suspend accepting for when resources run
+    /// out
+    #[inline]
+    pub fn set_backoff(&mut self, backoff: Duration) {
+        self.acc.backoff = backoff;
+    }
+
+    /// If accepting has been suspended due to lack of resources,
+    /// returns the time at which it will resume
+    #[inline]
+    pub fn backoff_until(&self) -> Option<Instant> {
+        self.acc.backoff_until
+    }
+
+    /// Accept as many pending connections as possible, passing each
+    /// accepted stream and its peer address to the callback.  Returns
+    /// the activity status: `Ok(true)` if any connection was
+    /// accepted, `Ok(false)` if none could be accepted, or `Err(_)`
+    /// if there was a fatal error on the listener.
+    pub fn process(
+        &mut self,
+        listener: &mut TcpListener,
+        cb: impl FnMut(TcpStream, SocketAddr),
+    ) -> Result<bool> {
+        self.acc.accept(|| listener.accept(), cb)
+    }
+}
+
+impl Default for TcpListenerLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for TcpListenerLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("TcpListenerLink");
+        self.acc.debug_fields(&mut d);
+        d.finish()
+    }
+}
+
+// Accept loop shared by the listener links
+pub(crate) struct Acceptor {
+    pub(crate) max_accepts: usize,
+    pub(crate) backoff: Duration,
+    pub(crate) backoff_until: Option<Instant>,
+}
+
+impl Acceptor {
+    pub(crate) fn new() -> Self {
+        Self {
+            max_accepts: 64,
+            backoff: Duration::from_millis(100),
+            backoff_until: None,
+        }
+    }
+
+    // Add the settings and state to a `Debug` output
+    pub(crate) fn debug_fields(&self, d: &mut fmt::DebugStruct<'_, '_>) {
+        d.field("max_accepts", &self.max_accepts)
+            .field("backoff", &self.backoff)
+            .field("backoff_until", &self.backoff_until);
+    }
+
+    pub(crate) fn accept<S, A>(
+        &mut self,
+        mut accept: impl FnMut() -> Result<(S, A)>,
+        mut cb: impl FnMut(S, A),
+    ) -> Result<bool> {
+        if let Some(until) = self.backoff_until {
+            if Instant::now() < until {
+                return Ok(false);
+            }
+            self.backoff_until = None;
+        }
+
+        let mut count = 0;
+        while count < self.max_accepts {
+            match retry!(accept()) {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == ErrorKind::ConnectionAborted => (),
+                Err(ref e) if is_resource_error(e) => {
+                    self.backoff_until = Some(Instant::now() + self.backoff);
+                    break;
+                }
+                Err(e) => return Err(e),
+                Ok((stream, addr)) => {
+                    count += 1;
+                    cb(stream, addr);
+                }
+            }
+        }
+        Ok(count > 0)
+    }
+}
+
+#[cfg(target_family = "unix")]
+fn is_resource_error(e: &Error) -> bool {
+    matches!(
+        e.raw_os_error(),
+        Some(libc::EMFILE | libc::ENFILE | libc::ENOBUFS | libc::ENOMEM)
+    )
+}
+
+#[cfg(not(target_family = "unix"))]
+fn is_resource_error(e: &Error) -> bool {
+    // WSAEMFILE and WSAENOBUFS
+    matches!(e.raw_os_error(), Some(10024 | 10055))
+}
+
+/// Accept incoming connections from a `mio` [`UnixListener`]
+///
+/// This behaves exactly like [`TcpListenerLink`], but hands back
+/// [`UnixStream`] connections ready to be used with
+/// [`crate::UnixStreamLink`].
+#[cfg(target_family = "unix")]
+pub struct UnixListenerLink {
+    acc: Acceptor,
+}
+
+#[cfg(target_family = "unix")]
+impl UnixListenerLink {
+    /// Create the component with default settings:
+    ///
+    /// - **max_accepts_per_process** of 64
+    ///
+    /// - **backoff** of 100ms
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            acc: Acceptor::new(),
+        }
+    }
+
+    /// Change the maximum number of connections accepted in each
+    /// `process` call
+    #[inline]
+    pub fn set_max_accepts_per_process(&mut self, max: usize) {
+        self.acc.max_accepts = max;
+    }
+
+    /// Change the time to suspend accepting for when resources run
+    /// out
+    #[inline]
+    pub fn set_backoff(&mut self, backoff: Duration) {
+        self.acc.backoff = backoff;
+    }
+
+    /// If accepting has been suspended due to lack of resources,
+    /// returns the time at which it will resume
+    #[inline]
+    pub fn backoff_until(&self) -> Option<Instant> {
+        self.acc.backoff_until
+    }
+
+    /// Accept as many pending connections as possible, passing each
+    /// accepted stream and its peer address to the callback.  Returns
+    /// the activity status: `Ok(true)` if any connection was
+    /// accepted, `Ok(false)` if none could be accepted, or `Err(_)`
+    /// if there was a fatal error on the listener.
+    pub fn process(
+        &mut self,
+        listener: &mut UnixListener,
+        cb: impl FnMut(UnixStream, UnixSocketAddr),
+    ) -> Result<bool> {
+        self.acc.accept(|| listener.accept(), cb)
+    }
+}
+
+#[cfg(target_family = "unix")]
+impl Default for UnixListenerLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(target_family = "unix")]
+impl fmt::Debug for UnixListenerLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("UnixListenerLink");
+        self.acc.debug_fields(&mut d);
+        d.finish()
+    }
+}
@@ -0,0 +1,244 @@
+use crate::generic::GenericLink;
+use crate::{Activity, Result};
+use pipebuf::PBufRdWr;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Error, ErrorKind, Read, Write};
+use std::net::Shutdown;
+use std::rc::Rc;
+
+// Data flowing in one direction between the two ends
+#[derive(Default)]
+struct Queue {
+    data: VecDeque<u8>,
+    capacity: Option<usize>,
+    closed: bool,
+    reset: bool,
+}
+
+/// One end of an in-memory stream, for deterministic testing
+///
+/// Create a connected pair with [`LoopbackStream::pair`].  Data
+/// written to one end can be read from the other.  This behaves like
+/// a non-blocking socket: reading when no data is queued gives
+/// `WouldBlock`, and writing when the queue is full gives
+/// `WouldBlock`.  After `shutdown` of the writing side, the other
+/// end reads EOF once the queued data has been read.  After `reset`,
+/// the other end gets `ConnectionReset`.
+///
+/// Conditions can be injected for testing: a queue capacity to test
+/// backpressure, a limit on the bytes transferred per `read` or
+/// `write` call to test short reads and writes, and a forced
+/// `WouldBlock` state.
+pub struct LoopbackStream {
+    queues: Rc<RefCell<[Queue; 2]>>,
+    // Index of the queue this end writes to
+    side: usize,
+    would_block: bool,
+    max_transfer: Option<usize>,
+}
+
+impl LoopbackStream {
+    /// Create a connected pair of stream ends
+    pub fn pair() -> (Self, Self) {
+        let queues = Rc::new(RefCell::new(Default::default()));
+        let end = |side| Self {
+            queues: Rc::clone(&queues),
+            side,
+            would_block: false,
+            max_transfer: None,
+        };
+        (end(0), end(1))
+    }
+
+    /// Limit the number of bytes that may be queued for the other end
+    /// to read.  `None` means no limit.
+    pub fn set_capacity(&mut self, capacity: Option<usize>) {
+        self.queues.borrow_mut()[self.side].capacity = capacity;
+    }
+
+    /// Limit the number of bytes transferred by each `read` or
+    /// `write` call on this end.  `None` means no limit.
+    pub fn set_max_transfer(&mut self, max_transfer: Option<usize>) {
+        self.max_transfer = max_transfer;
+    }
+
+    /// Force all `read` and `write` calls on this end to give
+    /// `WouldBlock` whilst set
+    pub fn set_would_block(&mut self, would_block: bool) {
+        self.would_block = would_block;
+    }
+
+    /// Get the number of bytes queued for this end to read
+    pub fn pending(&self) -> usize {
+        self.queues.borrow()[1 - self.side].data.len()
+    }
+
+    /// Shut down one or both directions of the stream.  Shutting
+    /// down writes causes the other end to read EOF once the queued
+    /// data has been read.  Shutting down reads has no effect.
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        if how != Shutdown::Read {
+            self.queues.borrow_mut()[self.side].closed = true;
+        }
+        Ok(())
+    }
+
+    /// Reset the stream, discarding queued data.  The other end gets
+    /// `ConnectionReset` on its next `read` or `write`.
+    pub fn reset(&self) {
+        let mut queues = self.queues.borrow_mut();
+        let q = &mut queues[self.side];
+        q.data.clear();
+        q.reset = true;
+    }
+
+    fn limit(&self, len: usize) -> usize {
+        self.max_transfer.map_or(len, |max| len.min(max))
+    }
+}
+
+impl Read for LoopbackStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.would_block {
+            return Err(ErrorKind::WouldBlock.into());
+        }
+        let len = self.limit(buf.len());
+        let mut queues = self.queues.borrow_mut();
+        let q = &mut queues[1 - self.side];
+        if q.reset {
+            return Err(ErrorKind::ConnectionReset.into());
+        }
+        if q.data.is_empty() {
+            return if q.closed {
+                Ok(0)
+            } else {
+                Err(ErrorKind::WouldBlock.into())
+            };
+        }
+        let len = len.min(q.data.len());
+        for (dst, src) in buf.iter_mut().zip(q.data.drain(..len)) {
+            *dst = src;
+        }
+        Ok(len)
+    }
+}
+
+impl Write for LoopbackStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.would_block {
+            return Err(ErrorKind::WouldBlock.into());
+        }
+        let len = self.limit(buf.len());
+        let mut queues = self.queues.borrow_mut();
+        if queues[1 - self.side].reset {
+            return Err(ErrorKind::ConnectionReset.into());
+        }
+        let q = &mut queues[self.side];
+        if q.closed {
+            return Err(Error::new(ErrorKind::BrokenPipe, "Write after shutdown"));
+        }
+        let space = q
+            .capacity
+            .map_or(usize::MAX, |c| c.saturating_sub(q.data.len()));
+        if space == 0 {
+            return Err(ErrorKind::WouldBlock.into());
+        }
+        let len = len.min(space);
+        q.data.extend(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Exchange stream data via an in-memory [`LoopbackStream`]
+///
+/// This behaves exactly like [`crate::UnixStreamLink`], with the same
+/// pause handling, activity reporting and EOF and abort mapping, but
+/// without involving the operating system.  This allows protocol code
+/// to be tested deterministically, including backpressure and
+/// shutdown sequencing.
+///
+/// To start with both reading and writing are paused, as for the
+/// stream links.
+pub struct LoopbackLink {
+    // Common settings and processing
+    link: GenericLink,
+}
+
+impl LoopbackLink {
+    /// Create the component with default settings:
+    ///
+    /// - **max_read_unit** of 2048
+    ///
+    /// - Both reads and writes paused
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            link: GenericLink::new(2048),
+        }
+    }
+
+    /// Change the maximum number of bytes to read in each `process`
+    /// call
+    #[inline]
+    pub fn set_max_read_unit(&mut self, max_read_unit: usize) {
+        self.link.max_read_unit = max_read_unit;
+    }
+
+    /// Pause or unpause writes.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_writes(&mut self, pause: bool) {
+        self.link.pause_writes = pause;
+    }
+
+    /// Pause or unpause reads.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_reads(&mut self, pause: bool) {
+        self.link.pause_reads = pause;
+    }
+
+    /// Read and write as much data as possible to and from the given
+    /// stream.  Returns the activity flags (see [`Activity`]), which
+    /// are empty if no progress could be made, or `Err(_)` if there
+    /// was a fatal error on the stream.
+    pub fn process(&mut self, stream: &mut LoopbackStream, pbuf: PBufRdWr) -> Result<Activity> {
+        self.link.process(stream, pbuf)
+    }
+
+    /// Write as much data as possible out to the given stream.
+    /// Returns the activity flags (see [`Activity`]), which are empty
+    /// if no progress could be made, or `Err(_)` if there was a fatal
+    /// error on the stream.
+    pub fn process_out(&mut self, stream: &mut LoopbackStream, pbuf: PBufRdWr) -> Result<Activity> {
+        self.link.process_out(stream, pbuf)
+    }
+
+    /// Read as much data as possible from the given stream, up to
+    /// **max_read_unit** bytes.  Returns the activity flags (see
+    /// [`Activity`]), which are empty if no progress could be made,
+    /// or `Err(_)` if there was a fatal error on the stream.
+    pub fn process_in(&mut self, stream: &mut LoopbackStream, pbuf: PBufRdWr) -> Result<Activity> {
+        self.link.process_in(stream, pbuf)
+    }
+}
+
+impl Default for LoopbackLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for LoopbackLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("LoopbackLink");
+        self.link.debug_fields(&mut d);
+        d.finish()
+    }
+}
@@ -0,0 +1,287 @@
+use crate::TcpConn;
+#[cfg(target_family = "unix")]
+use crate::UnixConn;
+use crate::{Activity, Error, Result};
+use mio::event::Event;
+use mio::{Interest, Registry, Token};
+use std::io;
+use std::time::{Duration, Instant};
+
+/// A connection that can be stored in a [`ConnectionManager`]
+///
+/// This is implemented for [`TcpConn`] and [`UnixConn`], and may be
+/// implemented for application types that wrap one of these along
+/// with protocol state.
+pub trait Connection {
+    /// Register the connection with the given token for both
+    /// readable and writable events
+    fn register(&mut self, registry: &Registry, token: Token) -> io::Result<()>;
+
+    /// Deregister the connection
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()>;
+
+    /// Handle an event for the connection's token.  Returns the
+    /// activity flags, or `Err(_)` on a fatal error, after which the
+    /// connection is removed.
+    fn process_event(&mut self, event: &Event) -> Result<Activity>;
+
+    /// Read and write as much data as possible, without an event.
+    /// This is called once the connection's deadline has passed.
+    /// Returns as for `process_event`.
+    fn process(&mut self) -> Result<Activity>;
+
+    /// Get the earliest time at which the connection wants `process`
+    /// to be called even if there is no event, or `None` if there is
+    /// no such deadline, which is the default
+    #[inline]
+    fn next_deadline(&self) -> Option<Instant> {
+        None
+    }
+
+    /// Test whether the connection has finished, after which it is
+    /// removed
+    fn is_done(&self) -> bool;
+}
+
+macro_rules! impl_connection {
+    ($conn:ty) => {
+        impl Connection for $conn {
+            #[inline]
+            fn register(&mut self, registry: &Registry, token: Token) -> io::Result<()> {
+                <$conn>::register(
+                    self,
+                    registry,
+                    token,
+                    Interest::READABLE | Interest::WRITABLE,
+                )
+            }
+            #[inline]
+            fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+                <$conn>::deregister(self, registry)
+            }
+            #[inline]
+            fn process_event(&mut self, event: &Event) -> Result<Activity> {
+                <$conn>::process_event(self, event)
+            }
+            #[inline]
+            fn process(&mut self) -> Result<Activity> {
+                <$conn>::process(self)
+            }
+            #[inline]
+            fn is_done(&self) -> bool {
+                <$conn>::is_done(self)
+            }
+        }
+    };
+}
+
+impl_connection!(TcpConn);
+#[cfg(target_family = "unix")]
+impl_connection!(UnixConn);
+
+/// A set of connections keyed by `Token`
+///
+/// This handles the bookkeeping that every server needs: storing the
+/// connections, allocating a token to each one, dispatching events
+/// to the right connection, and deregistering and removing
+/// connections once they are done or have failed.  Tokens of removed
+/// connections are reused.
+///
+/// Tokens below the number given to
+/// [`ConnectionManager::with_reserved`] are never allocated, so they
+/// can be used for listeners, wakers and so on.
+pub struct ConnectionManager<C> {
+    // Connection slots, indexed by token minus `reserved`
+    conns: Vec<Option<C>>,
+
+    // Indexes of the free slots
+    free: Vec<usize>,
+
+    // Number of tokens reserved at the start of the range
+    reserved: usize,
+}
+
+impl<C: Connection> ConnectionManager<C> {
+    /// Create an empty manager with no reserved tokens
+    #[inline]
+    pub fn new() -> Self {
+        Self::with_reserved(0)
+    }
+
+    /// Create an empty manager which doesn't allocate the tokens
+    /// `0..reserved`
+    #[inline]
+    pub fn with_reserved(reserved: usize) -> Self {
+        Self {
+            conns: Vec::new(),
+            free: Vec::new(),
+            reserved,
+        }
+    }
+
+    /// Insert a connection, allocating a token for it and registering
+    /// it with the given registry.  Returns the token.
+    pub fn insert(&mut self, registry: &Registry, mut conn: C) -> io::Result<Token> {
+        let index = match self.free.last() {
+            Some(&index) => index,
+            None => self.conns.len(),
+        };
+        let token = Token(self.reserved + index);
+        conn.register(registry, token)?;
+        if index == self.conns.len() {
+            self.conns.push(Some(conn));
+        } else {
+            self.free.pop();
+            self.conns[index] = Some(conn);
+        }
+        Ok(token)
+    }
+
+    /// Deregister and remove the connection with the given token.
+    /// Returns `Ok(None)` if there is no such connection.
+    pub fn remove(&mut self, registry: &Registry, token: Token) -> io::Result<Option<C>> {
+        let Some(index) = self.index(token) else {
+            return Ok(None);
+        };
+        let Some(mut conn) = self.conns[index].take() else {
+            return Ok(None);
+        };
+        self.free.push(index);
+        conn.deregister(registry)?;
+        Ok(Some(conn))
+    }
+
+    /// Get the connection with the given token
+    #[inline]
+    pub fn get(&self, token: Token) -> Option<&C> {
+        self.conns.get(self.index(token)?)?.as_ref()
+    }
+
+    /// Get the connection with the given token mutably
+    #[inline]
+    pub fn get_mut(&mut self, token: Token) -> Option<&mut C> {
+        let index = self.index(token)?;
+        self.conns.get_mut(index)?.as_mut()
+    }
+
+    /// Test whether the token belongs to a connection
+    #[inline]
+    pub fn contains(&self, token: Token) -> bool {
+        self.get(token).is_some()
+    }
+
+    /// Get the number of connections
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.conns.len() - self.free.len()
+    }
+
+    /// Test whether there are no connections
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over the connections and their tokens, for example
+    /// for periodic housekeeping
+    pub fn iter(&self) -> impl Iterator<Item = (Token, &C)> {
+        let reserved = self.reserved;
+        self.conns
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, c)| Some((Token(reserved + i), c.as_ref()?)))
+    }
+
+    /// Iterate mutably over the connections and their tokens
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Token, &mut C)> {
+        let reserved = self.reserved;
+        self.conns
+            .iter_mut()
+            .enumerate()
+            .filter_map(move |(i, c)| Some((Token(reserved + i), c.as_mut()?)))
+    }
+
+    /// Pass an event to the connection it belongs to.  Returns `None`
+    /// if the token doesn't belong to a connection, for example if
+    /// it is a reserved token.  Otherwise returns the result of
+    /// processing the event.  If the connection is done or has
+    /// failed, it is deregistered and removed.  A failure to
+    /// deregister is returned as an error with [`crate::Op::Register`]
+    /// if there was no other error.
+    pub fn handle_event(&mut self, registry: &Registry, event: &Event) -> Option<Result<Activity>> {
+        let token = event.token();
+        let conn = self.get_mut(token)?;
+        let rv = conn.process_event(event);
+        let done = conn.is_done();
+        Some(self.retire(registry, token, rv, done))
+    }
+
+    /// Get the earliest deadline of all the connections, or `None` if
+    /// none of them has a deadline
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.iter().filter_map(|(_, c)| c.next_deadline()).min()
+    }
+
+    /// Get the time from `now` until the earliest deadline, for use
+    /// as the `Poll` timeout.  Returns `None` if there is no deadline,
+    /// i.e. wait indefinitely, or a zero duration if a deadline has
+    /// already passed.
+    pub fn poll_timeout(&self, now: Instant) -> Option<Duration> {
+        Some(self.next_deadline()?.saturating_duration_since(now))
+    }
+
+    /// Call `process` on all the connections whose deadlines are at
+    /// or before `now`, passing the token and the result of each to
+    /// the closure.  Connections that are done or have failed are
+    /// deregistered and removed, as for
+    /// [`ConnectionManager::handle_event`].
+    pub fn process_expired(
+        &mut self,
+        registry: &Registry,
+        now: Instant,
+        mut f: impl FnMut(Token, Result<Activity>),
+    ) {
+        for index in 0..self.conns.len() {
+            let token = Token(self.reserved + index);
+            let Some(conn) = self.conns[index].as_mut() else {
+                continue;
+            };
+            match conn.next_deadline() {
+                Some(deadline) if deadline <= now => (),
+                _ => continue,
+            }
+            let rv = conn.process();
+            let done = conn.is_done();
+            f(token, self.retire(registry, token, rv, done));
+        }
+    }
+
+    // Remove the connection if it is done or has failed, and pass on
+    // the result of processing it
+    fn retire(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        rv: Result<Activity>,
+        done: bool,
+    ) -> Result<Activity> {
+        if rv.is_err() || done {
+            if let Err(e) = self.remove(registry, token) {
+                if rv.is_ok() {
+                    return Err(Error::register(e));
+                }
+            }
+        }
+        rv
+    }
+
+    fn index(&self, token: Token) -> Option<usize> {
+        token.0.checked_sub(self.reserved)
+    }
+}
+
+impl<C: Connection> Default for ConnectionManager<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,662 @@
+use crate::bufsize::SocketBuffers;
+use crate::tcpstream::TcpSocket;
+use crate::{Activity, KeepaliveConfig, Result, TcpLink};
+use pipebuf::PBufRdWr;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr};
+use std::time::Duration;
+
+/// One scripted operation for a [`MockStream`]
+#[derive(Debug)]
+pub enum MockOp {
+    /// Expect a `read` call.  `Ok(n)` returns `n` bytes of data,
+    /// which must fit in the buffer provided.  The data is a running
+    /// byte sequence 0, 1, 2, ..., wrapping at 256, continuing across
+    /// reads.  `Ok(0)` indicates EOF.
+    Read(io::Result<usize>),
+
+    /// Expect a `write` call.  `Ok(n)` accepts the first `n` bytes
+    /// offered, or all of them if fewer are offered.  Accepted data
+    /// can be checked with [`MockStream::written`].
+    Write(io::Result<usize>),
+
+    /// Expect a `flush` call
+    Flush(io::Result<()>),
+
+    /// Expect a `shutdown` call with the given direction
+    Shutdown(Shutdown, io::Result<()>),
+}
+
+/// A stream whose behaviour is driven by a script, for fault
+/// injection in tests
+///
+/// Each `read`, `write`, `flush` or `shutdown` call takes the next
+/// [`MockOp`] from the script and returns its result.  If the call
+/// does not match the next operation, or if the script has run out,
+/// then this panics.  Use [`MockStream::assert_done`] at the end of a
+/// test to check that the whole script was consumed.
+///
+/// This is only available with the `test-util` feature.
+pub struct MockStream {
+    script: VecDeque<MockOp>,
+    written: Vec<u8>,
+    read_seq: u8,
+}
+
+impl MockStream {
+    /// Create a mock stream that will follow the given script
+    pub fn new(script: impl IntoIterator<Item = MockOp>) -> Self {
+        Self {
+            script: script.into_iter().collect(),
+            written: Vec::new(),
+            read_seq: 0,
+        }
+    }
+
+    /// Append more operations to the script
+    pub fn push(&mut self, op: MockOp) {
+        self.script.push_back(op);
+    }
+
+    /// Get all the data accepted by `write` calls so far
+    pub fn written(&self) -> &[u8] {
+        &self.written
+    }
+
+    /// Get the number of script operations not yet consumed
+    pub fn remaining(&self) -> usize {
+        self.script.len()
+    }
+
+    /// Panic if the script has not been fully consumed
+    #[track_caller]
+    pub fn assert_done(&self) {
+        assert!(
+            self.script.is_empty(),
+            "MockStream script not fully consumed; remaining: {:?}",
+            self.script
+        );
+    }
+
+    /// Perform a `shutdown` call according to the script
+    #[track_caller]
+    pub fn shutdown(&mut self, how: Shutdown) -> io::Result<()> {
+        match self.next_op("shutdown") {
+            MockOp::Shutdown(expected, rv) => {
+                assert_eq!(expected, how, "MockStream: unexpected shutdown direction");
+                rv
+            }
+            op => panic!("MockStream: expected {:?}, got shutdown call", op),
+        }
+    }
+
+    #[track_caller]
+    fn next_op(&mut self, call: &str) -> MockOp {
+        match self.script.pop_front() {
+            Some(op) => op,
+            None => panic!("MockStream: unexpected {} call after end of script", call),
+        }
+    }
+}
+
+impl Read for MockStream {
+    #[track_caller]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.next_op("read") {
+            MockOp::Read(Ok(len)) => {
+                assert!(
+                    len <= buf.len(),
+                    "MockStream: scripted read of {} bytes into {}-byte buffer",
+                    len,
+                    buf.len()
+                );
+                for b in &mut buf[..len] {
+                    *b = self.read_seq;
+                    self.read_seq = self.read_seq.wrapping_add(1);
+                }
+                Ok(len)
+            }
+            MockOp::Read(Err(e)) => Err(e),
+            op => panic!("MockStream: expected {:?}, got read call", op),
+        }
+    }
+}
+
+impl Write for MockStream {
+    #[track_caller]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.next_op("write") {
+            MockOp::Write(Ok(len)) => {
+                let len = len.min(buf.len());
+                self.written.extend_from_slice(&buf[..len]);
+                Ok(len)
+            }
+            MockOp::Write(Err(e)) => Err(e),
+            op => panic!("MockStream: expected {:?}, got write call", op),
+        }
+    }
+
+    #[track_caller]
+    fn flush(&mut self) -> io::Result<()> {
+        match self.next_op("flush") {
+            MockOp::Flush(rv) => rv,
+            op => panic!("MockStream: expected {:?}, got flush call", op),
+        }
+    }
+}
+
+/// Exchange stream data via a [`MockStream`]
+///
+/// This wraps a [`crate::TcpLink`] and runs its `process` calls over
+/// the scripted stream, so it has the same pause handling, activity
+/// reporting and EOF, abort and shutdown handling, allowing every
+/// branch of that handling to be exercised via a script.  Use
+/// [`MockStreamLink::link_mut`] to change any other setting.  Socket
+/// options always succeed on a [`MockStream`] without doing anything,
+/// and the stream reports itself as connected.
+///
+/// This is only available with the `test-util` feature.
+pub struct MockStreamLink {
+    // The link being exercised
+    link: TcpLink,
+}
+
+impl MockStreamLink {
+    /// Create the component with the same default settings as
+    /// [`TcpLink::new`]:
+    ///
+    /// - **max_read_unit** of 2048
+    ///
+    /// - Both reads and writes paused
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            link: TcpLink::new(),
+        }
+    }
+
+    /// Get the wrapped [`TcpLink`], e.g. to check its state
+    #[inline]
+    pub fn link(&self) -> &TcpLink {
+        &self.link
+    }
+
+    /// Get the wrapped [`TcpLink`] to change its settings
+    #[inline]
+    pub fn link_mut(&mut self) -> &mut TcpLink {
+        &mut self.link
+    }
+
+    /// Change the maximum number of bytes to read in each `process`
+    /// call
+    #[inline]
+    pub fn set_max_read_unit(&mut self, max_read_unit: usize) {
+        self.link.set_max_read_unit(max_read_unit);
+    }
+
+    /// Pause or unpause writes.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_writes(&mut self, pause: bool) {
+        self.link.set_pause_writes(pause);
+    }
+
+    /// Pause or unpause reads.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_reads(&mut self, pause: bool) {
+        self.link.set_pause_reads(pause);
+    }
+
+    /// Read and write as much data as possible to and from the given
+    /// stream.  See [`TcpLink::process`].
+    pub fn process(&mut self, stream: &mut MockStream, pbuf: PBufRdWr) -> Result<Activity> {
+        self.link.process_stream(stream, pbuf)
+    }
+
+    /// Write as much data as possible out to the given stream.  See
+    /// [`TcpLink::process_out`].
+    pub fn process_out(&mut self, stream: &mut MockStream, pbuf: PBufRdWr) -> Result<Activity> {
+        self.link.process_out_stream(stream, pbuf)
+    }
+
+    /// Read as much data as possible from the given stream, up to
+    /// **max_read_unit** bytes.  See [`TcpLink::process_in`].
+    pub fn process_in(&mut self, stream: &mut MockStream, pbuf: PBufRdWr) -> Result<Activity> {
+        self.link.process_in_stream(stream, pbuf)
+    }
+}
+
+impl Default for MockStreamLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for MockStreamLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockStreamLink")
+            .field("link", &self.link)
+            .finish()
+    }
+}
+
+// Socket options are accepted and ignored, and the addresses are
+// fixed, so that `TcpLink` can run over the script
+impl TcpSocket for MockStream {
+    fn set_nodelay(&self, _: bool) -> io::Result<()> {
+        Ok(())
+    }
+    fn set_keepalive(&self, _: Option<&KeepaliveConfig>) -> io::Result<()> {
+        Ok(())
+    }
+    fn set_ttl(&self, _: u32) -> io::Result<()> {
+        Ok(())
+    }
+    fn set_tos(&self, _: u8) -> io::Result<()> {
+        Ok(())
+    }
+    fn set_quickack(&self, _: bool) -> io::Result<()> {
+        Ok(())
+    }
+    fn set_cork(&self, _: bool) -> io::Result<()> {
+        Ok(())
+    }
+    fn set_notsent_lowat(&self, _: u32) -> io::Result<()> {
+        Ok(())
+    }
+    fn set_user_timeout(&self, _: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+    fn set_mss(&self, _: u32) -> io::Result<()> {
+        Ok(())
+    }
+    fn set_oob_inline(&self, _: bool) -> io::Result<()> {
+        Ok(())
+    }
+    fn discard_oob(&self) -> io::Result<bool> {
+        Ok(false)
+    }
+    fn take_error(&self) -> io::Result<Option<io::Error>> {
+        Ok(None)
+    }
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Ok(SocketAddr::from(([127, 0, 0, 1], 2)))
+    }
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(SocketAddr::from(([127, 0, 0, 1], 1)))
+    }
+}
+
+impl SocketBuffers for MockStream {
+    fn set_recv_buffer_size(&self, _: usize) -> io::Result<()> {
+        Ok(())
+    }
+    fn set_send_buffer_size(&self, _: usize) -> io::Result<()> {
+        Ok(())
+    }
+    fn recv_buffer_size(&self) -> io::Result<usize> {
+        Ok(65536)
+    }
+    fn send_buffer_size(&self) -> io::Result<usize> {
+        Ok(65536)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LinkState, Op};
+    use pipebuf::PipeBufPair;
+
+    fn open_link() -> MockStreamLink {
+        let mut link = MockStreamLink::new();
+        link.set_pause_reads(false);
+        link.set_pause_writes(false);
+        link
+    }
+
+    fn would_block() -> io::Result<usize> {
+        Err(io::Error::from(io::ErrorKind::WouldBlock))
+    }
+
+    #[test]
+    fn paused_link_touches_nothing() {
+        let mut stream = MockStream::new([]);
+        let mut link = MockStreamLink::new();
+        let mut pipes = PipeBufPair::new();
+        pipes.upper().wr.append(b"data");
+        let act = link.process(&mut stream, pipes.lower()).unwrap();
+        assert_eq!(act, Activity::NONE);
+        stream.assert_done();
+    }
+
+    #[test]
+    fn data_and_eof_both_ways() {
+        let mut stream = MockStream::new([
+            MockOp::Write(Ok(3)),
+            MockOp::Write(Ok(2)),
+            MockOp::Shutdown(Shutdown::Write, Ok(())),
+            MockOp::Read(Ok(4)),
+            MockOp::Read(Ok(0)),
+        ]);
+        let mut link = open_link();
+        let mut pipes = PipeBufPair::new();
+        pipes.upper().wr.append(b"hello");
+        pipes.upper().wr.close();
+
+        let act = link.process(&mut stream, pipes.lower()).unwrap();
+        assert!(act.contains(Activity::EOF_OUT));
+        assert!(act.contains(Activity::EOF_IN));
+        stream.assert_done();
+        assert_eq!(stream.written(), b"hello");
+        let mut app = pipes.upper();
+        assert_eq!(app.rd.data(), [0, 1, 2, 3]);
+        app.rd.consume(4);
+        assert!(app.rd.consume_eof());
+        assert_eq!(link.link().state(), LinkState::Closed);
+    }
+
+    #[test]
+    fn reset_aborts_incoming() {
+        let mut stream = MockStream::new([MockOp::Read(Err(io::Error::from(
+            io::ErrorKind::ConnectionReset,
+        )))]);
+        let mut link = open_link();
+        let mut pipes = PipeBufPair::new();
+        let act = link.process(&mut stream, pipes.lower()).unwrap();
+        assert!(act.contains(Activity::ABORTED));
+        assert!(pipes.upper().rd.is_aborted());
+        stream.assert_done();
+    }
+
+    #[test]
+    fn failed_link_stops() {
+        let mut stream = MockStream::new([MockOp::Read(Err(io::Error::other("boom")))]);
+        let mut link = open_link();
+        let mut pipes = PipeBufPair::new();
+        let e = link.process(&mut stream, pipes.lower()).unwrap_err();
+        assert_eq!(e.op(), Op::Read);
+        assert_eq!(link.link().state(), LinkState::Failed);
+
+        // The stream is not touched again, or the empty script panics
+        pipes.upper().wr.append(b"more");
+        let act = link.process(&mut stream, pipes.lower()).unwrap();
+        assert_eq!(act, Activity::NONE);
+        stream.assert_done();
+    }
+
+    #[test]
+    fn read_quota_shutdown_error_is_not_fatal() {
+        let mut stream = MockStream::new([
+            MockOp::Read(Ok(4)),
+            MockOp::Shutdown(Shutdown::Read, Err(io::Error::other("refused"))),
+            MockOp::Write(Ok(5)),
+        ]);
+        let mut link = open_link();
+        link.link_mut().set_read_quota(Some(4));
+        link.link_mut().set_read_quota_shutdown(true);
+        let mut pipes = PipeBufPair::new();
+
+        let act = link.process_in(&mut stream, pipes.lower()).unwrap();
+        assert!(act.contains(Activity::QUOTA));
+        assert!(pipes.upper().rd.is_eof());
+        let error = link.link().last_read_error().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::Other);
+        assert_ne!(link.link().state(), LinkState::Failed);
+
+        // Writes carry on
+        pipes.upper().wr.append(b"reply");
+        link.process(&mut stream, pipes.lower()).unwrap();
+        assert_eq!(stream.written(), b"reply");
+        stream.assert_done();
+    }
+
+    #[test]
+    fn dead_connection_stops_writes() {
+        let mut stream = MockStream::new([
+            MockOp::Write(Ok(2)),
+            MockOp::Write(Err(io::Error::from(io::ErrorKind::BrokenPipe))),
+        ]);
+        let mut link = open_link();
+        let mut pipes = PipeBufPair::new();
+        pipes.upper().wr.append(b"one");
+
+        let act = link.process_out(&mut stream, pipes.lower()).unwrap();
+        assert!(act.contains(Activity::ABORTED));
+        assert!(pipes.upper().rd.is_aborted());
+        assert_eq!(link.link().state(), LinkState::Aborted);
+
+        // Later data is discarded without another write
+        pipes.upper().wr.append(b"two");
+        assert_eq!(link.link().needed_interest(&pipes.lower()), None);
+        link.process_out(&mut stream, pipes.lower()).unwrap();
+        assert!(pipes.lower().rd.is_empty());
+        assert_eq!(stream.written(), b"on");
+        stream.assert_done();
+    }
+
+    #[test]
+    fn write_timeout_aborts() {
+        let mut stream =
+            MockStream::new([MockOp::Write(Err(io::Error::from(io::ErrorKind::TimedOut)))]);
+        let mut link = open_link();
+        let mut pipes = PipeBufPair::new();
+        pipes.upper().wr.append(b"data");
+        pipes.upper().wr.close();
+
+        let act = link.process_out(&mut stream, pipes.lower()).unwrap();
+        assert!(act.contains(Activity::ABORTED));
+        assert!(act.contains(Activity::EOF_OUT));
+        assert!(pipes.upper().rd.is_aborted());
+        assert_eq!(link.link().state(), LinkState::Aborted);
+        assert!(link.link().is_done(&pipes.lower()));
+        stream.assert_done();
+    }
+
+    #[test]
+    fn read_timeout_aborts() {
+        let mut stream =
+            MockStream::new([MockOp::Read(Err(io::Error::from(io::ErrorKind::TimedOut)))]);
+        let mut link = open_link();
+        let mut pipes = PipeBufPair::new();
+
+        let act = link.process_in(&mut stream, pipes.lower()).unwrap();
+        assert!(act.contains(Activity::ABORTED));
+        assert!(pipes.upper().rd.is_aborted());
+        let error = link.link().last_read_error().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        stream.assert_done();
+    }
+
+    #[test]
+    fn would_block_waits() {
+        let mut stream =
+            MockStream::new([MockOp::Write(would_block()), MockOp::Read(would_block())]);
+        let mut link = open_link();
+        let mut pipes = PipeBufPair::new();
+        pipes.upper().wr.append(b"stuck");
+        let act = link.process(&mut stream, pipes.lower()).unwrap();
+        assert_eq!(act, Activity::NONE);
+        assert_eq!(pipes.lower().rd.data(), b"stuck");
+        stream.assert_done();
+    }
+
+    #[test]
+    fn abort_after_peer_eof() {
+        let mut stream = MockStream::new([
+            MockOp::Read(Ok(0)),
+            MockOp::Write(Ok(4)),
+            MockOp::Shutdown(Shutdown::Both, Ok(())),
+        ]);
+        let mut link = open_link();
+        let mut pipes = PipeBufPair::new();
+        let act = link.process_in(&mut stream, pipes.lower()).unwrap();
+        assert!(act.contains(Activity::EOF_IN));
+
+        // The incoming pipe keeps its EOF instead of being aborted
+        pipes.upper().wr.append(b"last");
+        pipes.upper().wr.abort();
+        let act = link.process_out(&mut stream, pipes.lower()).unwrap();
+        assert!(act.contains(Activity::EOF_OUT));
+        assert!(act.contains(Activity::ABORTED));
+        let mut app = pipes.upper();
+        assert!(!app.rd.is_aborted());
+        assert!(app.rd.consume_eof());
+        assert_eq!(stream.written(), b"last");
+        stream.assert_done();
+    }
+
+    #[test]
+    fn zero_length_write_fails() {
+        let mut stream = MockStream::new([MockOp::Write(Ok(2)), MockOp::Write(Ok(0))]);
+        let mut link = open_link();
+        let mut pipes = PipeBufPair::new();
+        pipes.upper().wr.append(b"stall");
+
+        let e = link.process_out(&mut stream, pipes.lower()).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::WriteZero);
+        assert_eq!(e.op(), Op::Write);
+        assert_eq!(link.link().state(), LinkState::Failed);
+        assert_eq!(stream.written(), b"st");
+
+        // No further calls are made, so there is no busy loop
+        let act = link.process_out(&mut stream, pipes.lower()).unwrap();
+        assert_eq!(act, Activity::NONE);
+        stream.assert_done();
+    }
+
+    // The peer resets the connection between the last write and the
+    // shutdown, which then reports that the stream is not connected
+    #[test]
+    fn shutdown_after_reset_is_eof() {
+        for kind in [io::ErrorKind::NotConnected, io::ErrorKind::InvalidInput] {
+            let mut stream = MockStream::new([
+                MockOp::Write(Ok(3)),
+                MockOp::Shutdown(Shutdown::Write, Err(io::Error::from(kind))),
+            ]);
+            let mut link = open_link();
+            let mut pipes = PipeBufPair::new();
+            pipes.upper().wr.append(b"bye");
+            pipes.upper().wr.close();
+
+            let act = link.process_out(&mut stream, pipes.lower()).unwrap();
+            assert!(act.contains(Activity::EOF_OUT), "{:?}", kind);
+            assert!(!act.contains(Activity::ABORTED), "{:?}", kind);
+            assert_eq!(link.link().state(), LinkState::OutClosed);
+            assert!(pipes.lower().rd.is_eof());
+            stream.assert_done();
+        }
+    }
+
+    #[test]
+    fn shutdown_for_close_when_flushed_after_reset() {
+        let mut stream = MockStream::new([MockOp::Shutdown(
+            Shutdown::Write,
+            Err(io::Error::from(io::ErrorKind::NotConnected)),
+        )]);
+        let mut link = open_link();
+        link.link_mut().close_when_flushed();
+        let mut pipes = PipeBufPair::new();
+
+        let act = link.process_out(&mut stream, pipes.lower()).unwrap();
+        assert!(act.contains(Activity::EOF_OUT));
+        assert_ne!(link.link().state(), LinkState::Failed);
+        stream.assert_done();
+    }
+
+    #[test]
+    fn rate_limit_charges_only_bytes_written() {
+        use crate::testutil::{advance, fake_now};
+        use crate::RateLimit;
+
+        let mut stream = MockStream::new([MockOp::Write(Ok(30)), MockOp::Write(would_block())]);
+        let mut link = open_link();
+        link.link_mut().set_clock(fake_now);
+        link.link_mut()
+            .set_write_rate_limit(Some(RateLimit::new(1, 100)));
+        let mut pipes = PipeBufPair::new();
+        pipes.upper().wr.append(&[0; 1000]);
+
+        // 30 of the 100 tokens are used before the stream blocks
+        link.process_out(&mut stream, pipes.lower()).unwrap();
+        assert_eq!(stream.written().len(), 30);
+        assert_eq!(link.link().next_deadline(), None);
+
+        // So 70 are still available, and no more until refilled
+        stream.push(MockOp::Write(Ok(1000)));
+        let act = link.process_out(&mut stream, pipes.lower()).unwrap();
+        assert!(act.contains(Activity::WROTE));
+        assert_eq!(stream.written().len(), 100);
+        let deadline = link.link().next_deadline().unwrap();
+        assert_eq!(
+            link.process_out(&mut stream, pipes.lower()).unwrap(),
+            Activity::NONE
+        );
+        stream.assert_done();
+
+        // The deadline is when the bucket is full again
+        assert_eq!(deadline - fake_now(), Duration::from_secs(100));
+        advance(deadline - fake_now());
+        stream.push(MockOp::Write(Ok(1000)));
+        link.process_out(&mut stream, pipes.lower()).unwrap();
+        assert_eq!(stream.written().len(), 200);
+        stream.assert_done();
+    }
+
+    #[test]
+    fn write_quota_counts_only_bytes_written() {
+        let mut stream = MockStream::new([MockOp::Write(Ok(4)), MockOp::Write(would_block())]);
+        let mut link = open_link();
+        link.link_mut().set_write_quota(Some(10));
+        let mut pipes = PipeBufPair::new();
+        pipes.upper().wr.append(&[0; 100]);
+
+        link.process_out(&mut stream, pipes.lower()).unwrap();
+        assert_eq!(link.link().write_quota(), Some(6));
+
+        // Exactly the rest of the quota is offered
+        stream.push(MockOp::Write(Ok(1000)));
+        let act = link.process_out(&mut stream, pipes.lower()).unwrap();
+        assert!(act.contains(Activity::QUOTA));
+        assert_eq!(stream.written().len(), 10);
+        assert_eq!(link.link().write_quota(), Some(0));
+        stream.assert_done();
+    }
+
+    #[test]
+    fn shutdown_would_block_is_pending() {
+        let mut stream = MockStream::new([
+            MockOp::Write(Ok(3)),
+            MockOp::Shutdown(
+                Shutdown::Write,
+                Err(io::Error::from(io::ErrorKind::WouldBlock)),
+            ),
+        ]);
+        let mut link = open_link();
+        let mut pipes = PipeBufPair::new();
+        pipes.upper().wr.append(b"bye");
+        pipes.upper().wr.close();
+
+        // The EOF is held back until the shutdown goes through
+        let act = link.process_out(&mut stream, pipes.lower()).unwrap();
+        assert!(!act.contains(Activity::EOF_OUT));
+        assert_eq!(link.link().state(), LinkState::ShutdownPending);
+        assert!(pipes.lower().rd.has_pending_eof());
+        stream.assert_done();
+
+        stream.push(MockOp::Shutdown(Shutdown::Write, Ok(())));
+        let act = link.process_out(&mut stream, pipes.lower()).unwrap();
+        assert!(act.contains(Activity::EOF_OUT));
+        assert_eq!(link.link().state(), LinkState::OutClosed);
+        stream.assert_done();
+
+        // Nothing more is done after that
+        let act = link.process_out(&mut stream, pipes.lower()).unwrap();
+        assert_eq!(act, Activity::NONE);
+        assert_eq!(link.link().state(), LinkState::OutClosed);
+        stream.assert_done();
+    }
+}
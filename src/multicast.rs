@@ -0,0 +1,350 @@
+use crate::UdpPeerLink;
+use crate::{Activity, Direction, Error, Op, Result};
+use mio::net::UdpSocket;
+use pipebuf::PBufRdWr;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+// Socket changes waiting for the next `process` call
+#[derive(Debug)]
+enum Pending {
+    Join(IpAddr, Membership),
+    Leave(IpAddr),
+    Loop(bool),
+    Ttl(u32),
+    DestAddr,
+}
+
+// Interface used for a group membership
+#[derive(Clone, Copy, Debug)]
+enum Membership {
+    V4(Ipv4Addr),
+    V6(u32),
+}
+
+/// Exchange multicast datagrams via an unconnected `mio`
+/// [`UdpSocket`]
+///
+/// This works like [`UdpPeerLink`], with the addition of multicast
+/// group membership and options.  Calls that change the socket are
+/// not applied immediately, but are queued and applied in order at
+/// the start of the next `process` call, in the same way that
+/// `set_nodelay` is handled by [`TcpLink`].  Any error from applying
+/// them is returned from that `process` call.
+///
+/// If **include_dest_addr** is enabled, each incoming record carries
+/// a second encoded address after the source address: the
+/// destination IP address of the datagram (for example the multicast
+/// group it was sent to), with the local port of the socket.  This
+/// allows datagrams for several groups received on one socket to be
+/// separated downstream.  This is only supported on Linux.
+///
+/// [`TcpLink`]: crate::TcpLink
+#[derive(Debug)]
+pub struct MulticastLink {
+    // Underlying unicast link and its settings
+    link: UdpPeerLink,
+
+    // Groups currently joined, with the interface used
+    groups: Vec<(IpAddr, Membership)>,
+
+    // Changes to apply on the next `process` call
+    pending: Vec<Pending>,
+
+    // Set once destination address reporting has been enabled
+    include_dest_addr: bool,
+}
+
+impl MulticastLink {
+    /// Create the component with the same defaults as
+    /// [`UdpPeerLink`], with no groups joined and
+    /// **include_dest_addr** disabled
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            link: UdpPeerLink::new(),
+            groups: Vec::new(),
+            pending: Vec::new(),
+            include_dest_addr: false,
+        }
+    }
+
+    /// Change the maximum number of bytes of records to read in each
+    /// `process` call.  See [`UdpPeerLink::set_max_read_unit`].
+    #[inline]
+    pub fn set_max_read_unit(&mut self, max_read_unit: usize) {
+        self.link.set_max_read_unit(max_read_unit);
+    }
+
+    /// Change the maximum size of a datagram that will be accepted.
+    /// Larger datagrams are discarded.
+    #[inline]
+    pub fn set_max_datagram_size(&mut self, max_datagram_size: usize) {
+        self.link.set_max_datagram_size(max_datagram_size);
+    }
+
+    /// Pause or unpause writes.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_writes(&mut self, pause: bool) {
+        self.link.set_pause_writes(pause);
+    }
+
+    /// Pause or unpause reads.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_reads(&mut self, pause: bool) {
+        self.link.set_pause_reads(pause);
+    }
+
+    /// Join an IPv4 multicast group on the given local interface
+    /// address, or on the default interface if `interface` is
+    /// `0.0.0.0`.  This takes effect on the next `process` call.
+    #[inline]
+    pub fn join_multicast_v4(&mut self, group: Ipv4Addr, interface: Ipv4Addr) {
+        self.pending
+            .push(Pending::Join(group.into(), Membership::V4(interface)));
+    }
+
+    /// Join an IPv6 multicast group on the interface with the given
+    /// index, or on the default interface if `interface` is 0.  This
+    /// takes effect on the next `process` call.
+    #[inline]
+    pub fn join_multicast_v6(&mut self, group: Ipv6Addr, interface: u32) {
+        self.pending
+            .push(Pending::Join(group.into(), Membership::V6(interface)));
+    }
+
+    /// Leave a multicast group previously joined, on all the
+    /// interfaces it was joined on.  Groups not joined are ignored.
+    /// This takes effect on the next `process` call.
+    #[inline]
+    pub fn leave_multicast(&mut self, group: IpAddr) {
+        self.pending.push(Pending::Leave(group));
+    }
+
+    /// Enable or disable local loopback of multicast datagrams sent
+    /// on this socket.  The IPv4 or IPv6 option is set according to
+    /// the family of the socket's local address.  This takes effect
+    /// on the next `process` call.
+    #[inline]
+    pub fn set_multicast_loop(&mut self, enable: bool) {
+        self.pending.push(Pending::Loop(enable));
+    }
+
+    /// Set the time-to-live (IPv4) or hop limit (IPv6) of multicast
+    /// datagrams sent on this socket.  This takes effect on the next
+    /// `process` call.  The IPv6 hop limit is only supported on Unix.
+    #[inline]
+    pub fn set_multicast_ttl(&mut self, ttl: u32) {
+        self.pending.push(Pending::Ttl(ttl));
+    }
+
+    /// Include the destination address of each incoming datagram in
+    /// its record, after the source address.  This takes effect on
+    /// the next `process` call, which gives an `Unsupported` error on
+    /// platforms other than Linux.  It cannot be disabled again.
+    #[inline]
+    pub fn set_include_dest_addr(&mut self) {
+        if !self.include_dest_addr {
+            self.pending.push(Pending::DestAddr);
+        }
+    }
+
+    /// Send and receive as many datagrams as possible to and from the
+    /// given UDP socket, after first applying any pending changes.
+    /// Returns the activity flags (see [`Activity`]), which are empty
+    /// if no progress could be made, or `Err(_)` if there was a fatal
+    /// error on the socket or when applying a change.
+    ///
+    /// Assumes that it is always called with the same `UdpSocket`
+    /// and pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process(&mut self, sock: &mut UdpSocket, mut pbuf: PBufRdWr) -> Result<Activity> {
+        self.apply_pending(sock)?;
+        let rd_activity = self.link.process_out(sock, pbuf.reborrow())?;
+        let wr_activity = self.process_in(sock, pbuf.reborrow())?;
+        Ok(rd_activity | wr_activity)
+    }
+
+    /// Send as many complete records as possible from the outgoing
+    /// pipe, after first applying any pending changes.  See
+    /// [`UdpPeerLink::process_out`].
+    ///
+    /// Assumes that it is always called with the same `UdpSocket`
+    /// and pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process_out(&mut self, sock: &mut UdpSocket, pbuf: PBufRdWr) -> Result<Activity> {
+        self.apply_pending(sock)?;
+        self.link.process_out(sock, pbuf)
+    }
+
+    /// Receive as many datagrams as possible, after first applying
+    /// any pending changes.  Records are as for
+    /// [`UdpPeerLink::process_in`], with the addition of the
+    /// destination address if **include_dest_addr** is enabled.
+    ///
+    /// Assumes that it is always called with the same `UdpSocket`
+    /// and pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process_in(&mut self, sock: &mut UdpSocket, pbuf: PBufRdWr) -> Result<Activity> {
+        self.apply_pending(sock)?;
+        if !self.include_dest_addr {
+            return self.link.process_in(sock, pbuf);
+        }
+        self.process_in_dst(sock, pbuf)
+    }
+
+    fn apply_pending(&mut self, sock: &mut UdpSocket) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        for (i, op) in self.pending.iter().enumerate() {
+            let direction = match *op {
+                Pending::Loop(_) | Pending::Ttl(_) => Direction::Outgoing,
+                _ => Direction::Incoming,
+            };
+            let rv = match *op {
+                Pending::Join(group, memb) => {
+                    let rv = join_or_leave(sock, group, memb, true);
+                    if rv.is_ok() {
+                        self.groups.push((group, memb));
+                    }
+                    rv
+                }
+                Pending::Leave(group) => {
+                    let mut rv = Ok(());
+                    for &(g, memb) in self.groups.iter().filter(|(g, _)| *g == group) {
+                        rv = rv.and(join_or_leave(sock, g, memb, false));
+                    }
+                    self.groups.retain(|(g, _)| *g != group);
+                    rv
+                }
+                Pending::Loop(enable) => sock.local_addr().and_then(|addr| match addr.ip() {
+                    IpAddr::V4(_) => sock.set_multicast_loop_v4(enable),
+                    IpAddr::V6(_) => sock.set_multicast_loop_v6(enable),
+                }),
+                Pending::Ttl(ttl) => sock.local_addr().and_then(|addr| match addr.ip() {
+                    IpAddr::V4(_) => sock.set_multicast_ttl_v4(ttl),
+                    IpAddr::V6(_) => set_multicast_hops_v6(sock, ttl),
+                }),
+                Pending::DestAddr => {
+                    let rv = enable_dest_addr(sock);
+                    if rv.is_ok() {
+                        self.include_dest_addr = true;
+                    }
+                    rv
+                }
+            };
+            if let Err(e) = rv {
+                self.pending.drain(..=i);
+                return Err(Error::new(direction, Op::SetOption, e));
+            }
+        }
+        self.pending.clear();
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn process_in_dst(&mut self, sock: &mut UdpSocket, pbuf: PBufRdWr) -> Result<Activity> {
+        use crate::record::{self, ADDR_MAX_LEN, HEADER_LEN};
+        use std::io::ErrorKind;
+        use std::os::unix::io::AsRawFd;
+
+        let mut pwr = pbuf.wr;
+        if self.link.pause_reads || pwr.is_eof() {
+            return Ok(Activity::NONE);
+        }
+
+        let local = sock.local_addr().map_err(Error::read)?;
+        let max_datagram_size = self.link.max_datagram_size;
+        let mut act = Activity::NONE;
+        let mut total = 0;
+        while total < self.link.max_read_unit {
+            // Reserve one extra byte to detect truncation.  The
+            // datagram is received after space for two of the largest
+            // addresses, and moved down once the addresses are known.
+            let start = HEADER_LEN + 2 * ADDR_MAX_LEN;
+            let Some(space) = pwr.try_space(start + max_datagram_size + 1) else {
+                // No room for a whole datagram in a fixed-capacity pipe
+                break;
+            };
+            let fd = sock.as_raw_fd();
+            let buf = &mut space[start..];
+            match retry!(sock.try_io(|| crate::sockopt::recv_with_dst(fd, buf))) {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == ErrorKind::ConnectionRefused => (),
+                Err(e) => return Err(Error::read(e)),
+                Ok((len, _, _)) if len > max_datagram_size => (),
+                Ok((len, src, dst)) => {
+                    let mut dst_addr = local;
+                    if let Some(ip) = dst {
+                        dst_addr.set_ip(ip);
+                    }
+                    let mut addr_len = record::put_addr(&mut space[HEADER_LEN..], &src);
+                    addr_len += record::put_addr(&mut space[HEADER_LEN + addr_len..], &dst_addr);
+                    let rec_len = addr_len + len;
+                    space.copy_within(start..start + len, HEADER_LEN + addr_len);
+                    record::put_header(space, rec_len);
+                    pwr.commit(HEADER_LEN + rec_len);
+                    act |= Activity::READ;
+                    total += HEADER_LEN + rec_len;
+                }
+            }
+        }
+        Ok(act)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn process_in_dst(&mut self, _sock: &mut UdpSocket, _pbuf: PBufRdWr) -> Result<Activity> {
+        // Never enabled on this platform
+        unreachable!()
+    }
+}
+
+impl Default for MulticastLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn join_or_leave(sock: &UdpSocket, group: IpAddr, memb: Membership, join: bool) -> io::Result<()> {
+    match (group, memb) {
+        (IpAddr::V4(g), Membership::V4(i)) if join => sock.join_multicast_v4(&g, &i),
+        (IpAddr::V4(g), Membership::V4(i)) => sock.leave_multicast_v4(&g, &i),
+        (IpAddr::V6(g), Membership::V6(i)) if join => sock.join_multicast_v6(&g, i),
+        (IpAddr::V6(g), Membership::V6(i)) => sock.leave_multicast_v6(&g, i),
+        // Not constructed by the public methods
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(target_family = "unix")]
+fn set_multicast_hops_v6(sock: &UdpSocket, hops: u32) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    let hops = hops as libc::c_int;
+    crate::sockopt::setsockopt(
+        sock.as_raw_fd(),
+        libc::IPPROTO_IPV6,
+        libc::IPV6_MULTICAST_HOPS,
+        hops,
+    )
+}
+
+#[cfg(not(target_family = "unix"))]
+fn set_multicast_hops_v6(_sock: &UdpSocket, _hops: u32) -> io::Result<()> {
+    Err(crate::sockopt::unsupported(
+        "Setting the IPv6 multicast hop limit",
+    ))
+}
+
+#[cfg(target_os = "linux")]
+fn enable_dest_addr(sock: &UdpSocket) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    let ipv6 = sock.local_addr()?.is_ipv6();
+    crate::sockopt::enable_pktinfo(sock.as_raw_fd(), ipv6)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn enable_dest_addr(_sock: &UdpSocket) -> io::Result<()> {
+    Err(crate::sockopt::unsupported(
+        "Reporting datagram destination addresses",
+    ))
+}
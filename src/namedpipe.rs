@@ -0,0 +1,218 @@
+use crate::activity::{abort_in, close_in, consume_eof_out, input_from, output_to};
+use crate::{Activity, Error, Result};
+use mio::windows::NamedPipe;
+use pipebuf::PBufRdWr;
+use std::io::{self, ErrorKind};
+
+// Windows error codes
+const ERROR_BROKEN_PIPE: i32 = 109;
+const ERROR_NO_DATA: i32 = 232;
+const ERROR_PIPE_CONNECTED: i32 = 535;
+const ERROR_PIPE_LISTENING: i32 = 536;
+
+/// Exchange stream data via the `mio` [`NamedPipe`] type on Windows
+///
+/// This handles the server side of a named pipe.  Call
+/// [`NamedPipeLink::connect`] once the pipe has been registered, and
+/// again on each "writable" event until it returns `Ok(true)`.  At
+/// that point a client has connected and both reads and writes are
+/// unpaused.  Once the connection is finished, call
+/// [`NamedPipeLink::disconnect`], after which the pipe and link may be
+/// reused for another client.
+///
+/// Windows reports the state of the pipe through various error
+/// codes, which are mapped as follows:
+///
+/// - `ERROR_PIPE_LISTENING`: No client has connected yet, treated
+///   like `WouldBlock`
+///
+/// - `ERROR_BROKEN_PIPE`: The client closed its end, passed on as a
+///   "close" of the incoming pipe
+///
+/// - `ERROR_NO_DATA`: The pipe is being closed, passed on as an
+///   "abort" of the incoming pipe.  On writing, any pending outgoing
+///   data is discarded.
+///
+/// Named pipes have no way to shut down one direction, so on an
+/// outgoing "close" the EOF is just consumed once all the data has
+/// been written.  Disconnecting would discard data not yet read by
+/// the client, so that is left to the caller.  On an outgoing
+/// "abort", the pipe is disconnected immediately and the incoming
+/// pipe is aborted.
+#[derive(Debug)]
+pub struct NamedPipeLink {
+    // Maximum amount of data to read in one go (in bytes)
+    max_read_unit: usize,
+
+    // Set to pause writes (waiting for client to connect)
+    pause_writes: bool,
+
+    // Set to pause reads (waiting for client to connect)
+    pause_reads: bool,
+
+    // Set once a client has connected
+    connected: bool,
+}
+
+impl NamedPipeLink {
+    /// Create the component with default settings:
+    ///
+    /// - **max_read_unit** of 2048
+    ///
+    /// - Both reads and writes paused until a client connects
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            max_read_unit: 2048,
+            pause_writes: true,
+            pause_reads: true,
+            connected: false,
+        }
+    }
+
+    /// Change the maximum number of bytes to read in each `process`
+    /// call
+    #[inline]
+    pub fn set_max_read_unit(&mut self, max_read_unit: usize) {
+        self.max_read_unit = max_read_unit;
+    }
+
+    /// Pause or unpause writes.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_writes(&mut self, pause: bool) {
+        self.pause_writes = pause;
+    }
+
+    /// Pause or unpause reads.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_reads(&mut self, pause: bool) {
+        self.pause_reads = pause;
+    }
+
+    /// Test whether a client is connected
+    #[inline]
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Wait for a client to connect.  Returns `Ok(true)` if a client
+    /// is connected, `Ok(false)` if the connection is still pending,
+    /// or `Err(_)` if the connection failed.  When the client
+    /// connects, reads and writes are unpaused.
+    pub fn connect(&mut self, pipe: &NamedPipe) -> io::Result<bool> {
+        if self.connected {
+            return Ok(true);
+        }
+        if let Some(e) = pipe.take_error()? {
+            return Err(e);
+        }
+        match pipe.connect() {
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(false),
+            Err(ref e) if e.raw_os_error() == Some(ERROR_PIPE_CONNECTED) => (),
+            Err(e) => return Err(e),
+            Ok(()) => (),
+        }
+        self.connected = true;
+        self.pause_reads = false;
+        self.pause_writes = false;
+        Ok(true)
+    }
+
+    /// Disconnect the client, if any.  Reads and writes are paused
+    /// again, ready for [`NamedPipeLink::connect`] to be called for
+    /// the next client.  Fresh pipe-buffers will be needed for the
+    /// next client.
+    pub fn disconnect(&mut self, pipe: &NamedPipe) -> io::Result<()> {
+        self.connected = false;
+        self.pause_reads = true;
+        self.pause_writes = true;
+        pipe.disconnect()
+    }
+
+    /// Read and write as much data as possible to and from the given
+    /// named pipe.  Returns the activity flags (see [`Activity`]),
+    /// which are empty if no progress could be made, or `Err(_)` if
+    /// there was a fatal error on the pipe.
+    ///
+    /// Assumes that it is always called with the same `NamedPipe` and
+    /// pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process(&mut self, pipe: &mut NamedPipe, mut pbuf: PBufRdWr) -> Result<Activity> {
+        let rd_activity = self.process_out(pipe, pbuf.reborrow())?;
+        let wr_activity = self.process_in(pipe, pbuf.reborrow())?;
+        Ok(rd_activity | wr_activity)
+    }
+
+    /// Write as much data as possible out to the given named pipe.
+    /// Returns the activity flags (see [`Activity`]), which are empty
+    /// if no progress could be made, or `Err(_)` if there was a fatal
+    /// error on the pipe.
+    ///
+    /// Assumes that it is always called with the same `NamedPipe` and
+    /// pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process_out(&mut self, pipe: &mut NamedPipe, mut pbuf: PBufRdWr) -> Result<Activity> {
+        if self.pause_writes {
+            return Ok(Activity::NONE);
+        }
+
+        let mut prd = pbuf.rd;
+        let mut act = Activity::NONE;
+        match output_to(&mut prd, pipe, &mut act) {
+            Err(ref e) if is_not_ready(e) => (),
+            Err(ref e) if e.raw_os_error() == Some(ERROR_NO_DATA) => {
+                let len = prd.len();
+                prd.consume(len);
+                consume_eof_out(&mut prd, &mut act);
+                abort_in(&mut pbuf.wr, &mut act);
+            }
+            Err(e) => return Err(Error::write(e)),
+            Ok(_) => {
+                if prd.is_empty() && prd.has_pending_eof() {
+                    consume_eof_out(&mut prd, &mut act);
+                    if prd.is_aborted() {
+                        abort_in(&mut pbuf.wr, &mut act);
+                        self.connected = false;
+                        pipe.disconnect().map_err(Error::shutdown)?;
+                    }
+                }
+            }
+        }
+        Ok(act)
+    }
+
+    /// Read as much data as possible from the given named pipe, up to
+    /// **max_read_unit** bytes.  Returns the activity flags (see
+    /// [`Activity`]), which are empty if no progress could be made,
+    /// or `Err(_)` if there was a fatal error on the pipe.
+    ///
+    /// Assumes that it is always called with the same `NamedPipe` and
+    /// pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process_in(&mut self, pipe: &mut NamedPipe, pbuf: PBufRdWr) -> Result<Activity> {
+        let mut pwr = pbuf.wr;
+        if self.pause_reads || pwr.is_eof() {
+            return Ok(Activity::NONE);
+        }
+
+        let mut act = Activity::NONE;
+        if let Err(e) = input_from(&mut pwr, pipe, self.max_read_unit, &mut act) {
+            match e.raw_os_error() {
+                Some(ERROR_BROKEN_PIPE) => close_in(&mut pwr, &mut act),
+                Some(ERROR_NO_DATA) => abort_in(&mut pwr, &mut act),
+                _ if is_not_ready(&e) => (),
+                _ => return Err(Error::read(e)),
+            }
+        }
+        Ok(act)
+    }
+}
+
+impl Default for NamedPipeLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn is_not_ready(e: &io::Error) -> bool {
+    e.kind() == ErrorKind::WouldBlock || e.raw_os_error() == Some(ERROR_PIPE_LISTENING)
+}
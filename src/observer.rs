@@ -0,0 +1,227 @@
+use crate::Activity;
+
+/// Callbacks for counting the traffic of a stream link
+///
+/// Install an implementation on a link with
+/// [`crate::TcpLink::set_observer`] or
+/// [`crate::UnixStreamLink::set_observer`], for example to maintain
+/// per-connection byte counters for metrics.  The callbacks are made
+/// from the link's `process` calls.  All the methods default to doing
+/// nothing, so implement just the ones needed.  A link without an
+/// observer has no overhead beyond a check for `None`.
+pub trait LinkObserver: Send {
+    /// Called with the number of bytes read from the stream into the
+    /// incoming pipe by one `process` call, if non-zero
+    fn on_read(&mut self, _bytes: usize) {}
+
+    /// Called with the number of bytes written out to the stream from
+    /// the outgoing pipe by one `process` call, if non-zero
+    fn on_write(&mut self, _bytes: usize) {}
+
+    /// Called when EOF from the stream, either close or abort, has
+    /// been passed on to the incoming pipe
+    fn on_eof_in(&mut self) {}
+
+    /// Called when EOF from the outgoing pipe, either close or abort,
+    /// has been passed on to the stream
+    fn on_eof_out(&mut self) {}
+
+    /// Called when an abort has been passed on in either direction,
+    /// in addition to `on_eof_in` or `on_eof_out`
+    fn on_abort(&mut self) {}
+}
+
+// Pass on the outcome of a read to the observer
+pub(crate) fn observe_read(observer: &mut dyn LinkObserver, bytes: usize, act: Activity) {
+    if bytes > 0 {
+        observer.on_read(bytes);
+    }
+    if act.contains(Activity::EOF_IN) {
+        observer.on_eof_in();
+        if act.contains(Activity::ABORTED) {
+            observer.on_abort();
+        }
+    }
+}
+
+// Pass on the outcome of a write to the observer.  An outgoing abort
+// also aborts the incoming pipe, which is reported too.
+pub(crate) fn observe_write(observer: &mut dyn LinkObserver, bytes: usize, act: Activity) {
+    if bytes > 0 {
+        observer.on_write(bytes);
+    }
+    if act.contains(Activity::EOF_OUT) {
+        observer.on_eof_out();
+    }
+    if act.contains(Activity::EOF_IN) {
+        observer.on_eof_in();
+    }
+    if act.contains(Activity::ABORTED) {
+        observer.on_abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{read_to_end, tcp_pair, wait_for};
+    use crate::TcpLink;
+    use pipebuf::PipeBufPair;
+    use std::io::Write;
+    use std::net::Shutdown;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    #[derive(Default, Debug, PartialEq, Eq)]
+    struct Counts {
+        read: usize,
+        read_calls: usize,
+        write: usize,
+        write_calls: usize,
+        eof_in: usize,
+        eof_out: usize,
+        abort: usize,
+    }
+
+    struct Counter(Arc<Mutex<Counts>>);
+
+    impl LinkObserver for Counter {
+        fn on_read(&mut self, bytes: usize) {
+            let mut c = self.0.lock().unwrap();
+            c.read += bytes;
+            c.read_calls += 1;
+        }
+        fn on_write(&mut self, bytes: usize) {
+            let mut c = self.0.lock().unwrap();
+            c.write += bytes;
+            c.write_calls += 1;
+        }
+        fn on_eof_in(&mut self) {
+            self.0.lock().unwrap().eof_in += 1;
+        }
+        fn on_eof_out(&mut self) {
+            self.0.lock().unwrap().eof_out += 1;
+        }
+        fn on_abort(&mut self) {
+            self.0.lock().unwrap().abort += 1;
+        }
+    }
+
+    fn counter() -> (Box<dyn LinkObserver>, Arc<Mutex<Counts>>) {
+        let counts = Arc::new(Mutex::new(Counts::default()));
+        (Box::new(Counter(counts.clone())), counts)
+    }
+
+    const IN_LEN: usize = 300_000;
+    const OUT_LEN: usize = 1 << 20;
+
+    // Exchange data with a peer that trickles its data in and only
+    // starts reading once it has finished writing, so that the link
+    // sees WouldBlock in both directions along the way.  Returns the
+    // bytes received by each side.
+    fn exchange<P: std::io::Read + Write + Send + 'static>(
+        mut peer: P,
+        shutdown: fn(&P),
+        mut process: impl FnMut(&mut PipeBufPair),
+    ) -> (usize, usize) {
+        let writer = thread::spawn(move || {
+            for chunk in vec![0x11; IN_LEN].chunks(7000) {
+                peer.write_all(chunk).unwrap();
+                thread::sleep(Duration::from_micros(200));
+            }
+            shutdown(&peer);
+            read_to_end(&mut peer).0.len()
+        });
+
+        let mut pair = PipeBufPair::new();
+        pair.upper().wr.append(&vec![0x22; OUT_LEN]);
+        pair.upper().wr.close();
+        let mut received = 0;
+        wait_for(|| {
+            process(&mut pair);
+            let mut app = pair.upper();
+            received += app.rd.len();
+            app.rd.consume(app.rd.len());
+            app.rd.consume_eof()
+        });
+        wait_for(|| {
+            process(&mut pair);
+            pair.lower().rd.is_done()
+        });
+        (received, writer.join().unwrap())
+    }
+
+    fn check(counts: &Mutex<Counts>, received: usize, sent: usize) {
+        assert_eq!((received, sent), (IN_LEN, OUT_LEN));
+        let c = counts.lock().unwrap();
+        assert_eq!(c.read, received);
+        assert_eq!(c.write, sent);
+        assert!(c.read_calls > 1 && c.write_calls > 1, "{:?}", *c);
+        assert_eq!((c.eof_in, c.eof_out, c.abort), (1, 1, 0));
+    }
+
+    #[test]
+    fn tcp_totals_match() {
+        let (mut stream, peer) = tcp_pair();
+        let mut link = TcpLink::new();
+        link.set_pause_reads(false);
+        link.set_pause_writes(false);
+        // Loopback buffers can swallow the whole transfer in one
+        // write otherwise
+        link.set_max_write_unit(65536);
+        let (observer, counts) = counter();
+        link.set_observer(Some(observer));
+
+        let (received, sent) = exchange(
+            peer,
+            |p| p.shutdown(Shutdown::Write).unwrap(),
+            |pair| {
+                link.process(&mut stream, pair.lower()).unwrap();
+            },
+        );
+        check(&counts, received, sent);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_totals_match() {
+        let (stream, peer) = std::os::unix::net::UnixStream::pair().unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let mut stream = mio::net::UnixStream::from_std(stream);
+        let mut link = crate::UnixStreamLink::new();
+        link.set_pause_reads(false);
+        link.set_pause_writes(false);
+        let (observer, counts) = counter();
+        link.set_observer(Some(observer));
+
+        let (received, sent) = exchange(
+            peer,
+            |p| p.shutdown(Shutdown::Write).unwrap(),
+            |pair| {
+                link.process(&mut stream, pair.lower()).unwrap();
+            },
+        );
+        check(&counts, received, sent);
+    }
+
+    #[test]
+    fn abort_is_reported() {
+        let (mut stream, mut peer) = tcp_pair();
+        let mut link = TcpLink::new();
+        link.set_pause_reads(false);
+        link.set_pause_writes(false);
+        let (observer, counts) = counter();
+        link.set_observer(Some(observer));
+
+        let mut pair = PipeBufPair::new();
+        pair.upper().wr.append(b"bye");
+        pair.upper().wr.abort();
+        link.process(&mut stream, pair.lower()).unwrap();
+        assert_eq!(read_to_end(&mut peer).0, b"bye");
+
+        let c = counts.lock().unwrap();
+        assert_eq!(c.write, 3);
+        assert_eq!((c.eof_in, c.eof_out, c.abort), (1, 1, 1));
+    }
+}
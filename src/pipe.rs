@@ -0,0 +1,344 @@
+use crate::activity::{abort_in, consume_eof_out, input_from, output_to};
+use crate::{Activity, Error, Result};
+use mio::unix::pipe::{Receiver, Sender};
+use pipebuf::{PBufRd, PBufWr};
+use std::io::ErrorKind;
+
+/// Read data from a `mio` [`Receiver`], the read end of a Unix pipe
+///
+/// This is typically used to read the output of a child process.
+/// EOF on the Unix pipe is passed on as a "close" of the pipe-buffer.
+/// A `BrokenPipe` (EPIPE) or EBADF error is passed on as an "abort".
+///
+/// Reading starts unpaused, since a Unix pipe does not need to wait
+/// for a "ready" indication.
+#[derive(Debug)]
+pub struct PipeReceiverLink {
+    // Maximum amount of data to read in one go (in bytes)
+    max_read_unit: usize,
+
+    // Set to pause reads
+    pause_reads: bool,
+}
+
+impl PipeReceiverLink {
+    /// Create the component with default settings:
+    ///
+    /// - **max_read_unit** of 2048
+    ///
+    /// - Reads unpaused
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            max_read_unit: 2048,
+            pause_reads: false,
+        }
+    }
+
+    /// Change the maximum number of bytes to read in each
+    /// `process_in` call.  This allows managing how much data you
+    /// wish to handle at a time, to allow the possibility of
+    /// backpressure.
+    #[inline]
+    pub fn set_max_read_unit(&mut self, max_read_unit: usize) {
+        self.max_read_unit = max_read_unit;
+    }
+
+    /// Pause or unpause reads.  This takes effect on the next
+    /// `process_in` call.
+    #[inline]
+    pub fn set_pause_reads(&mut self, pause: bool) {
+        self.pause_reads = pause;
+    }
+
+    /// Read as much data as possible from the given Unix pipe, up to
+    /// **max_read_unit** bytes.  Returns the activity flags (see
+    /// [`Activity`]), which are empty if no progress could be made,
+    /// or `Err(_)` if there was a fatal error on the pipe.
+    ///
+    /// Assumes that it is always called with the same `Receiver` and
+    /// pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process_in(&mut self, rx: &mut Receiver, mut pwr: PBufWr) -> Result<Activity> {
+        if self.pause_reads || pwr.is_eof() {
+            return Ok(Activity::NONE);
+        }
+
+        let mut act = Activity::NONE;
+        if let Err(e) = input_from(&mut pwr, rx, self.max_read_unit, &mut act) {
+            match e.kind() {
+                ErrorKind::BrokenPipe => abort_in(&mut pwr, &mut act),
+                ErrorKind::WouldBlock => (),
+                _ if e.raw_os_error() == Some(libc::EBADF) => abort_in(&mut pwr, &mut act),
+                _ => return Err(Error::read(e)),
+            }
+        }
+        Ok(act)
+    }
+}
+
+impl Default for PipeReceiverLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Write data to a `mio` [`Sender`], the write end of a Unix pipe
+///
+/// This is typically used to feed the input of a child process.  The
+/// `Sender` is passed as an `Option` so that the link can drop it to
+/// close the Unix pipe once the pipe-buffer EOF is reached.  That is
+/// the only way to signal EOF to the reading end.  The `Sender` is
+/// dropped and set to `None` in these cases:
+///
+/// - On a "close" of the pipe-buffer, once all the data has been
+///   written.
+///
+/// - On an "abort" of the pipe-buffer.  Any pending data is discarded
+///   and the Unix pipe is closed immediately.
+///
+/// - On `BrokenPipe` (EPIPE), which means that the reader has gone
+///   away.  This is not treated as an error.  Instead all pending and
+///   future data is discarded, and the pipe-buffer EOF is consumed
+///   when it arrives.  [`PipeSenderLink::is_broken`] reports this
+///   condition.
+///
+/// If `None` is passed, then all data is discarded.
+///
+/// If a `Sender` registered with a `mio` `Registry` is dropped like
+/// this, it is automatically removed from the `Registry` as the file
+/// descriptor is closed.
+///
+/// Writing starts unpaused, since a Unix pipe does not need to wait
+/// for a "ready" indication.
+#[derive(Debug)]
+pub struct PipeSenderLink {
+    // Set to pause writes
+    pause_writes: bool,
+
+    // Set if the reader has gone away
+    broken: bool,
+}
+
+impl PipeSenderLink {
+    /// Create the component with writes unpaused
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            pause_writes: false,
+            broken: false,
+        }
+    }
+
+    /// Pause or unpause writes.  This takes effect on the next
+    /// `process_out` call.
+    #[inline]
+    pub fn set_pause_writes(&mut self, pause: bool) {
+        self.pause_writes = pause;
+    }
+
+    /// Test whether the reading end of the Unix pipe has gone away,
+    /// i.e. whether a `BrokenPipe` error has been seen
+    #[inline]
+    pub fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    /// Write as much data as possible out to the given Unix pipe.
+    /// Returns the activity flags (see [`Activity`]), which are empty
+    /// if no progress could be made, or `Err(_)` if there was a fatal
+    /// error on the pipe.
+    ///
+    /// Assumes that it is always called with the same `Sender` and
+    /// pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process_out(&mut self, tx: &mut Option<Sender>, mut prd: PBufRd) -> Result<Activity> {
+        if self.pause_writes {
+            return Ok(Activity::NONE);
+        }
+
+        let mut act = Activity::NONE;
+        let was_broken = self.broken;
+        if let Some(sender) = tx {
+            if !prd.is_aborted() {
+                match output_to(&mut prd, sender, &mut act) {
+                    Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(act),
+                    Err(ref e) if e.kind() == ErrorKind::BrokenPipe => self.broken = true,
+                    Err(e) => return Err(Error::write(e)),
+                    Ok(_) => (),
+                }
+            }
+        }
+        if self.broken || prd.is_aborted() || tx.is_none() {
+            let len = prd.len();
+            if len > 0 {
+                prd.consume(len);
+                act |= Activity::WROTE;
+            }
+            *tx = None;
+        }
+        if prd.is_empty() && prd.has_pending_eof() {
+            consume_eof_out(&mut prd, &mut act);
+            *tx = None;
+        }
+        if self.broken != was_broken {
+            act |= Activity::ABORTED;
+        }
+        Ok(act)
+    }
+}
+
+impl Default for PipeSenderLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::wait_for;
+    use mio::unix::pipe;
+    use pipebuf::PipeBuf;
+    use std::io::Read;
+
+    // Read from the receiver until EOF
+    fn drain(rx: &mut Receiver) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut buf = [0; 4096];
+        wait_for(|| match rx.read(&mut buf) {
+            Ok(0) => true,
+            Ok(len) => {
+                data.extend_from_slice(&buf[..len]);
+                false
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => false,
+            Err(e) => panic!("{}", e),
+        });
+        data
+    }
+
+    #[test]
+    fn receiver_data_and_eof() {
+        let (mut tx, mut rx) = pipe::new().unwrap();
+        let mut link = PipeReceiverLink::new();
+        let mut pb = PipeBuf::new();
+
+        assert_eq!(link.process_in(&mut rx, pb.wr()).unwrap(), Activity::NONE);
+        std::io::Write::write_all(&mut tx, b"hello").unwrap();
+        drop(tx);
+        wait_for(|| {
+            link.process_in(&mut rx, pb.wr()).unwrap();
+            pb.rd().is_eof()
+        });
+        assert_eq!(pb.rd().data(), b"hello");
+        assert!(!pb.rd().is_aborted());
+    }
+
+    #[test]
+    fn sender_writes_then_closes() {
+        let (tx, mut rx) = pipe::new().unwrap();
+        let mut tx = Some(tx);
+        let mut link = PipeSenderLink::new();
+        let mut pb = PipeBuf::new();
+
+        pb.wr().append(b"hello");
+        let act = link.process_out(&mut tx, pb.rd()).unwrap();
+        assert!(act.contains(Activity::WROTE));
+        assert!(tx.is_some());
+
+        pb.wr().append(b" world");
+        pb.wr().close();
+        let act = link.process_out(&mut tx, pb.rd()).unwrap();
+        assert!(act.contains(Activity::WROTE | Activity::EOF_OUT));
+        assert!(tx.is_none());
+        assert!(!link.is_broken());
+        assert_eq!(drain(&mut rx), b"hello world");
+    }
+
+    #[test]
+    fn sender_waits_when_full() {
+        let (tx, mut rx) = pipe::new().unwrap();
+        let mut tx = Some(tx);
+        let mut link = PipeSenderLink::new();
+        let mut pb = PipeBuf::new();
+
+        let data: Vec<u8> = (0..1 << 20).map(|i| i as u8).collect();
+        pb.wr().append(&data);
+        pb.wr().close();
+        link.process_out(&mut tx, pb.rd()).unwrap();
+        assert!(!pb.rd().is_empty());
+        assert!(tx.is_some());
+
+        // The EOF is only passed on once all the data has gone
+        let reader = std::thread::spawn(move || drain(&mut rx));
+        wait_for(|| {
+            link.process_out(&mut tx, pb.rd()).unwrap();
+            tx.is_none()
+        });
+        assert!(pb.rd().is_empty());
+        assert_eq!(reader.join().unwrap(), data);
+    }
+
+    #[test]
+    fn sender_abort_discards_and_closes() {
+        let (tx, mut rx) = pipe::new().unwrap();
+        let mut tx = Some(tx);
+        let mut link = PipeSenderLink::new();
+        let mut pb = PipeBuf::new();
+
+        pb.wr().append(b"never sent");
+        pb.wr().abort();
+        let act = link.process_out(&mut tx, pb.rd()).unwrap();
+        assert!(act.contains(Activity::WROTE | Activity::EOF_OUT));
+        assert!(tx.is_none());
+        assert!(pb.rd().is_empty());
+        assert!(!link.is_broken());
+        assert_eq!(drain(&mut rx), b"");
+    }
+
+    #[test]
+    fn sender_broken_pipe_discards() {
+        let (tx, rx) = pipe::new().unwrap();
+        let mut tx = Some(tx);
+        let mut link = PipeSenderLink::new();
+        let mut pb = PipeBuf::new();
+        drop(rx);
+
+        pb.wr().append(b"nobody listening");
+        let act = link.process_out(&mut tx, pb.rd()).unwrap();
+        assert!(act.contains(Activity::ABORTED | Activity::WROTE));
+        assert!(link.is_broken());
+        assert!(tx.is_none());
+        assert!(pb.rd().is_empty());
+
+        // Later data is discarded, and the EOF consumed, without error
+        pb.wr().append(b"more");
+        let act = link.process_out(&mut tx, pb.rd()).unwrap();
+        assert_eq!(act, Activity::WROTE);
+        pb.wr().close();
+        let act = link.process_out(&mut tx, pb.rd()).unwrap();
+        assert_eq!(act, Activity::EOF_OUT);
+        assert!(!pb.rd().has_pending_eof());
+    }
+
+    #[test]
+    fn receiver_fills_fixed_capacity_pipe() {
+        let (mut tx, mut rx) = pipe::new().unwrap();
+        let mut link = PipeReceiverLink::new();
+        link.set_max_read_unit(16384);
+        let mut pb = PipeBuf::with_fixed_capacity(4096);
+        std::io::Write::write_all(&mut tx, &[7; 10000]).unwrap();
+
+        let act = link.process_in(&mut rx, pb.wr()).unwrap();
+        assert_eq!(act, Activity::READ);
+        assert_eq!(pb.rd().len(), 4096);
+        assert_eq!(link.process_in(&mut rx, pb.wr()).unwrap(), Activity::NONE);
+
+        // Reading resumes once the consumer makes room
+        pb.rd().consume(4096);
+        link.process_in(&mut rx, pb.wr()).unwrap();
+        assert_eq!(pb.rd().len(), 4096);
+        pb.rd().consume(4096);
+        link.process_in(&mut rx, pb.wr()).unwrap();
+        assert_eq!(pb.rd().len(), 10000 - 8192);
+    }
+}
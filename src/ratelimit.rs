@@ -0,0 +1,184 @@
+use std::time::{Duration, Instant};
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// Bandwidth limit for a stream link, as a token bucket
+///
+/// Install on a link with [`crate::TcpLink::set_read_rate_limit`] or
+/// [`crate::TcpLink::set_write_rate_limit`].  The bucket holds up to
+/// **burst** bytes of allowance, and refills at **bytes_per_sec**.
+/// Each read or write is limited to the allowance currently
+/// available, and uses up only as much as was actually
+/// transferred.  The bucket starts full, so
+/// up to **burst** bytes may be read at once after a quiet period.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RateLimit {
+    bytes_per_sec: u64,
+    burst: u64,
+}
+
+impl RateLimit {
+    /// Create a limit of `bytes_per_sec` on average, allowing bursts
+    /// of up to `burst` bytes.  Both are raised to at least 1.
+    #[inline]
+    pub fn new(bytes_per_sec: u64, burst: u64) -> Self {
+        Self {
+            bytes_per_sec: bytes_per_sec.max(1),
+            burst: burst.max(1),
+        }
+    }
+
+    /// Get the average rate allowed, in bytes per second
+    #[inline]
+    pub fn bytes_per_sec(&self) -> u64 {
+        self.bytes_per_sec
+    }
+
+    /// Get the maximum burst size, in bytes
+    #[inline]
+    pub fn burst(&self) -> u64 {
+        self.burst
+    }
+}
+
+// Current state of a `RateLimit`
+#[derive(Debug)]
+pub(crate) struct TokenBucket {
+    limit: RateLimit,
+
+    // Bytes that may be transferred now
+    tokens: u64,
+
+    // Time up to which refills have been credited
+    refilled: Instant,
+
+    // Set when the last transfer was cut short by running out of
+    // tokens
+    throttled: bool,
+}
+
+impl TokenBucket {
+    // Create with a full bucket
+    pub(crate) fn new(limit: RateLimit, now: Instant) -> Self {
+        // A deserialized limit may not have been through
+        // `RateLimit::new`
+        let limit = RateLimit::new(limit.bytes_per_sec, limit.burst);
+        Self {
+            limit,
+            tokens: limit.burst,
+            refilled: now,
+            throttled: false,
+        }
+    }
+
+    // Credit the tokens earned since the last refill, and get the
+    // number of bytes that may be transferred now
+    pub(crate) fn refill(&mut self, now: Instant) -> usize {
+        let elapsed = now.saturating_duration_since(self.refilled).as_nanos();
+        let rate = u128::from(self.limit.bytes_per_sec);
+        let earned = elapsed * rate / NANOS_PER_SEC;
+        let space = u128::from(self.limit.burst - self.tokens);
+        if earned >= space {
+            self.tokens = self.limit.burst;
+            self.refilled = now;
+        } else {
+            // Only move on by the time that paid for whole tokens, so
+            // that fractions are not lost
+            self.tokens += earned as u64;
+            self.refilled += Duration::from_nanos((earned * NANOS_PER_SEC / rate) as u64);
+        }
+        usize::try_from(self.tokens).unwrap_or(usize::MAX)
+    }
+
+    // Use up tokens for `bytes` transferred.  `more` indicates
+    // whether there may be more to transfer once tokens are
+    // available.
+    pub(crate) fn take(&mut self, bytes: usize, more: bool) {
+        self.tokens = self.tokens.saturating_sub(bytes as u64);
+        self.throttled = more && self.tokens == 0;
+    }
+
+    // Test whether transfers are waiting for tokens
+    #[inline]
+    pub(crate) fn is_throttled(&self) -> bool {
+        self.throttled
+    }
+
+    // Get the time at which enough tokens will have built up to make
+    // waking up worthwhile, i.e. `unit` bytes or a full bucket, if
+    // throttled
+    pub(crate) fn next_deadline(&self, unit: usize) -> Option<Instant> {
+        if !self.throttled {
+            return None;
+        }
+        let want = (unit as u64).clamp(1, self.limit.burst);
+        let needed = u128::from(want.saturating_sub(self.tokens));
+        let rate = u128::from(self.limit.bytes_per_sec);
+        let nanos = (needed * NANOS_PER_SEC).div_ceil(rate);
+        self.refilled
+            .checked_add(Duration::from_nanos(u64::try_from(nanos).ok()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn limit_is_normalized() {
+        let limit = RateLimit::new(0, 0);
+        assert_eq!((limit.bytes_per_sec(), limit.burst()), (1, 1));
+    }
+
+    #[test]
+    fn refill_is_capped_at_burst() {
+        let t0 = Instant::now();
+        let mut bucket = TokenBucket::new(RateLimit::new(1000, 500), t0);
+        assert_eq!(bucket.refill(t0), 500);
+        bucket.take(500, true);
+        assert!(bucket.is_throttled());
+        assert_eq!(bucket.refill(t0 + ms(100)), 100);
+        assert_eq!(bucket.refill(t0 + ms(300)), 300);
+        assert_eq!(bucket.refill(t0 + ms(10_000)), 500);
+    }
+
+    #[test]
+    fn fractions_are_kept() {
+        // At 3 bytes per second, each 100ms earns 0.3 of a byte
+        let t0 = Instant::now();
+        let mut bucket = TokenBucket::new(RateLimit::new(3, 100), t0);
+        bucket.take(100, true);
+        let mut t = t0;
+        for _ in 0..10 {
+            t += ms(100);
+            bucket.refill(t);
+        }
+        assert_eq!(bucket.refill(t), 3);
+    }
+
+    #[test]
+    fn deadline_only_while_throttled() {
+        let t0 = Instant::now();
+        let mut bucket = TokenBucket::new(RateLimit::new(1000, 500), t0);
+        bucket.take(200, true);
+        assert!(!bucket.is_throttled());
+        assert_eq!(bucket.next_deadline(100), None);
+
+        // Out of tokens with more to do: wait for a unit's worth,
+        // or a full bucket if the unit is larger
+        bucket.take(300, true);
+        assert_eq!(bucket.next_deadline(100), Some(t0 + ms(100)));
+        assert_eq!(bucket.next_deadline(0), Some(t0 + ms(1)));
+        assert_eq!(bucket.next_deadline(10_000), Some(t0 + ms(500)));
+
+        // Out of tokens with nothing left to do isn't throttled
+        bucket.refill(t0 + ms(50));
+        bucket.take(50, false);
+        assert!(!bucket.is_throttled());
+    }
+}
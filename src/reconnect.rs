@@ -0,0 +1,340 @@
+use crate::activity::{abort_in, close_in, consume_eof_out, output_to, read_room};
+use crate::connector::check_connect;
+use crate::generic::shutdown_result;
+use crate::Activity;
+use mio::net::TcpStream;
+use mio::{Interest, Registry, Token};
+use pipebuf::{PBufRdWr, PBufWr};
+use std::io::{ErrorKind, Read, Result};
+use std::net::{Shutdown, SocketAddr};
+use std::time::{Duration, Instant};
+
+/// What to pass on to the incoming pipe when a
+/// [`ReconnectingTcpLink`] connection drops
+#[derive(Debug)]
+pub enum DisconnectMode {
+    /// Abort the incoming pipe.  Reading stops until the caller has
+    /// handled the abort and reset the pipe-buffer.
+    Abort,
+
+    /// Write the given bytes to the incoming pipe as a marker, and
+    /// carry on
+    Marker(Vec<u8>),
+
+    /// Pass nothing on
+    Silent,
+}
+
+#[derive(Debug)]
+enum State {
+    // Waiting to be registered
+    Idle,
+
+    // Waiting until the given time before connecting again
+    Waiting(Instant),
+
+    // Connect in progress
+    Connecting,
+
+    // Connected and exchanging data
+    Connected,
+
+    // Outgoing pipe has ended, so no more reconnects
+    Finished,
+}
+
+/// Exchange stream data via a TCP connection that is re-established
+/// whenever it drops
+///
+/// This owns the address, the current `mio` [`TcpStream`] (if any)
+/// and a backoff policy.  Call [`ReconnectingTcpLink::register`] to
+/// start the first connect, and then call
+/// [`ReconnectingTcpLink::process`] on every event for the token, and
+/// also once the time returned by
+/// [`ReconnectingTcpLink::next_deadline`] has passed.
+///
+/// When the connection fails, whether due to an error or EOF from
+/// the peer, the stream is deregistered and dropped, the incoming
+/// pipe is informed according to the [`DisconnectMode`], and a
+/// reconnect is scheduled after the backoff delay.  Failed connect
+/// attempts just schedule another reconnect.  The delay starts
+/// at **initial_backoff** and doubles on each consecutive failure up
+/// to **max_backoff**, and is reset once a connection succeeds.  The
+/// incoming pipe is otherwise never closed, so it appears as one
+/// continuous stream.
+///
+/// Outgoing data is held in the outgoing pipe while there is no
+/// connection, and is sent once reconnected.  Data already written to
+/// a connection that then drops may have been lost.  When the
+/// outgoing pipe reaches EOF, the connection is shut down as for
+/// [`crate::TcpLink`], and no further reconnects are attempted.  The
+/// incoming pipe is then closed when the peer closes.
+#[derive(Debug)]
+pub struct ReconnectingTcpLink {
+    addr: SocketAddr,
+    token: Token,
+    stream: Option<TcpStream>,
+    state: State,
+
+    // Maximum amount of data to read in one go (in bytes)
+    max_read_unit: usize,
+
+    // TCP_NODELAY flag, applied to each new connection
+    nodelay: bool,
+
+    // What to do to the incoming pipe on disconnect
+    disconnect_mode: DisconnectMode,
+
+    // Backoff policy and current delay
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    backoff: Duration,
+}
+
+impl ReconnectingTcpLink {
+    /// Create the component for the given address with default
+    /// settings:
+    ///
+    /// - **max_read_unit** of 2048
+    ///
+    /// - **nodelay** set to `false`
+    ///
+    /// - **disconnect_mode** of [`DisconnectMode::Abort`]
+    ///
+    /// - **initial_backoff** of 100ms and **max_backoff** of 30s
+    #[inline]
+    pub fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            token: Token(0),
+            stream: None,
+            state: State::Idle,
+            max_read_unit: 2048,
+            nodelay: false,
+            disconnect_mode: DisconnectMode::Abort,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+            backoff: Duration::from_millis(100),
+        }
+    }
+
+    /// Change the maximum number of bytes to read in each `process`
+    /// call
+    #[inline]
+    pub fn set_max_read_unit(&mut self, max_read_unit: usize) {
+        self.max_read_unit = max_read_unit;
+    }
+
+    /// Change the "no delay" flag.  This is applied to each new
+    /// connection.  See [`crate::TcpLink::set_nodelay`].
+    #[inline]
+    pub fn set_nodelay(&mut self, nodelay: bool) {
+        self.nodelay = nodelay;
+    }
+
+    /// Change what is passed on to the incoming pipe on each
+    /// disconnect
+    #[inline]
+    pub fn set_disconnect_mode(&mut self, mode: DisconnectMode) {
+        self.disconnect_mode = mode;
+    }
+
+    /// Change the backoff policy.  The delay before reconnecting
+    /// starts at `initial` and doubles on each consecutive failure up
+    /// to `max`.
+    #[inline]
+    pub fn set_backoff(&mut self, initial: Duration, max: Duration) {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self.backoff = initial;
+    }
+
+    /// Test whether there is currently an established connection
+    #[inline]
+    pub fn is_connected(&self) -> bool {
+        matches!(self.state, State::Connected)
+    }
+
+    /// If waiting to reconnect, returns the time at which the caller
+    /// should next call `process`
+    #[inline]
+    pub fn next_deadline(&self) -> Option<Instant> {
+        match self.state {
+            State::Waiting(until) => Some(until),
+            _ => None,
+        }
+    }
+
+    /// Start the first connect, registering each stream created with
+    /// the given token.  A failure to connect is not returned as an
+    /// error, but schedules a reconnect.
+    pub fn register(&mut self, registry: &Registry, token: Token) -> Result<()> {
+        self.token = token;
+        self.connect(registry)
+    }
+
+    /// Drive the connection: reconnect if the deadline has passed,
+    /// check for connect completion, and read and write as much data
+    /// as possible.  Returns the activity flags (see [`Activity`]),
+    /// which are empty if no progress could be made, or `Err(_)` if
+    /// there was an error from the registry.  Connection errors are
+    /// handled by reconnecting.
+    ///
+    /// Assumes that it is always called with the same registry and
+    /// pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process(&mut self, registry: &Registry, mut pbuf: PBufRdWr) -> Result<Activity> {
+        let mut activity = Activity::NONE;
+        if let State::Waiting(until) = self.state {
+            if Instant::now() < until {
+                return Ok(Activity::NONE);
+            }
+            self.connect(registry)?;
+        }
+        if let State::Connecting = self.state {
+            match check_connect(self.stream.as_ref(), |s| s.take_error(), |s| s.peer_addr()) {
+                Ok(false) => return Ok(Activity::NONE),
+                Ok(true) => {
+                    self.state = State::Connected;
+                    self.backoff = self.initial_backoff;
+                    if self.nodelay {
+                        if let Some(ref stream) = self.stream {
+                            let _ = stream.set_nodelay(true);
+                        }
+                    }
+                }
+                Err(_) => return self.disconnect(registry, pbuf.wr),
+            }
+        }
+        if let State::Connected | State::Finished = self.state {
+            let Some(ref mut stream) = self.stream else {
+                return Ok(activity);
+            };
+            match process_out(stream, pbuf.reborrow()) {
+                Ok((out_activity, finished)) => {
+                    activity |= out_activity;
+                    if finished {
+                        self.state = State::Finished;
+                    }
+                }
+                Err(_) => return self.disconnect(registry, pbuf.wr),
+            }
+            match self.process_in(pbuf.wr.reborrow()) {
+                Ok(in_activity) => activity |= in_activity,
+                Err(_) => return self.disconnect(registry, pbuf.wr),
+            }
+        }
+        Ok(activity)
+    }
+
+    fn connect(&mut self, registry: &Registry) -> Result<()> {
+        match TcpStream::connect(self.addr) {
+            Ok(mut stream) => {
+                registry.register(
+                    &mut stream,
+                    self.token,
+                    Interest::READABLE | Interest::WRITABLE,
+                )?;
+                self.stream = Some(stream);
+                self.state = State::Connecting;
+            }
+            Err(_) => self.schedule(),
+        }
+        Ok(())
+    }
+
+    fn schedule(&mut self) {
+        self.state = State::Waiting(Instant::now() + self.backoff);
+        self.backoff = (self.backoff * 2).min(self.max_backoff);
+    }
+
+    fn disconnect(&mut self, registry: &Registry, mut pwr: PBufWr) -> Result<Activity> {
+        let mut act = Activity::NONE;
+        let finished = matches!(self.state, State::Finished);
+        let connected = matches!(self.state, State::Connected);
+        if let Some(mut stream) = self.stream.take() {
+            registry.deregister(&mut stream)?;
+        }
+        if finished {
+            // No more reconnects, so pass on the failure
+            abort_in(&mut pwr, &mut act);
+            return Ok(act);
+        }
+        if connected && !pwr.is_eof() {
+            match self.disconnect_mode {
+                DisconnectMode::Abort => abort_in(&mut pwr, &mut act),
+                DisconnectMode::Marker(ref marker) => {
+                    pwr.append(marker);
+                    act |= Activity::READ;
+                }
+                DisconnectMode::Silent => (),
+            }
+        }
+        self.schedule();
+        Ok(act)
+    }
+
+    // Returns Err(_) on error or EOF from the peer, both of which are
+    // handled as a disconnect unless finished
+    fn process_in(&mut self, mut pwr: PBufWr) -> Result<Activity> {
+        if pwr.is_eof() {
+            return Ok(Activity::NONE);
+        }
+        let Some(ref mut stream) = self.stream else {
+            return Ok(Activity::NONE);
+        };
+        let mut act = Activity::NONE;
+        let mut total = 0;
+        while total < self.max_read_unit {
+            let room = read_room(&pwr, self.max_read_unit - total);
+            if room == 0 {
+                break;
+            }
+            let space = pwr.space(room);
+            match retry!(stream.read(space)) {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+                Ok(0) if matches!(self.state, State::Finished) => {
+                    close_in(&mut pwr, &mut act);
+                    break;
+                }
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(len) => {
+                    pwr.commit(len);
+                    act |= Activity::READ;
+                    total += len;
+                }
+            }
+        }
+        Ok(act)
+    }
+}
+
+// Returns the activity and whether the outgoing pipe has ended
+fn process_out(stream: &mut TcpStream, mut pbuf: PBufRdWr) -> Result<(Activity, bool)> {
+    let mut prd = pbuf.rd;
+    let mut act = Activity::NONE;
+    let mut finished = false;
+    match output_to(&mut prd, stream, &mut act) {
+        Err(ref e) if e.kind() == ErrorKind::WouldBlock => (),
+        Err(e) => return Err(e),
+        Ok(_) => {
+            if prd.is_empty() && prd.has_pending_eof() {
+                let shutdown = if prd.is_aborted() {
+                    abort_in(&mut pbuf.wr, &mut act);
+                    Shutdown::Both
+                } else {
+                    Shutdown::Write
+                };
+                match shutdown_result(retry!(stream.shutdown(shutdown))) {
+                    Err(ref e) if e.kind() == ErrorKind::WouldBlock => (),
+                    Err(e) => return Err(e),
+                    Ok(_) => {
+                        consume_eof_out(&mut prd, &mut act);
+                        finished = true;
+                    }
+                }
+            }
+        }
+    }
+    Ok((act, finished))
+}
@@ -0,0 +1,208 @@
+//! Record framing used to carry datagrams through a byte-stream
+//! pipe-buffer
+//!
+//! Each record is a 4-byte big-endian length followed by that many
+//! bytes of payload.  Where peer addresses are carried, the encoded
+//! address forms the first part of the payload.  All the datagram
+//! links use this framing, so the same parsing code can be used
+//! downstream whichever link the data came from.  See
+//! [`crate::DatagramLink`].
+
+use pipebuf::PBufWr;
+use std::net::{SocketAddr, SocketAddrV4, SocketAddrV6};
+
+/// Length of the record header in bytes
+pub const HEADER_LEN: usize = 4;
+
+/// Write a record header for a payload of `len` bytes into the first
+/// [`HEADER_LEN`] bytes of `buf`
+#[inline]
+pub fn put_header(buf: &mut [u8], len: usize) {
+    buf[..HEADER_LEN].copy_from_slice(&(len as u32).to_be_bytes());
+}
+
+/// Check for a complete record at the start of `data`.  Returns the
+/// total length of the record including the header, or `None` if
+/// the record is not yet complete.  The payload is found at
+/// `data[HEADER_LEN..total]`.
+#[inline]
+pub fn next_record(data: &[u8]) -> Option<usize> {
+    if data.len() < HEADER_LEN {
+        return None;
+    }
+    let mut hdr = [0; HEADER_LEN];
+    hdr.copy_from_slice(&data[..HEADER_LEN]);
+    let total = HEADER_LEN + u32::from_be_bytes(hdr) as usize;
+    (total <= data.len()).then_some(total)
+}
+
+/// Maximum encoded length of a socket address
+pub const ADDR_MAX_LEN: usize = 23;
+
+/// Encode a socket address at the start of `buf`, returning the
+/// number of bytes used.  The encoding is a family byte (4 or 6),
+/// followed by the IP address bytes and a 2-byte big-endian port.
+/// For IPv6 a 4-byte big-endian scope ID follows.  `buf` must have
+/// at least [`ADDR_MAX_LEN`] bytes.
+pub fn put_addr(buf: &mut [u8], addr: &SocketAddr) -> usize {
+    match addr {
+        SocketAddr::V4(a) => {
+            buf[0] = 4;
+            buf[1..5].copy_from_slice(&a.ip().octets());
+            buf[5..7].copy_from_slice(&a.port().to_be_bytes());
+            7
+        }
+        SocketAddr::V6(a) => {
+            buf[0] = 6;
+            buf[1..17].copy_from_slice(&a.ip().octets());
+            buf[17..19].copy_from_slice(&a.port().to_be_bytes());
+            buf[19..23].copy_from_slice(&a.scope_id().to_be_bytes());
+            23
+        }
+    }
+}
+
+/// Decode a socket address from the start of `data`, returning the
+/// address and the number of bytes used, or `None` if the encoding
+/// is invalid or incomplete
+pub fn get_addr(data: &[u8]) -> Option<(SocketAddr, usize)> {
+    match data.first()? {
+        4 if data.len() >= 7 => {
+            let ip: [u8; 4] = data[1..5].try_into().ok()?;
+            let port = u16::from_be_bytes(data[5..7].try_into().ok()?);
+            Some((SocketAddr::V4(SocketAddrV4::new(ip.into(), port)), 7))
+        }
+        6 if data.len() >= 23 => {
+            let ip: [u8; 16] = data[1..17].try_into().ok()?;
+            let port = u16::from_be_bytes(data[17..19].try_into().ok()?);
+            let scope_id = u32::from_be_bytes(data[19..23].try_into().ok()?);
+            let addr = SocketAddrV6::new(ip.into(), port, 0, scope_id);
+            Some((SocketAddr::V6(addr), 23))
+        }
+        _ => None,
+    }
+}
+
+/// Append a complete record containing `payload` to a pipe
+pub fn push_record(pwr: &mut PBufWr, payload: &[u8]) {
+    let space = pwr.space(HEADER_LEN + payload.len());
+    put_header(space, payload.len());
+    space[HEADER_LEN..].copy_from_slice(payload);
+    pwr.commit(HEADER_LEN + payload.len());
+}
+
+/// Append a complete record containing the encoded address `addr`
+/// followed by `payload` to a pipe, as used by
+/// [`crate::UdpPeerLink`]
+pub fn push_addr_record(pwr: &mut PBufWr, addr: &SocketAddr, payload: &[u8]) {
+    let space = pwr.space(HEADER_LEN + ADDR_MAX_LEN + payload.len());
+    let addr_len = put_addr(&mut space[HEADER_LEN..], addr);
+    let len = addr_len + payload.len();
+    put_header(space, len);
+    space[HEADER_LEN + addr_len..HEADER_LEN + len].copy_from_slice(payload);
+    pwr.commit(HEADER_LEN + len);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pipebuf::PipeBuf;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    fn round_trip(addr: SocketAddr, len: usize) {
+        let mut buf = [0xFF; ADDR_MAX_LEN + 1];
+        assert_eq!(put_addr(&mut buf, &addr), len);
+        assert_eq!(get_addr(&buf), Some((addr, len)));
+        // Anything short of the full encoding is incomplete
+        for cut in 0..len {
+            assert_eq!(get_addr(&buf[..cut]), None);
+        }
+    }
+
+    #[test]
+    fn ipv4_addr_round_trip() {
+        round_trip(SocketAddr::from((Ipv4Addr::new(192, 0, 2, 1), 5353)), 7);
+        round_trip(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)), 7);
+        round_trip(SocketAddr::from((Ipv4Addr::BROADCAST, 65535)), 7);
+    }
+
+    #[test]
+    fn ipv6_addr_round_trip() {
+        round_trip(SocketAddr::from((Ipv6Addr::LOCALHOST, 443)), 23);
+        let ip = "fe80::1:2:3:4".parse().unwrap();
+        let scoped = SocketAddrV6::new(ip, 65535, 0, 0x0102_0304);
+        round_trip(SocketAddr::V6(scoped), ADDR_MAX_LEN);
+    }
+
+    #[test]
+    fn invalid_addr_family() {
+        for family in [0, 5, 7, 0xFF] {
+            let mut buf = [0; ADDR_MAX_LEN];
+            buf[0] = family;
+            assert_eq!(get_addr(&buf), None);
+        }
+    }
+
+    #[test]
+    fn partial_header_is_incomplete() {
+        let mut buf = [0; HEADER_LEN];
+        put_header(&mut buf, 0);
+        for cut in 0..HEADER_LEN {
+            assert_eq!(next_record(&buf[..cut]), None);
+        }
+        assert_eq!(next_record(&buf), Some(HEADER_LEN));
+    }
+
+    #[test]
+    fn partial_payload_is_incomplete() {
+        let mut pb = PipeBuf::new();
+        push_record(&mut pb.wr(), b"hello");
+        push_record(&mut pb.wr(), b"world!");
+        let data = pb.rd().data().to_vec();
+        assert_eq!(data.len(), 2 * HEADER_LEN + 11);
+
+        // Only the first record is returned, however much of the
+        // second is available
+        let first = HEADER_LEN + 5;
+        for cut in first..data.len() {
+            assert_eq!(next_record(&data[..cut]), Some(first));
+        }
+        assert_eq!(&data[HEADER_LEN..first], b"hello");
+        assert_eq!(next_record(&data[first..]), Some(HEADER_LEN + 6));
+        assert_eq!(next_record(&data[first..data.len() - 1]), None);
+    }
+
+    #[test]
+    fn oversize_length_waits_for_data() {
+        // A header claiming more than a datagram can hold is never
+        // complete, rather than reading past the end of the data
+        let mut buf = vec![0; HEADER_LEN + 100];
+        put_header(&mut buf, u32::MAX as usize);
+        assert_eq!(next_record(&buf), None);
+        put_header(&mut buf, 101);
+        assert_eq!(next_record(&buf), None);
+        put_header(&mut buf, 100);
+        assert_eq!(next_record(&buf), Some(HEADER_LEN + 100));
+    }
+
+    #[test]
+    fn addr_record_layout() {
+        let addr = SocketAddr::from((Ipv6Addr::LOCALHOST, 9));
+        let payload = vec![0xAB; 70000];
+        let mut pb = PipeBuf::new();
+        push_addr_record(&mut pb.wr(), &addr, &payload);
+        push_addr_record(&mut pb.wr(), &addr, b"");
+
+        let mut rd = pb.rd();
+        let total = next_record(rd.data()).unwrap();
+        assert_eq!(total, HEADER_LEN + 23 + payload.len());
+        let rec = &rd.data()[HEADER_LEN..total];
+        assert_eq!(get_addr(rec), Some((addr, 23)));
+        assert_eq!(&rec[23..], &payload[..]);
+        rd.consume(total);
+
+        // An empty payload still carries the address
+        assert_eq!(next_record(rd.data()), Some(HEADER_LEN + 23));
+        assert_eq!(get_addr(&rd.data()[HEADER_LEN..]), Some((addr, 23)));
+    }
+}
@@ -0,0 +1,214 @@
+use crate::{Error, StreamLink};
+use mio::event::Source;
+use mio::{Events, Interest, Poll, Registry, Token};
+use pipebuf::{PBufRdWr, PipeBufPair};
+use std::io::{ErrorKind, Result};
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+
+/// Minimal poll-loop driver for simple tools
+///
+/// This owns a `mio` [`Poll`] and a set of connections, each made up
+/// of a stream, its link and the pipe-buffers between the link and
+/// the application.  [`Runner::run`] drives the links and calls a
+/// user closure to process the application side of each
+/// connection's pipe-buffers, repeating until things settle before
+/// waiting for more events.
+///
+/// Links are unpaused according to the readiness reported by the
+/// events, so they may be added in their initial paused state.  An
+/// error on one connection is passed to the closure and the link is
+/// no longer processed, but the other connections carry on.  A
+/// connection is removed and its stream deregistered once it has
+/// failed, or once EOF has been passed on in both directions, i.e.
+/// the link has passed the outgoing EOF on to the stream and the
+/// closure has consumed the incoming EOF.  The loop exits when there
+/// are no connections left, or when the closure returns
+/// `ControlFlow::Break`.
+///
+/// Requires the `runner` feature.
+pub struct Runner<L: StreamLink> {
+    poll: Poll,
+    events: Events,
+    timeout: Option<Duration>,
+    conns: Vec<Option<Conn<L>>>,
+}
+
+struct Conn<L: StreamLink> {
+    stream: L::Stream,
+    link: L,
+    pipes: PipeBufPair,
+
+    // Set once the link has failed, after which it is no longer
+    // processed
+    failed: bool,
+}
+
+impl<L: StreamLink> Runner<L>
+where
+    L::Stream: Source,
+{
+    /// Create the runner with its own `Poll` instance and no poll
+    /// timeout
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            poll: Poll::new()?,
+            events: Events::with_capacity(256),
+            timeout: None,
+            conns: Vec::new(),
+        })
+    }
+
+    /// Set the maximum time to wait for events.  When the timeout
+    /// expires, the closure is called for all connections as usual,
+    /// which allows it to handle timers.  The wait is also cut short
+    /// by the earliest [`StreamLink::next_deadline`] of the links.
+    #[inline]
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Get the registry of the `Poll` instance
+    #[inline]
+    pub fn registry(&self) -> &Registry {
+        self.poll.registry()
+    }
+
+    /// Add a connection, registering the stream for both readable and
+    /// writable events.  Returns the connection's ID, which is passed
+    /// to the closure in [`Runner::run`].  IDs of removed
+    /// connections may be reused.
+    pub fn add(&mut self, mut stream: L::Stream, link: L, pipes: PipeBufPair) -> Result<usize> {
+        let id = match self.conns.iter().position(Option::is_none) {
+            Some(id) => id,
+            None => {
+                self.conns.push(None);
+                self.conns.len() - 1
+            }
+        };
+        self.poll.registry().register(
+            &mut stream,
+            Token(id),
+            Interest::READABLE | Interest::WRITABLE,
+        )?;
+        self.conns[id] = Some(Conn {
+            stream,
+            link,
+            pipes,
+            failed: false,
+        });
+        Ok(id)
+    }
+
+    /// Get the number of connections still active
+    pub fn len(&self) -> usize {
+        self.conns.iter().filter(|c| c.is_some()).count()
+    }
+
+    /// Test whether there are no connections left
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Run the poll loop until there are no connections left or the
+    /// closure returns `ControlFlow::Break`.  The closure is called
+    /// with the connection ID, the application side of its
+    /// pipe-buffers and any error from the link.  It should process
+    /// incoming data and write outgoing data.  It may be called when
+    /// nothing has changed for that connection.  Returns `Err(_)` only
+    /// if there is an error from the `Poll` itself.
+    pub fn run<F>(&mut self, mut f: F) -> Result<()>
+    where
+        F: FnMut(usize, PBufRdWr, Option<Error>) -> ControlFlow<()>,
+    {
+        loop {
+            if self.process_all(&mut f)? {
+                return Ok(());
+            }
+            if self.conns.iter().all(Option::is_none) {
+                return Ok(());
+            }
+
+            let timeout = self.poll_timeout();
+            match self.poll.poll(&mut self.events, timeout) {
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+                Ok(()) => (),
+            }
+            for ev in self.events.iter() {
+                let Some(Some(conn)) = self.conns.get_mut(ev.token().0) else {
+                    continue;
+                };
+                if ev.is_readable() || ev.is_read_closed() || ev.is_error() {
+                    conn.link.set_pause_reads(false);
+                }
+                if ev.is_writable() || ev.is_write_closed() || ev.is_error() {
+                    conn.link.set_pause_writes(false);
+                }
+            }
+        }
+    }
+
+    // Get the time to wait for events, taking the link deadlines into
+    // account.  All links are processed after each wait, so those
+    // whose deadlines have passed get their `process` call.
+    fn poll_timeout(&self) -> Option<Duration> {
+        let deadline = self
+            .conns
+            .iter()
+            .flatten()
+            .filter(|c| !c.failed)
+            .filter_map(|c| c.link.next_deadline())
+            .min();
+        let Some(deadline) = deadline else {
+            return self.timeout;
+        };
+        let until = deadline.saturating_duration_since(Instant::now());
+        Some(self.timeout.map_or(until, |t| t.min(until)))
+    }
+
+    // Drive the links and the closure until nothing more changes.
+    // Returns `true` if the closure requested a stop.
+    fn process_all<F>(&mut self, f: &mut F) -> Result<bool>
+    where
+        F: FnMut(usize, PBufRdWr, Option<Error>) -> ControlFlow<()>,
+    {
+        loop {
+            let mut activity = false;
+            for id in 0..self.conns.len() {
+                let Some(ref mut conn) = self.conns[id] else {
+                    continue;
+                };
+                let mut error = None;
+                if !conn.failed {
+                    match conn.link.process(&mut conn.stream, conn.pipes.lower()) {
+                        Ok(act) => activity |= act.is_any(),
+                        Err(e) => {
+                            conn.failed = true;
+                            error = Some(e);
+                        }
+                    }
+                }
+
+                let (rd_trip, wr_trip) = conn.pipes.upper().tripwire();
+                let stop = f(id, conn.pipes.upper(), error).is_break();
+                let app = conn.pipes.upper();
+                activity |= app.rd.is_tripped(rd_trip) || app.wr.is_tripped(wr_trip);
+
+                if conn.failed
+                    || (conn.pipes.lower().rd.is_done() && conn.pipes.upper().rd.is_done())
+                {
+                    if let Some(mut conn) = self.conns[id].take() {
+                        self.poll.registry().deregister(&mut conn.stream)?;
+                    }
+                }
+                if stop {
+                    return Ok(true);
+                }
+            }
+            if !activity {
+                return Ok(false);
+            }
+        }
+    }
+}
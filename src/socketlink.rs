@@ -0,0 +1,258 @@
+use crate::activity::{abort_in, check_write_zero, consume_eof_out, read_room};
+use crate::generic::shutdown_result;
+use crate::record::{self, HEADER_LEN};
+use crate::{Activity, Error, Result};
+use pipebuf::PBufRdWr;
+use socket2::Socket;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::Shutdown;
+
+/// Whether a [`SocketLink`] handles a byte stream or datagrams
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SocketMode {
+    /// Stream socket (e.g. `SOCK_STREAM` or `SOCK_SEQPACKET` treated
+    /// as a stream).  Data is passed through unchanged.
+    Stream,
+
+    /// Connected datagram socket (e.g. `SOCK_DGRAM` or `SOCK_RAW`).
+    /// Each datagram is framed as a record in the pipe-buffer, as for
+    /// [`crate::UdpLink`].
+    Datagram,
+}
+
+/// Exchange data via a [`socket2::Socket`], for socket families that
+/// `mio` does not wrap
+///
+/// The socket can be registered with `mio` via
+/// [`mio::unix::SourceFd`].  It must already be connected and in
+/// non-blocking mode, and on Unix this is checked on creation.
+///
+/// In [`SocketMode::Stream`] mode, this behaves like
+/// [`crate::TcpLink`]: EOF is passed on as a "close",
+/// `ConnectionReset` or `ConnectionAborted` as an "abort", an
+/// outgoing "close" results in a shutdown of the outgoing half of
+/// the socket, and an outgoing "abort" results in a shutdown of both
+/// halves and an "abort" of the incoming pipe.  Both reading and
+/// writing start paused, waiting for the first "ready" indication.
+///
+/// In [`SocketMode::Datagram`] mode, this behaves like
+/// [`crate::UdpLink`], with each datagram carried as a 4-byte
+/// big-endian length followed by the payload, and reading and
+/// writing start unpaused.
+///
+/// This is only available with the `socket2` feature.
+#[derive(Debug)]
+pub struct SocketLink {
+    mode: SocketMode,
+
+    // Maximum amount of data to read in one go (in bytes)
+    max_read_unit: usize,
+
+    // Maximum size of a datagram accepted on receive
+    max_datagram_size: usize,
+
+    // Set to pause writes
+    pause_writes: bool,
+
+    // Set to pause reads
+    pause_reads: bool,
+}
+
+impl SocketLink {
+    /// Create the component for the given socket and mode, with
+    /// default settings:
+    ///
+    /// - **max_read_unit** of 2048 for stream mode, or 65536 for
+    ///   datagram mode
+    ///
+    /// - **max_datagram_size** of 65536
+    ///
+    /// - Reads and writes paused in stream mode, unpaused in datagram
+    ///   mode
+    ///
+    /// Fails with `InvalidInput` if the socket is not in non-blocking
+    /// mode.  On Windows this cannot be checked.
+    pub fn new(sock: &Socket, mode: SocketMode) -> io::Result<Self> {
+        #[cfg(target_family = "unix")]
+        if !sock.nonblocking()? {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "SocketLink requires a non-blocking socket",
+            ));
+        }
+        #[cfg(not(target_family = "unix"))]
+        let _ = sock;
+
+        let stream = mode == SocketMode::Stream;
+        Ok(Self {
+            mode,
+            max_read_unit: if stream { 2048 } else { 65536 },
+            max_datagram_size: 65536,
+            pause_writes: stream,
+            pause_reads: stream,
+        })
+    }
+
+    /// Get the mode selected on creation
+    #[inline]
+    pub fn mode(&self) -> SocketMode {
+        self.mode
+    }
+
+    /// Change the maximum number of bytes to read in each `process`
+    /// call.  In datagram mode this counts record bytes, and at least
+    /// one datagram is always read if available.
+    #[inline]
+    pub fn set_max_read_unit(&mut self, max_read_unit: usize) {
+        self.max_read_unit = max_read_unit;
+    }
+
+    /// Change the maximum size of a datagram that will be accepted in
+    /// datagram mode.  Larger datagrams are discarded.
+    #[inline]
+    pub fn set_max_datagram_size(&mut self, max_datagram_size: usize) {
+        self.max_datagram_size = max_datagram_size;
+    }
+
+    /// Pause or unpause writes.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_writes(&mut self, pause: bool) {
+        self.pause_writes = pause;
+    }
+
+    /// Pause or unpause reads.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_reads(&mut self, pause: bool) {
+        self.pause_reads = pause;
+    }
+
+    /// Read and write as much data as possible to and from the given
+    /// socket.  Returns the activity flags (see [`Activity`]), which
+    /// are empty if no progress could be made, or `Err(_)` if there
+    /// was a fatal error on the socket.
+    ///
+    /// Assumes that it is always called with the same socket and
+    /// pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process(&mut self, sock: &Socket, mut pbuf: PBufRdWr) -> Result<Activity> {
+        let rd_activity = self.process_out(sock, pbuf.reborrow())?;
+        let wr_activity = self.process_in(sock, pbuf.reborrow())?;
+        Ok(rd_activity | wr_activity)
+    }
+
+    /// Write as much data as possible out to the given socket.
+    /// Returns the activity flags (see [`Activity`]), which are empty
+    /// if no progress could be made, or `Err(_)` if there was a fatal
+    /// error on the socket.
+    ///
+    /// Assumes that it is always called with the same socket and
+    /// pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process_out(&mut self, mut sock: &Socket, mut pbuf: PBufRdWr) -> Result<Activity> {
+        if self.pause_writes {
+            return Ok(Activity::NONE);
+        }
+
+        let mut prd = pbuf.rd;
+        let mut act = Activity::NONE;
+        if self.mode == SocketMode::Datagram {
+            while let Some(total) = record::next_record(prd.data()) {
+                match retry!(sock.write(&prd.data()[HEADER_LEN..total])) {
+                    Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(act),
+                    Err(ref e) if e.kind() == ErrorKind::ConnectionRefused => (),
+                    Err(e) => return Err(Error::write(e)),
+                    Ok(_) => {
+                        prd.consume(total);
+                        act |= Activity::WROTE;
+                    }
+                }
+            }
+            if prd.has_pending_eof() {
+                let len = prd.len();
+                prd.consume(len);
+                consume_eof_out(&mut prd, &mut act);
+                if prd.is_aborted() && !pbuf.wr.is_eof() {
+                    abort_in(&mut pbuf.wr, &mut act);
+                }
+            }
+            return Ok(act);
+        }
+
+        let rv = prd.output_to(&mut sock, false);
+        match check_write_zero(&prd, rv) {
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => (),
+            Err(e) => return Err(Error::write(e)),
+            Ok(_) => {
+                if prd.is_empty() && prd.has_pending_eof() {
+                    let shutdown = if prd.is_aborted() {
+                        if !pbuf.wr.is_eof() {
+                            abort_in(&mut pbuf.wr, &mut act);
+                        }
+                        Shutdown::Both
+                    } else {
+                        Shutdown::Write
+                    };
+                    match shutdown_result(retry!(sock.shutdown(shutdown))) {
+                        Err(ref e) if e.kind() == ErrorKind::WouldBlock => (),
+                        Err(e) => return Err(Error::shutdown(e)),
+                        Ok(_) => {
+                            consume_eof_out(&mut prd, &mut act);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(act)
+    }
+
+    /// Read as much data as possible from the given socket, up to
+    /// **max_read_unit** bytes.  Returns the activity flags (see
+    /// [`Activity`]), which are empty if no progress could be made,
+    /// or `Err(_)` if there was a fatal error on the socket.
+    ///
+    /// Assumes that it is always called with the same socket and
+    /// pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process_in(&mut self, mut sock: &Socket, pbuf: PBufRdWr) -> Result<Activity> {
+        let mut pwr = pbuf.wr;
+        if self.pause_reads || pwr.is_eof() {
+            return Ok(Activity::NONE);
+        }
+
+        let mut act = Activity::NONE;
+        if self.mode == SocketMode::Datagram {
+            let mut total = 0;
+            while total < self.max_read_unit {
+                // Reserve one extra byte to detect truncation
+                let Some(space) = pwr.try_space(HEADER_LEN + self.max_datagram_size + 1) else {
+                    // No room for a whole datagram in a fixed-capacity pipe
+                    break;
+                };
+                match retry!(sock.read(&mut space[HEADER_LEN..])) {
+                    Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(ref e) if e.kind() == ErrorKind::ConnectionRefused => (),
+                    Err(e) => return Err(Error::read(e)),
+                    Ok(len) if len > self.max_datagram_size => (),
+                    Ok(len) => {
+                        record::put_header(space, len);
+                        pwr.commit(HEADER_LEN + len);
+                        act |= Activity::READ;
+                        total += HEADER_LEN + len;
+                    }
+                }
+            }
+            return Ok(act);
+        }
+
+        let len = read_room(&pwr, self.max_read_unit);
+        if let Err(e) = pwr.input_from(&mut sock, len) {
+            match e.kind() {
+                ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted => {
+                    abort_in(&mut pwr, &mut act)
+                }
+                ErrorKind::WouldBlock => (),
+                _ => return Err(Error::read(e)),
+            }
+        }
+        Ok(act)
+    }
+}
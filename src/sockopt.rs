@@ -0,0 +1,517 @@
+// Raw socket calls for things that `mio` doesn't expose
+
+/// Error for a feature not supported on this platform
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn unsupported(what: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!("{} is not supported on this platform", what),
+    )
+}
+
+#[cfg(target_family = "unix")]
+pub(crate) use self::unix::*;
+
+#[cfg(target_os = "linux")]
+pub(crate) use self::linux::*;
+
+#[cfg(target_family = "unix")]
+mod unix {
+    use crate::KeepaliveConfig;
+    use std::io::{Error, ErrorKind, Result};
+    use std::mem::{size_of, zeroed};
+    use std::net::{SocketAddr, TcpStream};
+    use std::os::unix::io::{FromRawFd, RawFd};
+    use std::time::Duration;
+
+    /// Set a socket option of any plain type
+    pub(crate) fn setsockopt<T: Copy>(
+        fd: RawFd,
+        level: libc::c_int,
+        name: libc::c_int,
+        val: T,
+    ) -> Result<()> {
+        // Safety: Pointer and length describe `val`, which is a
+        // plain value
+        let rv = unsafe {
+            libc::setsockopt(
+                fd,
+                level,
+                name,
+                &val as *const T as *const libc::c_void,
+                size_of::<T>() as libc::socklen_t,
+            )
+        };
+        if rv < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Set `SO_RCVBUF` or `SO_SNDBUF`, saturating at the maximum
+    pub(crate) fn set_buffer_size(fd: RawFd, name: libc::c_int, size: usize) -> Result<()> {
+        let size = size.min(libc::c_int::MAX as usize) as libc::c_int;
+        setsockopt(fd, libc::SOL_SOCKET, name, size)
+    }
+
+    /// Get an integer socket option
+    pub(crate) fn getsockopt_int(
+        fd: RawFd,
+        level: libc::c_int,
+        name: libc::c_int,
+    ) -> Result<libc::c_int> {
+        let mut val: libc::c_int = 0;
+        let mut len = size_of::<libc::c_int>() as libc::socklen_t;
+        // Safety: Pointer and length describe `val`, which is a
+        // plain value
+        let rv = unsafe {
+            libc::getsockopt(
+                fd,
+                level,
+                name,
+                &mut val as *mut libc::c_int as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if rv < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(val)
+    }
+
+    /// Get the effective `SO_RCVBUF` or `SO_SNDBUF` size
+    pub(crate) fn buffer_size(fd: RawFd, name: libc::c_int) -> Result<usize> {
+        Ok(getsockopt_int(fd, libc::SOL_SOCKET, name)?.max(0) as usize)
+    }
+
+    /// Set `SO_LINGER` with a zero timeout, so that the kernel sends
+    /// RST instead of FIN when the socket is closed
+    pub(crate) fn set_linger_zero(fd: RawFd) -> Result<()> {
+        let linger = libc::linger {
+            l_onoff: 1,
+            l_linger: 0,
+        };
+        setsockopt(fd, libc::SOL_SOCKET, libc::SO_LINGER, linger)
+    }
+
+    /// Reset a TCP connection.  With `SO_LINGER` set to zero the
+    /// kernel sends RST when the socket is closed.  On Linux a
+    /// `connect` to `AF_UNSPEC` then disconnects the socket straight
+    /// away, which sends the RST without waiting for the close.
+    pub(crate) fn reset(fd: RawFd) -> Result<()> {
+        set_linger_zero(fd)?;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            // Safety: Zero is a valid bit pattern for `sockaddr`
+            let mut addr: libc::sockaddr = unsafe { zeroed() };
+            addr.sa_family = libc::AF_UNSPEC as libc::sa_family_t;
+            // Safety: Pointer and length describe `addr`
+            let rv =
+                unsafe { libc::connect(fd, &addr, size_of::<libc::sockaddr>() as libc::socklen_t) };
+            if rv < 0 {
+                return Err(Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// Set `TCP_MAXSEG`.  On Linux 0 removes the clamp.
+    pub(crate) fn set_mss(fd: RawFd, mss: u32) -> Result<()> {
+        let mss = mss.min(libc::c_int::MAX as u32) as libc::c_int;
+        setsockopt(fd, libc::IPPROTO_TCP, libc::TCP_MAXSEG, mss)
+    }
+
+    /// Get the effective `TCP_MAXSEG`
+    pub(crate) fn mss(fd: RawFd) -> Result<u32> {
+        Ok(getsockopt_int(fd, libc::IPPROTO_TCP, libc::TCP_MAXSEG)?.max(0) as u32)
+    }
+
+    /// Create a non-blocking TCP socket with `TCP_MAXSEG` set, and
+    /// start a connect to the given address.  Some platforms only
+    /// take the MSS fully into account when it is set before the
+    /// connect, since it goes into the SYN.
+    pub(crate) fn connect_with_mss(addr: SocketAddr, mss: u32) -> Result<TcpStream> {
+        let domain = match addr {
+            SocketAddr::V4(_) => libc::AF_INET,
+            SocketAddr::V6(_) => libc::AF_INET6,
+        };
+        // Safety: Plain call with no pointers
+        let fd = unsafe { libc::socket(domain, libc::SOCK_STREAM, 0) };
+        if fd < 0 {
+            return Err(Error::last_os_error());
+        }
+        // Safety: `fd` was just created and is owned by nothing else,
+        // so from here on it is closed on error
+        let stream = unsafe { TcpStream::from_raw_fd(fd) };
+        // Safety: Plain call with no pointers
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+            return Err(Error::last_os_error());
+        }
+        stream.set_nonblocking(true)?;
+        #[cfg(target_vendor = "apple")]
+        setsockopt(fd, libc::SOL_SOCKET, libc::SO_NOSIGPIPE, 1 as libc::c_int)?;
+        set_mss(fd, mss)?;
+
+        // Safety: Zero is a valid bit-pattern for `sockaddr_storage`
+        let mut ss: libc::sockaddr_storage = unsafe { zeroed() };
+        let len = match addr {
+            SocketAddr::V4(a) => {
+                // Safety: `sockaddr_storage` is big enough and aligned
+                // for a `sockaddr_in`
+                let sin = unsafe { &mut *(&mut ss as *mut _ as *mut libc::sockaddr_in) };
+                sin.sin_family = libc::AF_INET as libc::sa_family_t;
+                sin.sin_port = a.port().to_be();
+                sin.sin_addr.s_addr = u32::from(*a.ip()).to_be();
+                size_of::<libc::sockaddr_in>()
+            }
+            SocketAddr::V6(a) => {
+                // Safety: As above for `sockaddr_in6`
+                let sin6 = unsafe { &mut *(&mut ss as *mut _ as *mut libc::sockaddr_in6) };
+                sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                sin6.sin6_port = a.port().to_be();
+                sin6.sin6_flowinfo = a.flowinfo();
+                sin6.sin6_addr.s6_addr = a.ip().octets();
+                sin6.sin6_scope_id = a.scope_id();
+                size_of::<libc::sockaddr_in6>()
+            }
+        };
+        // Safety: Pointer and length describe the address filled in
+        // within `ss`
+        let rv = unsafe {
+            libc::connect(
+                fd,
+                &ss as *const _ as *const libc::sockaddr,
+                len as libc::socklen_t,
+            )
+        };
+        if rv < 0 {
+            let e = Error::last_os_error();
+            if e.raw_os_error() != Some(libc::EINPROGRESS) {
+                return Err(e);
+            }
+        }
+        Ok(stream)
+    }
+
+    /// Set or clear `SO_OOBINLINE`
+    pub(crate) fn set_oob_inline(fd: RawFd, inline: bool) -> Result<()> {
+        let on = inline as libc::c_int;
+        setsockopt(fd, libc::SOL_SOCKET, libc::SO_OOBINLINE, on)
+    }
+
+    /// Read and drop the pending TCP urgent byte, if any.  Returns
+    /// `false` if there is none, or it has not arrived yet.
+    pub(crate) fn discard_oob(fd: RawFd) -> Result<bool> {
+        let mut byte = 0u8;
+        // Safety: Pointer and length describe `byte`
+        let rv = unsafe {
+            libc::recv(
+                fd,
+                &mut byte as *mut u8 as *mut libc::c_void,
+                1,
+                libc::MSG_OOB,
+            )
+        };
+        if rv < 0 {
+            let e = Error::last_os_error();
+            return match e.kind() {
+                ErrorKind::InvalidInput | ErrorKind::WouldBlock => Ok(false),
+                _ => Err(e),
+            };
+        }
+        Ok(rv > 0)
+    }
+
+    /// Enable TCP keepalive with the given settings, or disable it
+    pub(crate) fn set_keepalive(fd: RawFd, keepalive: Option<&KeepaliveConfig>) -> Result<()> {
+        let on = keepalive.is_some() as libc::c_int;
+        setsockopt(fd, libc::SOL_SOCKET, libc::SO_KEEPALIVE, on)?;
+        match keepalive {
+            Some(ka) => set_keepalive_timing(fd, ka),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "illumos",
+        target_os = "solaris",
+        target_vendor = "apple",
+    ))]
+    fn set_keepalive_timing(fd: RawFd, ka: &KeepaliveConfig) -> Result<()> {
+        #[cfg(target_vendor = "apple")]
+        const TCP_KEEPIDLE: libc::c_int = libc::TCP_KEEPALIVE;
+        #[cfg(not(target_vendor = "apple"))]
+        const TCP_KEEPIDLE: libc::c_int = libc::TCP_KEEPIDLE;
+
+        // Times are in whole seconds, and zero is not accepted
+        let secs = |d: Duration| d.as_secs().clamp(1, libc::c_int::MAX as u64) as libc::c_int;
+
+        setsockopt(fd, libc::IPPROTO_TCP, TCP_KEEPIDLE, secs(ka.time))?;
+        if let Some(interval) = ka.interval {
+            setsockopt(fd, libc::IPPROTO_TCP, libc::TCP_KEEPINTVL, secs(interval))?;
+        }
+        if let Some(retries) = ka.retries {
+            let retries = retries.min(libc::c_int::MAX as u32) as libc::c_int;
+            setsockopt(fd, libc::IPPROTO_TCP, libc::TCP_KEEPCNT, retries)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "illumos",
+        target_os = "solaris",
+        target_vendor = "apple",
+    )))]
+    fn set_keepalive_timing(_fd: RawFd, _ka: &KeepaliveConfig) -> Result<()> {
+        Err(super::unsupported("TCP keepalive timing"))
+    }
+
+    /// Set `TCP_NOTSENT_LOWAT`
+    #[cfg(any(target_os = "linux", target_os = "android", target_vendor = "apple"))]
+    pub(crate) fn set_notsent_lowat(fd: RawFd, lowat: u32) -> Result<()> {
+        // Not defined by `libc` for Apple platforms
+        #[cfg(target_vendor = "apple")]
+        const TCP_NOTSENT_LOWAT: libc::c_int = 0x201;
+        #[cfg(not(target_vendor = "apple"))]
+        const TCP_NOTSENT_LOWAT: libc::c_int = libc::TCP_NOTSENT_LOWAT;
+
+        let lowat = lowat.min(libc::c_int::MAX as u32) as libc::c_int;
+        setsockopt(fd, libc::IPPROTO_TCP, TCP_NOTSENT_LOWAT, lowat)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_vendor = "apple")))]
+    pub(crate) fn set_notsent_lowat(_fd: RawFd, _lowat: u32) -> Result<()> {
+        Err(super::unsupported("TCP_NOTSENT_LOWAT"))
+    }
+
+    /// Set `IP_TOS`, or `IPV6_TCLASS` for an IPv6 socket
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_vendor = "apple",
+    ))]
+    pub(crate) fn set_tos(fd: RawFd, ipv6: bool, tos: u8) -> Result<()> {
+        let tos = tos as libc::c_int;
+        if ipv6 {
+            setsockopt(fd, libc::IPPROTO_IPV6, libc::IPV6_TCLASS, tos)
+        } else {
+            setsockopt(fd, libc::IPPROTO_IP, libc::IP_TOS, tos)
+        }
+    }
+
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_vendor = "apple",
+    )))]
+    pub(crate) fn set_tos(_fd: RawFd, _ipv6: bool, _tos: u8) -> Result<()> {
+        Err(super::unsupported("IP TOS"))
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::setsockopt;
+    use std::io::{Error, ErrorKind, Result};
+    use std::mem::{size_of_val, zeroed};
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+    use std::os::unix::io::RawFd;
+
+    /// Set or clear `TCP_QUICKACK`
+    pub(crate) fn set_quickack(fd: RawFd, quickack: bool) -> Result<()> {
+        let on = quickack as libc::c_int;
+        setsockopt(fd, libc::IPPROTO_TCP, libc::TCP_QUICKACK, on)
+    }
+
+    /// Set or clear `TCP_CORK`
+    pub(crate) fn set_cork(fd: RawFd, cork: bool) -> Result<()> {
+        let on = cork as libc::c_int;
+        setsockopt(fd, libc::IPPROTO_TCP, libc::TCP_CORK, on)
+    }
+
+    /// Set `TCP_USER_TIMEOUT` in milliseconds, where 0 restores the
+    /// system default
+    pub(crate) fn set_user_timeout(fd: RawFd, ms: u32) -> Result<()> {
+        setsockopt(
+            fd,
+            libc::IPPROTO_TCP,
+            libc::TCP_USER_TIMEOUT,
+            ms as libc::c_uint,
+        )
+    }
+
+    /// The start of the kernel's `struct tcp_info`, which only ever
+    /// has fields added at the end.  Declared here since the `libc`
+    /// definition differs between C libraries.
+    #[repr(C)]
+    #[derive(Default)]
+    pub(crate) struct RawTcpInfo {
+        pub(crate) state: [u8; 8],
+        pub(crate) rto: u32,
+        pub(crate) ato: u32,
+        pub(crate) snd_mss: u32,
+        pub(crate) rcv_mss: u32,
+        pub(crate) unacked: u32,
+        pub(crate) sacked: u32,
+        pub(crate) lost: u32,
+        pub(crate) retrans: u32,
+        pub(crate) fackets: u32,
+        pub(crate) last_data_sent: u32,
+        pub(crate) last_ack_sent: u32,
+        pub(crate) last_data_recv: u32,
+        pub(crate) last_ack_recv: u32,
+        pub(crate) pmtu: u32,
+        pub(crate) rcv_ssthresh: u32,
+        pub(crate) rtt: u32,
+        pub(crate) rttvar: u32,
+        pub(crate) snd_ssthresh: u32,
+        pub(crate) snd_cwnd: u32,
+        pub(crate) advmss: u32,
+        pub(crate) reordering: u32,
+        pub(crate) rcv_rtt: u32,
+        pub(crate) rcv_space: u32,
+        pub(crate) total_retrans: u32,
+        pub(crate) pacing_rate: u64,
+        pub(crate) max_pacing_rate: u64,
+        pub(crate) bytes_acked: u64,
+        pub(crate) bytes_received: u64,
+        pub(crate) segs_out: u32,
+        pub(crate) segs_in: u32,
+        pub(crate) notsent_bytes: u32,
+        pub(crate) min_rtt: u32,
+    }
+
+    /// Get `TCP_INFO`, returning the structure and the number of
+    /// bytes of it filled in by the kernel, which is less on older
+    /// kernels
+    pub(crate) fn tcp_info(fd: RawFd) -> Result<(RawTcpInfo, usize)> {
+        let mut info = RawTcpInfo::default();
+        let mut len = size_of_val(&info) as libc::socklen_t;
+        // Safety: Pointer and length describe `info`, which is a
+        // plain C structure
+        let rv = unsafe {
+            libc::getsockopt(
+                fd,
+                libc::IPPROTO_TCP,
+                libc::TCP_INFO,
+                &mut info as *mut RawTcpInfo as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if rv < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok((info, len as usize))
+    }
+
+    /// Enable reporting of the destination address of received
+    /// datagrams, for use with [`recv_with_dst`]
+    pub(crate) fn enable_pktinfo(fd: RawFd, ipv6: bool) -> Result<()> {
+        let on: libc::c_int = 1;
+        if ipv6 {
+            setsockopt(fd, libc::IPPROTO_IPV6, libc::IPV6_RECVPKTINFO, on)?;
+            // Also needed for IPv4-mapped traffic on a dual-stack
+            // socket, but fails on an IPv6-only socket
+            let _ = setsockopt(fd, libc::IPPROTO_IP, libc::IP_PKTINFO, on);
+            Ok(())
+        } else {
+            setsockopt(fd, libc::IPPROTO_IP, libc::IP_PKTINFO, on)
+        }
+    }
+
+    /// Receive a datagram, returning its length, the source address
+    /// and, if enabled with [`enable_pktinfo`], the destination IP
+    /// address
+    pub(crate) fn recv_with_dst(
+        fd: RawFd,
+        buf: &mut [u8],
+    ) -> Result<(usize, SocketAddr, Option<IpAddr>)> {
+        // Safety: All structures are plain C structures for which
+        // zero is a valid value.  All pointers refer to local buffers
+        // which outlive the call, with correct lengths given.
+        // Control messages are found with the CMSG_* macros, which
+        // keep within the control buffer, and are copied out with
+        // unaligned reads.
+        unsafe {
+            let mut src: libc::sockaddr_storage = zeroed();
+            let mut iov = libc::iovec {
+                iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+                iov_len: buf.len(),
+            };
+            let mut control = [0u64; 16];
+            let mut msg: libc::msghdr = zeroed();
+            msg.msg_name = &mut src as *mut _ as *mut libc::c_void;
+            msg.msg_namelen = size_of_val(&src) as libc::socklen_t;
+            msg.msg_iov = &mut iov;
+            msg.msg_iovlen = 1;
+            msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+            msg.msg_controllen = size_of_val(&control) as _;
+
+            let len = libc::recvmsg(fd, &mut msg, 0);
+            if len < 0 {
+                return Err(Error::last_os_error());
+            }
+
+            let mut dst = None;
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                let c = &*cmsg;
+                if c.cmsg_level == libc::IPPROTO_IP && c.cmsg_type == libc::IP_PKTINFO {
+                    let info: libc::in_pktinfo =
+                        std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const _);
+                    let ip = Ipv4Addr::from(u32::from_be(info.ipi_addr.s_addr));
+                    dst = Some(IpAddr::V4(ip));
+                } else if c.cmsg_level == libc::IPPROTO_IPV6 && c.cmsg_type == libc::IPV6_PKTINFO {
+                    let info: libc::in6_pktinfo =
+                        std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const _);
+                    dst = Some(IpAddr::V6(Ipv6Addr::from(info.ipi6_addr.s6_addr)));
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+
+            let src = to_socket_addr(&src)
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Unexpected address family"))?;
+            Ok((len as usize, src, dst))
+        }
+    }
+
+    /// Convert a `sockaddr_storage` filled in by the kernel to a
+    /// `SocketAddr`, if it is IPv4 or IPv6
+    pub(crate) fn to_socket_addr(ss: &libc::sockaddr_storage) -> Option<SocketAddr> {
+        match ss.ss_family as libc::c_int {
+            libc::AF_INET => {
+                // Safety: Family says this is a `sockaddr_in`, and
+                // `sockaddr_storage` is big enough and aligned
+                let sin = unsafe { &*(ss as *const _ as *const libc::sockaddr_in) };
+                let ip = Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr));
+                let port = u16::from_be(sin.sin_port);
+                Some(SocketAddr::V4(SocketAddrV4::new(ip, port)))
+            }
+            libc::AF_INET6 => {
+                // Safety: As above for `sockaddr_in6`
+                let sin6 = unsafe { &*(ss as *const _ as *const libc::sockaddr_in6) };
+                let ip = Ipv6Addr::from(sin6.sin6_addr.s6_addr);
+                let port = u16::from_be(sin6.sin6_port);
+                let (flow, scope) = (sin6.sin6_flowinfo, sin6.sin6_scope_id);
+                Some(SocketAddr::V6(SocketAddrV6::new(ip, port, flow, scope)))
+            }
+            _ => None,
+        }
+    }
+}
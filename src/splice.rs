@@ -0,0 +1,354 @@
+use crate::copy::{copy_in, copy_out, InStatus, OutStatus};
+use crate::generic::shutdown_result;
+use crate::{Activity, Error, Result};
+use mio::unix::pipe::{Receiver, Sender};
+use pipebuf::PipeBuf;
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+/// Relay data in one direction from one file descriptor to another
+/// with `splice(2)`, without copying it through user memory
+///
+/// This is a Linux-only fast path for a relay that has no need to
+/// see the data, e.g. a TCP proxy.  The data goes from the source
+/// into a kernel pipe, and from there out to the destination.  Use
+/// two instances for a bidirectional relay, one each way.  Where the
+/// data needs to be observed, use [`crate::Forwarder`] instead.
+///
+/// EOF from the source is passed on as a shutdown of the write half
+/// of the destination once all the data has gone out, and a reset of
+/// the source as a shutdown of both halves, as an outgoing "abort"
+/// is by [`crate::TcpLink`].  If the destination is not a socket,
+/// there is nothing to shut down, so pass EOF on by closing it once
+/// [`Splicer::is_done`] is true.  If the destination has gone away,
+/// this is reported as `ABORTED` and the relay finishes.
+///
+/// If `splice` is not supported for either descriptor, giving
+/// `EINVAL` before any data has been written, the relay falls back
+/// to copying through a pipe-buffer, with the same behaviour.  Any
+/// data already taken into the kernel pipe is carried over, so the
+/// byte count stays exact either way.
+pub struct Splicer {
+    // Write end of the kernel pipe
+    tx: Sender,
+
+    // Read end of the kernel pipe
+    rx: Receiver,
+
+    // Bytes held in the kernel pipe
+    held: usize,
+
+    // Maximum amount of data to take from the source in one go (in
+    // bytes)
+    max_unit: usize,
+
+    // Bytes written out to the destination
+    bytes: u64,
+
+    // Set once EOF or a reset has been seen on the source
+    eof_in: bool,
+
+    // Set if the source was reset, or the destination went away
+    aborted: bool,
+
+    // Set once EOF has been passed on to the destination
+    done: bool,
+
+    // Pipe-buffer used instead if `splice` is not supported
+    fallback: Option<PipeBuf<u8>>,
+}
+
+impl Splicer {
+    /// Create the relay with its kernel pipe, with a **max_unit** of
+    /// 65536 bytes, which is the default capacity of a Linux pipe
+    pub fn new() -> io::Result<Self> {
+        let (tx, rx) = mio::unix::pipe::new()?;
+        Ok(Self {
+            tx,
+            rx,
+            held: 0,
+            max_unit: 65536,
+            bytes: 0,
+            eof_in: false,
+            aborted: false,
+            done: false,
+            fallback: None,
+        })
+    }
+
+    /// Change the maximum number of bytes to take from the source in
+    /// each `process` call.  The kernel pipe holds at most its
+    /// capacity, typically 65536 bytes, so a larger value only has
+    /// effect if the pipe has been enlarged with `F_SETPIPE_SZ`.
+    #[inline]
+    pub fn set_max_unit(&mut self, max_unit: usize) {
+        self.max_unit = max_unit.max(1);
+    }
+
+    /// Get the number of bytes written out to the destination so far
+    #[inline]
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Test whether the relay has fallen back to copying through a
+    /// pipe-buffer because `splice` is not supported
+    #[inline]
+    pub fn is_fallback(&self) -> bool {
+        self.fallback.is_some()
+    }
+
+    /// Test whether the relay is finished, i.e. EOF has been passed
+    /// on to the destination, or the relay was aborted
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Move as much data as possible from the source to the
+    /// destination, taking up to **max_unit** bytes from the source.
+    /// Returns the activity flags (see [`Activity`]): `READ` if data
+    /// was taken from the source, `WROTE` if data was written out,
+    /// `EOF_IN` when EOF is seen on the source, `EOF_OUT` when it is
+    /// passed on, and `ABORTED` for a reset of either end.  These are
+    /// empty if no progress could be made, or `Err(_)` if there was a
+    /// fatal error on either descriptor.
+    ///
+    /// Since up to **max_unit** bytes are taken per call, with
+    /// edge-triggered polling call this again while `READ` is
+    /// returned.  Assumes that it is always called with the same two
+    /// descriptors.  Things will behave unpredictably otherwise.
+    pub fn process<S, D>(&mut self, src: &mut S, dst: &mut D) -> Result<Activity>
+    where
+        S: Read + AsRawFd,
+        D: Write + AsRawFd,
+    {
+        if self.done {
+            return Ok(Activity::NONE);
+        }
+        if self.fallback.is_some() {
+            return self.process_fallback(src, dst);
+        }
+        let mut act = Activity::NONE;
+
+        if !self.eof_in && self.held < self.max_unit {
+            match splice(
+                src.as_raw_fd(),
+                self.tx.as_raw_fd(),
+                self.max_unit - self.held,
+            ) {
+                Ok(0) => {
+                    self.eof_in = true;
+                    act |= Activity::EOF_IN;
+                }
+                Ok(n) => {
+                    self.held += n;
+                    act |= Activity::READ;
+                }
+                Err(e) => match e.kind() {
+                    ErrorKind::WouldBlock => (),
+                    ErrorKind::InvalidInput if self.bytes == 0 => {
+                        self.start_fallback().map_err(Error::read)?;
+                        return Ok(act | self.process_fallback(src, dst)?);
+                    }
+                    ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted => {
+                        self.eof_in = true;
+                        self.aborted = true;
+                        act |= Activity::EOF_IN | Activity::ABORTED;
+                    }
+                    _ => return Err(Error::read(e)),
+                },
+            }
+        }
+
+        while self.held > 0 && !self.aborted {
+            match splice(self.rx.as_raw_fd(), dst.as_raw_fd(), self.held) {
+                Ok(0) => return Err(Error::write(ErrorKind::WriteZero.into())),
+                Ok(n) => {
+                    self.held -= n;
+                    self.bytes += n as u64;
+                    act |= Activity::WROTE;
+                }
+                Err(e) => match e.kind() {
+                    ErrorKind::WouldBlock => break,
+                    ErrorKind::InvalidInput if self.bytes == 0 => {
+                        self.start_fallback().map_err(Error::write)?;
+                        return Ok(act | self.process_fallback(src, dst)?);
+                    }
+                    ErrorKind::BrokenPipe
+                    | ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted => {
+                        self.aborted = true;
+                        self.done = true;
+                        return Ok(act | Activity::ABORTED);
+                    }
+                    _ => return Err(Error::write(e)),
+                },
+            }
+        }
+
+        if self.eof_in && (self.held == 0 || self.aborted) {
+            act |= self.pass_eof(dst.as_raw_fd())?;
+        }
+        Ok(act)
+    }
+
+    // Switch to copying through a pipe-buffer, taking over any data
+    // already held in the kernel pipe
+    fn start_fallback(&mut self) -> io::Result<()> {
+        let mut pb = PipeBuf::new();
+        while self.held > 0 {
+            let mut pwr = pb.wr();
+            let space = pwr.space(self.held);
+            let n = match self.rx.read(space) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(n) => n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            pwr.commit(n);
+            self.held -= n;
+        }
+        if self.eof_in {
+            pb.wr().close();
+        }
+        self.fallback = Some(pb);
+        Ok(())
+    }
+
+    fn process_fallback<S: Read, D: Write + AsRawFd>(
+        &mut self,
+        src: &mut S,
+        dst: &mut D,
+    ) -> Result<Activity> {
+        let mut act = Activity::NONE;
+        let Some(ref mut pb) = self.fallback else {
+            return Ok(act);
+        };
+        if !self.eof_in {
+            let len = pb.rd().len();
+            match copy_in(src, pb.wr(), self.max_unit)? {
+                InStatus::Closed => {
+                    self.eof_in = true;
+                    act |= Activity::EOF_IN;
+                }
+                InStatus::Aborted => {
+                    self.eof_in = true;
+                    self.aborted = true;
+                    act |= Activity::EOF_IN | Activity::ABORTED;
+                }
+                InStatus::WouldBlock | InStatus::Limit => (),
+            }
+            if pb.rd().len() > len {
+                act |= Activity::READ;
+            }
+        }
+        if self.aborted {
+            return Ok(act | self.pass_eof(dst.as_raw_fd())?);
+        }
+        let len = pb.rd().len();
+        let status = copy_out(dst, pb.rd());
+        let written = len - pb.rd().len();
+        if written > 0 {
+            self.bytes += written as u64;
+            act |= Activity::WROTE;
+        }
+        match status {
+            Ok(OutStatus::Closed) | Ok(OutStatus::Aborted) => {
+                act |= self.pass_eof(dst.as_raw_fd())?
+            }
+            Ok(OutStatus::WouldBlock) | Ok(OutStatus::Flushed) => (),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    ErrorKind::BrokenPipe
+                        | ErrorKind::ConnectionReset
+                        | ErrorKind::ConnectionAborted
+                ) =>
+            {
+                self.aborted = true;
+                self.done = true;
+                act |= Activity::ABORTED;
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(act)
+    }
+
+    // Pass on EOF from the source to the destination: a shutdown of
+    // the write half, or of both halves if aborted
+    fn pass_eof(&mut self, dst: RawFd) -> Result<Activity> {
+        let how = if self.aborted {
+            Shutdown::Both
+        } else {
+            Shutdown::Write
+        };
+        match shutdown_result(shutdown(dst, how)) {
+            Err(e) if e.raw_os_error() != Some(libc::ENOTSOCK) => {
+                return Err(Error::shutdown(e));
+            }
+            _ => (),
+        }
+        self.done = true;
+        if self.aborted {
+            Ok(Activity::EOF_OUT | Activity::ABORTED)
+        } else {
+            Ok(Activity::EOF_OUT)
+        }
+    }
+}
+
+impl fmt::Debug for Splicer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Splicer")
+            .field("held", &self.held)
+            .field("max_unit", &self.max_unit)
+            .field("bytes", &self.bytes)
+            .field("eof_in", &self.eof_in)
+            .field("aborted", &self.aborted)
+            .field("done", &self.done)
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+// Move up to `len` bytes between descriptors without blocking,
+// retrying on `Interrupted`
+fn splice(fd_in: RawFd, fd_out: RawFd, len: usize) -> io::Result<usize> {
+    loop {
+        // Safety: Null offsets are valid for pipes and sockets, and
+        // no memory is passed
+        let rv = unsafe {
+            libc::splice(
+                fd_in,
+                std::ptr::null_mut(),
+                fd_out,
+                std::ptr::null_mut(),
+                len,
+                libc::SPLICE_F_MOVE | libc::SPLICE_F_NONBLOCK,
+            )
+        };
+        if rv >= 0 {
+            return Ok(rv as usize);
+        }
+        let e = io::Error::last_os_error();
+        if e.kind() != ErrorKind::Interrupted {
+            return Err(e);
+        }
+    }
+}
+
+fn shutdown(fd: RawFd, how: Shutdown) -> io::Result<()> {
+    let how = match how {
+        Shutdown::Read => libc::SHUT_RD,
+        Shutdown::Write => libc::SHUT_WR,
+        Shutdown::Both => libc::SHUT_RDWR,
+    };
+    // Safety: No memory is passed
+    if unsafe { libc::shutdown(fd, how) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,40 @@
+/// Position of a stream link in its shutdown sequence
+///
+/// This is tracked by the link as EOF is passed on in each
+/// direction, as returned by [`crate::TcpLink::state`] or
+/// [`crate::UnixStreamLink::state`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkState {
+    /// Data may still flow in both directions
+    Open,
+
+    /// The outgoing pipe has been closed and all its data written,
+    /// but the shutdown of the stream gave `WouldBlock`, so will be
+    /// retried on the next `process` call.  The incoming side may be
+    /// open or closed.  If the peer closes the connection in the
+    /// meantime, the retry gives `NotConnected`, `BrokenPipe` or on
+    /// some platforms EINVAL, which is treated as a successful
+    /// shutdown.
+    ShutdownPending,
+
+    /// The outgoing half of the stream has been shut down, but data
+    /// may still arrive on the incoming half
+    OutClosed,
+
+    /// The peer has closed the incoming half, but data may still be
+    /// written on the outgoing half
+    InClosed,
+
+    /// EOF has been passed on in both directions
+    Closed,
+
+    /// An abort has been passed on in either direction, i.e. the peer
+    /// reset the connection, or the outgoing pipe was aborted
+    Aborted,
+
+    /// A `process` call returned a fatal error from the stream.
+    /// Further `process` calls return no activity without touching
+    /// the stream, so the link only needs to be cleaned up.  Errors
+    /// from setting options ([`crate::Op::SetOption`]) don't count.
+    Failed,
+}
@@ -0,0 +1,112 @@
+use crate::generic::Shutdownable;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::Shutdown;
+
+/// Syscall statistics for a stream link, for performance tuning
+///
+/// Enable collection with [`crate::TcpLink::enable_stats`].  The
+/// counts cover the `read` and `write` calls made on the stream, and
+/// are kept per link, so there is no shared state between
+/// connections.  Comparing `full_reads` against `short_reads` shows
+/// whether **max_read_unit** is limiting throughput.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LinkStats {
+    /// Number of `read` calls made on the stream
+    pub read_calls: u64,
+
+    /// Number of `read` calls that returned `WouldBlock`
+    pub read_would_block: u64,
+
+    /// Number of `read` calls that filled the whole buffer offered,
+    /// meaning that more data may have been waiting
+    pub full_reads: u64,
+
+    /// Number of `read` calls that returned some data but less than
+    /// the buffer offered
+    pub short_reads: u64,
+
+    /// Number of bytes read
+    pub bytes_read: u64,
+
+    /// Number of `write` calls made on the stream
+    pub write_calls: u64,
+
+    /// Number of `write` calls that returned `WouldBlock`
+    pub write_would_block: u64,
+
+    /// Number of bytes written
+    pub bytes_written: u64,
+
+    /// Number of `read` or `write` calls that returned `Interrupted`
+    /// (EINTR) and were retried
+    pub interrupted: u64,
+}
+
+// Wrapper around a stream which counts the calls made on it
+pub(crate) struct StatsStream<'a, S> {
+    stream: &'a mut S,
+    stats: &'a mut LinkStats,
+}
+
+impl<'a, S> StatsStream<'a, S> {
+    #[inline]
+    pub(crate) fn new(stream: &'a mut S, stats: &'a mut LinkStats) -> Self {
+        Self { stream, stats }
+    }
+}
+
+impl<S: Read> Read for StatsStream<'_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let stats = &mut *self.stats;
+        stats.read_calls += 1;
+        let rv = self.stream.read(buf);
+        match rv {
+            Ok(0) => (),
+            Ok(len) => {
+                stats.bytes_read += len as u64;
+                if len == buf.len() {
+                    stats.full_reads += 1;
+                } else {
+                    stats.short_reads += 1;
+                }
+            }
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => stats.read_would_block += 1,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => stats.interrupted += 1,
+            Err(_) => (),
+        }
+        rv
+    }
+}
+
+impl<S: Write> Write for StatsStream<'_, S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let stats = &mut *self.stats;
+        stats.write_calls += 1;
+        let rv = self.stream.write(buf);
+        match rv {
+            Ok(len) => stats.bytes_written += len as u64,
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => stats.write_would_block += 1,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => stats.interrupted += 1,
+            Err(_) => (),
+        }
+        rv
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl<S: Shutdownable> Shutdownable for StatsStream<'_, S> {
+    #[inline]
+    fn shutdown(&mut self, how: Shutdown) -> io::Result<()> {
+        self.stream.shutdown(how)
+    }
+
+    #[inline]
+    fn reset(&mut self) -> io::Result<()> {
+        self.stream.reset()
+    }
+}
@@ -0,0 +1,217 @@
+use crate::activity::{abort_in, consume_eof_out, input_from, output_to};
+use crate::fd::{raw_file, restore_flags, set_nonblocking};
+use crate::{Activity, Error, Result};
+use mio::unix::SourceFd;
+use mio::{Interest, Registry, Token};
+use pipebuf::PBufRdWr;
+use std::io::{self, ErrorKind};
+use std::os::unix::io::RawFd;
+
+const STDIN: RawFd = 0;
+const STDOUT: RawFd = 1;
+
+/// Exchange data via the process's own stdin and stdout
+///
+/// On creation, stdin and stdout are switched to non-blocking mode.
+/// Since these file descriptors are usually shared with other
+/// processes (for example the shell), the original file status flags
+/// are restored when the link is dropped.  Data read from stdin is
+/// written to the incoming pipe, and EOF on stdin is passed on as a
+/// "close".  Data from the outgoing pipe is written to stdout.
+///
+/// When stdout is a pipe it may fill up and give `WouldBlock`, in
+/// which case writing continues on the next call after it becomes
+/// writable.  Terminals rarely block but are handled the same way.
+/// stdout is not closed on outgoing EOF, since it is shared with the
+/// rest of the process.  The EOF is just consumed.  An outgoing
+/// "abort" is passed on as an "abort" of the incoming pipe.
+///
+/// Regular files (e.g. redirected with `<` or `>`) cannot be
+/// registered with `mio`.  [`StdioLink::register`] detects this and
+/// skips registration of that stream.  Such a stream is always ready,
+/// so the caller should keep calling `process` whilst it makes
+/// progress.  Use [`StdioLink::is_stdin_polled`] and
+/// [`StdioLink::is_stdout_polled`] to check.
+///
+/// Reading and writing start unpaused.
+#[derive(Debug)]
+pub struct StdioLink {
+    // Maximum amount of data to read in one go (in bytes)
+    max_read_unit: usize,
+
+    // Set to pause writes
+    pause_writes: bool,
+
+    // Set to pause reads
+    pause_reads: bool,
+
+    // Original file status flags of stdin and stdout
+    stdin_flags: libc::c_int,
+    stdout_flags: libc::c_int,
+
+    // Set if registered with a `mio` Registry
+    stdin_polled: bool,
+    stdout_polled: bool,
+}
+
+impl StdioLink {
+    /// Switch stdin and stdout to non-blocking mode, and create the
+    /// component with default settings:
+    ///
+    /// - **max_read_unit** of 2048
+    ///
+    /// - Both reads and writes unpaused
+    pub fn new() -> io::Result<Self> {
+        let stdin_flags = set_nonblocking(STDIN)?;
+        let stdout_flags = match set_nonblocking(STDOUT) {
+            Ok(flags) => flags,
+            Err(e) => {
+                restore_flags(STDIN, stdin_flags);
+                return Err(e);
+            }
+        };
+        Ok(Self {
+            max_read_unit: 2048,
+            pause_writes: false,
+            pause_reads: false,
+            stdin_flags,
+            stdout_flags,
+            stdin_polled: false,
+            stdout_polled: false,
+        })
+    }
+
+    /// Change the maximum number of bytes to read in each `process`
+    /// call
+    #[inline]
+    pub fn set_max_read_unit(&mut self, max_read_unit: usize) {
+        self.max_read_unit = max_read_unit;
+    }
+
+    /// Pause or unpause writes.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_writes(&mut self, pause: bool) {
+        self.pause_writes = pause;
+    }
+
+    /// Pause or unpause reads.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_reads(&mut self, pause: bool) {
+        self.pause_reads = pause;
+    }
+
+    /// Register stdin for reading and stdout for writing with the
+    /// given `Registry`.  Streams that cannot be polled (regular
+    /// files) are skipped.
+    pub fn register(&mut self, registry: &Registry, stdin: Token, stdout: Token) -> io::Result<()> {
+        self.stdin_polled = register(registry, STDIN, stdin, Interest::READABLE)?;
+        self.stdout_polled = register(registry, STDOUT, stdout, Interest::WRITABLE)?;
+        Ok(())
+    }
+
+    /// Deregister stdin and stdout from the given `Registry`
+    pub fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        if self.stdin_polled {
+            self.stdin_polled = false;
+            registry.deregister(&mut SourceFd(&STDIN))?;
+        }
+        if self.stdout_polled {
+            self.stdout_polled = false;
+            registry.deregister(&mut SourceFd(&STDOUT))?;
+        }
+        Ok(())
+    }
+
+    /// Test whether stdin was registered with the `Registry`.  If
+    /// not, it is always ready.
+    #[inline]
+    pub fn is_stdin_polled(&self) -> bool {
+        self.stdin_polled
+    }
+
+    /// Test whether stdout was registered with the `Registry`.  If
+    /// not, it is always ready.
+    #[inline]
+    pub fn is_stdout_polled(&self) -> bool {
+        self.stdout_polled
+    }
+
+    /// Read and write as much data as possible from stdin and to
+    /// stdout.  Returns the activity flags (see [`Activity`]), which
+    /// are empty if no progress could be made, or `Err(_)` if there
+    /// was a fatal error.
+    ///
+    /// Assumes that it is always called with the same pipe-buffer.
+    /// Things will behave unpredictably otherwise.
+    pub fn process(&mut self, mut pbuf: PBufRdWr) -> Result<Activity> {
+        let rd_activity = self.process_out(pbuf.reborrow())?;
+        let wr_activity = self.process_in(pbuf.reborrow())?;
+        Ok(rd_activity | wr_activity)
+    }
+
+    /// Write as much data as possible out to stdout.  Returns the
+    /// activity flags (see [`Activity`]), which are empty if no
+    /// progress could be made, or `Err(_)` if there was a fatal
+    /// error.
+    pub fn process_out(&mut self, mut pbuf: PBufRdWr) -> Result<Activity> {
+        if self.pause_writes {
+            return Ok(Activity::NONE);
+        }
+
+        let mut prd = pbuf.rd;
+        let mut act = Activity::NONE;
+        let mut stdout = raw_file(STDOUT);
+        match output_to(&mut prd, &mut *stdout, &mut act) {
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => (),
+            Err(e) => return Err(Error::write(e)),
+            Ok(_) => {
+                if prd.is_empty() && prd.has_pending_eof() {
+                    consume_eof_out(&mut prd, &mut act);
+                    if prd.is_aborted() {
+                        abort_in(&mut pbuf.wr, &mut act);
+                    }
+                }
+            }
+        }
+        Ok(act)
+    }
+
+    /// Read as much data as possible from stdin, up to
+    /// **max_read_unit** bytes.  Returns the activity flags (see
+    /// [`Activity`]), which are empty if no progress could be made,
+    /// or `Err(_)` if there was a fatal error.
+    pub fn process_in(&mut self, pbuf: PBufRdWr) -> Result<Activity> {
+        let mut pwr = pbuf.wr;
+        if self.pause_reads || pwr.is_eof() {
+            return Ok(Activity::NONE);
+        }
+
+        let mut act = Activity::NONE;
+        let mut stdin = raw_file(STDIN);
+        if let Err(e) = input_from(&mut pwr, &mut *stdin, self.max_read_unit, &mut act) {
+            if e.kind() != ErrorKind::WouldBlock {
+                return Err(Error::read(e));
+            }
+        }
+        Ok(act)
+    }
+}
+
+impl Drop for StdioLink {
+    fn drop(&mut self) {
+        restore_flags(STDIN, self.stdin_flags);
+        restore_flags(STDOUT, self.stdout_flags);
+    }
+}
+
+// Register a file descriptor, returning `false` if it is a kind of
+// file that cannot be polled
+fn register(registry: &Registry, fd: RawFd, token: Token, interest: Interest) -> io::Result<bool> {
+    match registry.register(&mut SourceFd(&fd), token, interest) {
+        Ok(()) => Ok(true),
+        Err(ref e) if e.raw_os_error() == Some(libc::EPERM) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
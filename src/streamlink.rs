@@ -0,0 +1,99 @@
+#[cfg(target_family = "unix")]
+use crate::UnixStreamLink;
+use crate::{Activity, Result};
+use crate::{LoopbackLink, LoopbackStream, TcpLink};
+use mio::net::TcpStream;
+#[cfg(target_family = "unix")]
+use mio::net::UnixStream;
+use pipebuf::PBufRdWr;
+use std::time::Instant;
+
+/// Common interface to the stream links
+///
+/// This allows generic code to drive links for different stream
+/// types uniformly, either as a generic parameter or as a
+/// `Box<dyn StreamLink<Stream = ...>>`.  The methods behave as
+/// documented on each link type.
+pub trait StreamLink {
+    /// The stream type handled by the link
+    type Stream;
+
+    /// Change the maximum number of bytes to read in each `process`
+    /// call
+    fn set_max_read_unit(&mut self, max_read_unit: usize);
+
+    /// Pause or unpause writes.  This takes effect on the next
+    /// `process` call.
+    fn set_pause_writes(&mut self, pause: bool);
+
+    /// Pause or unpause reads.  This takes effect on the next
+    /// `process` call.
+    fn set_pause_reads(&mut self, pause: bool);
+
+    /// Read and write as much data as possible to and from the given
+    /// stream.  Returns the activity status.
+    fn process(&mut self, stream: &mut Self::Stream, pbuf: PBufRdWr) -> Result<Activity>;
+
+    /// Write as much data as possible out to the given stream.
+    /// Returns the activity status.
+    fn process_out(&mut self, stream: &mut Self::Stream, pbuf: PBufRdWr) -> Result<Activity>;
+
+    /// Read as much data as possible from the given stream, up to
+    /// **max_read_unit** bytes.  Returns the activity status.
+    fn process_in(&mut self, stream: &mut Self::Stream, pbuf: PBufRdWr) -> Result<Activity>;
+
+    /// Get the earliest time at which the link wants `process` to be
+    /// called, even if there is no I/O readiness, e.g. for a timeout.
+    /// Returns `None` if the link has no such deadline, which is the
+    /// default.
+    #[inline]
+    fn next_deadline(&self) -> Option<Instant> {
+        None
+    }
+}
+
+macro_rules! impl_stream_link {
+    ($link:ty, $stream:ty $(, $next_deadline:ident)?) => {
+        impl StreamLink for $link {
+            type Stream = $stream;
+
+            #[inline]
+            fn set_max_read_unit(&mut self, max_read_unit: usize) {
+                <$link>::set_max_read_unit(self, max_read_unit)
+            }
+            #[inline]
+            fn set_pause_writes(&mut self, pause: bool) {
+                <$link>::set_pause_writes(self, pause)
+            }
+            #[inline]
+            fn set_pause_reads(&mut self, pause: bool) {
+                <$link>::set_pause_reads(self, pause)
+            }
+            #[inline]
+            fn process(&mut self, stream: &mut $stream, pbuf: PBufRdWr) -> Result<Activity> {
+                <$link>::process(self, stream, pbuf)
+            }
+            #[inline]
+            fn process_out(&mut self, stream: &mut $stream, pbuf: PBufRdWr) -> Result<Activity> {
+                <$link>::process_out(self, stream, pbuf)
+            }
+            #[inline]
+            fn process_in(&mut self, stream: &mut $stream, pbuf: PBufRdWr) -> Result<Activity> {
+                <$link>::process_in(self, stream, pbuf)
+            }
+            $(
+                #[inline]
+                fn next_deadline(&self) -> Option<Instant> {
+                    <$link>::$next_deadline(self)
+                }
+            )?
+        }
+    };
+}
+
+impl_stream_link!(TcpLink, TcpStream, next_deadline);
+#[cfg(target_family = "unix")]
+impl_stream_link!(UnixStreamLink, UnixStream, next_deadline);
+impl_stream_link!(LoopbackLink, LoopbackStream);
+#[cfg(feature = "std-net")]
+impl_stream_link!(crate::StdTcpLink, std::net::TcpStream, next_deadline);
@@ -0,0 +1,126 @@
+use std::io;
+use std::time::Duration;
+
+/// Snapshot of the kernel's statistics for a TCP connection
+///
+/// Get this with [`crate::TcpLink::tcp_info`], at any time while the
+/// stream is open.  Fields which the running kernel doesn't report
+/// are `None`.  This is currently only supported on Linux, where it
+/// comes from `TCP_INFO`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TcpInfo {
+    /// Smoothed round-trip time
+    pub rtt: Duration,
+
+    /// Variation in the round-trip time
+    pub rtt_var: Duration,
+
+    /// Minimum round-trip time seen, if reported
+    pub min_rtt: Option<Duration>,
+
+    /// Total number of segments retransmitted over the life of the
+    /// connection
+    pub retransmits: u32,
+
+    /// Number of segments currently lost and not yet retransmitted
+    pub lost: u32,
+
+    /// Congestion window, in segments
+    pub cwnd: u32,
+
+    /// Maximum segment size for sending, in bytes
+    pub snd_mss: u32,
+
+    /// Number of bytes delivered, i.e. acknowledged by the peer, if
+    /// reported
+    pub bytes_acked: Option<u64>,
+
+    /// Number of bytes received from the peer, if reported
+    pub bytes_received: Option<u64>,
+
+    /// Current pacing rate in bytes per second, if reported.  This
+    /// is `u64::MAX` if pacing is not in use.
+    pub pacing_rate: Option<u64>,
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn tcp_info(sock: &impl std::os::unix::io::AsRawFd) -> io::Result<TcpInfo> {
+    use crate::sockopt::RawTcpInfo;
+    use std::mem::{offset_of, size_of};
+
+    let (raw, len) = crate::sockopt::tcp_info(sock.as_raw_fd())?;
+    // Fields added in later kernels are only valid if covered by the
+    // length returned
+    let has = |offset: usize, size: usize| len >= offset + size;
+    let u64_field = |offset: usize, val: u64| has(offset, size_of::<u64>()).then_some(val);
+    Ok(TcpInfo {
+        rtt: Duration::from_micros(raw.rtt.into()),
+        rtt_var: Duration::from_micros(raw.rttvar.into()),
+        min_rtt: has(offset_of!(RawTcpInfo, min_rtt), size_of::<u32>())
+            .then(|| Duration::from_micros(raw.min_rtt.into())),
+        retransmits: raw.total_retrans,
+        lost: raw.lost,
+        cwnd: raw.snd_cwnd,
+        snd_mss: raw.snd_mss,
+        bytes_acked: u64_field(offset_of!(RawTcpInfo, bytes_acked), raw.bytes_acked),
+        bytes_received: u64_field(offset_of!(RawTcpInfo, bytes_received), raw.bytes_received),
+        pacing_rate: u64_field(offset_of!(RawTcpInfo, pacing_rate), raw.pacing_rate),
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn tcp_info<S>(_sock: &S) -> io::Result<TcpInfo> {
+    Err(crate::sockopt::unsupported("TCP_INFO"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testutil::tcp_pair;
+    use crate::TcpLink;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn snapshot_after_round_trip() {
+        use crate::testutil::wait_for;
+        use pipebuf::PipeBufPair;
+        use std::io::{Read, Write};
+
+        let (mut stream, mut peer) = tcp_pair();
+        let mut link = TcpLink::new();
+
+        // Callable before the link has done anything
+        let info = link.tcp_info(&stream).unwrap();
+        assert!(info.snd_mss > 0 && info.cwnd > 0);
+        assert_eq!(info.bytes_received, Some(0));
+
+        link.set_pause_reads(false);
+        link.set_pause_writes(false);
+        let mut pair = PipeBufPair::new();
+        pair.upper().wr.append(b"ping");
+        link.process(&mut stream, pair.lower()).unwrap();
+        let mut buf = [0; 4];
+        peer.read_exact(&mut buf).unwrap();
+        peer.write_all(b"pong!").unwrap();
+        wait_for(|| {
+            link.process(&mut stream, pair.lower()).unwrap();
+            pair.upper().rd.len() == 5
+        });
+        wait_for(|| link.tcp_info(&stream).unwrap().bytes_acked == Some(4));
+
+        let info = link.tcp_info(&stream).unwrap();
+        assert!(info.rtt.as_nanos() > 0, "{:?}", info);
+        assert!(info.min_rtt.unwrap().as_nanos() > 0);
+        assert_eq!(info.bytes_received, Some(5));
+        assert_eq!(info.retransmits, 0);
+        assert!(info.pacing_rate.is_some());
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    #[test]
+    fn unsupported_elsewhere() {
+        let (stream, _peer) = tcp_pair();
+        let e = TcpLink::new().tcp_info(&stream).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::Unsupported);
+    }
+}
@@ -1,206 +1,4518 @@
+use crate::bufsize::{BufferSizes, SocketBuffers};
+use crate::connector::check_connect;
+use crate::generic::{GenericLink, Shutdownable, WriteHooks};
+use crate::tee::Tee;
+use crate::trace::Tracer;
+use crate::{
+    Activity, Direction, Error, LinkObserver, LinkState, LinkStats, Op, RateLimit, Result, TcpInfo,
+};
+use mio::event::Event;
 use mio::net::TcpStream;
-use pipebuf::PBufRdWr;
-use std::io::{ErrorKind, Result};
+use mio::{Interest, Registry, Token};
+use pipebuf::{PBufRd, PBufRdWr, PBufWr};
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
 
-/// Exchange stream data via the `mio` [`TcpStream`] type
-///
-/// For the TCP incoming stream both TCP "close" (FIN) and "abort"
-/// (RST) are detected and passed on.  For the TCP outgoing stream,
-/// "close" handling is mapped to a normal shutdown on the outgoing
-/// half of the TCP stream.  The incoming half of the TCP stream, if
-/// still open, remains open until the other end closes, as expected.
-///
-/// For TCP outgoing "abort", ideally we'd generate a TCP RST to tear
-/// things down at both ends as soon as possible.  This can be done
-/// with `set_linger(Some(0))` and a close.  However the linger API is
-/// not yet stable on `std`, is not present at all in `mio`.  So on
-/// "abort", this code does a normal shutdown on both incoming and
-/// outgoing TCP streams, and does an "abort" on the side of the pipe
-/// for incoming TCP data.  This should cause rapid shutdown of things
-/// locally.  The remote end however will not know that this is an
-/// abort.  Linger-based handling of outgoing "abort" may be added
-/// later as a runtime option once it is stable in the APIs.
-///
-/// To start with both reading and writing via the TCP stream are
-/// paused.  This is because, depending on the platform, reading or
-/// writing may give an error if a "ready" indication has not yet been
-/// received.  So call `set_pause_writes(false)` or
-/// `set_pause_reads(false)` as soon as the stream indicates "ready"
-/// in order to allow data to flow.
-pub struct TcpLink {
-    // Maximum amount of data to read in one go (in bytes)
-    max_read_unit: usize,
+// Operations needed by `TcpLink` on the TCP stream types supported
+pub(crate) trait TcpSocket: Shutdownable + SocketBuffers {
+    fn set_nodelay(&self, nodelay: bool) -> io::Result<()>;
+    fn set_keepalive(&self, keepalive: Option<&KeepaliveConfig>) -> io::Result<()>;
+    fn set_ttl(&self, ttl: u32) -> io::Result<()>;
+    fn set_tos(&self, tos: u8) -> io::Result<()>;
+    fn set_quickack(&self, quickack: bool) -> io::Result<()>;
+    fn set_cork(&self, cork: bool) -> io::Result<()>;
+    fn set_notsent_lowat(&self, lowat: u32) -> io::Result<()>;
+    fn set_user_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+    fn set_mss(&self, mss: u32) -> io::Result<()>;
+    fn set_oob_inline(&self, inline: bool) -> io::Result<()>;
+    fn discard_oob(&self) -> io::Result<bool>;
+    fn take_error(&self) -> io::Result<Option<io::Error>>;
+    fn peer_addr(&self) -> io::Result<SocketAddr>;
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+}
 
-    // TCP_NODELAY flag
-    nodelay: bool,
+impl TcpSocket for TcpStream {
+    fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        TcpStream::set_nodelay(self, nodelay)
+    }
+    fn set_keepalive(&self, keepalive: Option<&KeepaliveConfig>) -> io::Result<()> {
+        set_keepalive(self, keepalive)
+    }
+    fn set_ttl(&self, ttl: u32) -> io::Result<()> {
+        TcpStream::set_ttl(self, ttl)
+    }
+    fn set_tos(&self, tos: u8) -> io::Result<()> {
+        set_tos(self, TcpStream::local_addr(self)?.is_ipv6(), tos)
+    }
+    fn set_quickack(&self, quickack: bool) -> io::Result<()> {
+        set_quickack(self, quickack)
+    }
+    fn set_cork(&self, cork: bool) -> io::Result<()> {
+        set_cork(self, cork)
+    }
+    fn set_notsent_lowat(&self, lowat: u32) -> io::Result<()> {
+        set_notsent_lowat(self, lowat)
+    }
+    fn set_user_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        set_user_timeout(self, timeout)
+    }
+    fn set_mss(&self, mss: u32) -> io::Result<()> {
+        set_mss(self, mss)
+    }
+    fn set_oob_inline(&self, inline: bool) -> io::Result<()> {
+        set_oob_inline(self, inline)
+    }
+    fn discard_oob(&self) -> io::Result<bool> {
+        discard_oob(self)
+    }
+    fn take_error(&self) -> io::Result<Option<io::Error>> {
+        TcpStream::take_error(self)
+    }
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        TcpStream::peer_addr(self)
+    }
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        TcpStream::local_addr(self)
+    }
+}
 
-    // Set to pause writes (waiting for first "ready" indication)
-    pause_writes: bool,
+impl TcpSocket for &TcpStream {
+    fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        TcpStream::set_nodelay(self, nodelay)
+    }
+    fn set_keepalive(&self, keepalive: Option<&KeepaliveConfig>) -> io::Result<()> {
+        set_keepalive(*self, keepalive)
+    }
+    fn set_ttl(&self, ttl: u32) -> io::Result<()> {
+        TcpStream::set_ttl(self, ttl)
+    }
+    fn set_tos(&self, tos: u8) -> io::Result<()> {
+        set_tos(*self, TcpStream::local_addr(self)?.is_ipv6(), tos)
+    }
+    fn set_quickack(&self, quickack: bool) -> io::Result<()> {
+        set_quickack(*self, quickack)
+    }
+    fn set_cork(&self, cork: bool) -> io::Result<()> {
+        set_cork(*self, cork)
+    }
+    fn set_notsent_lowat(&self, lowat: u32) -> io::Result<()> {
+        set_notsent_lowat(*self, lowat)
+    }
+    fn set_user_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        set_user_timeout(*self, timeout)
+    }
+    fn set_mss(&self, mss: u32) -> io::Result<()> {
+        set_mss(*self, mss)
+    }
+    fn set_oob_inline(&self, inline: bool) -> io::Result<()> {
+        set_oob_inline(*self, inline)
+    }
+    fn discard_oob(&self) -> io::Result<bool> {
+        discard_oob(*self)
+    }
+    fn take_error(&self) -> io::Result<Option<io::Error>> {
+        TcpStream::take_error(self)
+    }
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        TcpStream::peer_addr(self)
+    }
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        TcpStream::local_addr(self)
+    }
+}
 
-    // Set to pause reads (waiting for first "ready" indication)
-    pause_reads: bool,
+#[cfg(feature = "std-net")]
+impl TcpSocket for std::net::TcpStream {
+    fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        std::net::TcpStream::set_nodelay(self, nodelay)
+    }
+    fn set_keepalive(&self, keepalive: Option<&KeepaliveConfig>) -> io::Result<()> {
+        set_keepalive(self, keepalive)
+    }
+    fn set_ttl(&self, ttl: u32) -> io::Result<()> {
+        std::net::TcpStream::set_ttl(self, ttl)
+    }
+    fn set_tos(&self, tos: u8) -> io::Result<()> {
+        set_tos(self, std::net::TcpStream::local_addr(self)?.is_ipv6(), tos)
+    }
+    fn set_quickack(&self, quickack: bool) -> io::Result<()> {
+        set_quickack(self, quickack)
+    }
+    fn set_cork(&self, cork: bool) -> io::Result<()> {
+        set_cork(self, cork)
+    }
+    fn set_notsent_lowat(&self, lowat: u32) -> io::Result<()> {
+        set_notsent_lowat(self, lowat)
+    }
+    fn set_user_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        set_user_timeout(self, timeout)
+    }
+    fn set_mss(&self, mss: u32) -> io::Result<()> {
+        set_mss(self, mss)
+    }
+    fn set_oob_inline(&self, inline: bool) -> io::Result<()> {
+        set_oob_inline(self, inline)
+    }
+    fn discard_oob(&self) -> io::Result<bool> {
+        discard_oob(self)
+    }
+    fn take_error(&self) -> io::Result<Option<io::Error>> {
+        std::net::TcpStream::take_error(self)
+    }
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        std::net::TcpStream::peer_addr(self)
+    }
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        std::net::TcpStream::local_addr(self)
+    }
+}
 
-    // Pending set_nodelay()
-    pending_set_nodelay: bool,
+#[cfg(target_family = "unix")]
+fn set_keepalive(
+    sock: &impl std::os::unix::io::AsRawFd,
+    keepalive: Option<&KeepaliveConfig>,
+) -> io::Result<()> {
+    crate::sockopt::set_keepalive(sock.as_raw_fd(), keepalive)
 }
 
-impl TcpLink {
-    /// Create the component with default settings:
-    ///
-    /// - **max_read_unit** of 2048.  This is bigger than a typical IP
-    /// packet's data load but you may want to increase this.
-    ///
-    /// - **nodelay** set to `false`, i.e. using the Nagle algorithm to
-    /// delay output to attempt to batch up data into fewer IP packets
-    ///
-    /// - Both reads and writes paused
+#[cfg(not(target_family = "unix"))]
+fn set_keepalive<S>(_sock: &S, _keepalive: Option<&KeepaliveConfig>) -> io::Result<()> {
+    Err(crate::sockopt::unsupported("TCP keepalive"))
+}
+
+#[cfg(target_family = "unix")]
+fn set_tos(sock: &impl std::os::unix::io::AsRawFd, ipv6: bool, tos: u8) -> io::Result<()> {
+    crate::sockopt::set_tos(sock.as_raw_fd(), ipv6, tos)
+}
+
+#[cfg(not(target_family = "unix"))]
+fn set_tos<S>(_sock: &S, _ipv6: bool, _tos: u8) -> io::Result<()> {
+    Err(crate::sockopt::unsupported("IP TOS"))
+}
+
+#[cfg(target_os = "linux")]
+fn set_quickack(sock: &impl std::os::unix::io::AsRawFd, quickack: bool) -> io::Result<()> {
+    crate::sockopt::set_quickack(sock.as_raw_fd(), quickack)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_quickack<S>(_sock: &S, _quickack: bool) -> io::Result<()> {
+    Err(crate::sockopt::unsupported("TCP quickack"))
+}
+
+#[cfg(target_os = "linux")]
+fn set_cork(sock: &impl std::os::unix::io::AsRawFd, cork: bool) -> io::Result<()> {
+    crate::sockopt::set_cork(sock.as_raw_fd(), cork)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_cork<S>(_sock: &S, _cork: bool) -> io::Result<()> {
+    Err(crate::sockopt::unsupported("TCP cork"))
+}
+
+#[cfg(target_family = "unix")]
+fn set_notsent_lowat(sock: &impl std::os::unix::io::AsRawFd, lowat: u32) -> io::Result<()> {
+    crate::sockopt::set_notsent_lowat(sock.as_raw_fd(), lowat)
+}
+
+#[cfg(not(target_family = "unix"))]
+fn set_notsent_lowat<S>(_sock: &S, _lowat: u32) -> io::Result<()> {
+    Err(crate::sockopt::unsupported("TCP_NOTSENT_LOWAT"))
+}
+
+#[cfg(target_os = "linux")]
+fn set_user_timeout(
+    sock: &impl std::os::unix::io::AsRawFd,
+    timeout: Option<Duration>,
+) -> io::Result<()> {
+    // Round up to a whole millisecond, as 0 means the system default
+    let ms = timeout.map_or(0, |t| {
+        t.as_nanos().div_ceil(1_000_000).clamp(1, u32::MAX as u128) as u32
+    });
+    crate::sockopt::set_user_timeout(sock.as_raw_fd(), ms)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_user_timeout<S>(_sock: &S, _timeout: Option<Duration>) -> io::Result<()> {
+    Err(crate::sockopt::unsupported("TCP_USER_TIMEOUT"))
+}
+
+#[cfg(target_family = "unix")]
+fn set_mss(sock: &impl std::os::unix::io::AsRawFd, mss: u32) -> io::Result<()> {
+    crate::sockopt::set_mss(sock.as_raw_fd(), mss)
+}
+
+#[cfg(not(target_family = "unix"))]
+fn set_mss<S>(_sock: &S, _mss: u32) -> io::Result<()> {
+    Err(crate::sockopt::unsupported("TCP_MAXSEG"))
+}
+
+#[cfg(target_family = "unix")]
+fn mss(sock: &impl std::os::unix::io::AsRawFd) -> io::Result<u32> {
+    crate::sockopt::mss(sock.as_raw_fd())
+}
+
+#[cfg(not(target_family = "unix"))]
+fn mss<S>(_sock: &S) -> io::Result<u32> {
+    Err(crate::sockopt::unsupported("TCP_MAXSEG"))
+}
+
+#[cfg(target_family = "unix")]
+fn set_oob_inline(sock: &impl std::os::unix::io::AsRawFd, inline: bool) -> io::Result<()> {
+    crate::sockopt::set_oob_inline(sock.as_raw_fd(), inline)
+}
+
+#[cfg(not(target_family = "unix"))]
+fn set_oob_inline<S>(_sock: &S, _inline: bool) -> io::Result<()> {
+    Err(crate::sockopt::unsupported("SO_OOBINLINE"))
+}
+
+#[cfg(target_family = "unix")]
+fn discard_oob(sock: &impl std::os::unix::io::AsRawFd) -> io::Result<bool> {
+    crate::sockopt::discard_oob(sock.as_raw_fd())
+}
+
+// Priority events are never reported here, so there is nothing to do
+#[cfg(not(target_family = "unix"))]
+fn discard_oob<S>(_sock: &S) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// TCP keepalive settings, for [`TcpLink::set_keepalive`]
+///
+/// Keepalive probes are sent once the connection has been idle for
+/// the given time, and then repeated at the given interval.  If the
+/// given number of probes go unanswered, the connection is dropped,
+/// which shows up as an error or abort on the stream.  The interval
+/// and retries default to the OS settings if not given.  Times are
+/// rounded down to whole seconds, with a minimum of one second.
+///
+/// This is currently only supported on Unix.  The idle time maps to
+/// `TCP_KEEPIDLE`, or `TCP_KEEPALIVE` on macOS and iOS, the interval
+/// to `TCP_KEEPINTVL` and the retries to `TCP_KEEPCNT`.  On Unix
+/// platforms without these options (e.g. OpenBSD), only enabling or
+/// disabling keepalive is supported, and giving any of the settings
+/// fails with `Unsupported`.  On Windows, setting keepalive fails
+/// with `Unsupported`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeepaliveConfig {
+    pub(crate) time: Duration,
+    pub(crate) interval: Option<Duration>,
+    pub(crate) retries: Option<u32>,
+}
+
+impl KeepaliveConfig {
+    /// Send probes after the connection has been idle for `time`
     #[inline]
-    pub fn new() -> Self {
+    pub fn new(time: Duration) -> Self {
         Self {
-            max_read_unit: 2048,
-            nodelay: false,
-            pause_writes: true,
-            pause_reads: true,
-            pending_set_nodelay: false,
+            time,
+            interval: None,
+            retries: None,
         }
     }
 
-    /// Change the maximum number of bytes to read in each `process`
-    /// call.  This allows managing how much data you wish to handle
-    /// at a time, to allow the possibility of backpressure, and to
-    /// control how large the pipe buffers in your processing chain
-    /// will grow.  If memory is not an issue, then there is no
-    /// problem with setting this large, which will likely give higher
-    /// efficiency if there is a lot of data queued.
+    /// Set the interval between unanswered probes
     #[inline]
-    pub fn set_max_read_unit(&mut self, max_read_unit: usize) {
-        self.max_read_unit = max_read_unit;
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
     }
 
-    /// Change the "no delay" flag on the stream.  This will be
-    /// updated on the next `process` call.
-    ///
-    /// Use `true` if you wish to disable the Nagle algorithm, e.g. if
-    /// you are sending large chunks or packets of data and wish them
-    /// to be sent immediately, even if that means using a part-filled
-    /// IP packet for the last part of the data.
-    ///
-    /// Use `false` if you are sending byte data a few bytes at a time
-    /// (e.g. terminal interaction) and you'd rather that TCP waited a
-    /// moment to attempt to batch up data together to reduce the
-    /// number of IP packets sent.  For the last part of the data it
-    /// may add a delay of a network round-trip.
+    /// Set the number of unanswered probes after which the
+    /// connection is dropped
     #[inline]
-    pub fn set_nodelay(&mut self, nodelay: bool) {
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = Some(retries);
+        self
+    }
+}
+
+// Socket options of a TCP stream.  Changes are applied on the next
+// `process` call.
+#[derive(Debug, Default)]
+struct TcpOptions {
+    // TCP_NODELAY flag
+    nodelay: bool,
+
+    // Pending set_nodelay()
+    pending_set_nodelay: bool,
+
+    // Keepalive settings, or `None` if disabled
+    keepalive: Option<KeepaliveConfig>,
+
+    // Pending set_keepalive()
+    pending_set_keepalive: bool,
+
+    // IP TTL requested, or `None` to leave the OS default
+    ttl: Option<u32>,
+
+    // Pending set_ttl()
+    pending_set_ttl: bool,
+
+    // IP TOS or IPv6 traffic class requested, or `None` to leave the
+    // OS default
+    tos: Option<u8>,
+
+    // Pending set_tos()
+    pending_set_tos: bool,
+
+    // TCP_QUICKACK flag, which is reapplied on every call while set
+    quickack: bool,
+
+    // Pending set_quickack()
+    pending_set_quickack: bool,
+
+    // Kernel socket buffer sizes
+    buffers: BufferSizes,
+
+    // TCP_NOTSENT_LOWAT requested, or `None` to leave the OS default
+    notsent_lowat: Option<u32>,
+
+    // Pending set_notsent_lowat()
+    pending_set_notsent_lowat: bool,
+
+    // TCP_USER_TIMEOUT requested, or `None` for the OS default
+    user_timeout: Option<Duration>,
+
+    // Pending set_user_timeout()
+    pending_set_user_timeout: bool,
+
+    // TCP_MAXSEG clamp requested, or `None` for no clamp
+    mss: Option<u32>,
+
+    // Pending set_mss()
+    pending_set_mss: bool,
+
+    // SO_OOBINLINE flag, to pass TCP urgent data into the stream
+    // instead of discarding it
+    oob_inline: bool,
+
+    // Pending set_oob_inline()
+    pending_set_oob_inline: bool,
+
+    // TCP_CORK flag as set by the caller
+    cork: bool,
+
+    // Pending set_cork()
+    pending_set_cork: bool,
+
+    // Set to cork automatically around writes
+    auto_cork: bool,
+
+    // Set while corked automatically
+    auto_corked: bool,
+
+    // Set when a "push" was consumed from the outgoing pipe while
+    // corked automatically
+    auto_push: bool,
+
+    // Set to leave "push" indications to Nagle, instead of pulsing
+    // TCP_NODELAY
+    ignore_push: bool,
+
+    // Set when a "push" was consumed from the outgoing pipe and the
+    // data has not yet been flushed
+    push: bool,
+}
+
+impl TcpOptions {
+    #[inline]
+    fn set_nodelay(&mut self, nodelay: bool) {
         if self.nodelay != nodelay {
             self.nodelay = nodelay;
             self.pending_set_nodelay = true
         }
     }
 
-    /// Pause or unpause writes.  This takes effect on the next
-    /// `process` call.
     #[inline]
-    pub fn set_pause_writes(&mut self, pause: bool) {
-        self.pause_writes = pause;
+    fn set_keepalive(&mut self, keepalive: Option<KeepaliveConfig>) {
+        if self.keepalive != keepalive {
+            self.keepalive = keepalive;
+            self.pending_set_keepalive = true
+        }
     }
 
-    /// Pause or unpause reads.  This takes effect on the next
-    /// `process` call.
     #[inline]
-    pub fn set_pause_reads(&mut self, pause: bool) {
-        self.pause_reads = pause;
+    fn set_ttl(&mut self, ttl: u32) {
+        if self.ttl != Some(ttl) {
+            self.ttl = Some(ttl);
+            self.pending_set_ttl = true
+        }
     }
 
-    /// Read and write as much data as possible to and from the given
-    /// TCP stream.  Returns the activity status: `Ok(true)` if
-    /// something changed, `Ok(false)` if no progress could be made,
-    /// or `Err(_)` if there was a fatal error on the stream.
-    ///
-    /// Assumes that it is always called with the same TcpStream and
-    /// pipe-buffer.  Things will behave unpredictably otherwise.
-    pub fn process(&mut self, stream: &mut TcpStream, mut pbuf: PBufRdWr) -> Result<bool> {
-        let rd_activity = self.process_out(stream, pbuf.reborrow())?;
-        let wr_activity = self.process_in(stream, pbuf.reborrow())?;
-        Ok(rd_activity || wr_activity)
+    #[inline]
+    fn set_tos(&mut self, tos: u8) {
+        if self.tos != Some(tos) {
+            self.tos = Some(tos);
+            self.pending_set_tos = true
+        }
     }
 
-    /// Write as much data as possible out to the given TCP stream.
-    /// Returns the activity status: `Ok(true)` if something changed,
-    /// `Ok(false)` if no progress could be made, or `Err(_)` if there
-    /// was a fatal error on the stream.
-    ///
-    /// Assumes that it is always called with the same TcpStream and
-    /// pipe-buffer.  Things will behave unpredictably otherwise.
-    pub fn process_out(&mut self, stream: &mut TcpStream, mut pbuf: PBufRdWr) -> Result<bool> {
-        if self.pause_writes {
-            return Ok(false);
+    #[inline]
+    fn set_oob_inline(&mut self, inline: bool) {
+        if self.oob_inline != inline {
+            self.oob_inline = inline;
+            self.pending_set_oob_inline = true
         }
+    }
 
-        if self.pending_set_nodelay {
-            self.pending_set_nodelay = false;
-            retry!(stream.set_nodelay(self.nodelay))?;
-        }
-
-        // TcpStream::flush() does nothing as it does write() syscalls
-        // directly (which don't buffer).  So there is no need to give
-        // the option to force flushes.
-        let mut prd = pbuf.rd;
-        let trip = prd.tripwire();
-        match prd.output_to(stream, false) {
-            Err(ref e) if e.kind() == ErrorKind::WouldBlock => (),
-            Err(e) => return Err(e),
-            Ok(_) => {
-                if prd.is_empty() && prd.has_pending_eof() {
-                    let shutdown = if prd.is_aborted() {
-                        if !pbuf.wr.is_eof() {
-                            pbuf.wr.abort();
-                        }
-                        std::net::Shutdown::Both
-                    } else {
-                        std::net::Shutdown::Write
-                    };
-                    match retry!(stream.shutdown(shutdown)) {
-                        Err(ref e) if e.kind() == ErrorKind::WouldBlock => (),
-                        Err(e) => return Err(e),
-                        Ok(_) => {
-                            prd.consume_eof();
-                        }
-                    }
-                }
-            }
+    #[inline]
+    fn set_quickack(&mut self, quickack: bool) {
+        if self.quickack != quickack {
+            self.quickack = quickack;
+            self.pending_set_quickack = true
         }
-        Ok(prd.is_tripped(trip))
     }
 
-    /// Read as much data as possible from to the given TCP stream, up
-    /// to **max_read_unit** bytes.  Returns the activity status:
-    /// `Ok(true)` if something changed, `Ok(false)` if no progress
-    /// could be made, or `Err(_)` if there was a fatal error on the
-    /// stream.
-    ///
-    /// Assumes that it is always called with the same TcpStream and
-    /// pipe-buffer.  Things will behave unpredictably otherwise.
-    pub fn process_in(&mut self, stream: &mut TcpStream, pbuf: PBufRdWr) -> Result<bool> {
-        let mut pwr = pbuf.wr;
-        if self.pause_reads || pwr.is_eof() {
-            return Ok(false);
+    #[inline]
+    fn set_cork(&mut self, cork: bool) {
+        if self.cork != cork {
+            self.cork = cork;
+            self.pending_set_cork = true
         }
+    }
 
-        let trip = pwr.tripwire();
-        if let Err(e) = pwr.input_from(stream, self.max_read_unit) {
-            match e.kind() {
-                ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted => pwr.abort(),
-                ErrorKind::WouldBlock => (),
-                _ => return Err(e),
-            }
+    #[inline]
+    fn set_notsent_lowat(&mut self, lowat: u32) {
+        if self.notsent_lowat != Some(lowat) {
+            self.notsent_lowat = Some(lowat);
+            self.pending_set_notsent_lowat = true
         }
-        Ok(pwr.is_tripped(trip))
     }
-}
 
-impl Default for TcpLink {
-    fn default() -> Self {
-        Self::new()
+    #[inline]
+    fn set_user_timeout(&mut self, timeout: Option<Duration>) {
+        if self.user_timeout != timeout {
+            self.user_timeout = timeout;
+            self.pending_set_user_timeout = true
+        }
+    }
+
+    #[inline]
+    fn set_mss(&mut self, mss: Option<u32>) {
+        if self.mss != mss {
+            self.mss = mss;
+            self.pending_set_mss = true
+        }
+    }
+
+    // Test whether any option changes are waiting to be applied
+    fn has_pending(&self) -> bool {
+        self.pending_set_nodelay
+            || self.pending_set_keepalive
+            || self.pending_set_ttl
+            || self.pending_set_tos
+            || self.pending_set_quickack
+            || self.pending_set_cork
+            || self.pending_set_notsent_lowat
+            || self.pending_set_user_timeout
+            || self.pending_set_mss
+            || self.pending_set_oob_inline
+            || self.buffers.has_pending()
+    }
+
+    // Cork the stream before a write if automatic corking is enabled
+    // and there is data to write.  Any "push" is noted for
+    // `auto_uncork`.
+    fn auto_cork(
+        &mut self,
+        stream: &impl TcpSocket,
+        prd: &mut PBufRd,
+        tracer: &Tracer,
+    ) -> Result<()> {
+        if !self.auto_cork || self.cork {
+            return Ok(());
+        }
+        self.auto_push |= prd.consume_push();
+        if !self.auto_corked && !prd.is_empty() {
+            self.set_auto_corked(stream, true, tracer)?;
+        }
+        Ok(())
+    }
+
+    // Uncork the stream after a write if it was corked automatically,
+    // once the outgoing pipe is drained or a "push" was seen
+    fn auto_uncork(
+        &mut self,
+        stream: &impl TcpSocket,
+        prd: &PBufRd,
+        tracer: &Tracer,
+    ) -> Result<()> {
+        if self.auto_corked && (prd.is_empty() || self.auto_push) {
+            self.auto_push = false;
+            self.set_auto_corked(stream, false, tracer)?;
+        }
+        Ok(())
+    }
+
+    // Take any "push" from the outgoing pipe before a write, if it
+    // needs handling with Nagle enabled
+    #[inline]
+    fn note_push(&mut self, prd: &mut PBufRd) {
+        if !self.ignore_push && !self.nodelay {
+            self.push |= prd.consume_push();
+        }
+    }
+
+    // Once the data before a "push" has all been written, flush it out
+    // by setting TCP_NODELAY and then clearing it again.  Setting the
+    // flag sends any data held back by Nagle, even when corked.  On
+    // failure this handling is turned off, so that the error is only
+    // reported once.
+    fn flush_push(&mut self, stream: &impl TcpSocket, prd: &PBufRd, tracer: &Tracer) -> Result<()> {
+        if !self.push || !prd.is_empty() {
+            return Ok(());
+        }
+        self.push = false;
+        let rv = retry!(stream.set_nodelay(true)).and_then(|_| retry!(stream.set_nodelay(false)));
+        if let Err(e) = rv {
+            self.ignore_push = true;
+            let e = Error::new(Direction::Outgoing, Op::SetOption, e);
+            tracer.error(&e);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    // On failure automatic corking is turned off, so that the error
+    // is only reported once
+    fn set_auto_corked(
+        &mut self,
+        stream: &impl TcpSocket,
+        cork: bool,
+        tracer: &Tracer,
+    ) -> Result<()> {
+        if let Err(e) = retry!(stream.set_cork(cork)) {
+            self.auto_cork = false;
+            self.auto_corked = false;
+            let e = Error::new(Direction::Outgoing, Op::SetOption, e);
+            tracer.error(&e);
+            return Err(e);
+        }
+        self.auto_corked = cork;
+        Ok(())
+    }
+
+    // Change the "no delay" flag on the stream at once, replacing any
+    // pending change
+    fn set_nodelay_now(
+        &mut self,
+        stream: &impl TcpSocket,
+        nodelay: bool,
+        tracer: &Tracer,
+    ) -> Result<()> {
+        self.nodelay = nodelay;
+        self.pending_set_nodelay = false;
+        retry!(stream.set_nodelay(nodelay))
+            .map_err(|e| Error::new(Direction::Outgoing, Op::SetOption, e))
+            .inspect_err(|e| tracer.error(e))?;
+        tracer.option("nodelay", nodelay);
+        Ok(())
+    }
+
+    // Apply any pending changes to the stream.  Each change is only
+    // attempted once, so a failure is not retried on later calls.
+    fn apply(&mut self, stream: &impl TcpSocket, tracer: &Tracer) -> Result<()> {
+        if self.pending_set_nodelay {
+            self.pending_set_nodelay = false;
+            retry!(stream.set_nodelay(self.nodelay))
+                .map_err(|e| Error::new(Direction::Outgoing, Op::SetOption, e))
+                .inspect_err(|e| tracer.error(e))?;
+            tracer.option("nodelay", self.nodelay);
+        }
+        if self.pending_set_keepalive {
+            self.pending_set_keepalive = false;
+            retry!(stream.set_keepalive(self.keepalive.as_ref()))
+                .map_err(|e| Error::new(Direction::Both, Op::SetOption, e))
+                .inspect_err(|e| tracer.error(e))?;
+            tracer.option("keepalive", self.keepalive.is_some());
+        }
+        if self.pending_set_ttl {
+            self.pending_set_ttl = false;
+            if let Some(ttl) = self.ttl {
+                retry!(stream.set_ttl(ttl))
+                    .map_err(|e| Error::new(Direction::Outgoing, Op::SetOption, e))
+                    .inspect_err(|e| tracer.error(e))?;
+                tracer.option("ttl", ttl);
+            }
+        }
+        if self.pending_set_tos {
+            self.pending_set_tos = false;
+            if let Some(tos) = self.tos {
+                retry!(stream.set_tos(tos))
+                    .map_err(|e| Error::new(Direction::Outgoing, Op::SetOption, e))
+                    .inspect_err(|e| tracer.error(e))?;
+                tracer.option("tos", tos);
+            }
+        }
+        if self.pending_set_quickack || self.quickack {
+            // The kernel clears quickack by itself, so while enabled
+            // it is set again each time.  On failure it is turned off,
+            // so that the error is only reported once.
+            let pending = self.pending_set_quickack;
+            self.pending_set_quickack = false;
+            if let Err(e) = retry!(stream.set_quickack(self.quickack)) {
+                self.quickack = false;
+                let e = Error::new(Direction::Incoming, Op::SetOption, e);
+                tracer.error(&e);
+                return Err(e);
+            }
+            if pending {
+                tracer.option("quickack", self.quickack);
+            }
+        }
+        self.buffers.apply(stream, tracer)?;
+        if self.pending_set_notsent_lowat {
+            self.pending_set_notsent_lowat = false;
+            if let Some(lowat) = self.notsent_lowat {
+                retry!(stream.set_notsent_lowat(lowat))
+                    .map_err(|e| Error::new(Direction::Outgoing, Op::SetOption, e))
+                    .inspect_err(|e| tracer.error(e))?;
+                tracer.option("notsent_lowat", lowat);
+            }
+        }
+        if self.pending_set_user_timeout {
+            self.pending_set_user_timeout = false;
+            retry!(stream.set_user_timeout(self.user_timeout))
+                .map_err(|e| Error::new(Direction::Both, Op::SetOption, e))
+                .inspect_err(|e| tracer.error(e))?;
+            tracer.option("user_timeout", self.user_timeout.is_some());
+        }
+        if self.pending_set_mss {
+            self.pending_set_mss = false;
+            retry!(stream.set_mss(self.mss.unwrap_or(0)))
+                .map_err(|e| Error::new(Direction::Both, Op::SetOption, e))
+                .inspect_err(|e| tracer.error(e))?;
+            tracer.option("mss", self.mss.unwrap_or(0));
+        }
+        if self.pending_set_oob_inline {
+            self.pending_set_oob_inline = false;
+            retry!(stream.set_oob_inline(self.oob_inline))
+                .map_err(|e| Error::new(Direction::Incoming, Op::SetOption, e))
+                .inspect_err(|e| tracer.error(e))?;
+            tracer.option("oob_inline", self.oob_inline);
+        }
+        if self.pending_set_cork {
+            self.pending_set_cork = false;
+            self.auto_corked = false;
+            retry!(stream.set_cork(self.cork))
+                .map_err(|e| Error::new(Direction::Outgoing, Op::SetOption, e))
+                .inspect_err(|e| tracer.error(e))?;
+            tracer.option("cork", self.cork);
+        }
+        Ok(())
+    }
+}
+
+// Automatic corking and "push" handling around each write
+impl<S: TcpSocket> WriteHooks<S> for TcpOptions {
+    fn before_write(&mut self, stream: &mut S, prd: &mut PBufRd, tracer: &Tracer) -> Result<()> {
+        self.auto_cork(stream, prd, tracer)?;
+        self.note_push(prd);
+        Ok(())
+    }
+
+    fn after_write(&mut self, stream: &mut S, prd: &PBufRd, tracer: &Tracer) -> Result<()> {
+        self.auto_uncork(stream, prd, tracer)?;
+        self.flush_push(stream, prd, tracer)
+    }
+}
+
+/// How a [`TcpLink`] ends the incoming pipe when its read quota runs
+/// out
+///
+/// See [`TcpLink::set_read_quota`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ReadQuotaMode {
+    /// Close the incoming pipe, as for EOF from the peer.  This is
+    /// the default.
+    #[default]
+    Close,
+
+    /// Abort the incoming pipe, as for a reset from the peer
+    Abort,
+}
+
+/// What a [`TcpLink`] does when its write quota runs out with data
+/// still waiting to be written
+///
+/// See [`TcpLink::set_write_quota`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WriteQuotaMode {
+    /// Stop writing, leaving the rest of the data in the outgoing
+    /// pipe.  Writing carries on if the quota is raised.  This is the
+    /// default.
+    #[default]
+    Stall,
+
+    /// Abort the link, as for an outgoing "abort" according to the
+    /// [`AbortMode`]
+    Abort,
+}
+
+/// How a [`TcpLink`] passes on an outgoing "abort"
+///
+/// The reset modes reset the connection instead of doing a shutdown.
+/// On Linux and Android the RST is sent at once.  On other Unix
+/// platforms `SO_LINGER` is set with a zero timeout, so that the
+/// kernel sends RST when the stream is closed, so there the stream
+/// must be dropped promptly after the abort has been passed on,
+/// which would normally happen anyway.  Resets are only supported on
+/// Unix.  If the reset fails, or on other platforms, the reset modes
+/// fall back to `ShutdownBoth`.  In all modes the incoming pipe is
+/// also aborted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AbortMode {
+    /// Write out any remaining data, and then shut down both halves
+    /// of the stream.  The peer sees a normal close.  This is the
+    /// default.
+    #[default]
+    ShutdownBoth,
+
+    /// Discard any remaining data and reset the connection at once
+    Reset,
+
+    /// Write out any remaining data, and then reset the connection,
+    /// e.g. to send an error response before tearing things down
+    FlushThenReset,
+}
+
+/// Order in which a `process` call of a stream link handles the two
+/// directions
+///
+/// Select with [`TcpLink::set_process_order`] or
+/// [`crate::UnixStreamLink::set_process_order`].  The activity flags
+/// returned cover both directions whatever the order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ProcessOrder {
+    /// Write out the outgoing pipe, and then read into the incoming
+    /// pipe.  This is the default.
+    #[default]
+    WriteThenRead,
+
+    /// Read into the incoming pipe first, so that newly arrived data
+    /// is seen by the application before time is spent writing
+    ReadThenWrite,
+
+    /// Alternate between writing and reading in chunks of up to
+    /// **chunk** bytes, so that neither direction can hold up the
+    /// other for long within one call.  This carries on until
+    /// neither direction makes progress, or the limits on a single
+    /// call in each direction are reached, e.g. **max_read_unit**.
+    Interleaved {
+        /// Maximum number of bytes to write or read at a time
+        chunk: usize,
+    },
+}
+
+impl ProcessOrder {
+    // Raise a zero chunk size to 1
+    pub(crate) fn normalize(self) -> Self {
+        match self {
+            Self::Interleaved { chunk } => Self::Interleaved {
+                chunk: chunk.max(1),
+            },
+            order => order,
+        }
+    }
+}
+
+/// Settings for creating [`TcpLink`] instances
+///
+/// This allows a server to hold one template and create many links
+/// with the same settings using [`TcpLink::with_config`], for
+/// example from `TcpLinkConfig::new().max_read_unit(65536).nodelay(true)`.
+///
+/// With the `serde` feature, this can be loaded from a configuration
+/// file.  The fields have the names of the builder methods below,
+/// and any that are missing take their default values.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct TcpLinkConfig {
+    max_read_unit: usize,
+    read_unit_range: Option<(usize, usize)>,
+    max_read_total: Option<usize>,
+    read_low_water: usize,
+    max_write_unit: Option<usize>,
+    read_backpressure: Option<usize>,
+    nodelay: bool,
+    keepalive: Option<KeepaliveConfig>,
+    ttl: Option<u32>,
+    tos: Option<u8>,
+    user_timeout: Option<Duration>,
+    recv_buffer_size: Option<usize>,
+    send_buffer_size: Option<usize>,
+    notsent_lowat: Option<u32>,
+    mss: Option<u32>,
+    push_nodelay: bool,
+    quickack: bool,
+    cork: bool,
+    auto_cork: bool,
+    oob_inline: bool,
+    abort_mode: AbortMode,
+    process_order: ProcessOrder,
+    wouldblock_latch: bool,
+    auto_start: bool,
+    idle_timeout: Option<Duration>,
+    close_deadline: Option<Duration>,
+    read_rate_limit: Option<RateLimit>,
+    write_rate_limit: Option<RateLimit>,
+    read_quota: Option<u64>,
+    read_quota_mode: ReadQuotaMode,
+    read_quota_shutdown: bool,
+    write_quota: Option<u64>,
+    write_quota_mode: WriteQuotaMode,
+    pause_reads: bool,
+    pause_writes: bool,
+}
+
+impl TcpLinkConfig {
+    /// Create a configuration with the same defaults as
+    /// [`TcpLink::new`]
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            max_read_unit: 2048,
+            read_unit_range: None,
+            max_read_total: None,
+            read_low_water: 0,
+            max_write_unit: None,
+            read_backpressure: None,
+            nodelay: false,
+            keepalive: None,
+            ttl: None,
+            tos: None,
+            user_timeout: None,
+            recv_buffer_size: None,
+            send_buffer_size: None,
+            notsent_lowat: None,
+            mss: None,
+            push_nodelay: true,
+            quickack: false,
+            cork: false,
+            auto_cork: false,
+            oob_inline: false,
+            abort_mode: AbortMode::ShutdownBoth,
+            process_order: ProcessOrder::WriteThenRead,
+            wouldblock_latch: false,
+            auto_start: false,
+            idle_timeout: None,
+            close_deadline: None,
+            read_rate_limit: None,
+            write_rate_limit: None,
+            read_quota: None,
+            read_quota_mode: ReadQuotaMode::Close,
+            read_quota_shutdown: false,
+            write_quota: None,
+            write_quota_mode: WriteQuotaMode::Stall,
+            pause_reads: true,
+            pause_writes: true,
+        }
+    }
+
+    /// Set the maximum number of bytes to read in each `process`
+    /// call.  See [`TcpLink::set_max_read_unit`].
+    #[inline]
+    pub fn max_read_unit(mut self, max_read_unit: usize) -> Self {
+        self.max_read_unit = max_read_unit;
+        self
+    }
+
+    /// Let **max_read_unit** adapt to the traffic between `min` and
+    /// `max`, or use the fixed **max_read_unit** with `None`.  See
+    /// [`TcpLink::set_read_unit_range`].
+    #[inline]
+    pub fn read_unit_range(mut self, range: Option<(usize, usize)>) -> Self {
+        self.read_unit_range = range;
+        self
+    }
+
+    /// Set the maximum number of bytes to read in each `process`
+    /// call across several reads.  See
+    /// [`TcpLink::set_max_read_total`].
+    #[inline]
+    pub fn max_read_total(mut self, max_read_total: usize) -> Self {
+        self.max_read_total = Some(max_read_total);
+        self
+    }
+
+    /// Set the minimum space in the incoming pipe needed to read.
+    /// See [`TcpLink::set_read_low_water`].
+    #[inline]
+    pub fn read_low_water(mut self, low_water: usize) -> Self {
+        self.read_low_water = low_water;
+        self
+    }
+
+    /// Set the maximum number of bytes to write in each `process`
+    /// call.  See [`TcpLink::set_max_write_unit`].
+    #[inline]
+    pub fn max_write_unit(mut self, max_write_unit: usize) -> Self {
+        self.max_write_unit = Some(max_write_unit);
+        self
+    }
+
+    /// Set the backlog in the incoming pipe above which reads are
+    /// held off.  See [`TcpLink::set_read_backpressure`].
+    #[inline]
+    pub fn read_backpressure(mut self, threshold: Option<usize>) -> Self {
+        self.read_backpressure = threshold;
+        self
+    }
+
+    /// Set the "no delay" flag.  See [`TcpLink::set_nodelay`].
+    #[inline]
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    /// Enable TCP keepalive.  See [`TcpLink::set_keepalive`].
+    #[inline]
+    pub fn keepalive(mut self, keepalive: KeepaliveConfig) -> Self {
+        self.keepalive = Some(keepalive);
+        self
+    }
+
+    /// Set the IP time-to-live.  See [`TcpLink::set_ttl`].
+    #[inline]
+    pub fn ttl(mut self, ttl: u32) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Set the IP type-of-service byte.  See [`TcpLink::set_tos`].
+    #[inline]
+    pub fn tos(mut self, tos: u8) -> Self {
+        self.tos = Some(tos);
+        self
+    }
+
+    /// Set `TCP_USER_TIMEOUT`.  See [`TcpLink::set_user_timeout`].
+    #[inline]
+    pub fn user_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.user_timeout = timeout;
+        self
+    }
+
+    /// Set the kernel receive buffer size.  See
+    /// [`TcpLink::set_recv_buffer_size`].
+    #[inline]
+    pub fn recv_buffer_size(mut self, size: usize) -> Self {
+        self.recv_buffer_size = Some(size);
+        self
+    }
+
+    /// Set the kernel send buffer size.  See
+    /// [`TcpLink::set_send_buffer_size`].
+    #[inline]
+    pub fn send_buffer_size(mut self, size: usize) -> Self {
+        self.send_buffer_size = Some(size);
+        self
+    }
+
+    /// Limit the unsent data queued in the kernel.  See
+    /// [`TcpLink::set_notsent_lowat`].
+    #[inline]
+    pub fn notsent_lowat(mut self, lowat: u32) -> Self {
+        self.notsent_lowat = Some(lowat);
+        self
+    }
+
+    /// Clamp the TCP maximum segment size.  See
+    /// [`TcpLink::set_mss`].
+    #[inline]
+    pub fn mss(mut self, mss: u32) -> Self {
+        self.mss = Some(mss);
+        self
+    }
+
+    /// Enable or disable flushing on "push".  See
+    /// [`TcpLink::set_push_nodelay`].
+    #[inline]
+    pub fn push_nodelay(mut self, enable: bool) -> Self {
+        self.push_nodelay = enable;
+        self
+    }
+
+    /// Enable or disable `TCP_QUICKACK`.  See
+    /// [`TcpLink::set_quickack`].
+    #[inline]
+    pub fn quickack(mut self, quickack: bool) -> Self {
+        self.quickack = quickack;
+        self
+    }
+
+    /// Enable or disable `TCP_CORK`.  See [`TcpLink::set_cork`].
+    #[inline]
+    pub fn cork(mut self, cork: bool) -> Self {
+        self.cork = cork;
+        self
+    }
+
+    /// Enable or disable automatic corking.  See
+    /// [`TcpLink::set_auto_cork`].
+    #[inline]
+    pub fn auto_cork(mut self, auto_cork: bool) -> Self {
+        self.auto_cork = auto_cork;
+        self
+    }
+
+    /// Select how TCP urgent data is handled.  See
+    /// [`TcpLink::set_oob_inline`].
+    #[inline]
+    pub fn oob_inline(mut self, inline: bool) -> Self {
+        self.oob_inline = inline;
+        self
+    }
+
+    /// Select how an outgoing "abort" is passed on.  See
+    /// [`TcpLink::set_abort_mode`].
+    #[inline]
+    pub fn abort_mode(mut self, mode: AbortMode) -> Self {
+        self.abort_mode = mode;
+        self
+    }
+
+    /// Select the order in which `process` handles the two
+    /// directions.  See [`TcpLink::set_process_order`].
+    #[inline]
+    pub fn process_order(mut self, order: ProcessOrder) -> Self {
+        self.process_order = order;
+        self
+    }
+
+    /// Skip reads and writes after `WouldBlock` until fresh
+    /// readiness.  See [`TcpLink::set_wouldblock_latch`].
+    #[inline]
+    pub fn wouldblock_latch(mut self, enable: bool) -> Self {
+        self.wouldblock_latch = enable;
+        self
+    }
+
+    /// Check the connect and unpause both directions once it has
+    /// completed.  See [`TcpLink::set_auto_start`].
+    #[inline]
+    pub fn auto_start(mut self, auto_start: bool) -> Self {
+        self.auto_start = auto_start;
+        self
+    }
+
+    /// Abort the link after this long without traffic.  See
+    /// [`TcpLink::set_idle_timeout`].
+    #[inline]
+    pub fn idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Abort the link if the peer doesn't close this long after our
+    /// EOF.  See [`TcpLink::set_close_deadline`].
+    #[inline]
+    pub fn close_deadline(mut self, deadline: Option<Duration>) -> Self {
+        self.close_deadline = deadline;
+        self
+    }
+
+    /// Limit the read bandwidth.  See
+    /// [`TcpLink::set_read_rate_limit`].
+    #[inline]
+    pub fn read_rate_limit(mut self, limit: Option<RateLimit>) -> Self {
+        self.read_rate_limit = limit;
+        self
+    }
+
+    /// Limit the write bandwidth.  See
+    /// [`TcpLink::set_write_rate_limit`].
+    #[inline]
+    pub fn write_rate_limit(mut self, limit: Option<RateLimit>) -> Self {
+        self.write_rate_limit = limit;
+        self
+    }
+
+    /// Limit the total number of bytes read.  This only affects new
+    /// links.  See [`TcpLink::set_read_quota`].
+    #[inline]
+    pub fn read_quota(mut self, quota: Option<u64>) -> Self {
+        self.read_quota = quota;
+        self
+    }
+
+    /// Select how the incoming pipe is ended when the read quota runs
+    /// out.  See [`TcpLink::set_read_quota_mode`].
+    #[inline]
+    pub fn read_quota_mode(mut self, mode: ReadQuotaMode) -> Self {
+        self.read_quota_mode = mode;
+        self
+    }
+
+    /// Shut down the read half when the read quota runs out.  See
+    /// [`TcpLink::set_read_quota_shutdown`].
+    #[inline]
+    pub fn read_quota_shutdown(mut self, shutdown: bool) -> Self {
+        self.read_quota_shutdown = shutdown;
+        self
+    }
+
+    /// Limit the total number of bytes written.  This only affects
+    /// new links.  See [`TcpLink::set_write_quota`].
+    #[inline]
+    pub fn write_quota(mut self, quota: Option<u64>) -> Self {
+        self.write_quota = quota;
+        self
+    }
+
+    /// Select what happens when the write quota runs out.  See
+    /// [`TcpLink::set_write_quota_mode`].
+    #[inline]
+    pub fn write_quota_mode(mut self, mode: WriteQuotaMode) -> Self {
+        self.write_quota_mode = mode;
+        self
+    }
+
+    /// Set whether reads start paused.  This only affects new links.
+    #[inline]
+    pub fn pause_reads(mut self, pause: bool) -> Self {
+        self.pause_reads = pause;
+        self
+    }
+
+    /// Set whether writes start paused.  This only affects new links.
+    #[inline]
+    pub fn pause_writes(mut self, pause: bool) -> Self {
+        self.pause_writes = pause;
+        self
+    }
+}
+
+impl Default for TcpLinkConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Exchange stream data via the `mio` [`TcpStream`] type
+///
+/// For the TCP incoming stream both TCP "close" (FIN) and "abort"
+/// (RST) are detected and passed on.  A connection that times out,
+/// e.g. with [`TcpLink::set_user_timeout`] or keepalive, is passed on
+/// as an abort in the same way.  For the TCP outgoing stream,
+/// "close" handling is mapped to a normal shutdown on the outgoing
+/// half of the TCP stream.  The incoming half of the TCP stream, if
+/// still open, remains open until the other end closes, as expected.
+///
+/// For TCP outgoing "abort", ideally we'd generate a TCP RST to tear
+/// things down at both ends as soon as possible.  This can be done
+/// with `set_linger(Some(0))` and a close.  However the linger API is
+/// not yet stable on `std`, is not present at all in `mio`.  So by
+/// default on "abort", this code does a normal shutdown on both
+/// incoming and outgoing TCP streams, and does an "abort" on the side
+/// of the pipe for incoming TCP data.  This should cause rapid
+/// shutdown of things locally.  The remote end however will not know
+/// that this is an abort.  To send a RST instead, see
+/// [`TcpLink::set_abort_mode`].
+///
+/// Data is always sent with a plain `write`, which copies it into
+/// the kernel.  Zero-copy sending (Linux `MSG_ZEROCOPY`) is not
+/// offered, because the kernel goes on reading the pages after the
+/// call returns, and the producer side of the [`pipebuf::PipeBuf`]
+/// may move unconsumed data down the buffer or reallocate it
+/// whenever it needs space.  The bytes transmitted could then differ
+/// from those written to the pipe.  For large transfers, a larger
+/// send buffer (see [`TcpLink::set_send_buffer_size`]) at least
+/// reduces the number of `write` calls needed.
+///
+/// To start with both reading and writing via the TCP stream are
+/// paused.  This is because, depending on the platform, reading or
+/// writing may give an error if a "ready" indication has not yet been
+/// received.  So call `set_pause_writes(false)` or
+/// `set_pause_reads(false)` as soon as the stream indicates "ready"
+/// in order to allow data to flow, or pass each event to
+/// [`TcpLink::process_event`] which does this automatically.
+/// Alternatively enable [`TcpLink::set_auto_start`] to have the link
+/// unpause both directions itself once the stream has connected.
+pub struct TcpLink {
+    // Common settings and processing
+    link: GenericLink,
+
+    // Socket options
+    opts: TcpOptions,
+
+    // Token the stream is registered with, if any
+    token: Option<Token>,
+
+    // Set once the stream is known to be connected
+    connected: bool,
+
+    // Set to unpause both directions once connected
+    auto_start: bool,
+
+    // Peer address, once known
+    peer_addr: Option<SocketAddr>,
+
+    // Local address, once known
+    local_addr: Option<SocketAddr>,
+}
+
+impl TcpLink {
+    /// Create the component with default settings:
+    ///
+    /// - **max_read_unit** of 2048.  This is bigger than a typical IP
+    ///   packet's data load but you may want to increase this.
+    ///
+    /// - **nodelay** set to `false`, i.e. using the Nagle algorithm to
+    ///   delay output to attempt to batch up data into fewer IP packets
+    ///
+    /// - Both reads and writes paused
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            link: GenericLink::new(2048),
+            opts: TcpOptions::default(),
+            token: None,
+            connected: false,
+            auto_start: false,
+            peer_addr: None,
+            local_addr: None,
+        }
+    }
+
+    /// Create the component with the settings from the given
+    /// configuration
+    pub fn with_config(config: &TcpLinkConfig) -> Self {
+        let mut link = Self::new();
+        link.apply_config(config);
+        link.set_read_quota(config.read_quota);
+        link.set_write_quota(config.write_quota);
+        link.link.pause_reads = config.pause_reads;
+        link.link.pause_writes = config.pause_writes;
+        link
+    }
+
+    /// Change the settings of a live link to match the given
+    /// configuration.  Socket options are updated on the next
+    /// `process` call, and those not given in the configuration are
+    /// left as they are.  The pause flags are not changed, since they
+    /// reflect the current state of the stream, and neither are the
+    /// quotas, since they count down from the creation of the link.
+    pub fn apply_config(&mut self, config: &TcpLinkConfig) {
+        match config.read_unit_range {
+            Some((min, max)) => self.set_read_unit_range(min, max),
+            None => self.set_max_read_unit(config.max_read_unit),
+        }
+        self.link.max_read_total = config.max_read_total;
+        self.set_read_low_water(config.read_low_water);
+        self.set_max_write_unit(config.max_write_unit.unwrap_or(usize::MAX));
+        self.set_read_backpressure(config.read_backpressure);
+        self.set_nodelay(config.nodelay);
+        if let Some(ref keepalive) = config.keepalive {
+            self.set_keepalive(Some(keepalive.clone()));
+        }
+        if let Some(ttl) = config.ttl {
+            self.set_ttl(ttl);
+        }
+        if let Some(tos) = config.tos {
+            self.set_tos(tos);
+        }
+        if config.user_timeout.is_some() {
+            self.set_user_timeout(config.user_timeout);
+        }
+        if let Some(size) = config.recv_buffer_size {
+            self.set_recv_buffer_size(size);
+        }
+        if let Some(size) = config.send_buffer_size {
+            self.set_send_buffer_size(size);
+        }
+        if let Some(lowat) = config.notsent_lowat {
+            self.set_notsent_lowat(lowat);
+        }
+        if config.mss.is_some() {
+            self.set_mss(config.mss);
+        }
+        self.set_push_nodelay(config.push_nodelay);
+        self.set_quickack(config.quickack);
+        self.set_cork(config.cork);
+        self.set_auto_cork(config.auto_cork);
+        self.set_oob_inline(config.oob_inline);
+        self.set_abort_mode(config.abort_mode);
+        self.set_process_order(config.process_order);
+        self.set_wouldblock_latch(config.wouldblock_latch);
+        self.set_auto_start(config.auto_start);
+        self.set_idle_timeout(config.idle_timeout);
+        self.set_close_deadline(config.close_deadline);
+        self.set_read_rate_limit(config.read_rate_limit);
+        self.set_write_rate_limit(config.write_rate_limit);
+        self.set_read_quota_mode(config.read_quota_mode);
+        self.set_read_quota_shutdown(config.read_quota_shutdown);
+        self.set_write_quota_mode(config.write_quota_mode);
+    }
+
+    /// Change the maximum number of bytes to read in each `process`
+    /// call.  This allows managing how much data you wish to handle
+    /// at a time, to allow the possibility of backpressure, and to
+    /// control how large the pipe buffers in your processing chain
+    /// will grow.  If memory is not an issue, then there is no
+    /// problem with setting this large, which will likely give higher
+    /// efficiency if there is a lot of data queued.
+    #[inline]
+    pub fn set_max_read_unit(&mut self, max_read_unit: usize) {
+        self.link.set_max_read_unit(max_read_unit);
+    }
+
+    /// Get the maximum number of bytes to read in each `process`
+    /// call.  See [`TcpLink::set_max_read_unit`].  With an adaptive
+    /// range set by [`TcpLink::set_read_unit_range`], this is the
+    /// current value.
+    #[inline]
+    pub fn max_read_unit(&self) -> usize {
+        self.link.max_read_unit
+    }
+
+    /// Let **max_read_unit** adapt to the traffic, between `min` and
+    /// `max` bytes, starting from `min`.  After each read, it is
+    /// doubled if a whole unit was read, since more data is then
+    /// likely to be waiting, or halved if less than a quarter of what
+    /// could have been read arrived, including when nothing had
+    /// arrived, e.g. once the connection has gone quiet.  This suits
+    /// a mix of bulk transfers and many mostly idle connections
+    /// better than a fixed size.  The current value is returned by
+    /// [`TcpLink::max_read_unit`].  Calling
+    /// [`TcpLink::set_max_read_unit`] returns to a fixed size.
+    #[inline]
+    pub fn set_read_unit_range(&mut self, min: usize, max: usize) {
+        self.link.set_read_unit_range(min, max);
+    }
+
+    /// Get the bounds set with [`TcpLink::set_read_unit_range`], or
+    /// `None` if **max_read_unit** is fixed
+    #[inline]
+    pub fn read_unit_range(&self) -> Option<(usize, usize)> {
+        self.link.read_unit_range
+    }
+
+    /// Change the maximum number of bytes to read in each `process`
+    /// or `process_in` call.  Reads are made in units of up to
+    /// **max_read_unit** bytes, repeated until the stream gives
+    /// `WouldBlock` or EOF, or this total is reached.  By default the
+    /// total is one **max_read_unit**, i.e. a single unit.
+    ///
+    /// With edge-triggered polling, a readiness event only arrives
+    /// when new data comes in, so data left unread in the stream may
+    /// stall the connection.  In that case, use `usize::MAX` to read
+    /// until `WouldBlock` on each call, or else call `process` again
+    /// whenever it reads a full **max_read_total**.
+    #[inline]
+    pub fn set_max_read_total(&mut self, max_read_total: usize) {
+        self.link.max_read_total = Some(max_read_total);
+    }
+
+    /// Skip reading when the incoming pipe has less than `low_water`
+    /// bytes of free space, counting up to **max_read_unit**, to
+    /// avoid many tiny reads into a nearly full fixed-capacity pipe.
+    /// In that case `process_in` makes no read call and reports no
+    /// activity, and [`TcpLink::needed_interest`] leaves out READABLE.
+    /// The default of zero only skips reading when the pipe is
+    /// completely full.  A pipe that grows as needed always has
+    /// **max_read_unit** bytes of space.
+    #[inline]
+    pub fn set_read_low_water(&mut self, low_water: usize) {
+        self.link.read_low_water = low_water;
+    }
+
+    /// Change the maximum number of bytes to write out in each
+    /// `process` or `process_out` call.  By default there is no
+    /// limit, and all the data in the outgoing pipe is written unless
+    /// the stream blocks.  A limit stops one connection with a deep
+    /// outgoing pipe from taking up a whole loop iteration.  If the
+    /// limit is reached, data remains in the pipe, and any pending EOF
+    /// is only passed on once the pipe is empty.  The stream is still
+    /// writable at that point, so with edge-triggered polling no new
+    /// event will arrive, and the caller must arrange to call
+    /// `process` again, e.g. when [`TcpLink::needed_interest`] still
+    /// includes WRITABLE.
+    #[inline]
+    pub fn set_max_write_unit(&mut self, max_write_unit: usize) {
+        self.link.max_write_unit = max_write_unit;
+    }
+
+    /// Hold off reading while the incoming pipe has a backlog of
+    /// more than `threshold` bytes of unconsumed data, or disable
+    /// this with `None`.  While held off, `process_in` reads nothing
+    /// and reports no activity, and [`TcpLink::needed_interest`]
+    /// leaves out READABLE.  Reads resume once the consumer has
+    /// drained the backlog to half the threshold or less, which
+    /// avoids flapping.  Memory use for the incoming pipe is then
+    /// bounded at around `threshold` plus **max_read_total**.
+    ///
+    /// The link only sees the backlog drop on its next `process`
+    /// call, so after consuming data, the caller should call
+    /// `process` or update its interest.
+    #[inline]
+    pub fn set_read_backpressure(&mut self, threshold: Option<usize>) {
+        self.link.read_backpressure = threshold;
+    }
+
+    /// Change the "no delay" flag on the stream.  This will be
+    /// updated on the next `process` call.
+    ///
+    /// Use `true` if you wish to disable the Nagle algorithm, e.g. if
+    /// you are sending large chunks or packets of data and wish them
+    /// to be sent immediately, even if that means using a part-filled
+    /// IP packet for the last part of the data.
+    ///
+    /// Use `false` if you are sending byte data a few bytes at a time
+    /// (e.g. terminal interaction) and you'd rather that TCP waited a
+    /// moment to attempt to batch up data together to reduce the
+    /// number of IP packets sent.  For the last part of the data it
+    /// may add a delay of a network round-trip.
+    ///
+    /// The change is made on the next `process` call, even if writes
+    /// are paused.  Use [`TcpLink::set_nodelay_now`] instead to make
+    /// it at once.
+    #[inline]
+    pub fn set_nodelay(&mut self, nodelay: bool) {
+        self.opts.set_nodelay(nodelay);
+    }
+
+    /// Change the "no delay" flag on the stream at once, rather than
+    /// on the next `process` call, replacing any change pending from
+    /// [`TcpLink::set_nodelay`].  Fails with [`Op::SetOption`] if
+    /// the option could not be set.
+    pub fn set_nodelay_now(&mut self, stream: &TcpStream, nodelay: bool) -> Result<()> {
+        self.opts
+            .set_nodelay_now(stream, nodelay, &self.link.tracer)
+    }
+
+    /// Get the "no delay" flag last requested with
+    /// [`TcpLink::set_nodelay`].  This may not yet have been applied
+    /// to the stream.
+    #[inline]
+    pub fn nodelay(&self) -> bool {
+        self.opts.nodelay
+    }
+
+    /// Enable or disable flushing on "push".  When enabled, which is
+    /// the default, a "push" on the outgoing pipe (see
+    /// [`pipebuf::PBufWr::push`]) makes the data up to that point go
+    /// out immediately even with the Nagle algorithm enabled, by
+    /// briefly setting the "no delay" flag once the data has been
+    /// written.  This costs two extra syscalls per "push".  Disable
+    /// this to leave all data to Nagle, as in earlier versions.  This
+    /// has no effect with "no delay" set.
+    #[inline]
+    pub fn set_push_nodelay(&mut self, enable: bool) {
+        self.opts.ignore_push = !enable;
+        self.opts.push &= enable;
+    }
+
+    /// Enable TCP keepalive with the given settings, or disable it
+    /// with `None`.  This will be updated on the next `process` call.
+    /// Keepalive is disabled by default.  See [`KeepaliveConfig`] for
+    /// the platform support.
+    #[inline]
+    pub fn set_keepalive(&mut self, keepalive: Option<KeepaliveConfig>) {
+        self.opts.set_keepalive(keepalive);
+    }
+
+    /// Change the IP time-to-live of packets sent on the stream,
+    /// i.e. the maximum number of hops they can take.  This will be
+    /// updated on the next `process` call.
+    #[inline]
+    pub fn set_ttl(&mut self, ttl: u32) {
+        self.opts.set_ttl(ttl);
+    }
+
+    /// Get the IP time-to-live last requested with
+    /// [`TcpLink::set_ttl`], or `None` if the OS default is in use.
+    /// This may not yet have been applied to the stream.
+    #[inline]
+    pub fn ttl(&self) -> Option<u32> {
+        self.opts.ttl
+    }
+
+    /// Change the IP type-of-service byte of packets sent on the
+    /// stream, which holds the DSCP value in its top 6 bits and ECN
+    /// in the bottom 2.  For an IPv6 stream this sets the traffic
+    /// class instead.  The address family is taken from the stream's
+    /// local address.  This will be updated on the next `process`
+    /// call.
+    ///
+    /// This uses `IP_TOS` or `IPV6_TCLASS`, and is supported on
+    /// Linux, Android, macOS, iOS, FreeBSD, NetBSD and OpenBSD.
+    /// Elsewhere, including Windows, it fails with `Unsupported`.  If
+    /// the change fails, the error is returned from that `process`
+    /// call only, and it is not attempted again unless a different
+    /// value is set.
+    #[inline]
+    pub fn set_tos(&mut self, tos: u8) {
+        self.opts.set_tos(tos);
+    }
+
+    /// Get the IP type-of-service byte last requested with
+    /// [`TcpLink::set_tos`], or `None` if the OS default is in use.
+    /// This may not yet have been applied to the stream.
+    #[inline]
+    pub fn tos(&self) -> Option<u8> {
+        self.opts.tos
+    }
+
+    /// Enable or disable `TCP_QUICKACK`, which makes the kernel send
+    /// ACKs straight away rather than delaying them.  This can cut
+    /// latency for request/response traffic.  The kernel turns
+    /// quickack off again by itself as traffic flows, so while it is
+    /// enabled it is set again on every `process` call.
+    ///
+    /// This is only supported on Linux.  Elsewhere the next `process`
+    /// call fails with `Unsupported`.  On any failure, quickack is
+    /// disabled again, so that the error is only returned once.
+    #[inline]
+    pub fn set_quickack(&mut self, quickack: bool) {
+        self.opts.set_quickack(quickack);
+    }
+
+    /// Test whether `TCP_QUICKACK` is enabled.  See
+    /// [`TcpLink::set_quickack`].
+    #[inline]
+    pub fn quickack(&self) -> bool {
+        self.opts.quickack
+    }
+
+    /// Change the size of the kernel receive buffer (`SO_RCVBUF`)
+    /// for the stream.  A larger buffer allows a larger TCP window,
+    /// which is needed for full throughput on high-bandwidth,
+    /// high-latency paths.  This will be updated on the next
+    /// `process` call.  The kernel may adjust the size, e.g. Linux
+    /// doubles it and caps it at `net.core.rmem_max`, so use
+    /// [`TcpLink::recv_buffer_size`] to see what took effect.
+    ///
+    /// This is only supported on Unix.  Elsewhere the next `process`
+    /// call fails with `Unsupported`.
+    #[inline]
+    pub fn set_recv_buffer_size(&mut self, size: usize) {
+        self.opts.buffers.set_recv(size);
+    }
+
+    /// Change the size of the kernel send buffer (`SO_SNDBUF`) for
+    /// the stream.  This will be updated on the next `process` call.
+    /// See [`TcpLink::set_recv_buffer_size`].
+    #[inline]
+    pub fn set_send_buffer_size(&mut self, size: usize) {
+        self.opts.buffers.set_send(size);
+    }
+
+    /// Query the effective size of the kernel receive buffer from
+    /// the given stream
+    #[inline]
+    pub fn recv_buffer_size(&self, stream: &TcpStream) -> io::Result<usize> {
+        stream.recv_buffer_size()
+    }
+
+    /// Query the effective size of the kernel send buffer from the
+    /// given stream
+    #[inline]
+    pub fn send_buffer_size(&self, stream: &TcpStream) -> io::Result<usize> {
+        stream.send_buffer_size()
+    }
+
+    /// Limit the amount of unsent data queued in the kernel for the
+    /// stream with `TCP_NOTSENT_LOWAT`.  The stream is only reported
+    /// as writable while less than `lowat` bytes remain unsent, so
+    /// data stays in the outgoing pipe instead of building up in the
+    /// kernel's send buffer.  Along with
+    /// [`TcpLink::needed_interest`] this keeps the memory used by
+    /// each connection bounded, without shrinking `SO_SNDBUF` and so
+    /// limiting the TCP window.  This will be updated on the next
+    /// `process` call.
+    ///
+    /// This is only supported on Linux, Android, macOS and iOS.
+    /// Elsewhere the next `process` call fails with `Unsupported`.
+    #[inline]
+    pub fn set_notsent_lowat(&mut self, lowat: u32) {
+        self.opts.set_notsent_lowat(lowat);
+    }
+
+    /// Bound how long sent data may remain unacknowledged with
+    /// `TCP_USER_TIMEOUT`, or pass `None` to restore the OS default.
+    /// If the peer vanishes mid-transfer, the kernel normally carries
+    /// on retransmitting for many minutes.  With this set, the
+    /// connection is dropped once data has gone unacknowledged for
+    /// `timeout`, which is rounded up to whole milliseconds.  The
+    /// resulting `TimedOut` error on a later write or read is handled
+    /// like a reset from the peer: the incoming pipe is aborted
+    /// instead of `process` returning an error.  This also applies
+    /// to keepalive probes, if enabled.  This will be updated on the
+    /// next `process` call.
+    ///
+    /// This is only supported on Linux.  Elsewhere the next `process`
+    /// call fails with `Unsupported`.
+    #[inline]
+    pub fn set_user_timeout(&mut self, timeout: Option<Duration>) {
+        self.opts.set_user_timeout(timeout);
+    }
+
+    /// Clamp the TCP maximum segment size with `TCP_MAXSEG`, for
+    /// example to avoid fragmentation when tunnelling over a link
+    /// with a reduced MTU, or pass `None` to remove the clamp.  This
+    /// will be updated on the next `process` call.  Once connected
+    /// this is best-effort: some platforms only take the MSS fully
+    /// into account if it is set before the connect, which
+    /// [`crate::TcpConnector::set_mss`] does.  Check the value in
+    /// effect with [`TcpLink::mss`].
+    ///
+    /// This is only supported on Unix, and removing the clamp only on
+    /// Linux.  Elsewhere the next `process` call fails with
+    /// `Unsupported` or `InvalidInput`.
+    #[inline]
+    pub fn set_mss(&mut self, mss: Option<u32>) {
+        self.opts.set_mss(mss);
+    }
+
+    /// Query the effective maximum segment size from the given
+    /// stream, as reported by the kernel
+    #[inline]
+    pub fn mss(&self, stream: &TcpStream) -> io::Result<u32> {
+        mss(stream)
+    }
+
+    /// Select how TCP urgent ("out-of-band") data from the peer is
+    /// handled.  By default urgent bytes never appear in the incoming
+    /// pipe: the kernel holds the urgent byte apart from the stream,
+    /// and when `process_event` sees the priority event that signals
+    /// it, the byte is read with `MSG_OOB` and dropped.  With `true`,
+    /// `SO_OOBINLINE` is set instead, so urgent bytes are passed into
+    /// the incoming pipe in their place in the stream, with nothing
+    /// to mark them out.  This will be updated on the next `process`
+    /// call.
+    ///
+    /// `mio` only reports priority events on Linux and Android, and
+    /// only if registered with `Interest::PRIORITY`, which
+    /// [`TcpLink::register`] and [`TcpLink::needed_interest`] include
+    /// there.  Elsewhere the urgent byte is just left with the
+    /// kernel, still apart from the stream.  Inline mode is only
+    /// supported on Unix.  Elsewhere the next `process` call fails
+    /// with `Unsupported`.
+    #[inline]
+    pub fn set_oob_inline(&mut self, inline: bool) {
+        self.opts.set_oob_inline(inline);
+    }
+
+    /// Test whether TCP urgent data is passed inline.  See
+    /// [`TcpLink::set_oob_inline`].
+    #[inline]
+    pub fn oob_inline(&self) -> bool {
+        self.opts.oob_inline
+    }
+
+    /// Test whether any socket option changes have been requested
+    /// that have not yet been applied to the stream.  They will be
+    /// applied on the next `process` call.
+    #[inline]
+    pub fn has_pending_option_changes(&self) -> bool {
+        self.opts.has_pending()
+    }
+
+    /// Get a snapshot of the kernel's statistics for the given
+    /// stream, such as round-trip time, retransmits and congestion
+    /// window.  This may be called at any time.  See [`TcpInfo`].
+    ///
+    /// This is only supported on Linux.  Elsewhere it fails with
+    /// `Unsupported`.
+    #[inline]
+    pub fn tcp_info(&self, stream: &TcpStream) -> io::Result<TcpInfo> {
+        crate::tcpinfo::tcp_info(stream)
+    }
+
+    /// Enable or disable `TCP_CORK`.  While corked, the kernel holds
+    /// back partial segments, so that data written in several pieces
+    /// goes out in as few full-sized segments as possible.  Uncorking
+    /// sends anything held back.  This will be updated on the next
+    /// `process` call.
+    ///
+    /// This is only supported on Linux.  Elsewhere the next `process`
+    /// call fails with `Unsupported`.
+    #[inline]
+    pub fn set_cork(&mut self, cork: bool) {
+        self.opts.set_cork(cork);
+    }
+
+    /// Enable or disable automatic corking.  When enabled, the
+    /// stream is corked before data is written out, and uncorked
+    /// once the outgoing pipe has been drained or when a "push" is
+    /// seen on the pipe.  This has no effect while the stream is
+    /// corked with [`TcpLink::set_cork`].
+    ///
+    /// This is only supported on Linux.  Elsewhere the next `process`
+    /// call with data to write fails with `Unsupported`.  On any
+    /// failure, automatic corking is disabled again, so that the
+    /// error is only returned once.
+    #[inline]
+    pub fn set_auto_cork(&mut self, auto_cork: bool) {
+        self.opts.auto_cork = auto_cork;
+    }
+
+    /// Select how an outgoing "abort" is passed on to the TCP
+    /// stream.  See [`AbortMode`].  The default is
+    /// `AbortMode::ShutdownBoth`.
+    #[inline]
+    pub fn set_abort_mode(&mut self, mode: AbortMode) {
+        self.link.abort_mode = mode;
+    }
+
+    /// Shut down the outgoing half of the stream once the outgoing
+    /// pipe has been written out, as if EOF had been pushed to the
+    /// pipe, without needing the cooperation of whatever is writing
+    /// to it.  This takes effect on the next `process` call with
+    /// writes unpaused and the pipe empty, which returns
+    /// [`Activity::EOF_OUT`], and [`TcpLink::state`] then moves on
+    /// as for EOF.  Any data put in the pipe after that is discarded.
+    #[inline]
+    pub fn close_when_flushed(&mut self) {
+        self.link.close_when_flushed = true;
+    }
+
+    /// Abort the link at once, as for an outgoing "abort" passed on
+    /// according to **abort_mode** (see [`AbortMode`]), without
+    /// waiting for the outgoing pipe: the incoming pipe is aborted,
+    /// and the stream is shut down or reset.  Returns the activity
+    /// flags, which include `ABORTED`, or are empty if the link was
+    /// already aborted or closed early.  Any data in the outgoing
+    /// pipe, then or later, is discarded.
+    pub fn abort_now(&mut self, stream: &mut TcpStream, pbuf: PBufRdWr) -> Activity {
+        self.link.abort_now(stream, pbuf)
+    }
+
+    /// Select the order in which `process` and `process_event`
+    /// handle the two directions.  See [`ProcessOrder`].  The
+    /// default is `ProcessOrder::WriteThenRead`.
+    #[inline]
+    pub fn set_process_order(&mut self, order: ProcessOrder) {
+        self.link.order = order.normalize();
+    }
+
+    /// Pause or unpause writes.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_writes(&mut self, pause: bool) {
+        self.link.set_pause_writes(pause);
+    }
+
+    /// Pause or unpause reads.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_reads(&mut self, pause: bool) {
+        self.link.set_pause_reads(pause);
+    }
+
+    /// Test whether writes are paused.  Writes are unpaused on a
+    /// writable event or with `set_pause_writes(false)`.
+    #[inline]
+    pub fn pause_writes(&self) -> bool {
+        self.link.pause_writes
+    }
+
+    /// Test whether reads are paused.  Reads are unpaused on a
+    /// readable event or with `set_pause_reads(false)`.
+    #[inline]
+    pub fn pause_reads(&self) -> bool {
+        self.link.pause_reads
+    }
+
+    /// Enable or disable the `WouldBlock` latch.  When enabled, once
+    /// a read or write gives `WouldBlock`, later `process` calls skip
+    /// that direction until fresh readiness is signalled, saving the
+    /// syscall that would just give `WouldBlock` again.  Readiness is
+    /// signalled by an event passed to
+    /// [`TcpLink::process_event`], by
+    /// [`TcpLink::note_readable`] or [`TcpLink::note_writable`], or
+    /// by unpausing with `set_pause_reads(false)` or
+    /// `set_pause_writes(false)`.  So only enable this if readiness is
+    /// always passed on.  This is disabled by default.
+    #[inline]
+    pub fn set_wouldblock_latch(&mut self, enable: bool) {
+        self.link.latch = enable;
+    }
+
+    /// Signal that the stream is readable, unpausing reads and
+    /// clearing the `WouldBlock` latch for reads.  See
+    /// [`TcpLink::set_wouldblock_latch`].
+    #[inline]
+    pub fn note_readable(&mut self) {
+        self.link.note_readable();
+    }
+
+    /// Signal that the stream is writable, unpausing writes and
+    /// clearing the `WouldBlock` latch for writes.  See
+    /// [`TcpLink::set_wouldblock_latch`].
+    #[inline]
+    pub fn note_writable(&mut self) {
+        self.link.note_writable();
+    }
+
+    /// Enable or disable auto-start.  When enabled, the link checks
+    /// whether the stream has connected (see
+    /// [`TcpLink::check_connected`]) on each `process` call until it
+    /// has, and then unpauses both reads and writes itself, so there
+    /// is no need to manage the pause flags.  Until then the calls do
+    /// nothing, and a failed connect is returned as an error.  After
+    /// that the pause flags are left alone, so they may still be set
+    /// explicitly.  This is disabled by default, leaving the pause
+    /// flags to the caller.
+    #[inline]
+    pub fn set_auto_start(&mut self, auto_start: bool) {
+        self.auto_start = auto_start;
+    }
+
+    /// Set the label attached to the `tracing` events from this link,
+    /// e.g. a peer address or connection ID, so that the events of
+    /// different connections can be told apart.  Events are emitted
+    /// at trace level for data moved and `WouldBlock`, and at debug
+    /// level for EOF, shutdown, abort, option changes and errors.
+    /// Without the `tracing` feature this does nothing.
+    #[inline]
+    pub fn set_label(&mut self, label: impl Into<String>) {
+        self.link.tracer.set_label(label.into());
+    }
+
+    /// Install an observer to be told about the traffic on the link,
+    /// or remove it with `None`.  See [`LinkObserver`].
+    #[inline]
+    pub fn set_observer(&mut self, observer: Option<Box<dyn LinkObserver>>) {
+        self.link.observer = observer;
+    }
+
+    /// Get the position of the link in its shutdown sequence.  See
+    /// [`LinkState`].
+    #[inline]
+    pub fn state(&self) -> LinkState {
+        self.link.state()
+    }
+
+    /// Test whether the link is finished, so that the stream can be
+    /// deregistered and dropped.  This is true if a `process` call
+    /// has returned a fatal error (see [`LinkState::Failed`]), or
+    /// else if both of these hold:
+    ///
+    /// - The incoming pipe has been closed or aborted, i.e. EOF or an
+    ///   abort from the peer has been passed on, or the link aborted
+    ///   it, e.g. on a timeout
+    ///
+    /// - EOF from the outgoing pipe has been passed on to the stream
+    ///   with a completed shutdown, so not while it is still
+    ///   `ShutdownPending`, or an abort has been passed on in either
+    ///   direction
+    ///
+    /// Data may still be waiting in the incoming pipe for the
+    /// application to consume.
+    #[inline]
+    pub fn is_done(&self, pbuf: &PBufRdWr) -> bool {
+        self.link.is_done(pbuf)
+    }
+
+    // Abort the incoming pipe and shut down or reset the stream, for
+    // a stream that is about to be dropped before the link is done
+    pub(crate) fn teardown(&mut self, stream: &mut TcpStream, pwr: &mut PBufWr) {
+        let mut act = Activity::NONE;
+        self.link.abandon(stream, Some(pwr), &mut act);
+    }
+
+    /// Get the last error seen on a read from the stream, if any.
+    /// This includes errors that did not cause `process` to fail,
+    /// such as `ConnectionReset`, which is passed on as an abort, so
+    /// that the reason for a connection going away can be logged.
+    /// Only the kind and OS error code are kept.
+    #[inline]
+    pub fn last_read_error(&self) -> Option<&io::Error> {
+        self.link.last_read_error.as_ref()
+    }
+
+    /// Get the last error seen on a write or shutdown of the stream,
+    /// if any, likewise including errors that were handled as an
+    /// abort or ignored, e.g. `BrokenPipe`.  Only the kind and OS
+    /// error code are kept.
+    #[inline]
+    pub fn last_write_error(&self) -> Option<&io::Error> {
+        self.link.last_write_error.as_ref()
+    }
+
+    /// Get the address of the peer, or `None` if it is not yet
+    /// known.  This is captured once the stream has connected, when
+    /// the connect is checked (see [`TcpLink::check_connected`]) or
+    /// on the first `process` call with reads or writes unpaused, and
+    /// is kept after that, so it is still available after the stream
+    /// has been reset or closed, e.g. for logging the error.
+    #[inline]
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// Get the local address of the stream, or `None` if it is not
+    /// yet known.  This is captured and kept in the same way as
+    /// [`TcpLink::peer_addr`].
+    #[inline]
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// Get the total number of bytes read from the stream since the
+    /// link was created, or since the last
+    /// [`TcpLink::take_counters`] call.  This includes data read by
+    /// calls that went on to return `WouldBlock` or an error.
+    #[inline]
+    pub fn bytes_in(&self) -> u64 {
+        self.link.bytes_in
+    }
+
+    /// Get the total number of bytes written to the stream since the
+    /// link was created, or since the last
+    /// [`TcpLink::take_counters`] call
+    #[inline]
+    pub fn bytes_out(&self) -> u64 {
+        self.link.bytes_out
+    }
+
+    /// Get the byte counters as `(bytes_in, bytes_out)` and reset
+    /// them to zero, for periodic scraping
+    #[inline]
+    pub fn take_counters(&mut self) -> (u64, u64) {
+        self.link.take_counters()
+    }
+
+    /// Get the time of the last read or write on the stream that
+    /// transferred data, or `None` if there has been none yet
+    #[inline]
+    pub fn last_activity(&self) -> Option<Instant> {
+        self.link.last_activity()
+    }
+
+    /// Get the time elapsed since the last read or write on the
+    /// stream that transferred data, or `None` if there has been
+    /// none yet.  This can be used to detect idle connections.
+    #[inline]
+    pub fn idle_for(&self) -> Option<Duration> {
+        self.link.idle_for()
+    }
+
+    /// Abort the link if it sees no traffic for the given time, or
+    /// disable this with `None`, which is the default.  Once the
+    /// timeout has passed without data being read or written, the
+    /// next `process` or `process_event` call aborts the incoming
+    /// pipe and passes on an outgoing abort according to the
+    /// **abort_mode**, returning [`Activity::TIMED_OUT`].  After that
+    /// the link does nothing more.  Call `process` once the time
+    /// returned by [`TcpLink::next_deadline`] has passed, so that
+    /// the timeout is noticed.
+    ///
+    /// The timer restarts on every read or write that transfers data,
+    /// and when the timeout is set.  It is held while both reads and
+    /// writes are paused, and while reads are held off because the
+    /// incoming pipe is not being consumed, since the lack of traffic
+    /// is then due to the local side.
+    #[inline]
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.link.set_idle_timeout(timeout);
+    }
+
+    /// Abort the link if the peer has not closed its side within the
+    /// given time after EOF was passed on to the stream, or disable
+    /// this with `None`, which is the default.  This stops a peer
+    /// from holding the connection open in the half-closed state
+    /// forever.  The timer starts when the outgoing EOF is consumed
+    /// and the write half is shut down, and stops when EOF arrives
+    /// from the peer, so it never fires if the peer closes first.  On
+    /// expiry, the next `process` or `process_event` call aborts the
+    /// incoming pipe and shuts down the stream fully, or resets it
+    /// according to the **abort_mode**, returning
+    /// [`Activity::TIMED_OUT`].  After that the link does nothing
+    /// more.  Call `process` once the time returned by
+    /// [`TcpLink::next_deadline`] has passed, so that the expiry is
+    /// noticed.
+    #[inline]
+    pub fn set_close_deadline(&mut self, deadline: Option<Duration>) {
+        self.link.set_close_deadline(deadline);
+    }
+
+    /// Get the earliest time at which `process` needs to be called
+    /// even without an event: when the idle timeout or the close
+    /// deadline will expire, or when reads or writes held off by a
+    /// rate limit can carry on.  Returns `None` if none of these
+    /// applies.  See [`TcpLink::set_idle_timeout`],
+    /// [`TcpLink::set_close_deadline`],
+    /// [`TcpLink::set_read_rate_limit`] and
+    /// [`TcpLink::set_write_rate_limit`].
+    #[inline]
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.link.next_deadline()
+    }
+
+    /// Replace the clock used to timestamp activity, for testing
+    /// timeouts without waiting.  The default is [`Instant::now`].
+    ///
+    /// This is only available with the `test-util` feature.
+    #[cfg(any(test, feature = "test-util"))]
+    #[inline]
+    pub fn set_clock(&mut self, clock: fn() -> Instant) {
+        self.link.set_clock(clock);
+    }
+
+    /// Limit the rate at which data is read from the stream, or
+    /// remove the limit with `None`, which is the default.  See
+    /// [`RateLimit`].  Reads are cut short once the allowance runs
+    /// out, and READABLE is left out of
+    /// [`TcpLink::needed_interest`] until more allowance has built
+    /// up.  Call `process` once the time returned by
+    /// [`TcpLink::next_deadline`] has passed to carry on reading,
+    /// since the stream won't signal readiness again for data that
+    /// is already waiting.  Setting a limit starts with a full
+    /// bucket.
+    #[inline]
+    pub fn set_read_rate_limit(&mut self, limit: Option<RateLimit>) {
+        self.link.set_read_rate_limit(limit);
+    }
+
+    /// Limit the rate at which data is written to the stream, or
+    /// remove the limit with `None`, which is the default.  See
+    /// [`RateLimit`].  Writes are cut short once the allowance runs
+    /// out, leaving the rest of the data in the outgoing pipe, and
+    /// WRITABLE is left out of [`TcpLink::needed_interest`] until
+    /// more allowance has built up.  Call `process` once the time
+    /// returned by [`TcpLink::next_deadline`] has passed to carry on
+    /// writing.  EOF is still passed on as soon as the pipe has been
+    /// emptied.  Setting a limit starts with a full bucket.
+    #[inline]
+    pub fn set_write_rate_limit(&mut self, limit: Option<RateLimit>) {
+        self.link.set_write_rate_limit(limit);
+    }
+
+    /// Limit the total number of bytes that may be read from the
+    /// stream from now on, or remove the limit with `None`, which is
+    /// the default.  Exactly this many bytes are passed into the
+    /// incoming pipe, with the last read cut short if necessary.  As
+    /// soon as they have all been read, reading stops and the pipe
+    /// is ended as selected with [`TcpLink::set_read_quota_mode`],
+    /// and the activity includes [`Activity::QUOTA`], so that the
+    /// application can tell this apart from EOF from the peer and
+    /// respond with a suitable error.  A quota of zero ends the pipe
+    /// on the next `process` call with reads unpaused.
+    #[inline]
+    pub fn set_read_quota(&mut self, quota: Option<u64>) {
+        self.link.read_quota = quota;
+    }
+
+    /// Get the number of bytes that may still be read under the read
+    /// quota, or `None` if there is no quota.  See
+    /// [`TcpLink::set_read_quota`].
+    #[inline]
+    pub fn read_quota(&self) -> Option<u64> {
+        self.link.read_quota
+    }
+
+    /// Select how the incoming pipe is ended when the read quota runs
+    /// out.  See [`ReadQuotaMode`].  The default is
+    /// `ReadQuotaMode::Close`.
+    #[inline]
+    pub fn set_read_quota_mode(&mut self, mode: ReadQuotaMode) {
+        self.link.read_quota_mode = mode;
+    }
+
+    /// Enable or disable shutting down the read half of the stream
+    /// when the read quota runs out, so that the peer sees that
+    /// nothing more will be read.  A failed shutdown is not fatal,
+    /// since the incoming pipe has already been ended, but its error
+    /// is kept as [`TcpLink::last_read_error`].  This is disabled by
+    /// default.
+    #[inline]
+    pub fn set_read_quota_shutdown(&mut self, shutdown: bool) {
+        self.link.read_quota_shutdown = shutdown;
+    }
+
+    /// Limit the total number of bytes that may be written to the
+    /// stream from now on, or remove the limit with `None`, which is
+    /// the default.  Writes are cut short so that exactly this many
+    /// bytes go out.  If the quota runs out with data still waiting
+    /// in the outgoing pipe, the link acts as selected with
+    /// [`TcpLink::set_write_quota_mode`], and the activity includes
+    /// [`Activity::QUOTA`], once.  If the pipe has been emptied, EOF
+    /// is still passed on as normal.
+    #[inline]
+    pub fn set_write_quota(&mut self, quota: Option<u64>) {
+        self.link.set_write_quota(quota);
+    }
+
+    /// Get the number of bytes that may still be written under the
+    /// write quota, or `None` if there is no quota.  See
+    /// [`TcpLink::set_write_quota`].
+    #[inline]
+    pub fn write_quota(&self) -> Option<u64> {
+        self.link.write_quota()
+    }
+
+    /// Select what happens when the write quota runs out.  See
+    /// [`WriteQuotaMode`].  The default is `WriteQuotaMode::Stall`,
+    /// which leaves WRITABLE out of [`TcpLink::needed_interest`].
+    #[inline]
+    pub fn set_write_quota_mode(&mut self, mode: WriteQuotaMode) {
+        self.link.write_quota_mode = mode;
+    }
+
+    /// Enable or disable collection of syscall statistics.  See
+    /// [`LinkStats`].  This is disabled by default to avoid the
+    /// overhead.  Enabling starts from zero counts, and disabling
+    /// discards the counts.
+    #[inline]
+    pub fn enable_stats(&mut self, enable: bool) {
+        self.link.enable_stats(enable);
+    }
+
+    /// Get the syscall statistics, or `None` if not enabled
+    #[inline]
+    pub fn stats(&self) -> Option<&LinkStats> {
+        self.link.stats.as_ref()
+    }
+
+    /// Reset the syscall statistics to zero, if enabled
+    #[inline]
+    pub fn reset_stats(&mut self) {
+        if let Some(ref mut stats) = self.link.stats {
+            *stats = LinkStats::default();
+        }
+    }
+
+    /// Install a tee to receive a copy of all data read from the
+    /// stream, or remove it with `None`.  See [`Tee`].
+    #[inline]
+    pub fn set_tee(&mut self, tee: Option<Tee>) {
+        self.link.tee = tee;
+    }
+
+    /// Get access to the tee, if installed, for example to consume
+    /// data from its pipe
+    #[inline]
+    pub fn tee(&mut self) -> Option<&mut Tee> {
+        self.link.tee.as_mut()
+    }
+
+    /// Register the stream with the given `Registry` for both
+    /// readable and writable events, and remember the token for
+    /// later calls to [`TcpLink::reregister`]
+    pub fn register(
+        &mut self,
+        stream: &mut TcpStream,
+        registry: &Registry,
+        token: Token,
+    ) -> Result<()> {
+        let interest = Interest::READABLE | Interest::WRITABLE;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let interest = interest | Interest::PRIORITY;
+        registry
+            .register(stream, token, interest)
+            .map_err(Error::register)?;
+        self.token = Some(token);
+        Ok(())
+    }
+
+    /// Change the interest of the registered stream, using the token
+    /// given to [`TcpLink::register`].  Fails with `InvalidInput` if
+    /// the stream has not been registered.
+    pub fn reregister(
+        &mut self,
+        stream: &mut TcpStream,
+        registry: &Registry,
+        interest: Interest,
+    ) -> Result<()> {
+        let Some(token) = self.token else {
+            return Err(Error::register(io::Error::new(
+                ErrorKind::InvalidInput,
+                "TcpLink stream is not registered",
+            )));
+        };
+        registry
+            .reregister(stream, token, interest)
+            .map_err(Error::register)
+    }
+
+    /// Deregister the stream from the given `Registry`, and forget
+    /// the token
+    pub fn deregister(&mut self, stream: &mut TcpStream, registry: &Registry) -> Result<()> {
+        registry.deregister(stream).map_err(Error::register)?;
+        self.token = None;
+        Ok(())
+    }
+
+    /// Get the token that the stream is registered with, if any
+    #[inline]
+    pub fn token(&self) -> Option<Token> {
+        self.token
+    }
+
+    /// Get the minimal interest needed for the current state of the
+    /// link and the given pipe-buffer, for reregistering with
+    /// level-triggered polling after each `process` call.  READABLE
+    /// is included only if reads are not paused and the incoming pipe
+    /// is not closed and has room, i.e. holds no more than
+    /// **max_read_unit** bytes of unconsumed data.  WRITABLE is
+    /// included only if the outgoing pipe has data or a pending EOF
+    /// to pass on.  Returns `None` if no events are needed, which
+    /// includes the case where the link has finished in both
+    /// directions.
+    #[inline]
+    pub fn needed_interest(&self, pbuf: &PBufRdWr) -> Option<Interest> {
+        let interest = self.link.needed_interest(pbuf)?;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if interest.is_readable() && !self.opts.oob_inline {
+            return Some(interest.add(Interest::PRIORITY));
+        }
+        Some(interest)
+    }
+
+    /// Read and write as much data as possible to and from the given
+    /// TCP stream.  Returns the activity flags (see [`Activity`]),
+    /// which are empty if no progress could be made, or `Err(_)` if
+    /// there was a fatal error on the stream.  After a fatal error,
+    /// this and the other `process` calls return no activity without
+    /// touching the stream again, and [`TcpLink::state`] reports
+    /// [`LinkState::Failed`].  A failure to apply a socket option
+    /// ([`Op::SetOption`]) is returned from one call but is not
+    /// fatal, and later calls carry on as normal.
+    ///
+    /// Assumes that it is always called with the same TcpStream and
+    /// pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process(&mut self, stream: &mut TcpStream, pbuf: PBufRdWr) -> Result<Activity> {
+        self.process_stream(stream, pbuf)
+    }
+
+    // The `process` call for any supported stream type, which lets
+    // `StdTcpLink` and `MockStreamLink` share this code
+    pub(crate) fn process_stream<S: TcpSocket>(
+        &mut self,
+        stream: &mut S,
+        pbuf: PBufRdWr,
+    ) -> Result<Activity> {
+        let rv = self.process_aux(stream, pbuf);
+        self.link.fail(rv)
+    }
+
+    /// Check whether a non-blocking connect on the stream has
+    /// completed, using the procedure recommended by `mio`:
+    /// `take_error` to pick up a failed connect, and then `peer_addr`
+    /// to tell a completed connect from a spurious wake-up.  Returns
+    /// `Ok(true)` if connected, `Ok(false)` if the connect is still in
+    /// progress, or the real error of a failed connect, such as
+    /// `ConnectionRefused`, with [`Op::Connect`].
+    ///
+    /// When driving the link with `process`, call this on the first
+    /// writable event, before unpausing writes.  Otherwise a failed
+    /// connect only shows up as a confusing error from the first
+    /// write, or not at all if there is nothing to write yet.
+    /// [`TcpLink::process_event`] does this automatically.  Once
+    /// `Ok(true)` has been returned, the link remembers it.
+    pub fn check_connected(&mut self, stream: &TcpStream) -> Result<bool> {
+        self.check_connected_aux(stream)
+    }
+
+    /// Enable or disable peek mode.  While enabled, no data is read
+    /// into the incoming pipe, so that the caller can look at the
+    /// first bytes from the peer with [`TcpLink::peek`] without
+    /// consuming them, e.g. to tell TLS from plaintext before
+    /// deciding how to handle the stream.  Writes are not affected.
+    /// Call [`TcpLink::commit`] to end peek mode and let reads
+    /// proceed.  This is disabled by default.
+    #[inline]
+    pub fn set_peek_mode(&mut self, enable: bool) {
+        self.link.hold_reads = enable;
+    }
+
+    /// End peek mode, so that reads into the incoming pipe proceed on
+    /// the next `process` call, starting with the bytes already
+    /// peeked.  See [`TcpLink::set_peek_mode`].
+    #[inline]
+    pub fn commit(&mut self) {
+        self.link.hold_reads = false;
+    }
+
+    /// Copy the data waiting on the stream into `buf`, without
+    /// consuming it, using `recv` with `MSG_PEEK`.  Returns the
+    /// number of bytes copied, which may be fewer than are needed if
+    /// not all have arrived yet.  `Ok(0)` with a non-empty `buf`
+    /// means that the peer has closed the stream.  If no data has
+    /// arrived yet, this fails with `WouldBlock`, so wait for the
+    /// next readable event and try again.  Other errors are reported
+    /// with [`Op::Read`].
+    ///
+    /// This may be called at any time, but is normally used in peek
+    /// mode (see [`TcpLink::set_peek_mode`]), since otherwise
+    /// `process` may already have read the data into the pipe.
+    pub fn peek(&mut self, stream: &mut TcpStream, buf: &mut [u8]) -> Result<usize> {
+        retry!(stream.peek(buf)).map_err(Error::read)
+    }
+
+    fn check_connected_aux(&mut self, stream: &impl TcpSocket) -> Result<bool> {
+        let connected = check_connect(Some(stream), |s| s.take_error(), |s| s.peer_addr())
+            .map_err(Error::connect)?;
+        self.connected |= connected;
+        if connected {
+            self.note_addrs(stream);
+        }
+        Ok(connected)
+    }
+
+    // Capture whichever addresses are not yet known.  Failures are
+    // ignored, since they are tried again on the next call.
+    fn note_addrs(&mut self, stream: &impl TcpSocket) {
+        if self.peer_addr.is_none() {
+            self.peer_addr = stream.peer_addr().ok();
+        }
+        if self.local_addr.is_none() {
+            self.local_addr = stream.local_addr().ok();
+        }
+    }
+
+    // Drop any pending urgent byte, unless it is passed inline
+    fn discard_oob(&mut self, stream: &impl TcpSocket) -> Result<()> {
+        if self.opts.oob_inline {
+            return Ok(());
+        }
+        let discarded = retry!(stream.discard_oob())
+            .map_err(|e| Error::new(Direction::Incoming, Op::Read, e))
+            .inspect_err(|e| self.link.tracer.error(e))?;
+        if discarded {
+            self.link.tracer.oob_discarded();
+        }
+        Ok(())
+    }
+
+    // Check the connect if it is not yet known to have completed,
+    // and with auto-start, unpause both directions once it has.
+    // Returns `false` if the connect is still in progress.
+    fn start(&mut self, stream: &impl TcpSocket) -> Result<bool> {
+        if !self.connected {
+            if !self.check_connected_aux(stream)? {
+                return Ok(false);
+            }
+            if self.auto_start {
+                self.link.pause_reads = false;
+                self.link.pause_writes = false;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Handle a `mio` event for the TCP stream: unpause reads if it
+    /// is readable or read-closed, unpause writes if it is writable
+    /// or write-closed, and then read and write as much data as
+    /// possible as for [`TcpLink::process`].  The read is skipped if
+    /// the event only reports that the stream is writable, since it
+    /// would just give `WouldBlock`.
+    ///
+    /// With auto-start enabled (see [`TcpLink::set_auto_start`]), the
+    /// connect is checked first with [`TcpLink::check_connected`], as
+    /// for the other `process` calls.  If it failed, its error is
+    /// returned.  If it is still in progress, the event is ignored.
+    /// Otherwise the connect is not checked here, so a stream reset
+    /// before its first event gives an abort of the incoming pipe.
+    ///
+    /// Assumes that it is always called with the same TcpStream and
+    /// pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process_event(
+        &mut self,
+        stream: &mut TcpStream,
+        pbuf: PBufRdWr,
+        event: &Event,
+    ) -> Result<Activity> {
+        let rv = self.process_event_aux(stream, pbuf, event);
+        self.link.fail(rv)
+    }
+
+    fn process_event_aux(
+        &mut self,
+        stream: &mut TcpStream,
+        mut pbuf: PBufRdWr,
+        event: &Event,
+    ) -> Result<Activity> {
+        if !self.ready(stream)? {
+            return Ok(Activity::NONE);
+        }
+        if event.is_priority() {
+            self.discard_oob(stream)?;
+        }
+        let read = self.link.apply_event(event);
+        if let Some(act) = self.link.check_idle(stream, pbuf.reborrow()) {
+            return Ok(act);
+        }
+        self.prepare(stream)?;
+        let act = self
+            .link
+            .process_both(stream, pbuf.reborrow(), read, &mut self.opts)?;
+        self.link.update_idle(&pbuf);
+        Ok(act)
+    }
+
+    /// Write as much data as possible out to the given TCP stream.
+    /// Returns the activity flags (see [`Activity`]), which are empty
+    /// if no progress could be made, or `Err(_)` if there was a fatal
+    /// error on the stream.
+    ///
+    /// Assumes that it is always called with the same TcpStream and
+    /// pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process_out(&mut self, stream: &mut TcpStream, pbuf: PBufRdWr) -> Result<Activity> {
+        self.process_out_stream(stream, pbuf)
+    }
+
+    pub(crate) fn process_out_stream<S: TcpSocket>(
+        &mut self,
+        stream: &mut S,
+        pbuf: PBufRdWr,
+    ) -> Result<Activity> {
+        let rv = self.process_out_aux(stream, pbuf);
+        self.link.fail(rv)
+    }
+
+    /// Read as much data as possible from to the given TCP stream, up
+    /// to **max_read_unit** bytes.  Returns the activity flags (see
+    /// [`Activity`]), which are empty if no progress could be made,
+    /// or `Err(_)` if there was a fatal error on the stream.
+    ///
+    /// Assumes that it is always called with the same TcpStream and
+    /// pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process_in(&mut self, stream: &mut TcpStream, pbuf: PBufRdWr) -> Result<Activity> {
+        self.process_in_stream(stream, pbuf)
+    }
+
+    pub(crate) fn process_in_stream<S: TcpSocket>(
+        &mut self,
+        stream: &mut S,
+        pbuf: PBufRdWr,
+    ) -> Result<Activity> {
+        let rv = self.process_in_aux(stream, pbuf);
+        self.link.fail(rv)
+    }
+
+    // Test whether a `process` call may go ahead: the link has not
+    // halted, and with auto-start, the stream has connected
+    fn ready(&mut self, stream: &impl TcpSocket) -> Result<bool> {
+        Ok(!self.link.halted() && (!self.auto_start || self.start(stream)?))
+    }
+
+    // Apply any pending options, which don't depend on the data flow
+    // so are applied even while paused, and capture the addresses
+    // once data may flow
+    fn prepare(&mut self, stream: &impl TcpSocket) -> Result<()> {
+        self.opts.apply(stream, &self.link.tracer)?;
+        if self.peer_addr.is_none() && !(self.link.pause_reads && self.link.pause_writes) {
+            self.note_addrs(stream);
+        }
+        Ok(())
+    }
+
+    fn process_aux<S: TcpSocket>(&mut self, stream: &mut S, pbuf: PBufRdWr) -> Result<Activity> {
+        if !self.ready(stream)? {
+            return Ok(Activity::NONE);
+        }
+        self.prepare(stream)?;
+        self.link.process_with(stream, pbuf, &mut self.opts)
+    }
+
+    fn process_out_aux<S: TcpSocket>(
+        &mut self,
+        stream: &mut S,
+        pbuf: PBufRdWr,
+    ) -> Result<Activity> {
+        if !self.ready(stream)? {
+            return Ok(Activity::NONE);
+        }
+        self.prepare(stream)?;
+        self.link
+            .process_out_max(stream, pbuf, usize::MAX, &mut self.opts)
+    }
+
+    fn process_in_aux(&mut self, stream: &mut impl TcpSocket, pbuf: PBufRdWr) -> Result<Activity> {
+        if !self.ready(stream)? {
+            return Ok(Activity::NONE);
+        }
+        self.prepare(stream)?;
+        self.link.process_in(stream, pbuf)
+    }
+
+    /// Split the component into independent read and write halves,
+    /// keeping the current settings, pause states and progress.  See
+    /// [`TcpReadLink`] and [`TcpWriteLink`].
+    ///
+    /// The read half keeps the read settings, including the read
+    /// quota and rate limit, backpressure and the adaptive read unit,
+    /// along with the tee, the observer and the incoming byte count.
+    /// The write half takes the socket options and the write
+    /// settings, including the abort mode, the write quota and rate
+    /// limit, **max_write_unit** and `close_when_flushed`, along with
+    /// the outgoing byte count.  Each half carries on with its own
+    /// copy of the syscall statistics.  The idle timeout and the
+    /// close deadline need both pipes, so they are dropped, as are
+    /// the observer's write events.
+    pub fn split(mut self) -> (TcpReadLink, TcpWriteLink) {
+        let wr = TcpWriteLink {
+            link: self.link.split_writes(),
+            opts: self.opts,
+        };
+        let rd = TcpReadLink { link: self.link };
+        (rd, wr)
+    }
+}
+
+impl Default for TcpLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for TcpLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("TcpLink");
+        self.link.debug_fields(&mut d);
+        d.field("opts", &self.opts)
+            .field("token", &self.token)
+            .field("connected", &self.connected)
+            .field("auto_start", &self.auto_start)
+            .field("peer_addr", &self.peer_addr)
+            .field("local_addr", &self.local_addr)
+            .finish()
+    }
+}
+
+/// Read half of a [`TcpLink`], created with [`TcpLink::split`]
+///
+/// This reads from a shared `&TcpStream` into the incoming pipe, with
+/// its own settings and pause flag, so that the inbound path can be
+/// owned separately from the outbound path.  Close and abort from the
+/// peer are passed on as for [`TcpLink`].
+pub struct TcpReadLink {
+    // Read settings and state carried over from the `TcpLink`
+    link: GenericLink,
+}
+
+impl TcpReadLink {
+    /// Install a tee to receive a copy of all data read from the
+    /// stream, or remove it with `None`.  See [`Tee`].
+    #[inline]
+    pub fn set_tee(&mut self, tee: Option<Tee>) {
+        self.link.tee = tee;
+    }
+
+    /// Get access to the tee, if installed
+    #[inline]
+    pub fn tee(&mut self) -> Option<&mut Tee> {
+        self.link.tee.as_mut()
+    }
+
+    /// Change the maximum number of bytes to read in each `process`
+    /// call.  See [`TcpLink::set_max_read_unit`].
+    #[inline]
+    pub fn set_max_read_unit(&mut self, max_read_unit: usize) {
+        self.link.set_max_read_unit(max_read_unit);
+    }
+
+    /// Change the maximum number of bytes to read in each `process`
+    /// call, in units of **max_read_unit**.  See
+    /// [`TcpLink::set_max_read_total`].
+    #[inline]
+    pub fn set_max_read_total(&mut self, max_read_total: usize) {
+        self.link.max_read_total = Some(max_read_total);
+    }
+
+    /// Skip reading when the incoming pipe has too little free
+    /// space.  See [`TcpLink::set_read_low_water`].
+    #[inline]
+    pub fn set_read_low_water(&mut self, low_water: usize) {
+        self.link.read_low_water = low_water;
+    }
+
+    /// Pause or unpause reads.  This takes effect on the next
+    /// `process` call.  Unpausing counts as fresh readiness for the
+    /// `WouldBlock` latch.
+    #[inline]
+    pub fn set_pause_reads(&mut self, pause: bool) {
+        self.link.set_pause_reads(pause);
+    }
+
+    /// Signal that the stream is readable, unpausing reads and
+    /// clearing the `WouldBlock` latch for reads.  See
+    /// [`TcpLink::set_wouldblock_latch`].
+    #[inline]
+    pub fn note_readable(&mut self) {
+        self.link.note_readable();
+    }
+
+    /// Get the number of bytes that may still be read under the read
+    /// quota, or `None` if there is no quota.  See
+    /// [`TcpLink::set_read_quota`].
+    #[inline]
+    pub fn read_quota(&self) -> Option<u64> {
+        self.link.read_quota
+    }
+
+    /// Get the total number of bytes read from the stream.  See
+    /// [`TcpLink::bytes_in`].
+    #[inline]
+    pub fn bytes_in(&self) -> u64 {
+        self.link.bytes_in
+    }
+
+    /// Get the syscall statistics, or `None` if not enabled
+    #[inline]
+    pub fn stats(&self) -> Option<&LinkStats> {
+        self.link.stats.as_ref()
+    }
+
+    /// Get the last error seen on a read from the stream, if any.
+    /// See [`TcpLink::last_read_error`].
+    #[inline]
+    pub fn last_read_error(&self) -> Option<&io::Error> {
+        self.link.last_read_error.as_ref()
+    }
+
+    /// Read as much data as possible from the given TCP stream, up to
+    /// **max_read_total** bytes.  Returns the activity flags (see
+    /// [`Activity`]), which are empty if no progress could be made,
+    /// or `Err(_)` if there was a fatal error on the stream.
+    ///
+    /// Assumes that it is always called with the same TcpStream and
+    /// pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process(&mut self, mut stream: &TcpStream, pwr: PBufWr) -> Result<Activity> {
+        let rv = self.link.process_in_max(&mut stream, pwr, usize::MAX);
+        self.link.fail(rv)
+    }
+}
+
+impl fmt::Debug for TcpReadLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("TcpReadLink");
+        self.link.debug_fields(&mut d);
+        d.finish()
+    }
+}
+
+/// Write half of a [`TcpLink`], created with [`TcpLink::split`]
+///
+/// This writes from the outgoing pipe to a shared `&TcpStream`, with
+/// its own socket options, settings and pause flag.  EOF and abort
+/// are handled as for [`TcpLink`], following its abort mode.
+///
+/// On an outgoing "abort", [`TcpLink`] also aborts the incoming pipe,
+/// and likewise when a write finds that the connection has been
+/// reset, or when the write quota runs out in
+/// [`WriteQuotaMode::Abort`].  Since the write half has no access to
+/// the incoming pipe, instead [`TcpWriteLink::take_abort_incoming`]
+/// returns `true` once after this happens, and the owner of the
+/// incoming pipe should then abort it.  This should be checked after
+/// each `process` call.
+pub struct TcpWriteLink {
+    // Write settings and state carried over from the `TcpLink`
+    link: GenericLink,
+
+    // Socket options
+    opts: TcpOptions,
+}
+
+impl TcpWriteLink {
+    /// Change the "no delay" flag on the stream.  This will be
+    /// updated on the next `process` call.  See
+    /// [`TcpLink::set_nodelay`].
+    #[inline]
+    pub fn set_nodelay(&mut self, nodelay: bool) {
+        self.opts.set_nodelay(nodelay);
+    }
+
+    /// Change the "no delay" flag on the stream at once.  See
+    /// [`TcpLink::set_nodelay_now`].
+    pub fn set_nodelay_now(&mut self, stream: &TcpStream, nodelay: bool) -> Result<()> {
+        self.opts
+            .set_nodelay_now(stream, nodelay, &self.link.tracer)
+    }
+
+    /// Enable or disable flushing on "push".  See
+    /// [`TcpLink::set_push_nodelay`].
+    #[inline]
+    pub fn set_push_nodelay(&mut self, enable: bool) {
+        self.opts.ignore_push = !enable;
+        self.opts.push &= enable;
+    }
+
+    /// Select how an outgoing "abort" is passed on to the TCP
+    /// stream.  See [`AbortMode`].
+    #[inline]
+    pub fn set_abort_mode(&mut self, mode: AbortMode) {
+        self.link.abort_mode = mode;
+    }
+
+    /// Pause or unpause writes.  This takes effect on the next
+    /// `process` call.  Unpausing counts as fresh readiness for the
+    /// `WouldBlock` latch.
+    #[inline]
+    pub fn set_pause_writes(&mut self, pause: bool) {
+        self.link.set_pause_writes(pause);
+    }
+
+    /// Signal that the stream is writable, unpausing writes and
+    /// clearing the `WouldBlock` latch for writes.  See
+    /// [`TcpLink::set_wouldblock_latch`].
+    #[inline]
+    pub fn note_writable(&mut self) {
+        self.link.note_writable();
+    }
+
+    /// Get the number of bytes that may still be written under the
+    /// write quota, or `None` if there is no quota.  See
+    /// [`TcpLink::set_write_quota`].
+    #[inline]
+    pub fn write_quota(&self) -> Option<u64> {
+        self.link.write_quota()
+    }
+
+    /// Get the total number of bytes written to the stream.  See
+    /// [`TcpLink::bytes_out`].
+    #[inline]
+    pub fn bytes_out(&self) -> u64 {
+        self.link.bytes_out
+    }
+
+    /// Get the syscall statistics, or `None` if not enabled
+    #[inline]
+    pub fn stats(&self) -> Option<&LinkStats> {
+        self.link.stats.as_ref()
+    }
+
+    /// Get the last error seen on a write or shutdown of the stream,
+    /// if any.  See [`TcpLink::last_write_error`].
+    #[inline]
+    pub fn last_write_error(&self) -> Option<&io::Error> {
+        self.link.last_write_error.as_ref()
+    }
+
+    /// Returns `true` once after an outgoing "abort" has been passed
+    /// on to the stream, to indicate that the incoming pipe should be
+    /// aborted by its owner
+    #[inline]
+    pub fn take_abort_incoming(&mut self) -> bool {
+        std::mem::take(&mut self.link.abort_incoming)
+    }
+
+    /// Write as much data as possible out to the given TCP stream.
+    /// Returns the activity flags (see [`Activity`]), which are empty
+    /// if no progress could be made, or `Err(_)` if there was a fatal
+    /// error on the stream.
+    ///
+    /// Assumes that it is always called with the same TcpStream and
+    /// pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process(&mut self, mut stream: &TcpStream, prd: PBufRd) -> Result<Activity> {
+        let rv = self.process_aux(&mut stream, prd);
+        self.link.fail(rv)
+    }
+
+    fn process_aux(&mut self, stream: &mut &TcpStream, prd: PBufRd) -> Result<Activity> {
+        if self.link.halted() {
+            return Ok(Activity::NONE);
+        }
+        self.opts.apply(stream, &self.link.tracer)?;
+        self.link.process_out_half(stream, prd, &mut self.opts)
+    }
+}
+
+impl fmt::Debug for TcpWriteLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("TcpWriteLink");
+        self.link.debug_fields(&mut d);
+        d.field("opts", &self.opts).finish()
+    }
+}
+
+/// Exchange stream data via a non-blocking [`std::net::TcpStream`]
+///
+/// This is for use with event loops other than `mio`.  The stream
+/// must have been switched to non-blocking mode using
+/// `set_nonblocking(true)`.  The handling of nodelay, shutdown, EOF
+/// and abort is identical to [`TcpLink`], so see the documentation
+/// there.  Both reading and writing start paused.
+///
+/// This is only available with the `std-net` feature.
+#[cfg(feature = "std-net")]
+#[derive(Debug)]
+pub struct StdTcpLink(TcpLink);
+
+#[cfg(feature = "std-net")]
+impl StdTcpLink {
+    /// Create the component with default settings, as for
+    /// [`TcpLink::new`]
+    #[inline]
+    pub fn new() -> Self {
+        Self(TcpLink::new())
+    }
+
+    /// Change the maximum number of bytes to read in each `process`
+    /// call.  See [`TcpLink::set_max_read_unit`].
+    #[inline]
+    pub fn set_max_read_unit(&mut self, max_read_unit: usize) {
+        self.0.set_max_read_unit(max_read_unit);
+    }
+
+    /// Get the maximum number of bytes to read in each `process`
+    /// call.  See [`TcpLink::max_read_unit`].
+    #[inline]
+    pub fn max_read_unit(&self) -> usize {
+        self.0.max_read_unit()
+    }
+
+    /// Let **max_read_unit** adapt to the traffic.  See
+    /// [`TcpLink::set_read_unit_range`].
+    #[inline]
+    pub fn set_read_unit_range(&mut self, min: usize, max: usize) {
+        self.0.set_read_unit_range(min, max);
+    }
+
+    /// Change the maximum number of bytes to read in each `process`
+    /// call.  See [`TcpLink::set_max_read_total`].
+    #[inline]
+    pub fn set_max_read_total(&mut self, max_read_total: usize) {
+        self.0.set_max_read_total(max_read_total);
+    }
+
+    /// Skip reading when the incoming pipe has too little free
+    /// space.  See [`TcpLink::set_read_low_water`].
+    #[inline]
+    pub fn set_read_low_water(&mut self, low_water: usize) {
+        self.0.set_read_low_water(low_water);
+    }
+
+    /// Get the total number of bytes read from the stream.  See
+    /// [`TcpLink::bytes_in`].
+    #[inline]
+    pub fn bytes_in(&self) -> u64 {
+        self.0.bytes_in()
+    }
+
+    /// Get the total number of bytes written to the stream.  See
+    /// [`TcpLink::bytes_out`].
+    #[inline]
+    pub fn bytes_out(&self) -> u64 {
+        self.0.bytes_out()
+    }
+
+    /// Get and reset the byte counters.  See
+    /// [`TcpLink::take_counters`].
+    #[inline]
+    pub fn take_counters(&mut self) -> (u64, u64) {
+        self.0.take_counters()
+    }
+
+    /// Get the time of the last data transfer.  See
+    /// [`TcpLink::last_activity`].
+    #[inline]
+    pub fn last_activity(&self) -> Option<Instant> {
+        self.0.last_activity()
+    }
+
+    /// Get the time since the last data transfer.  See
+    /// [`TcpLink::idle_for`].
+    #[inline]
+    pub fn idle_for(&self) -> Option<Duration> {
+        self.0.idle_for()
+    }
+
+    /// Abort the link if it sees no traffic for the given time.  See
+    /// [`TcpLink::set_idle_timeout`].
+    #[inline]
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.0.set_idle_timeout(timeout);
+    }
+
+    /// Abort the link if the peer doesn't close within the given time
+    /// after our EOF.  See [`TcpLink::set_close_deadline`].
+    #[inline]
+    pub fn set_close_deadline(&mut self, deadline: Option<Duration>) {
+        self.0.set_close_deadline(deadline);
+    }
+
+    /// Limit the rate at which data is read from the stream.  See
+    /// [`TcpLink::set_read_rate_limit`].
+    #[inline]
+    pub fn set_read_rate_limit(&mut self, limit: Option<RateLimit>) {
+        self.0.set_read_rate_limit(limit);
+    }
+
+    /// Limit the rate at which data is written to the stream.  See
+    /// [`TcpLink::set_write_rate_limit`].
+    #[inline]
+    pub fn set_write_rate_limit(&mut self, limit: Option<RateLimit>) {
+        self.0.set_write_rate_limit(limit);
+    }
+
+    /// Limit the total number of bytes that may be read from the
+    /// stream.  See [`TcpLink::set_read_quota`].
+    #[inline]
+    pub fn set_read_quota(&mut self, quota: Option<u64>) {
+        self.0.set_read_quota(quota);
+    }
+
+    /// Get the number of bytes that may still be read.  See
+    /// [`TcpLink::read_quota`].
+    #[inline]
+    pub fn read_quota(&self) -> Option<u64> {
+        self.0.read_quota()
+    }
+
+    /// Select how the incoming pipe is ended when the read quota runs
+    /// out.  See [`TcpLink::set_read_quota_mode`].
+    #[inline]
+    pub fn set_read_quota_mode(&mut self, mode: ReadQuotaMode) {
+        self.0.set_read_quota_mode(mode);
+    }
+
+    /// Enable or disable shutting down the read half when the read
+    /// quota runs out.  See [`TcpLink::set_read_quota_shutdown`].
+    #[inline]
+    pub fn set_read_quota_shutdown(&mut self, shutdown: bool) {
+        self.0.set_read_quota_shutdown(shutdown);
+    }
+
+    /// Limit the total number of bytes that may be written to the
+    /// stream.  See [`TcpLink::set_write_quota`].
+    #[inline]
+    pub fn set_write_quota(&mut self, quota: Option<u64>) {
+        self.0.set_write_quota(quota);
+    }
+
+    /// Get the number of bytes that may still be written.  See
+    /// [`TcpLink::write_quota`].
+    #[inline]
+    pub fn write_quota(&self) -> Option<u64> {
+        self.0.write_quota()
+    }
+
+    /// Select what happens when the write quota runs out.  See
+    /// [`TcpLink::set_write_quota_mode`].
+    #[inline]
+    pub fn set_write_quota_mode(&mut self, mode: WriteQuotaMode) {
+        self.0.set_write_quota_mode(mode);
+    }
+
+    /// Get the earliest time at which `process` needs to be called.
+    /// See [`TcpLink::next_deadline`].
+    #[inline]
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.0.next_deadline()
+    }
+
+    /// Enable or disable collection of syscall statistics.  See
+    /// [`TcpLink::enable_stats`].
+    #[inline]
+    pub fn enable_stats(&mut self, enable: bool) {
+        self.0.enable_stats(enable);
+    }
+
+    /// Get the syscall statistics, or `None` if not enabled
+    #[inline]
+    pub fn stats(&self) -> Option<&LinkStats> {
+        self.0.stats()
+    }
+
+    /// Reset the syscall statistics to zero, if enabled
+    #[inline]
+    pub fn reset_stats(&mut self) {
+        self.0.reset_stats();
+    }
+
+    /// Hold off reading while the incoming pipe has a large backlog.
+    /// See [`TcpLink::set_read_backpressure`].
+    #[inline]
+    pub fn set_read_backpressure(&mut self, threshold: Option<usize>) {
+        self.0.set_read_backpressure(threshold);
+    }
+
+    /// Change the maximum number of bytes to write out in each
+    /// `process` call.  See [`TcpLink::set_max_write_unit`].
+    #[inline]
+    pub fn set_max_write_unit(&mut self, max_write_unit: usize) {
+        self.0.set_max_write_unit(max_write_unit);
+    }
+
+    /// Change the "no delay" flag on the stream.  This will be
+    /// updated on the next `process` call.  See
+    /// [`TcpLink::set_nodelay`].
+    #[inline]
+    pub fn set_nodelay(&mut self, nodelay: bool) {
+        self.0.set_nodelay(nodelay);
+    }
+
+    /// Change the "no delay" flag on the stream at once.  See
+    /// [`TcpLink::set_nodelay_now`].
+    pub fn set_nodelay_now(&mut self, stream: &std::net::TcpStream, nodelay: bool) -> Result<()> {
+        self.0
+            .opts
+            .set_nodelay_now(stream, nodelay, &self.0.link.tracer)
+    }
+
+    /// Get the "no delay" flag last requested.  See
+    /// [`TcpLink::nodelay`].
+    #[inline]
+    pub fn nodelay(&self) -> bool {
+        self.0.nodelay()
+    }
+
+    /// Select how TCP urgent data is handled.  See
+    /// [`TcpLink::set_oob_inline`].  Since `StdTcpLink` has no
+    /// `process_event`, urgent bytes are never read and dropped
+    /// here, but they are still kept out of the stream unless inline
+    /// mode is enabled.
+    #[inline]
+    pub fn set_oob_inline(&mut self, inline: bool) {
+        self.0.set_oob_inline(inline);
+    }
+
+    /// Test whether TCP urgent data is passed inline.  See
+    /// [`TcpLink::oob_inline`].
+    #[inline]
+    pub fn oob_inline(&self) -> bool {
+        self.0.oob_inline()
+    }
+
+    /// Test whether any socket option changes are waiting to be
+    /// applied.  See [`TcpLink::has_pending_option_changes`].
+    #[inline]
+    pub fn has_pending_option_changes(&self) -> bool {
+        self.0.has_pending_option_changes()
+    }
+
+    /// Enable or disable flushing on "push".  See
+    /// [`TcpLink::set_push_nodelay`].
+    #[inline]
+    pub fn set_push_nodelay(&mut self, enable: bool) {
+        self.0.set_push_nodelay(enable);
+    }
+
+    /// Enable or disable auto-start.  See
+    /// [`TcpLink::set_auto_start`].
+    #[inline]
+    pub fn set_auto_start(&mut self, auto_start: bool) {
+        self.0.set_auto_start(auto_start);
+    }
+
+    /// Check whether a non-blocking connect on the stream has
+    /// completed.  See [`TcpLink::check_connected`].
+    #[inline]
+    pub fn check_connected(&mut self, stream: &std::net::TcpStream) -> Result<bool> {
+        self.0.check_connected_aux(stream)
+    }
+
+    /// Enable or disable peek mode.  See
+    /// [`TcpLink::set_peek_mode`].
+    #[inline]
+    pub fn set_peek_mode(&mut self, enable: bool) {
+        self.0.set_peek_mode(enable);
+    }
+
+    /// End peek mode.  See [`TcpLink::commit`].
+    #[inline]
+    pub fn commit(&mut self) {
+        self.0.commit();
+    }
+
+    /// Copy the data waiting on the stream without consuming it.  See
+    /// [`TcpLink::peek`].
+    pub fn peek(&mut self, stream: &mut std::net::TcpStream, buf: &mut [u8]) -> Result<usize> {
+        retry!(stream.peek(buf)).map_err(Error::read)
+    }
+
+    /// Get the address of the peer, once known.  See
+    /// [`TcpLink::peer_addr`].
+    #[inline]
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.0.peer_addr()
+    }
+
+    /// Get the local address of the stream, once known.  See
+    /// [`TcpLink::local_addr`].
+    #[inline]
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.0.local_addr()
+    }
+
+    /// Enable or disable TCP keepalive.  See
+    /// [`TcpLink::set_keepalive`].
+    #[inline]
+    pub fn set_keepalive(&mut self, keepalive: Option<KeepaliveConfig>) {
+        self.0.set_keepalive(keepalive);
+    }
+
+    /// Change the IP time-to-live.  See [`TcpLink::set_ttl`].
+    #[inline]
+    pub fn set_ttl(&mut self, ttl: u32) {
+        self.0.set_ttl(ttl);
+    }
+
+    /// Get the IP time-to-live last requested.  See
+    /// [`TcpLink::ttl`].
+    #[inline]
+    pub fn ttl(&self) -> Option<u32> {
+        self.0.ttl()
+    }
+
+    /// Change the IP type-of-service byte or IPv6 traffic class.  See
+    /// [`TcpLink::set_tos`].
+    #[inline]
+    pub fn set_tos(&mut self, tos: u8) {
+        self.0.set_tos(tos);
+    }
+
+    /// Get the IP type-of-service byte last requested.  See
+    /// [`TcpLink::tos`].
+    #[inline]
+    pub fn tos(&self) -> Option<u8> {
+        self.0.tos()
+    }
+
+    /// Enable or disable `TCP_QUICKACK`.  See
+    /// [`TcpLink::set_quickack`].
+    #[inline]
+    pub fn set_quickack(&mut self, quickack: bool) {
+        self.0.set_quickack(quickack);
+    }
+
+    /// Test whether `TCP_QUICKACK` is enabled.  See
+    /// [`TcpLink::quickack`].
+    #[inline]
+    pub fn quickack(&self) -> bool {
+        self.0.quickack()
+    }
+
+    /// Change the size of the kernel receive buffer.  See
+    /// [`TcpLink::set_recv_buffer_size`].
+    #[inline]
+    pub fn set_recv_buffer_size(&mut self, size: usize) {
+        self.0.set_recv_buffer_size(size);
+    }
+
+    /// Change the size of the kernel send buffer.  See
+    /// [`TcpLink::set_send_buffer_size`].
+    #[inline]
+    pub fn set_send_buffer_size(&mut self, size: usize) {
+        self.0.set_send_buffer_size(size);
+    }
+
+    /// Query the effective size of the kernel receive buffer from
+    /// the given stream
+    #[inline]
+    pub fn recv_buffer_size(&self, stream: &std::net::TcpStream) -> io::Result<usize> {
+        stream.recv_buffer_size()
+    }
+
+    /// Query the effective size of the kernel send buffer from the
+    /// given stream
+    #[inline]
+    pub fn send_buffer_size(&self, stream: &std::net::TcpStream) -> io::Result<usize> {
+        stream.send_buffer_size()
+    }
+
+    /// Limit the amount of unsent data queued in the kernel.  See
+    /// [`TcpLink::set_notsent_lowat`].
+    #[inline]
+    pub fn set_notsent_lowat(&mut self, lowat: u32) {
+        self.0.set_notsent_lowat(lowat);
+    }
+
+    /// Bound how long sent data may remain unacknowledged.  See
+    /// [`TcpLink::set_user_timeout`].
+    #[inline]
+    pub fn set_user_timeout(&mut self, timeout: Option<Duration>) {
+        self.0.set_user_timeout(timeout);
+    }
+
+    /// Clamp the TCP maximum segment size.  See
+    /// [`TcpLink::set_mss`].
+    #[inline]
+    pub fn set_mss(&mut self, mss: Option<u32>) {
+        self.0.set_mss(mss);
+    }
+
+    /// Query the effective maximum segment size from the given
+    /// stream.  See [`TcpLink::mss`].
+    #[inline]
+    pub fn mss(&self, stream: &std::net::TcpStream) -> io::Result<u32> {
+        mss(stream)
+    }
+
+    /// Get a snapshot of the kernel's statistics for the given
+    /// stream.  See [`TcpLink::tcp_info`].
+    #[inline]
+    pub fn tcp_info(&self, stream: &std::net::TcpStream) -> io::Result<TcpInfo> {
+        crate::tcpinfo::tcp_info(stream)
+    }
+
+    /// Enable or disable `TCP_CORK`.  See [`TcpLink::set_cork`].
+    #[inline]
+    pub fn set_cork(&mut self, cork: bool) {
+        self.0.set_cork(cork);
+    }
+
+    /// Enable or disable automatic corking.  See
+    /// [`TcpLink::set_auto_cork`].
+    #[inline]
+    pub fn set_auto_cork(&mut self, auto_cork: bool) {
+        self.0.set_auto_cork(auto_cork);
+    }
+
+    /// Pause or unpause writes.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_writes(&mut self, pause: bool) {
+        self.0.set_pause_writes(pause);
+    }
+
+    /// Pause or unpause reads.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_reads(&mut self, pause: bool) {
+        self.0.set_pause_reads(pause);
+    }
+
+    /// Test whether writes are paused.  See
+    /// [`TcpLink::pause_writes`].
+    #[inline]
+    pub fn pause_writes(&self) -> bool {
+        self.0.pause_writes()
+    }
+
+    /// Test whether reads are paused.  See [`TcpLink::pause_reads`].
+    #[inline]
+    pub fn pause_reads(&self) -> bool {
+        self.0.pause_reads()
+    }
+
+    /// Enable or disable the `WouldBlock` latch.  See
+    /// [`TcpLink::set_wouldblock_latch`].
+    #[inline]
+    pub fn set_wouldblock_latch(&mut self, enable: bool) {
+        self.0.set_wouldblock_latch(enable);
+    }
+
+    /// Select the order in which `process` handles the two
+    /// directions.  See [`TcpLink::set_process_order`].
+    #[inline]
+    pub fn set_process_order(&mut self, order: ProcessOrder) {
+        self.0.set_process_order(order);
+    }
+
+    /// Shut down the outgoing half of the stream once the outgoing
+    /// pipe is empty.  See [`TcpLink::close_when_flushed`].
+    #[inline]
+    pub fn close_when_flushed(&mut self) {
+        self.0.close_when_flushed();
+    }
+
+    /// Abort the link at once.  See [`TcpLink::abort_now`].
+    pub fn abort_now(&mut self, stream: &mut std::net::TcpStream, pbuf: PBufRdWr) -> Activity {
+        self.0.link.abort_now(stream, pbuf)
+    }
+
+    /// Test whether the link is finished.  See [`TcpLink::is_done`].
+    #[inline]
+    pub fn is_done(&self, pbuf: &PBufRdWr) -> bool {
+        self.0.is_done(pbuf)
+    }
+
+    /// Get the last error seen on a read.  See
+    /// [`TcpLink::last_read_error`].
+    #[inline]
+    pub fn last_read_error(&self) -> Option<&io::Error> {
+        self.0.last_read_error()
+    }
+
+    /// Get the last error seen on a write or shutdown.  See
+    /// [`TcpLink::last_write_error`].
+    #[inline]
+    pub fn last_write_error(&self) -> Option<&io::Error> {
+        self.0.last_write_error()
+    }
+
+    /// Signal that the stream is readable.  See
+    /// [`TcpLink::note_readable`].
+    #[inline]
+    pub fn note_readable(&mut self) {
+        self.0.note_readable();
+    }
+
+    /// Signal that the stream is writable.  See
+    /// [`TcpLink::note_writable`].
+    #[inline]
+    pub fn note_writable(&mut self) {
+        self.0.note_writable();
+    }
+
+    /// Read and write as much data as possible to and from the given
+    /// TCP stream.  See [`TcpLink::process`].
+    pub fn process(
+        &mut self,
+        stream: &mut std::net::TcpStream,
+        pbuf: PBufRdWr,
+    ) -> Result<Activity> {
+        self.0.process_stream(stream, pbuf)
+    }
+
+    /// Write as much data as possible out to the given TCP stream.
+    /// See [`TcpLink::process_out`].
+    pub fn process_out(
+        &mut self,
+        stream: &mut std::net::TcpStream,
+        pbuf: PBufRdWr,
+    ) -> Result<Activity> {
+        self.0.process_out_stream(stream, pbuf)
+    }
+
+    /// Read as much data as possible from the given TCP stream, up to
+    /// **max_read_unit** bytes.  See [`TcpLink::process_in`].
+    pub fn process_in(
+        &mut self,
+        stream: &mut std::net::TcpStream,
+        pbuf: PBufRdWr,
+    ) -> Result<Activity> {
+        self.0.process_in_stream(stream, pbuf)
+    }
+}
+
+#[cfg(feature = "std-net")]
+impl Default for StdTcpLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use crate::testutil::reset_peer;
+    use crate::testutil::{advance, fake_now, read_to_end, tcp_pair, wait_for};
+    use mio::{Events, Poll};
+    use pipebuf::{PipeBuf, PipeBufPair};
+    use std::io::{Read, Write};
+
+    // Link with both directions unpaused
+    fn open_link() -> TcpLink {
+        let mut link = TcpLink::new();
+        link.set_pause_writes(false);
+        link.set_pause_reads(false);
+        link
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn option_error_is_not_fatal() {
+        let (mut stream, mut peer) = tcp_pair();
+        let mut link = open_link();
+        let mut pair = PipeBufPair::new();
+
+        // Linux rejects an MSS below 88 with EINVAL
+        link.set_mss(Some(1));
+        pair.upper().wr.append(b"hello");
+        let e = link.process(&mut stream, pair.lower()).unwrap_err();
+        assert_eq!(e.op(), Op::SetOption);
+        assert_eq!(link.state(), LinkState::Open);
+
+        // The option isn't retried, and the data goes through
+        link.process(&mut stream, pair.lower()).unwrap();
+        let mut buf = [0; 5];
+        peer.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"hello");
+
+        peer.write_all(b"back").unwrap();
+        wait_for(|| {
+            link.process(&mut stream, pair.lower()).unwrap();
+            pair.upper().rd.len() == 4
+        });
+        assert_eq!(pair.upper().rd.data(), b"back");
+
+        pair.upper().wr.close();
+        link.process(&mut stream, pair.lower()).unwrap();
+        assert_eq!(read_to_end(&mut peer).0, b"");
+    }
+
+    // Wait for an event on the stream, registered for both directions
+    fn next_event(poll: &mut Poll, events: &mut Events) {
+        wait_for(|| {
+            poll.poll(events, Some(Duration::from_millis(100))).unwrap();
+            !events.is_empty()
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn event_without_auto_start_skips_connect_check() {
+        let (mut stream, peer) = tcp_pair();
+        reset_peer(peer);
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(4);
+        let mut link = TcpLink::new();
+        let mut pair = PipeBufPair::new();
+        link.register(&mut stream, poll.registry(), Token(0))
+            .unwrap();
+        next_event(&mut poll, &mut events);
+
+        // The reset is seen by the read, not returned as a failed
+        // connect
+        let event = events.iter().next().unwrap();
+        let act = link
+            .process_event(&mut stream, pair.lower(), event)
+            .unwrap();
+        assert!(act.contains(Activity::ABORTED));
+        assert!(pair.upper().rd.is_aborted());
+        assert_eq!(link.state(), LinkState::Aborted);
+    }
+
+    #[test]
+    fn event_with_auto_start_checks_connect() {
+        let (mut stream, peer) = tcp_pair();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(4);
+        let mut link = TcpLink::new();
+        link.set_auto_start(true);
+        let mut pair = PipeBufPair::new();
+        link.register(&mut stream, poll.registry(), Token(0))
+            .unwrap();
+        next_event(&mut poll, &mut events);
+
+        let event = events.iter().next().unwrap();
+        link.process_event(&mut stream, pair.lower(), event)
+            .unwrap();
+        assert!(!link.pause_reads() && !link.pause_writes());
+        assert_eq!(link.peer_addr(), Some(peer.local_addr().unwrap()));
+    }
+
+    #[test]
+    fn idle_timeout_follows_clock() {
+        let (mut stream, mut peer) = tcp_pair();
+        let mut link = open_link();
+        let mut pair = PipeBufPair::new();
+        link.set_clock(fake_now);
+        link.set_idle_timeout(Some(Duration::from_secs(10)));
+        let start = fake_now();
+        assert_eq!(link.next_deadline(), Some(start + Duration::from_secs(10)));
+
+        // Moving data restarts the timer
+        advance(Duration::from_secs(4));
+        pair.upper().wr.append(b"x");
+        link.process(&mut stream, pair.lower()).unwrap();
+        peer.read_exact(&mut [0]).unwrap();
+        let active = start + Duration::from_secs(4);
+        assert_eq!(link.last_activity(), Some(active));
+        assert_eq!(link.next_deadline(), Some(active + Duration::from_secs(10)));
+
+        advance(Duration::from_secs(9));
+        let act = link.process(&mut stream, pair.lower()).unwrap();
+        assert!(!act.contains(Activity::TIMED_OUT));
+        assert_eq!(link.idle_for(), Some(Duration::from_secs(9)));
+
+        advance(Duration::from_secs(1));
+        let act = link.process(&mut stream, pair.lower()).unwrap();
+        assert!(act.contains(Activity::TIMED_OUT));
+        assert!(pair.upper().rd.is_aborted());
+        assert_eq!(link.next_deadline(), None);
+    }
+
+    // Pass on an outgoing abort of a pipe holding `data` with the
+    // given mode, keeping the stream open, and return what the peer
+    // reads
+    fn abort_with(mode: AbortMode, data: &[u8]) -> (Vec<u8>, io::Result<()>) {
+        let (mut stream, mut peer) = tcp_pair();
+        let mut link = open_link();
+        link.set_abort_mode(mode);
+        let mut pair = PipeBufPair::new();
+        pair.upper().wr.append(data);
+        pair.upper().wr.abort();
+
+        let act = link.process(&mut stream, pair.lower()).unwrap();
+        assert!(act.contains(Activity::EOF_OUT));
+        assert!(act.contains(Activity::ABORTED));
+        assert!(pair.upper().rd.is_aborted());
+        assert_eq!(link.state(), LinkState::Aborted);
+        let rv = read_to_end(&mut peer);
+        drop(stream);
+        rv
+    }
+
+    #[test]
+    fn abort_shutdown_both() {
+        let (data, rv) = abort_with(AbortMode::ShutdownBoth, b"partial");
+        assert_eq!(data, b"partial");
+        rv.unwrap();
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn abort_reset() {
+        let (data, rv) = abort_with(AbortMode::Reset, b"discarded");
+        assert_eq!(data, b"");
+        assert_eq!(rv.unwrap_err().kind(), ErrorKind::ConnectionReset);
+    }
+
+    // There is no way to request a reset on Windows, so both reset
+    // modes fall back to the shutdown, after flushing the data
+    #[cfg(windows)]
+    #[test]
+    fn abort_reset_falls_back_to_shutdown() {
+        for mode in [AbortMode::Reset, AbortMode::FlushThenReset] {
+            let (data, rv) = abort_with(mode, b"flushed");
+            assert_eq!(data, b"flushed");
+            rv.unwrap();
+        }
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn abort_flush_then_reset() {
+        let (data, rv) = abort_with(AbortMode::FlushThenReset, b"error response");
+        assert_eq!(data, b"error response");
+        assert_eq!(rv.unwrap_err().kind(), ErrorKind::ConnectionReset);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn abort_now_resets_at_once() {
+        let (mut stream, mut peer) = tcp_pair();
+        let mut link = open_link();
+        link.set_abort_mode(AbortMode::Reset);
+        let mut pair = PipeBufPair::new();
+        pair.upper().wr.append(b"discarded");
+
+        let act = link.abort_now(&mut stream, pair.lower());
+        assert!(act.contains(Activity::ABORTED));
+        let (data, rv) = read_to_end(&mut peer);
+        assert_eq!(data, b"");
+        assert_eq!(rv.unwrap_err().kind(), ErrorKind::ConnectionReset);
+    }
+
+    #[test]
+    fn split_halves_keep_settings() {
+        let (stream, mut peer) = tcp_pair();
+        let mut link = open_link();
+        link.set_read_quota(Some(5));
+        link.set_write_quota(Some(3));
+        link.enable_stats(true);
+        let (mut rd, mut wr) = link.split();
+        let mut incoming = PipeBuf::new();
+        let mut outgoing = PipeBuf::new();
+
+        // The read quota ends the incoming pipe
+        peer.write_all(b"0123456789").unwrap();
+        let mut act = Activity::NONE;
+        wait_for(|| {
+            act |= rd.process(&stream, incoming.wr()).unwrap();
+            incoming.rd().is_eof()
+        });
+        assert!(act.contains(Activity::QUOTA));
+        assert_eq!(incoming.rd().data(), b"01234");
+        assert_eq!(rd.bytes_in(), 5);
+        assert_eq!(rd.read_quota(), Some(0));
+        assert_eq!(rd.stats().unwrap().bytes_read, 5);
+
+        // The write quota stalls the outgoing pipe
+        outgoing.wr().append(b"abcdef");
+        wr.process(&stream, outgoing.rd()).unwrap();
+        assert_eq!(outgoing.rd().data(), b"def");
+        assert_eq!(wr.bytes_out(), 3);
+        assert_eq!(wr.write_quota(), Some(0));
+        assert_eq!(wr.stats().unwrap().bytes_written, 3);
+        let mut buf = [0; 3];
+        peer.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"abc");
+        assert!(!wr.take_abort_incoming());
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn split_write_half_aborts() {
+        let (stream, mut peer) = tcp_pair();
+        let mut link = open_link();
+        link.set_abort_mode(AbortMode::Reset);
+        let (_rd, mut wr) = link.split();
+        let mut outgoing = PipeBuf::new();
+
+        outgoing.wr().append(b"discarded");
+        outgoing.wr().abort();
+        let act = wr.process(&stream, outgoing.rd()).unwrap();
+        assert!(act.contains(Activity::ABORTED));
+        assert!(wr.take_abort_incoming());
+        assert!(!wr.take_abort_incoming());
+        let (data, rv) = read_to_end(&mut peer);
+        assert_eq!(data, b"");
+        assert_eq!(rv.unwrap_err().kind(), ErrorKind::ConnectionReset);
+    }
+
+    #[test]
+    fn split_write_half_sees_reset() {
+        let (stream, peer) = tcp_pair();
+        let (_rd, mut wr) = open_link().split();
+        let mut outgoing = PipeBuf::new();
+        drop(peer);
+
+        // Keep writing until the reset from the closed peer shows up
+        let mut act = Activity::NONE;
+        wait_for(|| {
+            outgoing.wr().append(&[0; 1024]);
+            act |= wr.process(&stream, outgoing.rd()).unwrap();
+            act.contains(Activity::ABORTED)
+        });
+        assert!(wr.take_abort_incoming());
+        assert!(wr.last_write_error().is_some());
+
+        // Nothing more is written
+        outgoing.wr().append(b"more");
+        assert_eq!(wr.process(&stream, outgoing.rd()).unwrap(), Activity::NONE);
+        assert!(outgoing.rd().is_empty());
+    }
+
+    fn full_config() -> TcpLinkConfig {
+        TcpLinkConfig::new()
+            .read_unit_range(Some((1024, 65536)))
+            .max_read_total(8)
+            .read_low_water(512)
+            .max_write_unit(16384)
+            .read_backpressure(Some(100_000))
+            .nodelay(true)
+            .keepalive(KeepaliveConfig::new(Duration::from_secs(60)).retries(3))
+            .ttl(32)
+            .user_timeout(Some(Duration::from_secs(30)))
+            .notsent_lowat(16384)
+            .mss(1200)
+            .push_nodelay(false)
+            .quickack(true)
+            .cork(true)
+            .auto_cork(true)
+            .oob_inline(true)
+            .abort_mode(AbortMode::FlushThenReset)
+            .process_order(ProcessOrder::Interleaved { chunk: 4096 })
+            .wouldblock_latch(true)
+            .auto_start(true)
+            .idle_timeout(Some(Duration::from_secs(300)))
+            .read_rate_limit(Some(RateLimit::new(1_000_000, 65536)))
+            .read_quota(Some(1 << 20))
+            .read_quota_mode(ReadQuotaMode::Abort)
+            .write_quota(Some(1 << 30))
+            .write_quota_mode(WriteQuotaMode::Abort)
+            .pause_reads(false)
+    }
+
+    #[test]
+    fn with_config_applies_settings() {
+        let link = TcpLink::with_config(&full_config());
+        assert_eq!(link.read_unit_range(), Some((1024, 65536)));
+        assert_eq!(link.link.max_read_total, Some(8));
+        assert_eq!(link.link.read_low_water, 512);
+        assert_eq!(link.link.max_write_unit, 16384);
+        assert_eq!(link.link.read_backpressure, Some(100_000));
+        assert_eq!(link.opts.notsent_lowat, Some(16384));
+        assert_eq!(link.opts.mss, Some(1200));
+        assert!(link.opts.ignore_push);
+        assert!(link.opts.quickack);
+        assert!(link.opts.cork);
+        assert!(link.opts.auto_cork);
+        assert!(link.oob_inline());
+        assert_eq!(link.link.abort_mode, AbortMode::FlushThenReset);
+        assert_eq!(link.link.order, ProcessOrder::Interleaved { chunk: 4096 });
+        assert!(link.link.latch);
+        assert!(link.auto_start);
+        assert_eq!(link.read_quota(), Some(1 << 20));
+        assert_eq!(link.link.read_quota_mode, ReadQuotaMode::Abort);
+        assert_eq!(link.write_quota(), Some(1 << 30));
+        assert_eq!(link.link.write_quota_mode, WriteQuotaMode::Abort);
+        assert!(!link.link.pause_reads);
+        assert!(link.link.pause_writes);
+
+        // A live link keeps its quotas and pause flags
+        let mut link = open_link();
+        link.set_read_quota(Some(10));
+        link.apply_config(&full_config());
+        assert_eq!(link.read_quota(), Some(10));
+        assert!(!link.link.pause_writes);
+        assert_eq!(link.link.abort_mode, AbortMode::FlushThenReset);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn config_json_round_trip() {
+        let config = full_config();
+        let json = serde_json::to_string(&config).unwrap();
+        let loaded: TcpLinkConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, config);
+
+        // Missing fields take their defaults
+        let json = r#"{"max_read_unit": 4096, "abort_mode": "Reset", "read_quota": 100}"#;
+        let loaded: TcpLinkConfig = serde_json::from_str(json).unwrap();
+        let expected = TcpLinkConfig::new()
+            .max_read_unit(4096)
+            .abort_mode(AbortMode::Reset)
+            .read_quota(Some(100));
+        assert_eq!(loaded, expected);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn config_toml_round_trip() {
+        let config = full_config();
+        let text = toml::to_string(&config).unwrap();
+        let loaded: TcpLinkConfig = toml::from_str(&text).unwrap();
+        assert_eq!(loaded, config);
+
+        // A partial file, as written by hand
+        let text = r#"
+            max_read_unit = 65536
+            nodelay = true
+            mss = 1400
+            idle_timeout = { secs = 60, nanos = 0 }
+            read_quota_mode = "Abort"
+        "#;
+        let loaded: TcpLinkConfig = toml::from_str(text).unwrap();
+        let expected = TcpLinkConfig::new()
+            .max_read_unit(65536)
+            .nodelay(true)
+            .mss(1400)
+            .idle_timeout(Some(Duration::from_secs(60)))
+            .read_quota_mode(ReadQuotaMode::Abort);
+        assert_eq!(loaded, expected);
+    }
+
+    // Receive `len` bytes of bulk data, returning the read calls made
+    // and the final **max_read_unit**
+    fn bulk_read(mut link: TcpLink, len: usize) -> (u64, usize) {
+        let (mut stream, mut peer) = tcp_pair();
+        link.set_pause_reads(false);
+        link.enable_stats(true);
+        let mut pair = PipeBufPair::new();
+        let writer = std::thread::spawn(move || peer.write_all(&vec![0x5A; len]).map(|_| peer));
+
+        let mut received = 0;
+        wait_for(|| {
+            link.process(&mut stream, pair.lower()).unwrap();
+            let mut app = pair.upper();
+            received += app.rd.len();
+            app.rd.consume(app.rd.len());
+            received == len
+        });
+        let _peer = writer.join().unwrap().unwrap();
+        (link.stats().unwrap().read_calls, link.max_read_unit())
+    }
+
+    #[test]
+    fn adaptive_read_unit_beats_fixed() {
+        let len = 1 << 20;
+        let mut fixed = TcpLink::new();
+        fixed.set_max_read_unit(1024);
+        let (fixed_calls, unit) = bulk_read(fixed, len);
+        assert_eq!(unit, 1024);
+        assert!(fixed_calls >= (len / 1024) as u64);
+
+        let mut adaptive = TcpLink::new();
+        adaptive.set_read_unit_range(1024, 65536);
+        let (adaptive_calls, unit) = bulk_read(adaptive, len);
+        assert!((1024..=65536).contains(&unit));
+        assert!(
+            adaptive_calls * 4 < fixed_calls,
+            "adaptive {} vs fixed {} read calls",
+            adaptive_calls,
+            fixed_calls
+        );
+    }
+
+    #[test]
+    fn adaptive_read_unit_shrinks_when_quiet() {
+        let (mut stream, mut peer) = tcp_pair();
+        let mut link = open_link();
+        link.set_read_unit_range(1024, 65536);
+        let mut pair = PipeBufPair::new();
+
+        peer.write_all(&[0; 8192]).unwrap();
+        wait_for(|| {
+            link.process(&mut stream, pair.lower()).unwrap();
+            pair.upper().rd.len() == 8192
+        });
+        assert!(link.max_read_unit() > 1024);
+
+        // Reads that find nothing bring it back down
+        for _ in 0..8 {
+            link.process(&mut stream, pair.lower()).unwrap();
+        }
+        assert_eq!(link.max_read_unit(), 1024);
+    }
+
+    #[test]
+    fn ttl_applied_on_process() {
+        let (mut stream, _peer) = tcp_pair();
+        let mut link = open_link();
+        let mut pair = PipeBufPair::new();
+        let default_ttl = stream.ttl().unwrap();
+        assert_eq!(link.ttl(), None);
+
+        // Nothing changes until the next `process` call
+        link.set_ttl(7);
+        assert_eq!(link.ttl(), Some(7));
+        assert_eq!(stream.ttl().unwrap(), default_ttl);
+        link.process(&mut stream, pair.lower()).unwrap();
+        assert_eq!(stream.ttl().unwrap(), 7);
+
+        // Only a change is applied, so a TTL changed behind the
+        // link's back is left alone
+        stream.set_ttl(9).unwrap();
+        link.set_ttl(7);
+        link.process(&mut stream, pair.lower()).unwrap();
+        assert_eq!(stream.ttl().unwrap(), 9);
+        link.set_ttl(11);
+        link.process(&mut stream, pair.lower()).unwrap();
+        assert_eq!(stream.ttl().unwrap(), 11);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn invalid_ttl_is_reported() {
+        let (mut stream, _peer) = tcp_pair();
+        let mut link = open_link();
+        let mut pair = PipeBufPair::new();
+
+        link.set_ttl(1000);
+        let e = link.process(&mut stream, pair.lower()).unwrap_err();
+        assert_eq!(e.op(), Op::SetOption);
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        assert_eq!(link.state(), LinkState::Open);
+        assert_eq!(link.ttl(), Some(1000));
+
+        // Not retried
+        link.process(&mut stream, pair.lower()).unwrap();
+    }
+
+    // Write 10 pieces of 100 bytes, one per `process` call with a
+    // pause between, then close.  Returns the sizes of the reads made
+    // by a peer that reads continuously.
+    #[cfg(target_os = "linux")]
+    fn cork_read_sizes(mut link: TcpLink, one_piece_per_call: bool) -> Vec<usize> {
+        let (mut stream, mut peer) = tcp_pair();
+        let reader = std::thread::spawn(move || {
+            let mut sizes = Vec::new();
+            let mut buf = [0; 4096];
+            loop {
+                match peer.read(&mut buf).unwrap() {
+                    0 => return sizes,
+                    len => sizes.push(len),
+                }
+            }
+        });
+        link.set_nodelay(true);
+        let mut pair = PipeBufPair::new();
+        if one_piece_per_call {
+            for _ in 0..10 {
+                pair.upper().wr.append(&[0x33; 100]);
+                link.process(&mut stream, pair.lower()).unwrap();
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        } else {
+            link.set_max_write_unit(100);
+            pair.upper().wr.append(&[0x33; 1000]);
+            while !pair.lower().rd.is_empty() {
+                link.process(&mut stream, pair.lower()).unwrap();
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        }
+        link.set_cork(false);
+        pair.upper().wr.close();
+        link.process(&mut stream, pair.lower()).unwrap();
+        let sizes = reader.join().unwrap();
+        assert_eq!(sizes.iter().sum::<usize>(), 1000);
+        sizes
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn cork_batches_writes() {
+        // Each write goes out as it is made
+        let sizes = cork_read_sizes(open_link(), true);
+        assert!(sizes.len() > 1, "{:?}", sizes);
+
+        // Corked, nothing goes out until the uncork
+        let mut link = open_link();
+        link.set_cork(true);
+        assert_eq!(cork_read_sizes(link, true), [1000]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn auto_cork_batches_until_drained() {
+        let sizes = cork_read_sizes(open_link(), false);
+        assert!(sizes.len() > 1, "{:?}", sizes);
+
+        // Held back across calls until the pipe is drained
+        let mut link = open_link();
+        link.set_auto_cork(true);
+        assert_eq!(cork_read_sizes(link, false), [1000]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn auto_cork_uncorks_on_push() {
+        for push in [false, true] {
+            let (mut stream, mut peer) = tcp_pair();
+            peer.set_read_timeout(Some(Duration::from_millis(50)))
+                .unwrap();
+            let mut link = open_link();
+            link.set_nodelay(true);
+            link.set_auto_cork(true);
+            link.set_max_write_unit(100);
+            let mut pair = PipeBufPair::new();
+
+            // A push releases the first piece before the pipe is
+            // drained
+            pair.upper().wr.append(&[0x44; 300]);
+            if push {
+                pair.upper().wr.push();
+            }
+            link.process(&mut stream, pair.lower()).unwrap();
+            assert_eq!(pair.lower().rd.len(), 200);
+            let mut buf = [0; 4096];
+            match peer.read(&mut buf) {
+                Ok(len) => assert!(push && len == 100),
+                Err(e) => assert!(!push && e.kind() == ErrorKind::WouldBlock, "{}", e),
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    #[test]
+    fn cork_unsupported() {
+        let (mut stream, _peer) = tcp_pair();
+        let mut link = open_link();
+        let mut pair = PipeBufPair::new();
+        link.set_cork(true);
+        let e = link.process(&mut stream, pair.lower()).unwrap_err();
+        assert_eq!(e.op(), Op::SetOption);
+        assert_eq!(e.kind(), ErrorKind::Unsupported);
+    }
+
+    // Drive the link only from edge-triggered events, as a `mio`
+    // event loop would, while a peer sends `len` bytes and closes.
+    // Returns the bytes received once events stop arriving, and
+    // whether the EOF was seen.
+    fn edge_triggered_receive(mut link: TcpLink, len: usize) -> (usize, bool) {
+        let (mut stream, mut peer) = tcp_pair();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(8);
+        link.register(&mut stream, poll.registry(), Token(0))
+            .unwrap();
+        let writer = std::thread::spawn(move || {
+            peer.write_all(&vec![0x66; len]).unwrap();
+            peer.shutdown(std::net::Shutdown::Write).unwrap();
+            peer
+        });
+
+        let mut pair = PipeBufPair::new();
+        let mut received = 0;
+        let mut eof = false;
+        loop {
+            poll.poll(&mut events, Some(Duration::from_millis(300)))
+                .unwrap();
+            if events.is_empty() {
+                break;
+            }
+            for event in events.iter() {
+                link.process_event(&mut stream, pair.lower(), event)
+                    .unwrap();
+                let mut app = pair.upper();
+                received += app.rd.len();
+                app.rd.consume(app.rd.len());
+                eof |= app.rd.consume_eof();
+            }
+        }
+        let _peer = writer.join().unwrap();
+        (received, eof)
+    }
+
+    #[test]
+    fn edge_triggered_drains_all() {
+        let len = 1 << 20;
+        let mut link = open_link();
+        link.set_max_read_unit(2048);
+        link.set_max_read_total(usize::MAX);
+        assert_eq!(edge_triggered_receive(link, len), (len, true));
+    }
+
+    #[test]
+    fn edge_triggered_stalls_on_single_unit() {
+        // The documented hazard: with one unit per call, data is left
+        // in the stream with no further event to report it
+        let len = 1 << 20;
+        let mut link = open_link();
+        link.set_max_read_unit(2048);
+        let (received, eof) = edge_triggered_receive(link, len);
+        assert!(received < len);
+        assert!(!eof);
+    }
+
+    #[test]
+    fn backpressure_bounds_slow_consumer() {
+        let (mut stream, mut peer) = tcp_pair();
+        let mut link = open_link();
+        link.set_max_read_unit(8192);
+        link.set_read_backpressure(Some(65536));
+        let len = 2 << 20;
+        let writer = std::thread::spawn(move || {
+            peer.write_all(&vec![0x77; len]).unwrap();
+            peer.shutdown(std::net::Shutdown::Write).unwrap();
+            peer
+        });
+
+        // The consumer takes 4KB per iteration, slower than the link
+        // can read
+        let mut pair = PipeBufPair::new();
+        let mut received = 0;
+        let mut peak = 0;
+        wait_for(|| {
+            link.process(&mut stream, pair.lower()).unwrap();
+            let mut app = pair.upper();
+            peak = peak.max(app.rd.len());
+            let take = app.rd.len().min(4096);
+            app.rd.consume(take);
+            received += take;
+            app.rd.is_empty() && app.rd.consume_eof()
+        });
+        let _peer = writer.join().unwrap();
+        assert_eq!(received, len);
+        assert!(peak > 65536 - 8192, "peak {}", peak);
+        assert!(peak <= 65536 + 8192, "peak {}", peak);
+    }
+
+    #[test]
+    fn backpressure_resumes_at_half() {
+        let (mut stream, mut peer) = tcp_pair();
+        let mut link = open_link();
+        link.set_max_read_unit(4096);
+        link.set_read_backpressure(Some(10000));
+        let mut pair = PipeBufPair::new();
+        peer.write_all(&[0; 65536]).unwrap();
+
+        wait_for(|| {
+            link.process(&mut stream, pair.lower()).unwrap();
+            pair.upper().rd.len() > 10000
+        });
+        let held = pair.upper().rd.len();
+        let act = link.process(&mut stream, pair.lower()).unwrap();
+        assert_eq!(act, Activity::NONE);
+        assert!(link.needed_interest(&pair.lower()).is_none());
+
+        // Still held off until drained to half the threshold
+        pair.upper().rd.consume(held - 6000);
+        assert_eq!(
+            link.process(&mut stream, pair.lower()).unwrap(),
+            Activity::NONE
+        );
+        assert_eq!(pair.upper().rd.len(), 6000);
+        pair.upper().rd.consume(1000);
+        let act = link.process(&mut stream, pair.lower()).unwrap();
+        assert!(act.contains(Activity::READ));
+        assert_eq!(pair.upper().rd.len(), 5000 + 4096);
+    }
+
+    #[test]
+    fn read_rate_limit_accuracy() {
+        let (mut stream, mut peer) = tcp_pair();
+        let mut link = open_link();
+        let mut pair = PipeBufPair::new();
+        link.set_clock(fake_now);
+        link.set_max_read_unit(4096);
+        link.set_max_read_total(usize::MAX);
+        link.set_read_rate_limit(Some(RateLimit::new(100_000, 8192)));
+        let writer = std::thread::spawn(move || {
+            peer.write_all(&vec![0; 400_000]).unwrap();
+            peer
+        });
+
+        // Run for one second of the link's clock, jumping ahead to
+        // each deadline
+        let start = fake_now();
+        let end = start + Duration::from_secs(1);
+        let mut received = 0;
+        while fake_now() < end {
+            link.process(&mut stream, pair.lower()).unwrap();
+            let mut app = pair.upper();
+            received += app.rd.len();
+            app.rd.consume(app.rd.len());
+            match link.next_deadline() {
+                Some(deadline) => {
+                    let interest = link.needed_interest(&pair.lower());
+                    assert!(!interest.is_some_and(|i| i.is_readable()));
+                    advance(deadline.min(end).saturating_duration_since(fake_now()));
+                }
+                // Waiting for the peer's data to arrive
+                None => std::thread::sleep(Duration::from_millis(1)),
+            }
+        }
+        let expected = 100_000 + 8192;
+        let err = (received as f64 - expected as f64).abs() / expected as f64;
+        assert!(
+            err < 0.03,
+            "received {} in 1s, expected {}",
+            received,
+            expected
+        );
+
+        // Removing the limit lets the rest through
+        link.set_read_rate_limit(None);
+        assert_eq!(link.next_deadline(), None);
+        let _peer = writer.join().unwrap();
+        wait_for(|| {
+            link.process(&mut stream, pair.lower()).unwrap();
+            let mut app = pair.upper();
+            received += app.rd.len();
+            app.rd.consume(app.rd.len());
+            received == 400_000
+        });
+    }
+
+    #[test]
+    fn write_rate_limit_shapes_transfer() {
+        let (mut stream, mut peer) = tcp_pair();
+        let mut link = open_link();
+        let mut pair = PipeBufPair::new();
+        link.set_clock(fake_now);
+        link.set_write_rate_limit(Some(RateLimit::new(100_000, 16384)));
+        let len = 1 << 20;
+        let reader = std::thread::spawn(move || read_to_end(&mut peer));
+
+        pair.upper().wr.append(&vec![0x88; len]);
+        pair.upper().wr.close();
+        let start = fake_now();
+        wait_for(|| {
+            let act = link.process(&mut stream, pair.lower()).unwrap();
+            if act.contains(Activity::EOF_OUT) {
+                return true;
+            }
+            if let Some(deadline) = link.next_deadline() {
+                let interest = link.needed_interest(&pair.lower());
+                assert!(!interest.is_some_and(|i| i.is_writable()));
+                advance(deadline.saturating_duration_since(fake_now()));
+            }
+            false
+        });
+
+        // The EOF goes out with the last throttled chunk
+        assert!(pair.lower().rd.is_done());
+        let secs = (fake_now() - start).as_secs_f64();
+        let expected = (len - 16384) as f64 / 100_000.0;
+        assert!((secs - expected).abs() / expected < 0.02, "took {}s", secs);
+        let (data, rv) = reader.join().unwrap();
+        rv.unwrap();
+        assert_eq!(data.len(), len);
+    }
+
+    // Run a busy event loop over a link where the peer only sends
+    // occasionally, calling `process` on every iteration as a
+    // level-triggered loop would for other connections' events.
+    // Returns the read and write syscalls made.
+    fn busy_loop_syscalls(latch: bool) -> (u64, u64) {
+        let (mut stream, mut peer) = tcp_pair();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(4);
+        let mut link = TcpLink::new();
+        link.set_wouldblock_latch(latch);
+        link.enable_stats(true);
+        link.register(&mut stream, poll.registry(), Token(0))
+            .unwrap();
+        let mut pair = PipeBufPair::new();
+
+        let mut received = 0;
+        for i in 0..200 {
+            if i % 20 == 0 {
+                peer.write_all(b"tick").unwrap();
+                std::thread::sleep(Duration::from_millis(2));
+            }
+            poll.poll(&mut events, Some(Duration::ZERO)).unwrap();
+            match events.iter().next() {
+                Some(event) => link.process_event(&mut stream, pair.lower(), event),
+                None => link.process(&mut stream, pair.lower()),
+            }
+            .unwrap();
+            let mut app = pair.upper();
+            received += app.rd.len();
+            app.rd.consume(app.rd.len());
+        }
+        assert_eq!(received, 40);
+        let stats = link.stats().unwrap();
+        (stats.read_calls, stats.write_calls)
+    }
+
+    #[test]
+    fn wouldblock_latch_saves_syscalls() {
+        let (reads, writes) = busy_loop_syscalls(false);
+        assert!(reads >= 200, "{} reads", reads);
+        assert_eq!(writes, 0);
+        let (latched, writes) = busy_loop_syscalls(true);
+        assert!(latched * 2 <= reads, "{} vs {} reads", latched, reads);
+        assert!(latched >= 10, "{} reads", latched);
+        assert_eq!(writes, 0);
+    }
+
+    #[test]
+    fn wouldblock_latch_cleared_by_note() {
+        let (mut stream, mut peer) = tcp_pair();
+        let mut link = open_link();
+        link.set_wouldblock_latch(true);
+        link.enable_stats(true);
+        let mut pair = PipeBufPair::new();
+
+        link.process(&mut stream, pair.lower()).unwrap();
+        assert_eq!(link.stats().unwrap().read_would_block, 1);
+        peer.write_all(b"data").unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        link.process(&mut stream, pair.lower()).unwrap();
+        assert_eq!(link.stats().unwrap().read_calls, 1);
+        assert!(pair.upper().rd.is_empty());
+
+        link.note_readable();
+        link.process(&mut stream, pair.lower()).unwrap();
+        assert_eq!(pair.upper().rd.data(), b"data");
+    }
+
+    #[test]
+    fn write_quota_exact_boundary() {
+        let (mut stream, mut peer) = tcp_pair();
+        let mut link = open_link();
+        link.set_write_quota(Some(10));
+        let mut pair = PipeBufPair::new();
+
+        pair.upper().wr.append(b"0123456789");
+        pair.upper().wr.close();
+        let act = link.process(&mut stream, pair.lower()).unwrap();
+        assert!(act.contains(Activity::EOF_OUT));
+        assert!(!act.contains(Activity::QUOTA));
+        assert_eq!(link.write_quota(), Some(0));
+        let (data, rv) = read_to_end(&mut peer);
+        rv.unwrap();
+        assert_eq!(data, b"0123456789");
+    }
+
+    #[test]
+    fn write_quota_stalls() {
+        let (mut stream, mut peer) = tcp_pair();
+        let mut link = open_link();
+        link.set_write_quota(Some(10));
+        let mut pair = PipeBufPair::new();
+
+        pair.upper().wr.append(b"0123456789abcde");
+        pair.upper().wr.close();
+        let act = link.process(&mut stream, pair.lower()).unwrap();
+        assert!(act.contains(Activity::QUOTA | Activity::WROTE));
+        assert!(!act.contains(Activity::EOF_OUT));
+        assert_eq!(pair.lower().rd.data(), b"abcde");
+        let mut buf = [0; 10];
+        peer.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"0123456789");
+
+        // Reported once, and no more writes are wanted
+        let act = link.process(&mut stream, pair.lower()).unwrap();
+        assert_eq!(act, Activity::NONE);
+        let interest = link.needed_interest(&pair.lower());
+        assert!(!interest.is_some_and(|i| i.is_writable()));
+
+        // Raising the quota lets the rest through
+        link.set_write_quota(Some(5));
+        let act = link.process(&mut stream, pair.lower()).unwrap();
+        assert!(act.contains(Activity::EOF_OUT));
+        assert!(!act.contains(Activity::QUOTA));
+        let (data, rv) = read_to_end(&mut peer);
+        rv.unwrap();
+        assert_eq!(data, b"abcde");
+    }
+
+    #[test]
+    fn write_quota_aborts() {
+        let (mut stream, mut peer) = tcp_pair();
+        let mut link = open_link();
+        link.set_write_quota(Some(10));
+        link.set_write_quota_mode(WriteQuotaMode::Abort);
+        let mut pair = PipeBufPair::new();
+
+        pair.upper().wr.append(b"0123456789abcde");
+        let act = link.process(&mut stream, pair.lower()).unwrap();
+        assert!(act.contains(Activity::QUOTA | Activity::ABORTED));
+        assert!(pair.upper().rd.is_aborted());
+        assert_eq!(link.state(), LinkState::Aborted);
+        let (data, rv) = read_to_end(&mut peer);
+        rv.unwrap();
+        assert_eq!(data, b"0123456789");
+    }
+
+    #[test]
+    fn fixed_capacity_pipe_bounds_memory() {
+        let (mut stream, mut peer) = tcp_pair();
+        let mut link = open_link();
+        link.set_max_read_unit(16384);
+        link.set_max_read_total(usize::MAX);
+        let len = 1 << 20;
+        let writer = std::thread::spawn(move || {
+            peer.write_all(&vec![0x99; len]).unwrap();
+            peer.shutdown(std::net::Shutdown::Write).unwrap();
+            peer
+        });
+
+        let mut pair = PipeBufPair::with_fixed_capacities(4096, 4096);
+        let mut received = 0;
+        let mut full = 0;
+        wait_for(|| {
+            link.process(&mut stream, pair.lower()).unwrap();
+            let mut app = pair.upper();
+            assert!(app.rd.len() <= 4096);
+            if app.rd.len() == 4096 {
+                // A full pipe is not an error, and nothing more is read
+                full += 1;
+                let act = link.process(&mut stream, pair.lower()).unwrap();
+                assert_eq!(act, Activity::NONE);
+                pair.upper().rd.consume(1000);
+                received += 1000;
+                return false;
+            }
+            let take = app.rd.len();
+            app.rd.consume(take);
+            received += take;
+            app.rd.consume_eof()
+        });
+        let _peer = writer.join().unwrap();
+        assert!(full > 0);
+        assert_eq!(received, len);
+    }
+
+    #[test]
+    fn split_halves_recover_from_latch() {
+        let (stream, mut peer) = tcp_pair();
+        let mut link = open_link();
+        link.set_wouldblock_latch(true);
+        link.enable_stats(true);
+        let (mut rd, mut wr) = link.split();
+        let mut incoming = PipeBuf::new();
+        let mut outgoing = PipeBuf::new();
+
+        // The read half latches on `WouldBlock`, and data arriving
+        // later is left alone until readiness is signalled
+        rd.process(&stream, incoming.wr()).unwrap();
+        assert_eq!(rd.stats().unwrap().read_would_block, 1);
+        peer.write_all(b"late").unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        rd.process(&stream, incoming.wr()).unwrap();
+        assert_eq!(rd.stats().unwrap().read_calls, 1);
+        rd.note_readable();
+        rd.process(&stream, incoming.wr()).unwrap();
+        assert_eq!(incoming.rd().data(), b"late");
+
+        // Likewise for unpausing
+        rd.process(&stream, incoming.wr()).unwrap();
+        peer.write_all(b"more").unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        rd.set_pause_reads(false);
+        rd.process(&stream, incoming.wr()).unwrap();
+        assert_eq!(incoming.rd().data(), b"latemore");
+
+        // The write half latches once the stream is full
+        let len = 16 << 20;
+        outgoing.wr().append(&vec![0; len]);
+        wait_for(|| {
+            wr.process(&stream, outgoing.rd()).unwrap();
+            wr.stats().unwrap().write_would_block > 0
+        });
+        let left = outgoing.rd().len();
+        let reader = std::thread::spawn(move || {
+            let mut buf = vec![0; len - left];
+            peer.read_exact(&mut buf).unwrap();
+            peer
+        });
+        let mut peer = reader.join().unwrap();
+        wr.process(&stream, outgoing.rd()).unwrap();
+        assert_eq!(outgoing.rd().len(), left);
+
+        wr.note_writable();
+        outgoing.wr().close();
+        let reader = std::thread::spawn(move || read_to_end(&mut peer).0.len());
+        wait_for(|| {
+            wr.note_writable();
+            wr.process(&stream, outgoing.rd()).unwrap();
+            outgoing.rd().is_done()
+        });
+        assert_eq!(reader.join().unwrap(), left);
+    }
+
+    // Send a single TCP urgent byte from the peer
+    #[cfg(target_os = "linux")]
+    fn send_urgent(peer: &std::net::TcpStream, byte: u8) {
+        use std::os::unix::io::AsRawFd;
+        let rv = unsafe {
+            libc::send(
+                peer.as_raw_fd(),
+                &byte as *const u8 as *const libc::c_void,
+                1,
+                libc::MSG_OOB,
+            )
+        };
+        assert_eq!(rv, 1);
+    }
+
+    // Send "ab", an urgent "X" and then "cd" from the peer, and pass
+    // events to the link until `want` bytes and a priority event
+    // have been seen.  Returns the data received.
+    #[cfg(target_os = "linux")]
+    fn receive_with_urgent(link: &mut TcpLink, want: usize) -> Vec<u8> {
+        let (mut stream, mut peer) = tcp_pair();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(4);
+        let mut pair = PipeBufPair::new();
+        link.register(&mut stream, poll.registry(), Token(0))
+            .unwrap();
+        link.process(&mut stream, pair.lower()).unwrap();
+
+        peer.write_all(b"ab").unwrap();
+        send_urgent(&peer, b'X');
+        peer.write_all(b"cd").unwrap();
+        let mut received = Vec::new();
+        let mut priority = false;
+        wait_for(|| {
+            poll.poll(&mut events, Some(Duration::from_millis(10)))
+                .unwrap();
+            for event in events.iter() {
+                priority |= event.is_priority();
+                link.process_event(&mut stream, pair.lower(), event)
+                    .unwrap();
+            }
+            let mut app = pair.upper();
+            received.extend_from_slice(app.rd.data());
+            app.rd.consume(app.rd.len());
+            priority && received.len() >= want
+        });
+
+        // Nothing is left waiting in the urgent slot either way
+        let fd = std::os::unix::io::AsRawFd::as_raw_fd(&stream);
+        assert!(!crate::sockopt::discard_oob(fd).unwrap());
+        received
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn urgent_byte_is_discarded() {
+        let mut link = open_link();
+        let received = receive_with_urgent(&mut link, 4);
+        assert_eq!(received, b"abcd");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn urgent_byte_passed_inline() {
+        let mut link = open_link();
+        link.set_oob_inline(true);
+        let received = receive_with_urgent(&mut link, 5);
+        assert_eq!(received, b"abXcd");
     }
 }
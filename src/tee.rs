@@ -0,0 +1,150 @@
+use crate::generic::{read_in_with, ReadLimits};
+use crate::{Activity, Result};
+use pipebuf::{PBufWr, PipeBuf};
+use std::fmt;
+use std::io::Read;
+
+/// What a [`Tee`] does when its pipe is full
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TeePolicy {
+    /// Drop any chunk of data that doesn't fit completely
+    Drop,
+
+    /// Copy as much of each chunk as fits, and drop the rest
+    Truncate,
+
+    /// Stop reading from the stream until the consumer of the tee
+    /// pipe has made room.  This stalls the main path.
+    Backpressure,
+}
+
+/// Secondary pipe that receives a copy of all incoming stream data
+///
+/// This is for traffic inspection or recording.  Install it on a
+/// link with [`crate::TcpLink::set_tee`], and every byte read from
+/// the stream is also copied into the tee's pipe, which the recorder
+/// consumes via [`Tee::pipe`].  The pipe is considered full when it
+/// holds more than the limit given on creation, and then the
+/// [`TeePolicy`] decides what happens.  With `Drop` or `Truncate`,
+/// the recorder cannot stall the main path.  Data that is dropped is
+/// counted, see [`Tee::dropped`].
+///
+/// When the incoming pipe of the main path is closed or aborted, the
+/// tee pipe is closed or aborted in the same way, so the recorder
+/// sees a complete picture.
+pub struct Tee {
+    // Pipe that the copies go into
+    pipe: PipeBuf,
+
+    // What to do when the pipe is full
+    policy: TeePolicy,
+
+    // Maximum amount of unconsumed data in the pipe (in bytes)
+    limit: usize,
+
+    // Count of bytes dropped
+    dropped: u64,
+}
+
+impl Tee {
+    /// Create a tee with the given policy, which considers its pipe
+    /// full when it holds more than `limit` bytes of unconsumed data
+    #[inline]
+    pub fn new(policy: TeePolicy, limit: usize) -> Self {
+        Self {
+            pipe: PipeBuf::new(),
+            policy,
+            limit,
+            dropped: 0,
+        }
+    }
+
+    /// Get access to the tee pipe, for the recorder to consume the
+    /// data via `PipeBuf::rd`
+    #[inline]
+    pub fn pipe(&mut self) -> &mut PipeBuf {
+        &mut self.pipe
+    }
+
+    /// Get the number of bytes dropped so far because the pipe was
+    /// full
+    #[inline]
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    // Test whether reads on the main path should be held back
+    fn is_blocking(&mut self) -> bool {
+        self.policy == TeePolicy::Backpressure && self.pipe.wr().exceeds_limit(self.limit)
+    }
+
+    // Copy a chunk of data read on the main path
+    fn copy(&mut self, data: &[u8]) {
+        if self.pipe.wr().is_eof() {
+            return;
+        }
+        let room = self.limit.saturating_sub(self.pipe.rd().len());
+        let len = match self.policy {
+            TeePolicy::Backpressure => data.len(),
+            TeePolicy::Truncate => data.len().min(room),
+            TeePolicy::Drop if data.len() <= room => data.len(),
+            TeePolicy::Drop => 0,
+        };
+        self.pipe.wr().append(&data[..len]);
+        self.dropped += (data.len() - len) as u64;
+    }
+
+    // Pass on close or abort of the main path's incoming pipe
+    fn reflect_eof(&mut self, act: Activity) {
+        if act.contains(Activity::EOF_IN) {
+            let mut pwr = self.pipe.wr();
+            if act.contains(Activity::ABORTED) {
+                pwr.abort();
+            } else {
+                pwr.close();
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Tee {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tee")
+            .field("policy", &self.policy)
+            .field("limit", &self.limit)
+            .field("dropped", &self.dropped)
+            .field("done", &self.pipe.is_done())
+            .finish()
+    }
+}
+
+// Read as for `read_in_with`, copying the data into the tee if there
+// is one
+pub(crate) fn read_in_tee(
+    stream: &mut impl Read,
+    pwr: PBufWr,
+    limits: ReadLimits,
+    tee: &mut Option<Tee>,
+    mut on_data: impl FnMut(&[u8]),
+) -> Result<Activity> {
+    let Some(tee) = tee else {
+        return read_in_with(stream, pwr, limits, on_data);
+    };
+    if tee.is_blocking() {
+        return Ok(Activity::NONE);
+    }
+    let act = read_in_with(stream, pwr, limits, |data| {
+        tee.copy(data);
+        on_data(data);
+    })?;
+    tee.reflect_eof(act);
+    Ok(act)
+}
+
+// Pass on close or abort of the incoming pipe from other paths
+#[inline]
+pub(crate) fn tee_eof(tee: &mut Option<Tee>, act: Activity) {
+    if let Some(tee) = tee {
+        tee.reflect_eof(act);
+    }
+}
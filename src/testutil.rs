@@ -0,0 +1,99 @@
+// Helpers shared by the unit tests
+
+use std::cell::Cell;
+use std::io::{self, Read};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Connected TCP pair over loopback: a non-blocking `mio` stream for
+// the link, and a blocking std stream for the peer, with a read
+// timeout so that a broken test fails instead of hanging
+pub(crate) fn tcp_pair() -> (mio::net::TcpStream, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let peer = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (stream, _) = listener.accept().unwrap();
+    stream.set_nonblocking(true).unwrap();
+    peer.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    (mio::net::TcpStream::from_std(stream), peer)
+}
+
+// Call `step` until it returns true, failing after 5 seconds
+#[track_caller]
+pub(crate) fn wait_for(mut step: impl FnMut() -> bool) {
+    let start = Instant::now();
+    while !step() {
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "timed out waiting for condition"
+        );
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+// Read from the peer until EOF or an error, returning the data and
+// the final outcome
+pub(crate) fn read_to_end(peer: &mut impl Read) -> (Vec<u8>, io::Result<()>) {
+    let mut data = Vec::new();
+    let mut buf = [0; 4096];
+    loop {
+        match peer.read(&mut buf) {
+            Ok(0) => return (data, Ok(())),
+            Ok(len) => data.extend_from_slice(&buf[..len]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return (data, Err(e)),
+        }
+    }
+}
+
+// Close the peer with `SO_LINGER` set to zero, so that the link's
+// end of the connection sees a reset
+#[cfg(unix)]
+pub(crate) fn reset_peer(peer: TcpStream) {
+    use std::os::unix::io::AsRawFd;
+    crate::sockopt::set_linger_zero(peer.as_raw_fd()).unwrap();
+}
+
+// A unique path in the temporary directory for a Unix socket.  Any
+// file already there is removed.
+#[cfg(unix)]
+pub(crate) fn socket_path(name: &str) -> std::path::PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let n = COUNT.fetch_add(1, Ordering::Relaxed);
+    let path = std::env::temp_dir().join(format!(
+        "pipebuf_mio-{}-{}-{}.sock",
+        std::process::id(),
+        name,
+        n
+    ));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+// Lower the listen backlog of a listening socket to zero, so that it
+// fills up after a single unaccepted connection
+#[cfg(target_os = "linux")]
+pub(crate) fn shrink_backlog(listener: &impl std::os::unix::io::AsRawFd) {
+    assert_eq!(unsafe { libc::listen(listener.as_raw_fd(), 0) }, 0);
+}
+
+thread_local! {
+    static FAKE_NOW: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+// Clock for `set_clock` that only moves on `advance`.  Each test
+// thread has its own.
+pub(crate) fn fake_now() -> Instant {
+    FAKE_NOW.with(|now| {
+        let t = now.get().unwrap_or_else(Instant::now);
+        now.set(Some(t));
+        t
+    })
+}
+
+// Move the fake clock forwards
+pub(crate) fn advance(by: Duration) {
+    let t = fake_now() + by;
+    FAKE_NOW.with(|now| now.set(Some(t)));
+}
@@ -0,0 +1,121 @@
+// Optional `tracing` events from the stream links.  Without the
+// `tracing` feature, `Tracer` is zero-sized and all its methods
+// compile to nothing.
+
+use crate::{Activity, Direction, Error};
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Tracer {
+    // Label attached to every event, to tell links apart
+    #[cfg(feature = "tracing")]
+    pub(crate) label: String,
+}
+
+#[cfg(feature = "tracing")]
+use std::fmt;
+
+#[cfg(feature = "tracing")]
+impl Tracer {
+    #[inline]
+    pub(crate) fn set_label(&mut self, label: String) {
+        self.label = label;
+    }
+
+    // Report the outcome of a read of up to `limit` bytes.  A short
+    // read that didn't end in EOF must have hit `WouldBlock`.
+    pub(crate) fn read(&self, bytes: usize, limit: usize, act: Activity) {
+        let link = &self.label;
+        if bytes > 0 {
+            tracing::trace!(link, bytes, "read");
+        }
+        if act.contains(Activity::EOF_IN) {
+            if act.contains(Activity::ABORTED) {
+                tracing::debug!(link, "incoming abort, passed on to pipe");
+            } else {
+                tracing::debug!(link, "incoming EOF, passed on to pipe");
+            }
+        } else if bytes < limit {
+            tracing::trace!(link, "read would block");
+        }
+    }
+
+    // Report the outcome of a write, with `left` bytes remaining in
+    // the outgoing pipe
+    pub(crate) fn wrote(&self, bytes: usize, left: usize, act: Activity) {
+        let link = &self.label;
+        if bytes > 0 {
+            tracing::trace!(link, bytes, "wrote");
+        }
+        if left > 0 {
+            tracing::trace!(link, left, "write would block");
+        }
+        if act.contains(Activity::EOF_OUT) {
+            if act.contains(Activity::ABORTED) {
+                tracing::debug!(link, "outgoing abort, shutdown of both halves");
+                tracing::debug!(link, "abort passed on to incoming pipe");
+            } else {
+                tracing::debug!(link, "outgoing EOF, shutdown of write half");
+            }
+        }
+    }
+
+    // Report an option change applied to the stream
+    pub(crate) fn option(&self, name: &'static str, value: impl fmt::Debug) {
+        tracing::debug!(link = &self.label, option = name, ?value, "option applied");
+    }
+
+    // Report an abort due to the idle timeout
+    pub(crate) fn timed_out(&self) {
+        tracing::debug!(link = &self.label, "idle timeout, link aborted");
+    }
+
+    // Report an abort due to the close deadline
+    pub(crate) fn close_expired(&self) {
+        tracing::debug!(link = &self.label, "close deadline passed, link aborted");
+    }
+
+    // Report a byte quota running out
+    pub(crate) fn quota(&self, direction: Direction) {
+        tracing::debug!(link = &self.label, %direction, "quota reached");
+    }
+
+    // Report a TCP urgent byte read and dropped
+    pub(crate) fn oob_discarded(&self) {
+        tracing::debug!(link = &self.label, "urgent byte discarded");
+    }
+
+    // Report a fatal error from the stream
+    pub(crate) fn error(&self, error: &Error) {
+        tracing::debug!(link = &self.label, %error, "link failed");
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl Tracer {
+    #[inline(always)]
+    pub(crate) fn set_label(&mut self, _label: String) {}
+
+    #[inline(always)]
+    pub(crate) fn read(&self, _bytes: usize, _limit: usize, _act: Activity) {}
+
+    #[inline(always)]
+    pub(crate) fn wrote(&self, _bytes: usize, _left: usize, _act: Activity) {}
+
+    #[inline(always)]
+    pub(crate) fn option(&self, _name: &'static str, _value: impl std::fmt::Debug) {}
+
+    #[inline(always)]
+    pub(crate) fn timed_out(&self) {}
+
+    #[inline(always)]
+    pub(crate) fn close_expired(&self) {}
+
+    #[inline(always)]
+    pub(crate) fn quota(&self, _direction: Direction) {}
+
+    #[inline(always)]
+    pub(crate) fn oob_discarded(&self) {}
+
+    #[inline(always)]
+    pub(crate) fn error(&self, _error: &Error) {}
+}
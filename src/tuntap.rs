@@ -0,0 +1,198 @@
+use crate::activity::{abort_in, consume_eof_out};
+use crate::fd::{raw_file, set_nonblocking};
+use crate::record::{self, HEADER_LEN};
+use crate::{Activity, Error, Result};
+use pipebuf::PBufRdWr;
+use std::io::{self, ErrorKind, Read, Write};
+use std::os::unix::io::RawFd;
+
+/// Exchange packets via a tun or tap device file descriptor
+///
+/// Each `read` on a tun/tap device returns exactly one packet, and
+/// each `write` must provide exactly one packet.  So packet
+/// boundaries are preserved by framing each packet as a record in
+/// the pipe-buffer: a 4-byte big-endian length followed by the
+/// packet, as for [`crate::UdpLink`].
+///
+/// The file descriptor is not owned by the link, and must remain open
+/// for as long as the link is in use.  On creation it is switched to
+/// non-blocking mode if it is not already.
+///
+/// Packets read that are longer than **max_packet_size** are
+/// discarded, since they would have been truncated.  A write that
+/// does not accept the whole packet means that the device is not
+/// behaving as expected, so this is treated as an abort: pending
+/// outgoing data is discarded and the incoming pipe is aborted.  An
+/// outgoing record that the device rejects gives an error.
+///
+/// On outgoing EOF, any incomplete trailing record is discarded and
+/// the EOF is consumed.  An outgoing "abort" is passed on as an
+/// "abort" of the incoming pipe.  The incoming pipe is never closed
+/// by this link.
+///
+/// Reading and writing start unpaused.
+#[derive(Debug)]
+pub struct TunTapLink {
+    // The file descriptor
+    fd: RawFd,
+
+    // Maximum amount of record data to read in one go (in bytes)
+    max_read_unit: usize,
+
+    // Maximum size of a packet accepted on read
+    max_packet_size: usize,
+
+    // Set to pause writes
+    pause_writes: bool,
+
+    // Set to pause reads
+    pause_reads: bool,
+}
+
+impl TunTapLink {
+    /// Create the component for the given file descriptor, switching
+    /// it to non-blocking mode if necessary, with default settings:
+    ///
+    /// - **max_read_unit** of 65536.  At least one packet is always
+    ///   read if available, even if this limit is smaller.
+    ///
+    /// - **max_packet_size** of 65535
+    ///
+    /// - Both reads and writes unpaused
+    pub fn new(fd: RawFd) -> io::Result<Self> {
+        set_nonblocking(fd)?;
+        Ok(Self {
+            fd,
+            max_read_unit: 65536,
+            max_packet_size: 65535,
+            pause_writes: false,
+            pause_reads: false,
+        })
+    }
+
+    /// Get the file descriptor, e.g. for registering via
+    /// [`mio::unix::SourceFd`]
+    #[inline]
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    /// Change the maximum number of bytes of records to read in each
+    /// `process` call.  Reading stops once this limit has been
+    /// reached or exceeded.
+    #[inline]
+    pub fn set_max_read_unit(&mut self, max_read_unit: usize) {
+        self.max_read_unit = max_read_unit;
+    }
+
+    /// Change the maximum size of a packet that will be accepted.
+    /// Larger packets are discarded.  This should be at least the
+    /// MTU of the device, plus any header added by the device.
+    #[inline]
+    pub fn set_max_packet_size(&mut self, max_packet_size: usize) {
+        self.max_packet_size = max_packet_size;
+    }
+
+    /// Pause or unpause writes.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_writes(&mut self, pause: bool) {
+        self.pause_writes = pause;
+    }
+
+    /// Pause or unpause reads.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_reads(&mut self, pause: bool) {
+        self.pause_reads = pause;
+    }
+
+    /// Read and write as many packets as possible from and to the
+    /// device.  Returns the activity flags (see [`Activity`]), which
+    /// are empty if no progress could be made, or `Err(_)` if there
+    /// was a fatal error.
+    ///
+    /// Assumes that it is always called with the same pipe-buffer.
+    /// Things will behave unpredictably otherwise.
+    pub fn process(&mut self, mut pbuf: PBufRdWr) -> Result<Activity> {
+        let rd_activity = self.process_out(pbuf.reborrow())?;
+        let wr_activity = self.process_in(pbuf.reborrow())?;
+        Ok(rd_activity | wr_activity)
+    }
+
+    /// Write as many complete records as possible from the outgoing
+    /// pipe to the device, one packet per record.  Returns the
+    /// activity flags (see [`Activity`]), which are empty if no
+    /// progress could be made, or `Err(_)` if there was a fatal
+    /// error.
+    pub fn process_out(&mut self, mut pbuf: PBufRdWr) -> Result<Activity> {
+        if self.pause_writes {
+            return Ok(Activity::NONE);
+        }
+
+        let mut prd = pbuf.rd;
+        let mut act = Activity::NONE;
+        let mut dev = raw_file(self.fd);
+        while let Some(total) = record::next_record(prd.data()) {
+            match retry!(dev.write(&prd.data()[HEADER_LEN..total])) {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(act),
+                Err(e) => return Err(Error::write(e)),
+                Ok(len) if len + HEADER_LEN == total => {
+                    prd.consume(total);
+                    act |= Activity::WROTE;
+                }
+                Ok(_) => {
+                    let len = prd.len();
+                    prd.consume(len);
+                    consume_eof_out(&mut prd, &mut act);
+                    abort_in(&mut pbuf.wr, &mut act);
+                    return Ok(act);
+                }
+            }
+        }
+        if prd.has_pending_eof() {
+            let len = prd.len();
+            prd.consume(len);
+            consume_eof_out(&mut prd, &mut act);
+            if prd.is_aborted() && !pbuf.wr.is_eof() {
+                abort_in(&mut pbuf.wr, &mut act);
+            }
+        }
+        Ok(act)
+    }
+
+    /// Read as many packets as possible from the device, up to
+    /// **max_read_unit** bytes of records, writing each as a record
+    /// to the incoming pipe.  Returns the activity flags (see
+    /// [`Activity`]), which are empty if no progress could be made,
+    /// or `Err(_)` if there was a fatal error.
+    pub fn process_in(&mut self, pbuf: PBufRdWr) -> Result<Activity> {
+        let mut pwr = pbuf.wr;
+        if self.pause_reads || pwr.is_eof() {
+            return Ok(Activity::NONE);
+        }
+
+        let mut act = Activity::NONE;
+        let mut dev = raw_file(self.fd);
+        let mut total = 0;
+        while total < self.max_read_unit {
+            // Reserve one extra byte to detect truncation
+            let Some(space) = pwr.try_space(HEADER_LEN + self.max_packet_size + 1) else {
+                // No room for a whole datagram in a fixed-capacity pipe
+                break;
+            };
+            match retry!(dev.read(&mut space[HEADER_LEN..])) {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(Error::read(e)),
+                Ok(len) if len > self.max_packet_size => (),
+                Ok(len) => {
+                    record::put_header(space, len);
+                    pwr.commit(HEADER_LEN + len);
+                    act |= Activity::READ;
+                    total += HEADER_LEN + len;
+                }
+            }
+        }
+        Ok(act)
+    }
+}
@@ -0,0 +1,531 @@
+use crate::activity::{abort_in, consume_eof_out};
+use crate::record::{self, ADDR_MAX_LEN, HEADER_LEN};
+use crate::{Activity, Error, Result};
+use mio::net::UdpSocket;
+use pipebuf::PBufRdWr;
+use std::io::{self, ErrorKind};
+
+/// Exchange datagrams via a connected `mio` [`UdpSocket`]
+///
+/// Since a pipe-buffer carries a byte stream, datagram boundaries are
+/// preserved by framing each datagram as a record: a 4-byte
+/// big-endian length followed by the payload.  Each datagram received
+/// is written to the incoming pipe as one record.  On the outgoing
+/// side, records are parsed from the pipe and each complete record is
+/// sent as one datagram.  A partially-written record is left in the
+/// pipe until the rest of it arrives.
+///
+/// UDP has no notion of a stream ending, so the incoming pipe is
+/// never closed by this link.  When the outgoing pipe reaches EOF,
+/// any incomplete trailing record is discarded and the EOF is
+/// consumed.  An outgoing "abort" is passed on as an "abort" of the
+/// incoming pipe.
+///
+/// A `ConnectionRefused` error, which results from an ICMP
+/// "unreachable" response to an earlier datagram, is ignored since
+/// UDP delivery is unreliable anyway.  Received datagrams longer than
+/// **max_datagram_size** are discarded, since they would have been
+/// truncated.  A record too large to send gives an error.
+///
+/// Unlike the stream links, reading and writing start unpaused, since
+/// a UDP socket does not need to wait for a connection to complete.
+#[derive(Debug)]
+pub struct UdpLink {
+    // Maximum amount of record data to read in one go (in bytes)
+    max_read_unit: usize,
+
+    // Maximum size of a datagram accepted on receive
+    max_datagram_size: usize,
+
+    // Set to pause writes
+    pause_writes: bool,
+
+    // Set to pause reads
+    pause_reads: bool,
+}
+
+impl UdpLink {
+    /// Create the component with default settings:
+    ///
+    /// - **max_read_unit** of 65536.  At least one datagram is always
+    ///   read if available, even if this limit is smaller.
+    ///
+    /// - **max_datagram_size** of 65507, the maximum UDP payload over
+    ///   IPv4
+    ///
+    /// - Both reads and writes unpaused
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            max_read_unit: 65536,
+            max_datagram_size: 65507,
+            pause_writes: false,
+            pause_reads: false,
+        }
+    }
+
+    /// Change the maximum number of bytes of records to read in each
+    /// `process` call.  Reading stops once this limit has been
+    /// reached or exceeded.
+    #[inline]
+    pub fn set_max_read_unit(&mut self, max_read_unit: usize) {
+        self.max_read_unit = max_read_unit;
+    }
+
+    /// Change the maximum size of a datagram that will be accepted.
+    /// Larger datagrams are discarded.  A receive buffer of this size
+    /// is reserved in the incoming pipe-buffer on each read.
+    #[inline]
+    pub fn set_max_datagram_size(&mut self, max_datagram_size: usize) {
+        self.max_datagram_size = max_datagram_size;
+    }
+
+    /// Pause or unpause writes.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_writes(&mut self, pause: bool) {
+        self.pause_writes = pause;
+    }
+
+    /// Pause or unpause reads.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_reads(&mut self, pause: bool) {
+        self.pause_reads = pause;
+    }
+
+    /// Send and receive as many datagrams as possible to and from the
+    /// given UDP socket.  Returns the activity flags (see
+    /// [`Activity`]), which are empty if no progress could be made,
+    /// or `Err(_)` if there was a fatal error on the socket.
+    ///
+    /// Assumes that it is always called with the same `UdpSocket`
+    /// and pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process(&mut self, sock: &mut UdpSocket, mut pbuf: PBufRdWr) -> Result<Activity> {
+        let rd_activity = self.process_out(sock, pbuf.reborrow())?;
+        let wr_activity = self.process_in(sock, pbuf.reborrow())?;
+        Ok(rd_activity | wr_activity)
+    }
+
+    /// Send as many complete records as possible from the outgoing
+    /// pipe to the given UDP socket, one datagram per record.
+    /// Returns the activity flags (see [`Activity`]), which are empty
+    /// if no progress could be made, or `Err(_)` if there was a fatal
+    /// error on the socket.
+    ///
+    /// Assumes that it is always called with the same `UdpSocket`
+    /// and pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process_out(&mut self, sock: &mut UdpSocket, mut pbuf: PBufRdWr) -> Result<Activity> {
+        if self.pause_writes {
+            return Ok(Activity::NONE);
+        }
+
+        let mut prd = pbuf.rd;
+        let mut act = Activity::NONE;
+        while let Some(total) = record::next_record(prd.data()) {
+            match retry!(sock.send(&prd.data()[HEADER_LEN..total])) {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(act),
+                // Error from an earlier datagram, so try again
+                Err(ref e) if e.kind() == ErrorKind::ConnectionRefused => (),
+                Err(e) => return Err(Error::write(e)),
+                Ok(_) => {
+                    prd.consume(total);
+                    act |= Activity::WROTE;
+                }
+            }
+        }
+        if prd.has_pending_eof() {
+            let len = prd.len();
+            prd.consume(len);
+            consume_eof_out(&mut prd, &mut act);
+            if prd.is_aborted() && !pbuf.wr.is_eof() {
+                abort_in(&mut pbuf.wr, &mut act);
+            }
+        }
+        Ok(act)
+    }
+
+    /// Receive as many datagrams as possible from the given UDP
+    /// socket, up to **max_read_unit** bytes of records, writing each
+    /// as a record to the incoming pipe.  Returns the activity flags
+    /// (see [`Activity`]), which are empty if no progress could be
+    /// made, or `Err(_)` if there was a fatal error on the socket.
+    ///
+    /// Assumes that it is always called with the same `UdpSocket`
+    /// and pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process_in(&mut self, sock: &mut UdpSocket, pbuf: PBufRdWr) -> Result<Activity> {
+        let mut pwr = pbuf.wr;
+        if self.pause_reads || pwr.is_eof() {
+            return Ok(Activity::NONE);
+        }
+
+        let mut act = Activity::NONE;
+        let mut total = 0;
+        while total < self.max_read_unit {
+            // Reserve one extra byte to detect truncation
+            let Some(space) = pwr.try_space(HEADER_LEN + self.max_datagram_size + 1) else {
+                // No room for a whole datagram in a fixed-capacity pipe
+                break;
+            };
+            match retry!(sock.recv(&mut space[HEADER_LEN..])) {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == ErrorKind::ConnectionRefused => (),
+                Err(e) => return Err(Error::read(e)),
+                Ok(len) if len > self.max_datagram_size => (),
+                Ok(len) => {
+                    record::put_header(space, len);
+                    pwr.commit(HEADER_LEN + len);
+                    act |= Activity::READ;
+                    total += HEADER_LEN + len;
+                }
+            }
+        }
+        Ok(act)
+    }
+}
+
+impl Default for UdpLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Exchange datagrams with arbitrary peers via an unconnected `mio`
+/// [`UdpSocket`]
+///
+/// This works like [`UdpLink`], except that the payload of each
+/// record starts with an encoded peer address.  For incoming records
+/// this is the source address of the datagram, and for outgoing
+/// records it is the destination address to use for `send_to`.  The
+/// address encoding is a family byte (4 or 6), followed by the 4 or
+/// 16 bytes of the IP address and a 2-byte big-endian port.  For
+/// IPv6 a 4-byte big-endian scope ID follows.  So an IPv4 address
+/// takes 7 bytes and an IPv6 address 23 bytes.  The datagram
+/// payload follows the address.
+///
+/// An outgoing record with an invalid address gives an
+/// `InvalidData` error.  Other behaviour is as for [`UdpLink`].
+#[derive(Debug)]
+pub struct UdpPeerLink {
+    // Maximum amount of record data to read in one go (in bytes)
+    pub(crate) max_read_unit: usize,
+
+    // Maximum size of a datagram accepted on receive
+    pub(crate) max_datagram_size: usize,
+
+    // Set to pause writes
+    pub(crate) pause_writes: bool,
+
+    // Set to pause reads
+    pub(crate) pause_reads: bool,
+}
+
+impl UdpPeerLink {
+    /// Create the component with default settings:
+    ///
+    /// - **max_read_unit** of 65536.  At least one datagram is always
+    ///   read if available, even if this limit is smaller.
+    ///
+    /// - **max_datagram_size** of 65507, the maximum UDP payload over
+    ///   IPv4
+    ///
+    /// - Both reads and writes unpaused
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            max_read_unit: 65536,
+            max_datagram_size: 65507,
+            pause_writes: false,
+            pause_reads: false,
+        }
+    }
+
+    /// Change the maximum number of bytes of records to read in each
+    /// `process` call.  Reading stops once this limit has been
+    /// reached or exceeded.
+    #[inline]
+    pub fn set_max_read_unit(&mut self, max_read_unit: usize) {
+        self.max_read_unit = max_read_unit;
+    }
+
+    /// Change the maximum size of a datagram that will be accepted.
+    /// Larger datagrams are discarded.
+    #[inline]
+    pub fn set_max_datagram_size(&mut self, max_datagram_size: usize) {
+        self.max_datagram_size = max_datagram_size;
+    }
+
+    /// Pause or unpause writes.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_writes(&mut self, pause: bool) {
+        self.pause_writes = pause;
+    }
+
+    /// Pause or unpause reads.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_reads(&mut self, pause: bool) {
+        self.pause_reads = pause;
+    }
+
+    /// Send and receive as many datagrams as possible to and from the
+    /// given UDP socket.  Returns the activity flags (see
+    /// [`Activity`]), which are empty if no progress could be made,
+    /// or `Err(_)` if there was a fatal error on the socket.
+    ///
+    /// Assumes that it is always called with the same `UdpSocket`
+    /// and pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process(&mut self, sock: &mut UdpSocket, mut pbuf: PBufRdWr) -> Result<Activity> {
+        let rd_activity = self.process_out(sock, pbuf.reborrow())?;
+        let wr_activity = self.process_in(sock, pbuf.reborrow())?;
+        Ok(rd_activity | wr_activity)
+    }
+
+    /// Send as many complete records as possible from the outgoing
+    /// pipe to the given UDP socket, one datagram per record, each to
+    /// the address found at the start of the record.  Returns the
+    /// activity flags (see [`Activity`]), which are empty if no
+    /// progress could be made, or `Err(_)` if there was a fatal error
+    /// on the socket or an invalid record.
+    ///
+    /// Assumes that it is always called with the same `UdpSocket`
+    /// and pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process_out(&mut self, sock: &mut UdpSocket, mut pbuf: PBufRdWr) -> Result<Activity> {
+        if self.pause_writes {
+            return Ok(Activity::NONE);
+        }
+
+        let mut prd = pbuf.rd;
+        let mut act = Activity::NONE;
+        while let Some(total) = record::next_record(prd.data()) {
+            let rec = &prd.data()[HEADER_LEN..total];
+            let Some((addr, addr_len)) = record::get_addr(rec) else {
+                return Err(Error::write(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "Invalid peer address in outgoing record",
+                )));
+            };
+            match retry!(sock.send_to(&rec[addr_len..], addr)) {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(act),
+                // Error from an earlier datagram, so try again
+                Err(ref e) if e.kind() == ErrorKind::ConnectionRefused => (),
+                Err(e) => return Err(Error::write(e)),
+                Ok(_) => {
+                    prd.consume(total);
+                    act |= Activity::WROTE;
+                }
+            }
+        }
+        if prd.has_pending_eof() {
+            let len = prd.len();
+            prd.consume(len);
+            consume_eof_out(&mut prd, &mut act);
+            if prd.is_aborted() && !pbuf.wr.is_eof() {
+                abort_in(&mut pbuf.wr, &mut act);
+            }
+        }
+        Ok(act)
+    }
+
+    /// Receive as many datagrams as possible from the given UDP
+    /// socket, up to **max_read_unit** bytes of records, writing each
+    /// as a record prefixed with the source address to the incoming
+    /// pipe.  Returns the activity flags (see [`Activity`]), which
+    /// are empty if no progress could be made, or `Err(_)` if there
+    /// was a fatal error on the socket.
+    ///
+    /// Assumes that it is always called with the same `UdpSocket`
+    /// and pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process_in(&mut self, sock: &mut UdpSocket, pbuf: PBufRdWr) -> Result<Activity> {
+        let mut pwr = pbuf.wr;
+        if self.pause_reads || pwr.is_eof() {
+            return Ok(Activity::NONE);
+        }
+
+        let mut act = Activity::NONE;
+        let mut total = 0;
+        while total < self.max_read_unit {
+            // Reserve one extra byte to detect truncation.  The
+            // datagram is received after space for the largest
+            // address, and moved down once the address is known.
+            let start = HEADER_LEN + ADDR_MAX_LEN;
+            let Some(space) = pwr.try_space(start + self.max_datagram_size + 1) else {
+                // No room for a whole datagram in a fixed-capacity pipe
+                break;
+            };
+            match retry!(sock.recv_from(&mut space[start..])) {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == ErrorKind::ConnectionRefused => (),
+                Err(e) => return Err(Error::read(e)),
+                Ok((len, _)) if len > self.max_datagram_size => (),
+                Ok((len, addr)) => {
+                    let addr_len = record::put_addr(&mut space[HEADER_LEN..], &addr);
+                    let rec_len = addr_len + len;
+                    space.copy_within(start..start + len, HEADER_LEN + addr_len);
+                    record::put_header(space, rec_len);
+                    pwr.commit(HEADER_LEN + rec_len);
+                    act |= Activity::READ;
+                    total += HEADER_LEN + rec_len;
+                }
+            }
+        }
+        Ok(act)
+    }
+}
+
+impl Default for UdpPeerLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::wait_for;
+    use pipebuf::PipeBufPair;
+    use std::net::{SocketAddr, UdpSocket as StdUdpSocket};
+    use std::time::Duration;
+
+    // A link socket and a blocking peer socket on the given loopback
+    // address, or `None` if that family isn't available
+    fn udp_pair(ip: &str) -> Option<(UdpSocket, StdUdpSocket)> {
+        let sock = UdpSocket::bind(format!("{}:0", ip).parse().unwrap()).ok()?;
+        let peer = StdUdpSocket::bind(format!("{}:0", ip)).ok()?;
+        peer.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        Some((sock, peer))
+    }
+
+    fn push_to(pair: &mut PipeBufPair, addr: SocketAddr, payload: &[u8]) {
+        record::push_addr_record(&mut pair.upper().wr, &addr, payload);
+    }
+
+    // Wait for the next incoming record, returning its address and
+    // payload
+    fn recv_record(
+        link: &mut UdpPeerLink,
+        sock: &mut UdpSocket,
+        pair: &mut PipeBufPair,
+    ) -> (SocketAddr, Vec<u8>) {
+        wait_for(|| {
+            link.process(sock, pair.lower()).unwrap();
+            record::next_record(pair.upper().rd.data()).is_some()
+        });
+        let mut app = pair.upper();
+        let total = record::next_record(app.rd.data()).unwrap();
+        let rec = &app.rd.data()[HEADER_LEN..total];
+        let (addr, addr_len) = record::get_addr(rec).unwrap();
+        let payload = rec[addr_len..].to_vec();
+        app.rd.consume(total);
+        (addr, payload)
+    }
+
+    fn round_trip(ip: &str) {
+        let Some((mut sock, peer)) = udp_pair(ip) else {
+            return;
+        };
+        let mut link = UdpPeerLink::new();
+        let mut pair = PipeBufPair::new();
+
+        peer.send_to(b"ping", sock.local_addr().unwrap()).unwrap();
+        let (from, payload) = recv_record(&mut link, &mut sock, &mut pair);
+        assert_eq!(from, peer.local_addr().unwrap());
+        assert_eq!(payload, b"ping");
+
+        push_to(&mut pair, from, b"pong");
+        link.process(&mut sock, pair.lower()).unwrap();
+        let mut buf = [0; 16];
+        let (len, to) = peer.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"pong");
+        assert_eq!(to, sock.local_addr().unwrap());
+    }
+
+    #[test]
+    fn ipv4_round_trip() {
+        round_trip("127.0.0.1");
+    }
+
+    #[test]
+    fn ipv6_round_trip() {
+        round_trip("[::1]");
+    }
+
+    #[test]
+    fn partial_record_not_sent() {
+        let (mut sock, peer) = udp_pair("127.0.0.1").unwrap();
+        peer.set_nonblocking(true).unwrap();
+        let mut link = UdpPeerLink::new();
+        let mut pair = PipeBufPair::new();
+
+        let mut buf = [0; HEADER_LEN + ADDR_MAX_LEN + 5];
+        let addr_len = record::put_addr(&mut buf[HEADER_LEN..], &peer.local_addr().unwrap());
+        let total = HEADER_LEN + addr_len + 5;
+        record::put_header(&mut buf, addr_len + 5);
+        buf[HEADER_LEN + addr_len..total].copy_from_slice(b"hello");
+
+        // Feed the record in one byte at a time.  Nothing goes out
+        // until the last byte is there.
+        let mut rx = [0; 16];
+        for i in 0..total {
+            pair.upper().wr.append(&buf[i..i + 1]);
+            let act = link.process_out(&mut sock, pair.lower()).unwrap();
+            if i + 1 < total {
+                assert_eq!(act, Activity::NONE);
+                assert_eq!(pair.lower().rd.len(), i + 1);
+                std::thread::sleep(Duration::from_millis(1));
+                assert!(peer.recv_from(&mut rx).is_err());
+            }
+        }
+        assert!(pair.lower().rd.is_empty());
+        peer.set_nonblocking(false).unwrap();
+        let (len, _) = peer.recv_from(&mut rx).unwrap();
+        assert_eq!(&rx[..len], b"hello");
+    }
+
+    #[test]
+    fn oversize_datagram_discarded() {
+        let (mut sock, peer) = udp_pair("127.0.0.1").unwrap();
+        let mut link = UdpPeerLink::new();
+        link.set_max_datagram_size(8);
+        let mut pair = PipeBufPair::new();
+
+        let to = sock.local_addr().unwrap();
+        peer.send_to(b"far too long", to).unwrap();
+        peer.send_to(b"", to).unwrap();
+        peer.send_to(b"12345678", to).unwrap();
+
+        let (_, payload) = recv_record(&mut link, &mut sock, &mut pair);
+        assert!(payload.is_empty());
+        let (_, payload) = recv_record(&mut link, &mut sock, &mut pair);
+        assert_eq!(payload, b"12345678");
+        assert!(pair.upper().rd.is_empty());
+    }
+
+    #[test]
+    fn oversize_record_fails() {
+        let (mut sock, peer) = udp_pair("127.0.0.1").unwrap();
+        let mut link = UdpPeerLink::new();
+        let mut pair = PipeBufPair::new();
+
+        push_to(&mut pair, peer.local_addr().unwrap(), &[0; 70000]);
+        let err = link.process_out(&mut sock, pair.lower()).unwrap_err();
+        assert_eq!(err.op(), crate::Op::Write);
+        // The record stays in the pipe
+        assert!(!pair.lower().rd.is_empty());
+    }
+
+    #[test]
+    fn invalid_addr_fails() {
+        let (mut sock, _peer) = udp_pair("127.0.0.1").unwrap();
+        let mut link = UdpPeerLink::new();
+        let mut pair = PipeBufPair::new();
+
+        let mut buf = [0; HEADER_LEN + 3];
+        record::put_header(&mut buf, 3);
+        buf[HEADER_LEN..].copy_from_slice(&[9, 9, 9]);
+        pair.upper().wr.append(&buf);
+        let err = link.process_out(&mut sock, pair.lower()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
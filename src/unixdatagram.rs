@@ -0,0 +1,185 @@
+use crate::activity::{abort_in, consume_eof_out};
+use crate::record::{self, HEADER_LEN};
+use crate::{Activity, Error, Result};
+use mio::net::UnixDatagram;
+use pipebuf::PBufRdWr;
+use std::io::ErrorKind;
+
+/// Exchange datagrams via a connected `mio` [`UnixDatagram`] socket
+///
+/// Datagram boundaries are preserved by framing each datagram as a
+/// record in the pipe-buffer: a 4-byte big-endian length followed by
+/// the payload, as for [`crate::UdpLink`].  Zero-length datagrams are
+/// legal on Unix sockets, and are passed through as zero-length
+/// records.
+///
+/// A datagram socket has no stream to shut down, so when the
+/// outgoing pipe reaches EOF, any incomplete trailing record is
+/// discarded, the EOF is consumed and no more sends are done.  The
+/// socket itself is left alone.  An outgoing "abort" is passed on as
+/// an "abort" of the incoming pipe.  The incoming pipe is never
+/// closed by this link.
+///
+/// Received datagrams longer than **max_datagram_size** are
+/// discarded, since they would have been truncated.  A record too
+/// large to send gives an error.
+///
+/// Reading and writing start unpaused, since a datagram socket does
+/// not need to wait for a connection to complete.
+#[derive(Debug)]
+pub struct UnixDatagramLink {
+    // Maximum amount of record data to read in one go (in bytes)
+    max_read_unit: usize,
+
+    // Maximum size of a datagram accepted on receive
+    max_datagram_size: usize,
+
+    // Set to pause writes
+    pause_writes: bool,
+
+    // Set to pause reads
+    pause_reads: bool,
+}
+
+impl UnixDatagramLink {
+    /// Create the component with default settings:
+    ///
+    /// - **max_read_unit** of 65536.  At least one datagram is always
+    ///   read if available, even if this limit is smaller.
+    ///
+    /// - **max_datagram_size** of 65536
+    ///
+    /// - Both reads and writes unpaused
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            max_read_unit: 65536,
+            max_datagram_size: 65536,
+            pause_writes: false,
+            pause_reads: false,
+        }
+    }
+
+    /// Change the maximum number of bytes of records to read in each
+    /// `process` call.  Reading stops once this limit has been
+    /// reached or exceeded.
+    #[inline]
+    pub fn set_max_read_unit(&mut self, max_read_unit: usize) {
+        self.max_read_unit = max_read_unit;
+    }
+
+    /// Change the maximum size of a datagram that will be accepted.
+    /// Larger datagrams are discarded.  A receive buffer of this size
+    /// is reserved in the incoming pipe-buffer on each read.
+    #[inline]
+    pub fn set_max_datagram_size(&mut self, max_datagram_size: usize) {
+        self.max_datagram_size = max_datagram_size;
+    }
+
+    /// Pause or unpause writes.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_writes(&mut self, pause: bool) {
+        self.pause_writes = pause;
+    }
+
+    /// Pause or unpause reads.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_reads(&mut self, pause: bool) {
+        self.pause_reads = pause;
+    }
+
+    /// Send and receive as many datagrams as possible to and from the
+    /// given Unix datagram socket.  Returns the activity flags (see
+    /// [`Activity`]), which are empty if no progress could be made,
+    /// or `Err(_)` if there was a fatal error on the socket.
+    ///
+    /// Assumes that it is always called with the same `UnixDatagram`
+    /// and pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process(&mut self, sock: &mut UnixDatagram, mut pbuf: PBufRdWr) -> Result<Activity> {
+        let rd_activity = self.process_out(sock, pbuf.reborrow())?;
+        let wr_activity = self.process_in(sock, pbuf.reborrow())?;
+        Ok(rd_activity | wr_activity)
+    }
+
+    /// Send as many complete records as possible from the outgoing
+    /// pipe to the given Unix datagram socket, one datagram per
+    /// record.  Returns the activity flags (see [`Activity`]), which
+    /// are empty if no progress could be made, or `Err(_)` if there
+    /// was a fatal error on the socket.
+    ///
+    /// Assumes that it is always called with the same `UnixDatagram`
+    /// and pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process_out(&mut self, sock: &mut UnixDatagram, mut pbuf: PBufRdWr) -> Result<Activity> {
+        if self.pause_writes {
+            return Ok(Activity::NONE);
+        }
+
+        let mut prd = pbuf.rd;
+        let mut act = Activity::NONE;
+        while let Some(total) = record::next_record(prd.data()) {
+            match retry!(sock.send(&prd.data()[HEADER_LEN..total])) {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(act),
+                Err(e) => return Err(Error::write(e)),
+                Ok(_) => {
+                    prd.consume(total);
+                    act |= Activity::WROTE;
+                }
+            }
+        }
+        if prd.has_pending_eof() {
+            let len = prd.len();
+            prd.consume(len);
+            consume_eof_out(&mut prd, &mut act);
+            if prd.is_aborted() && !pbuf.wr.is_eof() {
+                abort_in(&mut pbuf.wr, &mut act);
+            }
+        }
+        Ok(act)
+    }
+
+    /// Receive as many datagrams as possible from the given Unix
+    /// datagram socket, up to **max_read_unit** bytes of records,
+    /// writing each as a record to the incoming pipe.  Returns the
+    /// activity flags (see [`Activity`]), which are empty if no
+    /// progress could be made, or `Err(_)` if there was a fatal error
+    /// on the socket.
+    ///
+    /// Assumes that it is always called with the same `UnixDatagram`
+    /// and pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process_in(&mut self, sock: &mut UnixDatagram, pbuf: PBufRdWr) -> Result<Activity> {
+        let mut pwr = pbuf.wr;
+        if self.pause_reads || pwr.is_eof() {
+            return Ok(Activity::NONE);
+        }
+
+        let mut act = Activity::NONE;
+        let mut total = 0;
+        while total < self.max_read_unit {
+            // Reserve one extra byte to detect truncation
+            let Some(space) = pwr.try_space(HEADER_LEN + self.max_datagram_size + 1) else {
+                // No room for a whole datagram in a fixed-capacity pipe
+                break;
+            };
+            match retry!(sock.recv(&mut space[HEADER_LEN..])) {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(Error::read(e)),
+                Ok(len) if len > self.max_datagram_size => (),
+                Ok(len) => {
+                    record::put_header(space, len);
+                    pwr.commit(HEADER_LEN + len);
+                    act |= Activity::READ;
+                    total += HEADER_LEN + len;
+                }
+            }
+        }
+        Ok(act)
+    }
+}
+
+impl Default for UnixDatagramLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
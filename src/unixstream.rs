@@ -1,6 +1,16 @@
-use mio::net::UnixStream;
-use pipebuf::PBufRdWr;
-use std::io::{ErrorKind, Result};
+use crate::bufsize::{BufferSizes, SocketBuffers};
+use crate::generic::{GenericLink, NoHooks};
+use crate::{
+    Activity, LinkObserver, LinkState, LinkStats, ProcessOrder, ReadQuotaMode, Result,
+    WriteQuotaMode,
+};
+use mio::event::Event;
+use mio::net::{SocketAddr, UnixStream};
+use mio::Interest;
+use pipebuf::{PBufRd, PBufRdWr, PBufWr};
+use std::fmt;
+use std::io;
+use std::time::{Duration, Instant};
 
 /// Exchange stream data via the `mio` [`UnixStream`] type
 ///
@@ -18,16 +28,20 @@ use std::io::{ErrorKind, Result};
 /// To start with both reading and writing via the Unix stream are
 /// paused.  So call `set_pause_writes(false)` or
 /// `set_pause_reads(false)` as soon as the stream indicates "ready"
-/// in order to allow data to flow.
+/// in order to allow data to flow, or pass each event to
+/// [`UnixStreamLink::process_event`] which does this automatically.
 pub struct UnixStreamLink {
-    // Maximum amount of data to read in one go (in bytes)
-    max_read_unit: usize,
+    // Common settings and processing
+    link: GenericLink,
 
-    // Set to pause writes (waiting for first "ready" indication)
-    pause_writes: bool,
+    // Kernel socket buffer sizes
+    buffers: BufferSizes,
 
-    // Set to pause reads (waiting for first "ready" indication)
-    pause_reads: bool,
+    // Peer address, once known
+    peer_addr: Option<SocketAddr>,
+
+    // Local address, once known
+    local_addr: Option<SocketAddr>,
 }
 
 impl UnixStreamLink {
@@ -39,9 +53,10 @@ impl UnixStreamLink {
     #[inline]
     pub fn new() -> Self {
         Self {
-            max_read_unit: 2048,
-            pause_writes: true,
-            pause_reads: true,
+            link: GenericLink::new(2048),
+            buffers: BufferSizes::default(),
+            peer_addr: None,
+            local_addr: None,
         }
     }
 
@@ -54,104 +69,915 @@ impl UnixStreamLink {
     /// efficiency.
     #[inline]
     pub fn set_max_read_unit(&mut self, max_read_unit: usize) {
-        self.max_read_unit = max_read_unit;
+        self.link.set_max_read_unit(max_read_unit);
+    }
+
+    /// Get the maximum number of bytes to read in each `process`
+    /// call.  See [`UnixStreamLink::set_max_read_unit`].  With an adaptive
+    /// range set by [`UnixStreamLink::set_read_unit_range`], this is the
+    /// current value.
+    #[inline]
+    pub fn max_read_unit(&self) -> usize {
+        self.link.max_read_unit
+    }
+
+    /// Let **max_read_unit** adapt to the traffic, between `min` and
+    /// `max` bytes, starting from `min`.  After each read, it is
+    /// doubled if a whole unit was read, since more data is then
+    /// likely to be waiting, or halved if less than a quarter of what
+    /// could have been read arrived, including when nothing had
+    /// arrived, e.g. once the connection has gone quiet.  This suits
+    /// a mix of bulk transfers and many mostly idle connections
+    /// better than a fixed size.  The current value is returned by
+    /// [`UnixStreamLink::max_read_unit`].  Calling
+    /// [`UnixStreamLink::set_max_read_unit`] returns to a fixed size.
+    #[inline]
+    pub fn set_read_unit_range(&mut self, min: usize, max: usize) {
+        self.link.set_read_unit_range(min, max);
+    }
+
+    /// Get the bounds set with [`UnixStreamLink::set_read_unit_range`], or
+    /// `None` if **max_read_unit** is fixed
+    #[inline]
+    pub fn read_unit_range(&self) -> Option<(usize, usize)> {
+        self.link.read_unit_range
+    }
+
+    /// Change the maximum number of bytes to read in each `process`
+    /// or `process_in` call.  Reads are made in units of up to
+    /// **max_read_unit** bytes, repeated until the stream gives
+    /// `WouldBlock` or EOF, or this total is reached.  By default the
+    /// total is one **max_read_unit**, i.e. a single unit.
+    ///
+    /// With edge-triggered polling, a readiness event only arrives
+    /// when new data comes in, so data left unread in the stream may
+    /// stall the connection.  In that case, use `usize::MAX` to read
+    /// until `WouldBlock` on each call, or else call `process` again
+    /// whenever it reads a full **max_read_total**.
+    #[inline]
+    pub fn set_max_read_total(&mut self, max_read_total: usize) {
+        self.link.max_read_total = Some(max_read_total);
+    }
+
+    /// Skip reading when the incoming pipe has less than `low_water`
+    /// bytes of free space, counting up to **max_read_unit**, to
+    /// avoid many tiny reads into a nearly full fixed-capacity pipe.
+    /// In that case `process_in` makes no read call and reports no
+    /// activity, and [`UnixStreamLink::needed_interest`] leaves out READABLE.
+    /// The default of zero only skips reading when the pipe is
+    /// completely full.  A pipe that grows as needed always has
+    /// **max_read_unit** bytes of space.
+    #[inline]
+    pub fn set_read_low_water(&mut self, low_water: usize) {
+        self.link.read_low_water = low_water;
+    }
+
+    /// Change the maximum number of bytes to write out in each
+    /// `process` or `process_out` call.  By default there is no
+    /// limit, and all the data in the outgoing pipe is written unless
+    /// the stream blocks.  A limit stops one connection with a deep
+    /// outgoing pipe from taking up a whole loop iteration.  If the
+    /// limit is reached, data remains in the pipe, and any pending EOF
+    /// is only passed on once the pipe is empty.  The stream is still
+    /// writable at that point, so with edge-triggered polling no new
+    /// event will arrive, and the caller must arrange to call
+    /// `process` again, e.g. when [`UnixStreamLink::needed_interest`] still
+    /// includes WRITABLE.
+    #[inline]
+    pub fn set_max_write_unit(&mut self, max_write_unit: usize) {
+        self.link.max_write_unit = max_write_unit;
+    }
+
+    /// Hold off reading while the incoming pipe has a backlog of
+    /// more than `threshold` bytes of unconsumed data, or disable
+    /// this with `None`.  While held off, `process_in` reads nothing
+    /// and reports no activity, and [`UnixStreamLink::needed_interest`]
+    /// leaves out READABLE.  Reads resume once the consumer has
+    /// drained the backlog to half the threshold or less, which
+    /// avoids flapping.  Memory use for the incoming pipe is then
+    /// bounded at around `threshold` plus **max_read_total**.
+    ///
+    /// The link only sees the backlog drop on its next `process`
+    /// call, so after consuming data, the caller should call
+    /// `process` or update its interest.
+    #[inline]
+    pub fn set_read_backpressure(&mut self, threshold: Option<usize>) {
+        self.link.read_backpressure = threshold;
+    }
+
+    /// Change the size of the kernel receive buffer (`SO_RCVBUF`)
+    /// for the stream.  This will be updated on the next `process`
+    /// call.  The kernel may adjust the size, so use
+    /// [`UnixStreamLink::recv_buffer_size`] to see what took effect.
+    #[inline]
+    pub fn set_recv_buffer_size(&mut self, size: usize) {
+        self.buffers.set_recv(size);
+    }
+
+    /// Change the size of the kernel send buffer (`SO_SNDBUF`) for
+    /// the stream.  This will be updated on the next `process` call.
+    #[inline]
+    pub fn set_send_buffer_size(&mut self, size: usize) {
+        self.buffers.set_send(size);
+    }
+
+    /// Test whether any socket option changes have been requested
+    /// that have not yet been applied to the stream.  They will be
+    /// applied on the next `process` call.
+    #[inline]
+    pub fn has_pending_option_changes(&self) -> bool {
+        self.buffers.has_pending()
+    }
+
+    /// Query the effective size of the kernel receive buffer from
+    /// the given stream
+    #[inline]
+    pub fn recv_buffer_size(&self, stream: &UnixStream) -> io::Result<usize> {
+        stream.recv_buffer_size()
+    }
+
+    /// Query the effective size of the kernel send buffer from the
+    /// given stream
+    #[inline]
+    pub fn send_buffer_size(&self, stream: &UnixStream) -> io::Result<usize> {
+        stream.send_buffer_size()
     }
 
     /// Pause or unpause writes.  This takes effect on the next
     /// `process` call.
     #[inline]
     pub fn set_pause_writes(&mut self, pause: bool) {
-        self.pause_writes = pause;
+        self.link.set_pause_writes(pause);
     }
 
     /// Pause or unpause reads.  This takes effect on the next
     /// `process` call.
     #[inline]
     pub fn set_pause_reads(&mut self, pause: bool) {
-        self.pause_reads = pause;
+        self.link.set_pause_reads(pause);
+    }
+
+    /// Test whether writes are paused.  Writes are unpaused on a
+    /// writable event or with `set_pause_writes(false)`.
+    #[inline]
+    pub fn pause_writes(&self) -> bool {
+        self.link.pause_writes
+    }
+
+    /// Test whether reads are paused.  Reads are unpaused on a
+    /// readable event or with `set_pause_reads(false)`.
+    #[inline]
+    pub fn pause_reads(&self) -> bool {
+        self.link.pause_reads
+    }
+
+    /// Enable or disable the `WouldBlock` latch.  When enabled, once
+    /// a read or write gives `WouldBlock`, later `process` calls skip
+    /// that direction until fresh readiness is signalled, saving the
+    /// syscall that would just give `WouldBlock` again.  Readiness is
+    /// signalled by an event passed to
+    /// [`UnixStreamLink::process_event`], by
+    /// [`UnixStreamLink::note_readable`] or [`UnixStreamLink::note_writable`], or
+    /// by unpausing with `set_pause_reads(false)` or
+    /// `set_pause_writes(false)`.  So only enable this if readiness is
+    /// always passed on.  This is disabled by default.
+    #[inline]
+    pub fn set_wouldblock_latch(&mut self, enable: bool) {
+        self.link.latch = enable;
+    }
+
+    /// Select the order in which `process` and `process_event`
+    /// handle the two directions.  See [`ProcessOrder`].  The
+    /// default is `ProcessOrder::WriteThenRead`.
+    #[inline]
+    pub fn set_process_order(&mut self, order: ProcessOrder) {
+        self.link.order = order.normalize();
+    }
+
+    /// Shut down the outgoing half of the stream once the outgoing
+    /// pipe has been written out, as if EOF had been pushed to the
+    /// pipe, without needing the cooperation of whatever is writing
+    /// to it.  This takes effect on the next `process` call with
+    /// writes unpaused and the pipe empty, which returns
+    /// [`Activity::EOF_OUT`], and [`UnixStreamLink::state`] then moves on
+    /// as for EOF.  Any data put in the pipe after that is discarded.
+    #[inline]
+    pub fn close_when_flushed(&mut self) {
+        self.link.close_when_flushed = true;
+    }
+
+    /// Abort the link at once, as for an outgoing "abort", without
+    /// waiting for the outgoing pipe: the incoming pipe is aborted,
+    /// and both halves of the stream are shut down.  Returns the
+    /// activity flags, which include `ABORTED`, or are empty if the
+    /// link was already aborted or closed early.  Any data in the
+    /// outgoing pipe, then or later, is discarded.
+    pub fn abort_now(&mut self, stream: &mut UnixStream, pbuf: PBufRdWr) -> Activity {
+        self.link.abort_now(stream, pbuf)
+    }
+
+    /// Signal that the stream is readable, unpausing reads and
+    /// clearing the `WouldBlock` latch for reads.  See
+    /// [`UnixStreamLink::set_wouldblock_latch`].
+    #[inline]
+    pub fn note_readable(&mut self) {
+        self.link.note_readable();
+    }
+
+    /// Signal that the stream is writable, unpausing writes and
+    /// clearing the `WouldBlock` latch for writes.  See
+    /// [`UnixStreamLink::set_wouldblock_latch`].
+    #[inline]
+    pub fn note_writable(&mut self) {
+        self.link.note_writable();
+    }
+
+    /// Set the label attached to the `tracing` events from this link,
+    /// e.g. a peer address or connection ID, so that the events of
+    /// different connections can be told apart.  Events are emitted
+    /// at trace level for data moved and `WouldBlock`, and at debug
+    /// level for EOF, shutdown, abort, option changes and errors.
+    /// Without the `tracing` feature this does nothing.
+    #[inline]
+    pub fn set_label(&mut self, label: impl Into<String>) {
+        self.link.tracer.set_label(label.into());
+    }
+
+    /// Install an observer to be told about the traffic on the link,
+    /// or remove it with `None`.  See [`LinkObserver`].
+    #[inline]
+    pub fn set_observer(&mut self, observer: Option<Box<dyn LinkObserver>>) {
+        self.link.observer = observer;
+    }
+
+    /// Get the position of the link in its shutdown sequence.  See
+    /// [`LinkState`].
+    #[inline]
+    pub fn state(&self) -> LinkState {
+        self.link.state()
+    }
+
+    /// Test whether the link is finished, so that the stream can be
+    /// deregistered and dropped.  This is true if a `process` call
+    /// has returned a fatal error (see [`LinkState::Failed`]), or
+    /// else if both of these hold:
+    ///
+    /// - The incoming pipe has been closed or aborted, i.e. EOF or an
+    ///   abort from the peer has been passed on, or the link aborted
+    ///   it, e.g. on a timeout
+    ///
+    /// - EOF from the outgoing pipe has been passed on to the stream
+    ///   with a completed shutdown, so not while it is still
+    ///   `ShutdownPending`, or an abort has been passed on in either
+    ///   direction
+    ///
+    /// Data may still be waiting in the incoming pipe for the
+    /// application to consume.
+    #[inline]
+    pub fn is_done(&self, pbuf: &PBufRdWr) -> bool {
+        self.link.is_done(pbuf)
+    }
+
+    /// Get the last error seen on a read from the stream, if any.
+    /// This includes errors that did not cause `process` to fail,
+    /// such as `ConnectionReset`, which is passed on as an abort, so
+    /// that the reason for a connection going away can be logged.
+    /// Only the kind and OS error code are kept.
+    #[inline]
+    pub fn last_read_error(&self) -> Option<&io::Error> {
+        self.link.last_read_error.as_ref()
+    }
+
+    /// Get the last error seen on a write or shutdown of the stream,
+    /// if any, likewise including errors that were handled as an
+    /// abort or ignored, e.g. `BrokenPipe`.  Only the kind and OS
+    /// error code are kept.
+    #[inline]
+    pub fn last_write_error(&self) -> Option<&io::Error> {
+        self.link.last_write_error.as_ref()
+    }
+
+    /// Get the address of the peer, i.e. its path or abstract name,
+    /// or `None` if it is not yet known.  This is captured on the
+    /// first `process` call and kept after that, so it is still
+    /// available after the stream has been closed, e.g. for logging
+    /// an error.
+    #[inline]
+    pub fn peer_addr(&self) -> Option<&SocketAddr> {
+        self.peer_addr.as_ref()
+    }
+
+    /// Get the local address of the stream, or `None` if it is not
+    /// yet known.  This is captured and kept in the same way as
+    /// [`UnixStreamLink::peer_addr`].
+    #[inline]
+    pub fn local_addr(&self) -> Option<&SocketAddr> {
+        self.local_addr.as_ref()
+    }
+
+    // Capture whichever addresses are not yet known.  Failures are
+    // ignored, since they are tried again on the next call.
+    fn note_addrs(&mut self, stream: &UnixStream) {
+        if self.peer_addr.is_none() {
+            self.peer_addr = stream.peer_addr().ok();
+        }
+        if self.local_addr.is_none() {
+            self.local_addr = stream.local_addr().ok();
+        }
+    }
+
+    /// Get the time of the last read or write on the stream that
+    /// transferred data, or `None` if there has been none yet
+    #[inline]
+    pub fn last_activity(&self) -> Option<Instant> {
+        self.link.last_activity()
+    }
+
+    /// Get the time elapsed since the last read or write on the
+    /// stream that transferred data, or `None` if there has been
+    /// none yet.  This can be used to detect idle connections.
+    #[inline]
+    pub fn idle_for(&self) -> Option<Duration> {
+        self.link.idle_for()
+    }
+
+    /// Abort the link if it sees no traffic for the given time, or
+    /// disable this with `None`, which is the default.  Once the
+    /// timeout has passed without data being read or written, the
+    /// next `process` or `process_event` call aborts the incoming
+    /// pipe and shuts down both halves of the stream, returning
+    /// [`Activity::TIMED_OUT`].  After that the link does nothing
+    /// more.  Call `process` once the time returned by
+    /// [`UnixStreamLink::next_deadline`] has passed, so that the
+    /// timeout is noticed.
+    ///
+    /// The timer restarts on every read or write that transfers data,
+    /// and when the timeout is set.  It is held while both reads and
+    /// writes are paused, and while reads are held off because the
+    /// incoming pipe is not being consumed, since the lack of traffic
+    /// is then due to the local side.
+    #[inline]
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.link.set_idle_timeout(timeout);
+    }
+
+    /// Get the time at which the idle timeout will expire, or `None`
+    /// if it is disabled, currently held, or the link has finished.
+    /// See [`UnixStreamLink::set_idle_timeout`].
+    #[inline]
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.link.next_deadline()
+    }
+
+    /// Replace the clock used to timestamp activity, for testing
+    /// timeouts without waiting.  The default is [`Instant::now`].
+    ///
+    /// This is only available with the `test-util` feature.
+    #[cfg(any(test, feature = "test-util"))]
+    #[inline]
+    pub fn set_clock(&mut self, clock: fn() -> Instant) {
+        self.link.set_clock(clock);
+    }
+
+    /// Get the minimal interest needed for the current state of the
+    /// link and the given pipe-buffer, for reregistering with
+    /// level-triggered polling after each `process` call.  READABLE
+    /// is included only if reads are not paused and the incoming pipe
+    /// is not closed and has room, i.e. holds no more than
+    /// **max_read_unit** bytes of unconsumed data.  WRITABLE is
+    /// included only if the outgoing pipe has data or a pending EOF
+    /// to pass on.  Returns `None` if no events are needed, which
+    /// includes the case where the link has finished in both
+    /// directions.
+    #[inline]
+    pub fn needed_interest(&self, pbuf: &PBufRdWr) -> Option<Interest> {
+        self.link.needed_interest(pbuf)
     }
 
     /// Read and write as much data as possible to and from the given
-    /// Unix stream.  Returns the activity status: `Ok(true)` if
-    /// something changed, `Ok(false)` if no progress could be made,
-    /// or `Err(_)` if there was a fatal error on the stream.
+    /// Unix stream.  Returns the activity flags (see [`Activity`]),
+    /// which are empty if no progress could be made, or `Err(_)` if
+    /// there was a fatal error on the stream.  After a fatal error,
+    /// this and the other `process` calls return no activity without
+    /// touching the stream again, and [`UnixStreamLink::state`]
+    /// reports [`LinkState::Failed`].
     ///
     /// Assumes that it is always called with the same `UnixStream`
     /// and pipe-buffer.  Things will behave unpredictably otherwise.
-    pub fn process(&mut self, stream: &mut UnixStream, mut pbuf: PBufRdWr) -> Result<bool> {
-        let rd_activity = self.process_out(stream, pbuf.reborrow())?;
-        let wr_activity = self.process_in(stream, pbuf.reborrow())?;
-        Ok(rd_activity || wr_activity)
+    pub fn process(&mut self, stream: &mut UnixStream, pbuf: PBufRdWr) -> Result<Activity> {
+        if self.link.halted() {
+            return Ok(Activity::NONE);
+        }
+        self.note_addrs(stream);
+        self.link
+            .fail(self.buffers.apply(stream, &self.link.tracer))?;
+        self.link.process(stream, pbuf)
     }
 
-    /// Write as much data as possible out to the given Unix stream.
-    /// Returns the activity status: `Ok(true)` if something changed,
-    /// `Ok(false)` if no progress could be made, or `Err(_)` if there
-    /// was a fatal error on the stream.
+    /// Handle a `mio` event for the Unix stream: unpause reads if it
+    /// is readable or read-closed, unpause writes if it is writable
+    /// or write-closed, and then read and write as much data as
+    /// possible as for [`UnixStreamLink::process`].  The read is
+    /// skipped if the event only reports that the stream is writable,
+    /// since it would just give `WouldBlock`.
     ///
     /// Assumes that it is always called with the same `UnixStream`
     /// and pipe-buffer.  Things will behave unpredictably otherwise.
-    pub fn process_out(&mut self, stream: &mut UnixStream, mut pbuf: PBufRdWr) -> Result<bool> {
-        if self.pause_writes {
-            return Ok(false);
+    pub fn process_event(
+        &mut self,
+        stream: &mut UnixStream,
+        mut pbuf: PBufRdWr,
+        event: &Event,
+    ) -> Result<Activity> {
+        if self.link.halted() {
+            return Ok(Activity::NONE);
         }
+        let read = self.link.apply_event(event);
+        if let Some(act) = self.link.check_idle(stream, pbuf.reborrow()) {
+            return Ok(act);
+        }
+        self.note_addrs(stream);
+        self.link
+            .fail(self.buffers.apply(stream, &self.link.tracer))?;
+        let act = self
+            .link
+            .process_both(stream, pbuf.reborrow(), read, &mut NoHooks)?;
+        self.link.update_idle(&pbuf);
+        Ok(act)
+    }
 
-        let mut prd = pbuf.rd;
-        let trip = prd.tripwire();
-        match prd.output_to(stream, false) {
-            Err(ref e) if e.kind() == ErrorKind::WouldBlock => (),
-            Err(e) => return Err(e),
-            Ok(_) => {
-                if prd.is_empty() && prd.has_pending_eof() {
-                    let shutdown = if prd.is_aborted() {
-                        if !pbuf.wr.is_eof() {
-                            pbuf.wr.abort();
-                        }
-                        std::net::Shutdown::Both
-                    } else {
-                        std::net::Shutdown::Write
-                    };
-                    match retry!(stream.shutdown(shutdown)) {
-                        Err(ref e) if e.kind() == ErrorKind::WouldBlock => (),
-                        Err(e) => return Err(e),
-                        Ok(_) => {
-                            prd.consume_eof();
-                        }
-                    }
-                }
-            }
+    /// Write as much data as possible out to the given Unix stream.
+    /// Returns the activity flags (see [`Activity`]), which are empty
+    /// if no progress could be made, or `Err(_)` if there was a fatal
+    /// error on the stream.
+    ///
+    /// Assumes that it is always called with the same `UnixStream`
+    /// and pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process_out(&mut self, stream: &mut UnixStream, pbuf: PBufRdWr) -> Result<Activity> {
+        if self.link.halted() {
+            return Ok(Activity::NONE);
         }
-        Ok(prd.is_tripped(trip))
+        self.note_addrs(stream);
+        self.link
+            .fail(self.buffers.apply(stream, &self.link.tracer))?;
+        self.link.process_out(stream, pbuf)
     }
 
     /// Read as much data as possible from to the given Unix stream,
-    /// up to **max_read_unit** bytes.  Returns the activity status:
-    /// `Ok(true)` if something changed, `Ok(false)` if no progress
-    /// could be made, or `Err(_)` if there was a fatal error on the
-    /// stream.
+    /// up to **max_read_unit** bytes.  Returns the activity flags
+    /// (see [`Activity`]), which are empty if no progress could be
+    /// made, or `Err(_)` if there was a fatal error on the stream.
     ///
     /// Assumes that it is always called with the same `UnixStream`
     /// and pipe-buffer.  Things will behave unpredictably otherwise.
-    pub fn process_in(&mut self, stream: &mut UnixStream, pbuf: PBufRdWr) -> Result<bool> {
-        let mut pwr = pbuf.wr;
-        if self.pause_reads || pwr.is_eof() {
-            return Ok(false);
+    pub fn process_in(&mut self, stream: &mut UnixStream, pbuf: PBufRdWr) -> Result<Activity> {
+        if self.link.halted() {
+            return Ok(Activity::NONE);
         }
+        self.note_addrs(stream);
+        self.link
+            .fail(self.buffers.apply(stream, &self.link.tracer))?;
+        self.link.process_in(stream, pbuf)
+    }
+}
 
-        let trip = pwr.tripwire();
-        if let Err(e) = pwr.input_from(stream, self.max_read_unit) {
-            match e.kind() {
-                ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted => pwr.abort(),
-                ErrorKind::WouldBlock => (),
-                _ => return Err(e),
-            }
+impl Default for UnixStreamLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for UnixStreamLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("UnixStreamLink");
+        self.link.debug_fields(&mut d);
+        d.field("buffers", &self.buffers)
+            .field("peer_addr", &self.peer_addr)
+            .field("local_addr", &self.local_addr)
+            .finish()
+    }
+}
+
+/// Write-only variant of [`UnixStreamLink`]
+///
+/// This is for one-directional use, where there is no incoming pipe.
+/// Pipe "close" is mapped to a shutdown of the outgoing half of the
+/// Unix stream, and "abort" to a shutdown of both halves, as for
+/// [`UnixStreamLink`].  If the peer has gone away, the failed write
+/// (EPIPE or a reset) is not returned as an error.  Instead any data
+/// remaining in the outgoing pipe is discarded, a pending EOF is
+/// consumed, and `process_out` returns [`Activity::ABORTED`].  Data
+/// put in the pipe after that is discarded, and the error is kept
+/// for [`UnixStreamWriteLink::last_write_error`].
+///
+/// [`UnixStreamWriteLink::state`] follows the outgoing direction
+/// only: it moves from `Open` to `OutClosed` once EOF has been passed
+/// on, or to `Aborted` or `Failed`.
+///
+/// Writing starts paused, so call `set_pause_writes(false)` as soon
+/// as the stream indicates "ready".
+pub struct UnixStreamWriteLink {
+    // Common settings and processing, used for writes only
+    link: GenericLink,
+}
+
+impl UnixStreamWriteLink {
+    /// Create the component with writes paused
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            link: GenericLink::new(2048),
         }
-        Ok(pwr.is_tripped(trip))
+    }
+
+    /// Change the maximum number of bytes to write in each
+    /// `process_out` call.  See [`UnixStreamLink::set_max_write_unit`].
+    #[inline]
+    pub fn set_max_write_unit(&mut self, max_write_unit: usize) {
+        self.link.max_write_unit = max_write_unit;
+    }
+
+    /// Pause or unpause writes.  This takes effect on the next
+    /// `process_out` call.  Unpausing counts as fresh readiness for
+    /// the `WouldBlock` latch.
+    #[inline]
+    pub fn set_pause_writes(&mut self, pause: bool) {
+        self.link.set_pause_writes(pause);
+    }
+
+    /// Skip writes after `WouldBlock` until fresh readiness.  See
+    /// [`UnixStreamLink::set_wouldblock_latch`].
+    #[inline]
+    pub fn set_wouldblock_latch(&mut self, enable: bool) {
+        self.link.latch = enable;
+    }
+
+    /// Signal that the stream is writable, unpausing writes and
+    /// clearing the `WouldBlock` latch for writes
+    #[inline]
+    pub fn note_writable(&mut self) {
+        self.link.note_writable();
+    }
+
+    /// Limit the total number of bytes that may be written to the
+    /// stream, or remove the limit with `None`.  See
+    /// [`crate::TcpLink::set_write_quota`].
+    #[inline]
+    pub fn set_write_quota(&mut self, quota: Option<u64>) {
+        self.link.set_write_quota(quota);
+    }
+
+    /// Get the number of bytes that may still be written under the
+    /// write quota, or `None` if there is no quota
+    #[inline]
+    pub fn write_quota(&self) -> Option<u64> {
+        self.link.write_quota()
+    }
+
+    /// Select what happens when the write quota runs out.  See
+    /// [`WriteQuotaMode`].
+    #[inline]
+    pub fn set_write_quota_mode(&mut self, mode: WriteQuotaMode) {
+        self.link.write_quota_mode = mode;
+    }
+
+    /// Get the total number of bytes written to the stream
+    #[inline]
+    pub fn bytes_out(&self) -> u64 {
+        self.link.bytes_out
+    }
+
+    /// Enable or disable collection of syscall statistics.  See
+    /// [`LinkStats`].
+    #[inline]
+    pub fn enable_stats(&mut self, enable: bool) {
+        self.link.enable_stats(enable);
+    }
+
+    /// Get the syscall statistics, or `None` if not enabled
+    #[inline]
+    pub fn stats(&self) -> Option<&LinkStats> {
+        self.link.stats.as_ref()
+    }
+
+    /// Get the position of the link in its shutdown sequence.  See
+    /// [`LinkState`].
+    #[inline]
+    pub fn state(&self) -> LinkState {
+        self.link.state()
+    }
+
+    /// Get the last error seen on a write or shutdown of the stream,
+    /// if any.  See [`UnixStreamLink::last_write_error`].
+    #[inline]
+    pub fn last_write_error(&self) -> Option<&io::Error> {
+        self.link.last_write_error.as_ref()
+    }
+
+    /// Write as much data as possible out to the given Unix stream.
+    /// Returns the activity flags (see [`Activity`]), which are empty
+    /// if no progress could be made, or `Err(_)` if there was a fatal
+    /// error on the stream.  After a fatal error, this returns no
+    /// activity without touching the stream again.
+    ///
+    /// Assumes that it is always called with the same `UnixStream`
+    /// and pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process_out(&mut self, stream: &mut UnixStream, prd: PBufRd) -> Result<Activity> {
+        let rv = self.link.process_out_half(stream, prd, &mut NoHooks);
+        // There is no incoming pipe to abort
+        self.link.abort_incoming = false;
+        self.link.fail(rv)
     }
 }
 
-impl Default for UnixStreamLink {
+impl Default for UnixStreamWriteLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for UnixStreamWriteLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("UnixStreamWriteLink");
+        self.link.debug_fields(&mut d);
+        d.finish()
+    }
+}
+
+/// Read-only variant of [`UnixStreamLink`]
+///
+/// This is for one-directional use, where there is no outgoing pipe.
+/// Both "close" and "abort" from the peer are passed on to the
+/// incoming pipe, as for [`UnixStreamLink`].
+///
+/// [`UnixStreamReadLink::state`] follows the incoming direction
+/// only: it moves from `Open` to `InClosed` once EOF has been passed
+/// on, or to `Aborted` or `Failed`.
+///
+/// Reading starts paused, so call `set_pause_reads(false)` as soon as
+/// the stream indicates "ready".
+pub struct UnixStreamReadLink {
+    // Common settings and processing, used for reads only
+    link: GenericLink,
+}
+
+impl UnixStreamReadLink {
+    /// Create the component with default settings:
+    ///
+    /// - **max_read_unit** of 2048
+    ///
+    /// - Reads paused
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            link: GenericLink::new(2048),
+        }
+    }
+
+    /// Change the maximum number of bytes to read in each
+    /// `process_in` call.  See [`UnixStreamLink::set_max_read_unit`].
+    #[inline]
+    pub fn set_max_read_unit(&mut self, max_read_unit: usize) {
+        self.link.set_max_read_unit(max_read_unit);
+    }
+
+    /// Hold off reading while the incoming pipe has a backlog of
+    /// unconsumed data.  See
+    /// [`UnixStreamLink::set_read_backpressure`].
+    #[inline]
+    pub fn set_read_backpressure(&mut self, threshold: Option<usize>) {
+        self.link.read_backpressure = threshold;
+    }
+
+    /// Pause or unpause reads.  This takes effect on the next
+    /// `process_in` call.  Unpausing counts as fresh readiness for
+    /// the `WouldBlock` latch.
+    #[inline]
+    pub fn set_pause_reads(&mut self, pause: bool) {
+        self.link.set_pause_reads(pause);
+    }
+
+    /// Skip reads after `WouldBlock` until fresh readiness.  See
+    /// [`UnixStreamLink::set_wouldblock_latch`].
+    #[inline]
+    pub fn set_wouldblock_latch(&mut self, enable: bool) {
+        self.link.latch = enable;
+    }
+
+    /// Signal that the stream is readable, unpausing reads and
+    /// clearing the `WouldBlock` latch for reads
+    #[inline]
+    pub fn note_readable(&mut self) {
+        self.link.note_readable();
+    }
+
+    /// Limit the total number of bytes that may be read from the
+    /// stream, or remove the limit with `None`.  See
+    /// [`crate::TcpLink::set_read_quota`].
+    #[inline]
+    pub fn set_read_quota(&mut self, quota: Option<u64>) {
+        self.link.read_quota = quota;
+    }
+
+    /// Get the number of bytes that may still be read under the read
+    /// quota, or `None` if there is no quota
+    #[inline]
+    pub fn read_quota(&self) -> Option<u64> {
+        self.link.read_quota
+    }
+
+    /// Select how the incoming pipe is ended when the read quota runs
+    /// out.  See [`ReadQuotaMode`].
+    #[inline]
+    pub fn set_read_quota_mode(&mut self, mode: ReadQuotaMode) {
+        self.link.read_quota_mode = mode;
+    }
+
+    /// Get the total number of bytes read from the stream
+    #[inline]
+    pub fn bytes_in(&self) -> u64 {
+        self.link.bytes_in
+    }
+
+    /// Enable or disable collection of syscall statistics.  See
+    /// [`LinkStats`].
+    #[inline]
+    pub fn enable_stats(&mut self, enable: bool) {
+        self.link.enable_stats(enable);
+    }
+
+    /// Get the syscall statistics, or `None` if not enabled
+    #[inline]
+    pub fn stats(&self) -> Option<&LinkStats> {
+        self.link.stats.as_ref()
+    }
+
+    /// Get the position of the link in its shutdown sequence.  See
+    /// [`LinkState`].
+    #[inline]
+    pub fn state(&self) -> LinkState {
+        self.link.state()
+    }
+
+    /// Get the last error seen on a read from the stream, if any.
+    /// See [`UnixStreamLink::last_read_error`].
+    #[inline]
+    pub fn last_read_error(&self) -> Option<&io::Error> {
+        self.link.last_read_error.as_ref()
+    }
+
+    /// Read as much data as possible from the given Unix stream, up
+    /// to **max_read_unit** bytes.  Returns the activity flags (see
+    /// [`Activity`]), which are empty if no progress could be made,
+    /// or `Err(_)` if there was a fatal error on the stream.  After a
+    /// fatal error, this returns no activity without touching the
+    /// stream again.
+    ///
+    /// Assumes that it is always called with the same `UnixStream`
+    /// and pipe-buffer.  Things will behave unpredictably otherwise.
+    pub fn process_in(&mut self, stream: &mut UnixStream, pwr: PBufWr) -> Result<Activity> {
+        let rv = self.link.process_in_max(stream, pwr, usize::MAX);
+        self.link.fail(rv)
+    }
+}
+
+impl Default for UnixStreamReadLink {
     fn default() -> Self {
         Self::new()
     }
 }
+
+impl fmt::Debug for UnixStreamReadLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("UnixStreamReadLink");
+        self.link.debug_fields(&mut d);
+        d.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{read_to_end, wait_for};
+    use pipebuf::{PipeBuf, PipeBufPair};
+    use std::io::Write;
+    use std::net::Shutdown;
+
+    #[test]
+    fn backpressure_bounds_slow_consumer() {
+        let (stream, mut peer) = std::os::unix::net::UnixStream::pair().unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let mut stream = UnixStream::from_std(stream);
+        let mut link = UnixStreamLink::new();
+        link.set_pause_reads(false);
+        link.set_max_read_unit(8192);
+        link.set_read_backpressure(Some(65536));
+        let len = 2 << 20;
+        let writer = std::thread::spawn(move || {
+            peer.write_all(&vec![0x77; len]).unwrap();
+            peer.shutdown(Shutdown::Write).unwrap();
+            peer
+        });
+
+        let mut pair = PipeBufPair::new();
+        let mut received = 0;
+        let mut peak = 0;
+        wait_for(|| {
+            link.process(&mut stream, pair.lower()).unwrap();
+            let mut app = pair.upper();
+            peak = peak.max(app.rd.len());
+            let take = app.rd.len().min(4096);
+            app.rd.consume(take);
+            received += take;
+            app.rd.is_empty() && app.rd.consume_eof()
+        });
+        let _peer = writer.join().unwrap();
+        assert_eq!(received, len);
+        assert!(peak <= 65536 + 8192, "peak {}", peak);
+    }
+
+    #[test]
+    fn write_link_after_peer_dropped() {
+        let (stream, peer) = std::os::unix::net::UnixStream::pair().unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let mut stream = UnixStream::from_std(stream);
+        drop(peer);
+        let mut link = UnixStreamWriteLink::new();
+        link.set_pause_writes(false);
+
+        let mut pair = PipeBufPair::new();
+        pair.upper().wr.append(b"lost");
+        pair.upper().wr.close();
+        let act = link.process_out(&mut stream, pair.lower().rd).unwrap();
+        assert!(act.contains(Activity::ABORTED));
+        assert!(pair.lower().rd.is_empty());
+        assert!(!pair.lower().rd.has_pending_eof());
+        assert!(pair.lower().rd.is_done());
+        assert_eq!(link.state(), LinkState::Aborted);
+        let e = link.last_write_error().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::BrokenPipe);
+
+        // Nothing more is written
+        let mut pair = PipeBufPair::new();
+        pair.upper().wr.append(b"more");
+        let act = link.process_out(&mut stream, pair.lower().rd).unwrap();
+        assert_eq!(act, Activity::NONE);
+        assert!(pair.lower().rd.is_empty());
+    }
+
+    #[test]
+    fn write_link_quota_and_eof() {
+        let (stream, mut peer) = std::os::unix::net::UnixStream::pair().unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let mut stream = UnixStream::from_std(stream);
+        let mut link = UnixStreamWriteLink::new();
+        link.set_pause_writes(false);
+        link.enable_stats(true);
+        link.set_write_quota(Some(5));
+
+        let mut pair = PipeBufPair::new();
+        pair.upper().wr.append(b"hello world");
+        let act = link.process_out(&mut stream, pair.lower().rd).unwrap();
+        assert!(act.contains(Activity::QUOTA));
+        assert_eq!(link.write_quota(), Some(0));
+        assert_eq!(link.bytes_out(), 5);
+        assert_eq!(link.stats().unwrap().write_calls, 1);
+
+        // With more quota the rest goes out, followed by the EOF
+        link.set_write_quota(None);
+        pair.upper().wr.close();
+        let act = link.process_out(&mut stream, pair.lower().rd).unwrap();
+        assert!(act.contains(Activity::EOF_OUT));
+        assert_eq!(link.state(), LinkState::OutClosed);
+        assert_eq!(read_to_end(&mut peer).0, b"hello world");
+    }
+
+    #[test]
+    fn read_link_quota_and_state() {
+        let (stream, mut peer) = std::os::unix::net::UnixStream::pair().unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let mut stream = UnixStream::from_std(stream);
+        let mut link = UnixStreamReadLink::new();
+        link.set_pause_reads(false);
+        link.enable_stats(true);
+        link.set_read_quota(Some(4));
+
+        let mut pipe = PipeBuf::new();
+        peer.write_all(b"abcdefgh").unwrap();
+        // The quota running out closes the incoming pipe
+        let act = link.process_in(&mut stream, pipe.wr()).unwrap();
+        assert!(act.contains(Activity::READ | Activity::QUOTA));
+        assert_eq!(pipe.rd().data(), b"abcd");
+        assert!(pipe.rd().has_pending_eof());
+        assert_eq!(link.read_quota(), Some(0));
+        assert_eq!(link.bytes_in(), 4);
+        assert_eq!(link.state(), LinkState::InClosed);
+        assert_eq!(link.stats().unwrap().read_calls, 1);
+
+        // Nothing more is read
+        let act = link.process_in(&mut stream, pipe.wr()).unwrap();
+        assert_eq!(act, Activity::NONE);
+        assert_eq!(link.stats().unwrap().read_calls, 1);
+        assert!(link.last_read_error().is_none());
+    }
+}
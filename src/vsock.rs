@@ -0,0 +1,177 @@
+use crate::activity::{abort_in, consume_eof_out, input_from, output_to};
+use crate::fd::{raw_file, set_nonblocking};
+use crate::{Activity, Error, Result};
+use pipebuf::PBufRdWr;
+use std::io::{self, ErrorKind};
+use std::os::unix::io::RawFd;
+
+/// Exchange stream data via an `AF_VSOCK` stream socket
+///
+/// This is for communication between a virtual machine and its host.
+/// The socket is created and connected by the caller (e.g. via
+/// `libc` or `socket2`), and may be registered with `mio` via
+/// [`mio::unix::SourceFd`].  Pass its raw file descriptor, e.g. from
+/// `AsRawFd::as_raw_fd`.  The file descriptor is not owned by the
+/// link, and must remain open for as long as the link is in use.  On
+/// creation it is switched to non-blocking mode if it is not
+/// already.
+///
+/// The handling is the same as for [`crate::TcpLink`]: EOF (a
+/// zero-length read) is passed on as a "close", and
+/// `ConnectionReset` or `ConnectionAborted` as an "abort".  An
+/// outgoing "close" results in a shutdown of the outgoing half of the
+/// socket, and an outgoing "abort" results in a shutdown of both
+/// halves and an "abort" of the incoming pipe.
+///
+/// To start with both reading and writing are paused.  Call
+/// `set_pause_writes(false)` and `set_pause_reads(false)` as soon as
+/// the socket indicates "ready".
+///
+/// This is only available on Linux with the `vsock` feature.
+#[derive(Debug)]
+pub struct VsockLink {
+    // The file descriptor
+    fd: RawFd,
+
+    // Maximum amount of data to read in one go (in bytes)
+    max_read_unit: usize,
+
+    // Set to pause writes (waiting for first "ready" indication)
+    pause_writes: bool,
+
+    // Set to pause reads (waiting for first "ready" indication)
+    pause_reads: bool,
+}
+
+impl VsockLink {
+    /// Create the component for the given socket file descriptor,
+    /// switching it to non-blocking mode if necessary, with default
+    /// settings:
+    ///
+    /// - **max_read_unit** of 2048
+    ///
+    /// - Both reads and writes paused
+    pub fn new(fd: RawFd) -> io::Result<Self> {
+        set_nonblocking(fd)?;
+        Ok(Self {
+            fd,
+            max_read_unit: 2048,
+            pause_writes: true,
+            pause_reads: true,
+        })
+    }
+
+    /// Get the file descriptor, e.g. for registering via
+    /// [`mio::unix::SourceFd`]
+    #[inline]
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    /// Change the maximum number of bytes to read in each `process`
+    /// call
+    #[inline]
+    pub fn set_max_read_unit(&mut self, max_read_unit: usize) {
+        self.max_read_unit = max_read_unit;
+    }
+
+    /// Pause or unpause writes.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_writes(&mut self, pause: bool) {
+        self.pause_writes = pause;
+    }
+
+    /// Pause or unpause reads.  This takes effect on the next
+    /// `process` call.
+    #[inline]
+    pub fn set_pause_reads(&mut self, pause: bool) {
+        self.pause_reads = pause;
+    }
+
+    /// Read and write as much data as possible to and from the
+    /// socket.  Returns the activity flags (see [`Activity`]), which
+    /// are empty if no progress could be made, or `Err(_)` if there
+    /// was a fatal error on the socket.
+    ///
+    /// Assumes that it is always called with the same pipe-buffer.
+    /// Things will behave unpredictably otherwise.
+    pub fn process(&mut self, mut pbuf: PBufRdWr) -> Result<Activity> {
+        let rd_activity = self.process_out(pbuf.reborrow())?;
+        let wr_activity = self.process_in(pbuf.reborrow())?;
+        Ok(rd_activity | wr_activity)
+    }
+
+    /// Write as much data as possible out to the socket.  Returns the
+    /// activity flags (see [`Activity`]), which are empty if no
+    /// progress could be made, or `Err(_)` if there was a fatal error
+    /// on the socket.
+    pub fn process_out(&mut self, mut pbuf: PBufRdWr) -> Result<Activity> {
+        if self.pause_writes {
+            return Ok(Activity::NONE);
+        }
+
+        let mut prd = pbuf.rd;
+        let mut act = Activity::NONE;
+        match output_to(&mut prd, &mut *raw_file(self.fd), &mut act) {
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => (),
+            Err(e) => return Err(Error::write(e)),
+            Ok(_) => {
+                if prd.is_empty() && prd.has_pending_eof() {
+                    let how = if prd.is_aborted() {
+                        if !pbuf.wr.is_eof() {
+                            abort_in(&mut pbuf.wr, &mut act);
+                        }
+                        libc::SHUT_RDWR
+                    } else {
+                        libc::SHUT_WR
+                    };
+                    match retry!(shutdown(self.fd, how)) {
+                        Err(ref e) if e.kind() == ErrorKind::WouldBlock => (),
+                        Err(e) => return Err(Error::shutdown(e)),
+                        Ok(_) => {
+                            consume_eof_out(&mut prd, &mut act);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(act)
+    }
+
+    /// Read as much data as possible from the socket, up to
+    /// **max_read_unit** bytes.  Returns the activity flags (see
+    /// [`Activity`]), which are empty if no progress could be made,
+    /// or `Err(_)` if there was a fatal error on the socket.
+    pub fn process_in(&mut self, pbuf: PBufRdWr) -> Result<Activity> {
+        let mut pwr = pbuf.wr;
+        if self.pause_reads || pwr.is_eof() {
+            return Ok(Activity::NONE);
+        }
+
+        let mut act = Activity::NONE;
+        if let Err(e) = input_from(
+            &mut pwr,
+            &mut *raw_file(self.fd),
+            self.max_read_unit,
+            &mut act,
+        ) {
+            match e.kind() {
+                ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted => {
+                    abort_in(&mut pwr, &mut act)
+                }
+                ErrorKind::WouldBlock => (),
+                _ => return Err(Error::read(e)),
+            }
+        }
+        Ok(act)
+    }
+}
+
+fn shutdown(fd: RawFd, how: libc::c_int) -> io::Result<()> {
+    // Safety: shutdown() has no memory-safety implications
+    if unsafe { libc::shutdown(fd, how) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
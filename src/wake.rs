@@ -0,0 +1,125 @@
+use mio::Waker;
+use pipebuf::PBufWr;
+use std::io::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Feed an outgoing pipe from other threads, waking the `mio` poll
+/// loop when there is data to send
+///
+/// A pipe-buffer can only be accessed from one thread, so data pushed
+/// from other threads is collected here, and moved into the real
+/// pipe-buffer by the poll loop thread with [`WakeOnWrite::transfer`].
+/// Share it between threads using an `Arc`.
+///
+/// The first [`WakeOnWrite::push`] after the poll loop has called
+/// [`WakeOnWrite::take_wake`] calls [`Waker::wake`].  Further pushes
+/// don't wake again until the wake has been taken, so many small
+/// writes result in only one wake-up.  When the poll loop receives an
+/// event for the waker's token, it should call `take_wake` and if it
+/// returns `true`, call `transfer` and then `process_out` (or
+/// `process`) on the link that handles the outgoing pipe.
+pub struct WakeOnWrite {
+    waker: Waker,
+
+    // Set when a wake has been sent and not yet taken
+    woken: AtomicBool,
+
+    // Data pushed but not yet transferred
+    shared: Mutex<Shared>,
+}
+
+struct Shared {
+    data: Vec<u8>,
+
+    // Pending close (false) or abort (true)
+    eof: Option<bool>,
+}
+
+impl WakeOnWrite {
+    /// Create the helper using the given `mio` [`Waker`]
+    #[inline]
+    pub fn new(waker: Waker) -> Self {
+        Self {
+            waker,
+            woken: AtomicBool::new(false),
+            shared: Mutex::new(Shared {
+                data: Vec::new(),
+                eof: None,
+            }),
+        }
+    }
+
+    /// Add data to be sent, waking the poll loop if it has not
+    /// already been woken.  Data pushed after a close or abort is
+    /// discarded.  Returns an error only if the wake fails.
+    pub fn push(&self, data: &[u8]) -> Result<()> {
+        {
+            let mut shared = self.lock();
+            if shared.eof.is_some() {
+                return Ok(());
+            }
+            shared.data.extend_from_slice(data);
+        }
+        self.wake()
+    }
+
+    /// Close the outgoing pipe once all data pushed so far has been
+    /// transferred, waking the poll loop if necessary
+    pub fn close(&self) -> Result<()> {
+        self.lock().eof.get_or_insert(false);
+        self.wake()
+    }
+
+    /// Abort the outgoing pipe once all data pushed so far has been
+    /// transferred, waking the poll loop if necessary
+    pub fn abort(&self) -> Result<()> {
+        self.lock().eof.get_or_insert(true);
+        self.wake()
+    }
+
+    /// Called by the poll loop thread on an event for the waker's
+    /// token.  Returns `true` if a wake was pending, which means that
+    /// [`WakeOnWrite::transfer`] should be called and the outgoing
+    /// pipe processed.  Clears the pending wake, so that the next
+    /// push wakes the poll loop again.
+    #[inline]
+    pub fn take_wake(&self) -> bool {
+        self.woken.swap(false, Ordering::AcqRel)
+    }
+
+    /// Move all data pushed so far into the given pipe-buffer
+    /// writer, followed by a close or abort if one has been
+    /// requested.  Returns `true` if anything was transferred.  Does
+    /// nothing if the pipe is already closed.
+    pub fn transfer(&self, mut pwr: PBufWr) -> bool {
+        if pwr.is_eof() {
+            return false;
+        }
+        let mut shared = self.lock();
+        let mut activity = false;
+        if !shared.data.is_empty() {
+            pwr.append(&shared.data);
+            shared.data.clear();
+            activity = true;
+        }
+        match shared.eof {
+            Some(false) => pwr.close(),
+            Some(true) => pwr.abort(),
+            None => return activity,
+        }
+        true
+    }
+
+    fn wake(&self) -> Result<()> {
+        if self.woken.swap(true, Ordering::AcqRel) {
+            return Ok(());
+        }
+        self.waker.wake()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Shared> {
+        // The shared data is always valid, so ignore poisoning
+        self.shared.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
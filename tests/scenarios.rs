@@ -0,0 +1,358 @@
+//! The same scenarios run against `TcpLink` and `UnixStreamLink`, to
+//! check that the shared processing behaves identically for both
+
+use mio::net::TcpStream;
+use pipebuf::{PBufRdWr, PipeBufPair};
+use pipebuf_mio::{Activity, LinkState, ProcessOrder, Result, TcpLink};
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// A link type under test, along with its stream and peer types
+trait Subject: Sized {
+    type Stream;
+    type Peer: Read + Write + Send + 'static;
+
+    // Create a connected stream and peer, and a link with both
+    // directions unpaused
+    fn open() -> (Self::Stream, Self, Self::Peer);
+    fn process(&mut self, stream: &mut Self::Stream, pbuf: PBufRdWr) -> Result<Activity>;
+    fn abort_now(&mut self, stream: &mut Self::Stream, pbuf: PBufRdWr) -> Activity;
+    fn close_when_flushed(&mut self);
+    fn set_process_order(&mut self, order: ProcessOrder);
+    fn set_read_backpressure(&mut self, threshold: Option<usize>);
+    fn state(&self) -> LinkState;
+    fn is_done(&self, pbuf: &PBufRdWr) -> bool;
+    fn try_clone(peer: &Self::Peer) -> Self::Peer;
+    fn shutdown_write(peer: &Self::Peer);
+}
+
+impl Subject for TcpLink {
+    type Stream = TcpStream;
+    type Peer = std::net::TcpStream;
+
+    fn open() -> (TcpStream, Self, Self::Peer) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let peer = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        stream.set_nonblocking(true).unwrap();
+        peer.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut link = TcpLink::new();
+        link.set_pause_reads(false);
+        link.set_pause_writes(false);
+        (TcpStream::from_std(stream), link, peer)
+    }
+    fn process(&mut self, stream: &mut TcpStream, pbuf: PBufRdWr) -> Result<Activity> {
+        TcpLink::process(self, stream, pbuf)
+    }
+    fn abort_now(&mut self, stream: &mut TcpStream, pbuf: PBufRdWr) -> Activity {
+        TcpLink::abort_now(self, stream, pbuf)
+    }
+    fn close_when_flushed(&mut self) {
+        TcpLink::close_when_flushed(self)
+    }
+    fn set_process_order(&mut self, order: ProcessOrder) {
+        TcpLink::set_process_order(self, order)
+    }
+    fn set_read_backpressure(&mut self, threshold: Option<usize>) {
+        TcpLink::set_read_backpressure(self, threshold)
+    }
+    fn state(&self) -> LinkState {
+        TcpLink::state(self)
+    }
+    fn is_done(&self, pbuf: &PBufRdWr) -> bool {
+        TcpLink::is_done(self, pbuf)
+    }
+    fn try_clone(peer: &Self::Peer) -> Self::Peer {
+        peer.try_clone().unwrap()
+    }
+    fn shutdown_write(peer: &Self::Peer) {
+        peer.shutdown(Shutdown::Write).unwrap()
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::*;
+    use mio::net::UnixStream;
+    use pipebuf_mio::UnixStreamLink;
+
+    impl Subject for UnixStreamLink {
+        type Stream = UnixStream;
+        type Peer = std::os::unix::net::UnixStream;
+
+        fn open() -> (UnixStream, Self, Self::Peer) {
+            let (stream, peer) = std::os::unix::net::UnixStream::pair().unwrap();
+            stream.set_nonblocking(true).unwrap();
+            peer.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            let mut link = UnixStreamLink::new();
+            link.set_pause_reads(false);
+            link.set_pause_writes(false);
+            (UnixStream::from_std(stream), link, peer)
+        }
+        fn process(&mut self, stream: &mut UnixStream, pbuf: PBufRdWr) -> Result<Activity> {
+            UnixStreamLink::process(self, stream, pbuf)
+        }
+        fn abort_now(&mut self, stream: &mut UnixStream, pbuf: PBufRdWr) -> Activity {
+            UnixStreamLink::abort_now(self, stream, pbuf)
+        }
+        fn close_when_flushed(&mut self) {
+            UnixStreamLink::close_when_flushed(self)
+        }
+        fn set_process_order(&mut self, order: ProcessOrder) {
+            UnixStreamLink::set_process_order(self, order)
+        }
+        fn set_read_backpressure(&mut self, threshold: Option<usize>) {
+            UnixStreamLink::set_read_backpressure(self, threshold)
+        }
+        fn state(&self) -> LinkState {
+            UnixStreamLink::state(self)
+        }
+        fn is_done(&self, pbuf: &PBufRdWr) -> bool {
+            UnixStreamLink::is_done(self, pbuf)
+        }
+        fn try_clone(peer: &Self::Peer) -> Self::Peer {
+            peer.try_clone().unwrap()
+        }
+        fn shutdown_write(peer: &Self::Peer) {
+            peer.shutdown(Shutdown::Write).unwrap()
+        }
+    }
+}
+
+// Generate a test of each scenario for each link type
+macro_rules! scenarios {
+    ($module:ident, $link:ty) => {
+        mod $module {
+            use super::*;
+
+            #[test]
+            fn transfer() {
+                super::transfer::<$link>(ProcessOrder::WriteThenRead);
+            }
+
+            #[test]
+            fn transfer_read_first() {
+                super::transfer::<$link>(ProcessOrder::ReadThenWrite);
+            }
+
+            #[test]
+            fn transfer_interleaved() {
+                super::transfer::<$link>(ProcessOrder::Interleaved { chunk: 1000 });
+            }
+
+            #[test]
+            fn peer_eof() {
+                super::peer_eof::<$link>();
+            }
+
+            #[test]
+            fn outgoing_abort() {
+                super::outgoing_abort::<$link>();
+            }
+
+            #[test]
+            fn close_when_flushed() {
+                super::close_when_flushed::<$link>();
+            }
+
+            #[test]
+            fn abort_now() {
+                super::abort_now::<$link>();
+            }
+
+            #[test]
+            fn backpressure() {
+                super::backpressure::<$link>();
+            }
+        }
+    };
+}
+
+scenarios!(tcp, TcpLink);
+#[cfg(unix)]
+scenarios!(unix_stream, pipebuf_mio::UnixStreamLink);
+
+// Call `step` until it returns true, failing after 10 seconds
+#[track_caller]
+fn wait_for(mut step: impl FnMut() -> bool) {
+    let start = Instant::now();
+    while !step() {
+        assert!(start.elapsed() < Duration::from_secs(10), "timed out");
+        thread::sleep(Duration::from_micros(100));
+    }
+}
+
+// Test data that shows up misordering or loss
+fn pattern(len: usize, seed: u8) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8 ^ seed).collect()
+}
+
+// Read from the peer until EOF, failing on an error
+fn read_all(mut peer: impl Read) -> Vec<u8> {
+    let mut data = Vec::new();
+    peer.read_to_end(&mut data).unwrap();
+    data
+}
+
+// Move 512KB each way at once, with EOF following in each direction
+fn transfer<L: Subject>(order: ProcessOrder) {
+    let (mut stream, mut link, peer) = L::open();
+    link.set_process_order(order);
+    let mut pipes = PipeBufPair::new();
+    let outgoing = pattern(512 * 1024, 0x55);
+    let incoming = pattern(512 * 1024, 0xAA);
+
+    let mut writer = L::try_clone(&peer);
+    let send = incoming.clone();
+    let writer = thread::spawn(move || {
+        writer.write_all(&send).unwrap();
+        L::shutdown_write(&writer);
+    });
+    let reader = thread::spawn(move || read_all(peer));
+
+    let mut received = Vec::new();
+    let mut sent = 0;
+    wait_for(|| {
+        let pending = pipes.lower().rd.len();
+        let mut app = pipes.upper();
+        if sent < outgoing.len() && pending < 64 * 1024 {
+            let end = (sent + 16 * 1024).min(outgoing.len());
+            app.wr.append(&outgoing[sent..end]);
+            sent = end;
+            if sent == outgoing.len() {
+                app.wr.close();
+            }
+        }
+        received.extend_from_slice(app.rd.data());
+        let len = app.rd.len();
+        app.rd.consume(len);
+        link.process(&mut stream, pipes.lower()).unwrap();
+        link.is_done(&pipes.lower())
+    });
+    let mut app = pipes.upper();
+    received.extend_from_slice(app.rd.data());
+    assert!(app.rd.consume_eof());
+    assert!(!app.rd.is_aborted());
+
+    writer.join().unwrap();
+    assert_eq!(reader.join().unwrap(), outgoing);
+    assert_eq!(received, incoming);
+    assert_eq!(link.state(), LinkState::Closed);
+}
+
+// EOF from the peer closes the incoming pipe and nothing else
+fn peer_eof<L: Subject>() {
+    let (mut stream, mut link, mut peer) = L::open();
+    let mut pipes = PipeBufPair::new();
+    peer.write_all(b"last words").unwrap();
+    L::shutdown_write(&peer);
+
+    let mut act = Activity::NONE;
+    wait_for(|| {
+        act |= link.process(&mut stream, pipes.lower()).unwrap();
+        pipes.upper().rd.is_eof()
+    });
+    assert!(act.contains(Activity::EOF_IN));
+    assert!(!act.contains(Activity::ABORTED));
+    assert_eq!(pipes.upper().rd.data(), b"last words");
+    assert_eq!(link.state(), LinkState::InClosed);
+
+    // The outgoing side still works
+    pipes.upper().wr.append(b"reply");
+    pipes.upper().wr.close();
+    link.process(&mut stream, pipes.lower()).unwrap();
+    assert_eq!(read_all(peer), b"reply");
+    assert_eq!(link.state(), LinkState::Closed);
+}
+
+// An outgoing abort aborts the incoming pipe, and the peer sees the
+// stream shut down after any data already written
+fn outgoing_abort<L: Subject>() {
+    let (mut stream, mut link, peer) = L::open();
+    let mut pipes = PipeBufPair::new();
+    pipes.upper().wr.append(b"partial");
+    pipes.upper().wr.abort();
+
+    let act = link.process(&mut stream, pipes.lower()).unwrap();
+    assert!(act.contains(Activity::ABORTED));
+    assert!(act.contains(Activity::EOF_OUT));
+    assert!(pipes.upper().rd.is_aborted());
+    assert_eq!(link.state(), LinkState::Aborted);
+    assert!(link.is_done(&pipes.lower()));
+    assert_eq!(read_all(peer), b"partial");
+}
+
+// The stream is shut down once the pipe is flushed, without EOF in
+// the pipe, and later data is discarded
+fn close_when_flushed<L: Subject>() {
+    let (mut stream, mut link, peer) = L::open();
+    let mut pipes = PipeBufPair::new();
+    pipes.upper().wr.append(b"goodbye");
+    link.close_when_flushed();
+
+    let act = link.process(&mut stream, pipes.lower()).unwrap();
+    assert!(act.contains(Activity::EOF_OUT));
+    assert_eq!(link.state(), LinkState::OutClosed);
+    pipes.upper().wr.append(b"ignored");
+    link.process(&mut stream, pipes.lower()).unwrap();
+    assert!(pipes.lower().rd.is_empty());
+    assert_eq!(read_all(peer), b"goodbye");
+}
+
+// Aborting at once discards the pipe's data and ends both directions
+fn abort_now<L: Subject>() {
+    let (mut stream, mut link, peer) = L::open();
+    let mut pipes = PipeBufPair::new();
+    pipes.upper().wr.append(b"never sent");
+
+    let act = link.abort_now(&mut stream, pipes.lower());
+    assert!(act.contains(Activity::ABORTED));
+    assert!(pipes.upper().rd.is_aborted());
+    assert!(pipes.lower().rd.is_empty());
+    assert_eq!(link.abort_now(&mut stream, pipes.lower()), Activity::NONE);
+    assert!(link.is_done(&pipes.lower()));
+    assert_eq!(read_all(peer), b"");
+}
+
+// A slow consumer holds the incoming pipe near the threshold, and
+// all the data arrives once it catches up
+fn backpressure<L: Subject>() {
+    let (mut stream, mut link, peer) = L::open();
+    link.set_read_backpressure(Some(8192));
+    let mut pipes = PipeBufPair::new();
+    let data = pattern(256 * 1024, 0x33);
+
+    let mut writer = L::try_clone(&peer);
+    let send = data.clone();
+    let writer = thread::spawn(move || {
+        writer.write_all(&send).unwrap();
+        L::shutdown_write(&writer);
+    });
+
+    // Without consuming, the pipe fills no further than the
+    // threshold plus one read
+    for _ in 0..50 {
+        link.process(&mut stream, pipes.lower()).unwrap();
+        thread::sleep(Duration::from_millis(1));
+    }
+    let backlog = pipes.upper().rd.len();
+    assert!(backlog > 0 && backlog <= 8192 + 2048, "backlog {}", backlog);
+
+    let mut received = Vec::new();
+    wait_for(|| {
+        let mut app = pipes.upper();
+        received.extend_from_slice(app.rd.data());
+        let len = app.rd.len();
+        app.rd.consume(len);
+        if app.rd.is_eof() {
+            return true;
+        }
+        link.process(&mut stream, pipes.lower()).unwrap();
+        assert!(pipes.upper().rd.len() <= 8192 + 2048);
+        false
+    });
+    writer.join().unwrap();
+    assert_eq!(received, data);
+}